@@ -0,0 +1,491 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use chrono::NaiveDate;
+
+use crate::billing::{
+    tier_by_month, CurrencyMismatch, Invoice, InvoiceItem, Money, Rate,
+    TaxRate, Unit, WeekProration,
+};
+use crate::clients::{Client, Clients, Event};
+
+/// Difference between an invoice as stored and what the client's current
+/// history would produce for it
+#[derive(Debug, PartialEq)]
+pub enum Finding {
+    NoRate {
+        item: String,
+        from: NaiveDate,
+    },
+    Rate {
+        item: String,
+        stored: Rate,
+        history: Rate,
+    },
+    Amount {
+        item: String,
+        stored: Money,
+        history: Money,
+    },
+    Taxes {
+        stored: Vec<TaxRate>,
+        history: Vec<TaxRate>,
+    },
+    /// Items in currencies that can't be totalled together
+    Currency(CurrencyMismatch),
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Finding::NoRate { item, from } => {
+                write!(f, "{}: no rate in the history as of {}", item, from)
+            }
+            Finding::Rate {
+                item,
+                stored,
+                history,
+            } => write!(
+                f,
+                "{}: invoiced at {}, history has {}",
+                item, stored, history
+            ),
+            Finding::Amount {
+                item,
+                stored,
+                history,
+            } => write!(
+                f,
+                "{}: invoiced {}, history gives {}",
+                item, stored, history
+            ),
+            Finding::Taxes { stored, history } => write!(
+                f,
+                "Taxes: invoiced {}, history has {}",
+                tax_list(stored),
+                tax_list(history)
+            ),
+            Finding::Currency(CurrencyMismatch(first, other)) => write!(
+                f,
+                "Total: items in {} and {} can't be added up",
+                first.code(),
+                other.code()
+            ),
+        }
+    }
+}
+
+fn tax_list(taxes: &[TaxRate]) -> String {
+    if taxes.is_empty() {
+        return "none".to_string();
+    }
+    let taxes: Vec<String> = taxes.iter().map(|t| t.to_string()).collect();
+    taxes.join(", ")
+}
+
+/// Recomputes each item and the taxes of an invoice from the client's rate
+/// and tax history, weekly items with the given week proration
+pub fn audit_invoice(
+    client: &Client,
+    invoice: &Invoice,
+    weeks: WeekProration,
+) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    let mut stored = Vec::new();
+    let mut recomputed = Vec::new();
+    for item in invoice.items.iter() {
+        let from = item.period.from;
+        let Some(rate) = client
+            .service(item.name.clone())
+            .and_then(|s| s.rates.as_of(from))
+        else {
+            findings.push(Finding::NoRate {
+                item: item.name.clone(),
+                from,
+            });
+            continue;
+        };
+
+        if *rate != item.rate {
+            findings.push(Finding::Rate {
+                item: item.name.clone(),
+                stored: item.rate.clone(),
+                history: rate.clone(),
+            });
+        }
+        stored.push(item);
+        recomputed.push(if rate.per == Unit::Hour {
+            InvoiceItem::new_hourly(
+                item.name.clone(),
+                rate.clone(),
+                item.period.clone(),
+                item.quantity,
+            )
+        } else {
+            let quantity = item.period.num_units_with(&rate.per, weeks);
+            InvoiceItem::raw(
+                item.name.clone(),
+                rate.clone(),
+                item.period.clone(),
+                quantity,
+                rate.amount * quantity,
+            )
+        });
+    }
+    // Tiered items spanning months were split on their own before hours
+    // were counted per month, so they're compared as they were made
+    let _ = tier_by_month(&mut recomputed);
+
+    for (item, recomputed) in stored.into_iter().zip(recomputed) {
+        let recomputed = match invoice.fx {
+            Some((from, fx)) if recomputed.amount.currency() == from => {
+                recomputed.converted(invoice.currency(), fx)
+            }
+            _ => recomputed,
+        };
+        if recomputed.amount != item.amount {
+            findings.push(Finding::Amount {
+                item: item.name.clone(),
+                stored: item.amount,
+                history: recomputed.amount,
+            });
+        }
+    }
+
+    let history = client.taxes_as_of(invoice.overall_period().from);
+    if history != invoice.tax_rates {
+        findings.push(Finding::Taxes {
+            stored: invoice.tax_rates.clone(),
+            history,
+        });
+    }
+    if let Err(mismatch) = invoice.calculate() {
+        findings.push(Finding::Currency(mismatch));
+    }
+    findings
+}
+
+/// Line numbers of events stamped earlier than an event before them, events
+/// recorded with an explicit timestamp are expected to be out of order
+pub fn out_of_order_events(events: &[Event]) -> Vec<usize> {
+    let mut latest = None;
+    let mut lines = Vec::new();
+    for (i, event) in events.iter().enumerate() {
+        if event.is_backdated() {
+            continue;
+        }
+        match latest {
+            Some(latest) if event.1 < latest => lines.push(i + 1),
+            _ => latest = Some(event.1),
+        }
+    }
+    lines
+}
+
+/// Problem with the invoice numbers clients share
+#[derive(Debug, PartialEq)]
+pub enum Numbering {
+    /// Number used by more than one client
+    Duplicate(usize, Vec<String>),
+    Missing(usize),
+}
+
+impl fmt::Display for Numbering {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Numbering::Duplicate(number, keys) => {
+                write!(f, "#{} is used by {}", number, keys.join(", "))
+            }
+            Numbering::Missing(number) => {
+                write!(f, "#{} is missing from the shared sequence", number)
+            }
+        }
+    }
+}
+
+/// Duplicate and missing numbers in the sequence shared by all clients,
+/// from its first number up to the highest
+pub fn shared_numbering(clients: &Clients, from: usize) -> Vec<Numbering> {
+    let mut used: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    for client in clients.iter() {
+        for invoice in client.invoices().filter(|i| i.number >= from) {
+            used.entry(invoice.number)
+                .or_default()
+                .push(client.key.clone());
+        }
+    }
+    let last = used.keys().next_back().copied().unwrap_or(0);
+    let mut problems = Vec::new();
+    for number in from..=last {
+        match used.remove(&number) {
+            None => problems.push(Numbering::Missing(number)),
+            Some(keys) if keys.len() > 1 => {
+                problems.push(Numbering::Duplicate(number, keys))
+            }
+            Some(_) => (),
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::billing::tests::{monthly, ymd};
+    use crate::billing::{Currency, Period};
+    use crate::clients::{Change, ClientError, InvoiceError, Update};
+    use chrono::{DateTime, TimeZone, Utc};
+    use rust_decimal::Decimal;
+
+    fn invoiced_client() -> Client {
+        let mut client = Client::new("innotech", "Innotech", "Some Place");
+        let gst = vec![TaxRate::new("GST".to_string(), 5)];
+        let rate = monthly(Currency::Cad, 1000);
+        let item = InvoiceItem::new(
+            "Stuff".to_string(),
+            rate.clone(),
+            Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30)),
+        );
+        let invoice = Invoice::new(1, vec![item], gst.clone()).unwrap();
+        for update in [
+            Update::ServiceRate("Stuff".to_string(), ymd(2024, 1, 1), rate),
+            Update::Taxes(ymd(2024, 1, 1), gst),
+            Update::Invoiced(invoice),
+        ] {
+            client.update(&update).unwrap();
+        }
+        client
+    }
+
+    fn audit(client: &Client) -> Vec<Finding> {
+        audit_invoice(client, client.invoice(&1).unwrap(), Default::default())
+    }
+
+    #[test]
+    fn consistent_invoice_has_no_findings() {
+        assert!(audit(&invoiced_client()).is_empty());
+    }
+
+    #[test]
+    fn rate_changed_after_invoicing() {
+        let mut client = invoiced_client();
+        let rate = monthly(Currency::Cad, 1200);
+        client
+            .update(&Update::ServiceRate(
+                "Stuff".to_string(),
+                ymd(2024, 3, 1),
+                rate.clone(),
+            ))
+            .unwrap();
+
+        assert_eq!(
+            audit(&client),
+            vec![
+                Finding::Rate {
+                    item: "Stuff".to_string(),
+                    stored: monthly(Currency::Cad, 1000),
+                    history: rate,
+                },
+                Finding::Amount {
+                    item: "Stuff".to_string(),
+                    stored: Money::new(Currency::Cad, Decimal::from(1000)),
+                    history: Money::new(Currency::Cad, Decimal::from(1200)),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn taxes_changed_after_invoicing() {
+        let mut client = invoiced_client();
+        client
+            .update(&Update::Taxes(ymd(2024, 2, 1), vec![]))
+            .unwrap();
+
+        let findings = audit(&client);
+        assert_eq!(
+            findings,
+            vec![Finding::Taxes {
+                stored: vec![TaxRate::new("GST".to_string(), 5)],
+                history: vec![],
+            }]
+        );
+        assert_eq!(
+            findings[0].to_string(),
+            "Taxes: invoiced GST @ 5.00%, history has none"
+        );
+    }
+
+    #[test]
+    fn service_missing_from_history() {
+        let mut client = invoiced_client();
+        client.services.clear();
+
+        assert_eq!(
+            audit(&client),
+            vec![Finding::NoRate {
+                item: "Stuff".to_string(),
+                from: ymd(2024, 4, 1),
+            }]
+        );
+    }
+
+    #[test]
+    fn mixed_currency_invoice() {
+        let mut client = invoiced_client();
+        let mut invoice = client.invoice(&1).unwrap().clone();
+        invoice.number = 2;
+        invoice.items.push(InvoiceItem::new(
+            "Stuff".to_string(),
+            monthly(Currency::Usd, 1000),
+            Period::new(ymd(2024, 5, 1), ymd(2024, 5, 31)),
+        ));
+
+        // Replay refuses the invoice, audits still flag ones they're given
+        assert!(matches!(
+            client.update(&Update::Invoiced(invoice.clone())),
+            Err(ClientError::Invoice(2, InvoiceError::MixedCurrencies(_)))
+        ));
+        let findings = audit_invoice(&client, &invoice, Default::default());
+        let mismatch = CurrencyMismatch(Currency::Cad, Currency::Usd);
+        assert!(findings.contains(&Finding::Currency(mismatch)));
+        assert_eq!(
+            findings.last().unwrap().to_string(),
+            "Total: items in CAD and USD can't be added up"
+        );
+    }
+
+    fn removed_at(day: u32, backdated: bool) -> Event {
+        let timestamp: DateTime<Utc> = Utc
+            .with_ymd_and_hms(2024, 5, day, 12, 0, 0)
+            .single()
+            .unwrap();
+        Event(
+            "innotech".to_string(),
+            timestamp,
+            Change::Removed,
+            backdated,
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn events_out_of_order() {
+        let events = vec![
+            removed_at(1, false),
+            removed_at(3, false),
+            removed_at(2, false),
+            removed_at(4, false),
+        ];
+        assert_eq!(out_of_order_events(&events), vec![3]);
+    }
+
+    #[test]
+    fn backdated_events_may_be_out_of_order() {
+        let events = vec![
+            removed_at(3, false),
+            removed_at(1, true),
+            removed_at(2, true),
+            removed_at(4, false),
+        ];
+        assert!(out_of_order_events(&events).is_empty());
+    }
+
+    #[test]
+    fn weekly_items_from_before_working_day_weeks() {
+        let mut client = Client::new("innotech", "Innotech", "Some Place");
+        let rate = Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(1000)),
+            per: Unit::Week,
+            tiers: Vec::new(),
+        };
+        // Friday to Thursday, half a week under the earlier proration
+        let period = Period::new(ymd(2025, 1, 3), ymd(2025, 1, 9));
+        let quantity =
+            period.num_units_with(&Unit::Week, WeekProration::Envelope);
+        assert_eq!(quantity, Decimal::new(5, 1));
+        let item = InvoiceItem::raw(
+            "Stuff".to_string(),
+            rate.clone(),
+            period,
+            quantity,
+            rate.amount * quantity,
+        );
+        let invoice = Invoice::new(1, vec![item], vec![]).unwrap();
+        for update in [
+            Update::ServiceRate("Stuff".to_string(), ymd(2024, 1, 1), rate),
+            Update::Invoiced(invoice),
+        ] {
+            client.update(&update).unwrap();
+        }
+        let invoice = client.invoice(&1).unwrap();
+
+        assert_eq!(
+            audit_invoice(&client, invoice, WeekProration::WorkingDays).len(),
+            1
+        );
+        assert!(
+            audit_invoice(&client, invoice, WeekProration::Envelope).is_empty()
+        );
+    }
+
+    #[test]
+    fn tiered_hours_counted_per_month() {
+        let mut client = Client::new("innotech", "Innotech", "Some Place");
+        let rate = Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(150)),
+            per: Unit::Hour,
+            tiers: vec![(
+                Decimal::from(40),
+                Money::new(Currency::Cad, Decimal::from(120)),
+            )],
+        };
+        let mut items: Vec<InvoiceItem> = [(1, 15), (16, 30)]
+            .into_iter()
+            .map(|(from, until)| {
+                InvoiceItem::new_hourly(
+                    "Support".to_string(),
+                    rate.clone(),
+                    Period::new(ymd(2024, 4, from), ymd(2024, 4, until)),
+                    Decimal::from(30),
+                )
+            })
+            .collect();
+        tier_by_month(&mut items).unwrap();
+        let invoice = Invoice::new(1, items, vec![]).unwrap();
+        for update in [
+            Update::ServiceRate("Support".to_string(), ymd(2024, 1, 1), rate),
+            Update::Invoiced(invoice),
+        ] {
+            client.update(&update).unwrap();
+        }
+        assert!(audit(&client).is_empty());
+    }
+
+    #[test]
+    fn shared_numbering_gaps_and_duplicates() {
+        let rate = monthly(Currency::Cad, 1000);
+        let mut clients = Clients::new();
+        for (key, numbers) in [("acme", [2, 3]), ("innotech", [1, 5])] {
+            let key = key.to_string();
+            let client = Client::new(&key, &key, "Some Place");
+            clients.add(&key, client).unwrap();
+            for number in numbers {
+                let period = Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30));
+                let item =
+                    InvoiceItem::new("Stuff".to_string(), rate.clone(), period);
+                let invoice = Invoice::new(number, vec![item], vec![]).unwrap();
+                clients.update(&key, &Update::Invoiced(invoice)).unwrap();
+            }
+        }
+        assert_eq!(shared_numbering(&clients, 2), vec![Numbering::Missing(4)]);
+        assert_eq!(shared_numbering(&clients, 6), vec![]);
+        assert_eq!(
+            Numbering::Duplicate(3, vec!["acme".into(), "innotech".into()])
+                .to_string(),
+            "#3 is used by acme, innotech"
+        );
+    }
+}
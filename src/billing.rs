@@ -1,15 +1,21 @@
 use std::cmp;
+use std::collections::BTreeMap;
 use std::fmt;
-use std::ops::{Add, Mul};
+use std::iter;
+use std::ops::{Add, Mul, Neg, Sub};
+use std::str::FromStr;
 
-use chrono::{Datelike, Local, NaiveDate};
+use chrono::{Datelike, Days, Local, Months, NaiveDate};
 use rust_decimal::{Decimal, RoundingStrategy};
 use serde::{Deserialize, Serialize};
 use strum_macros::{Display, EnumString, VariantNames};
+use thiserror::Error;
 
 use crate::calendar::DateBoundaries;
 use crate::historical::Historical;
-use crate::ledger_fmt::LedgerDisplay;
+use crate::ledger_fmt::{
+    ledger_fmt_with, CommodityPosition, LedgerDisplay, LedgerStyle,
+};
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Period {
@@ -22,7 +28,95 @@ impl Period {
         Self { from, until }
     }
 
-    fn working_days(&self) -> Decimal {
+    /// The period of the same length that follows this one, whole calendar
+    /// months are followed by the same number of whole months
+    pub fn next(&self) -> Self {
+        let from = self.until.succ_opt().expect("Date out of range");
+        let whole_months = self.from.start_of_month() == Some(self.from)
+            && self.until.end_of_month() == Some(self.until);
+
+        let until = if whole_months {
+            let months = (self.until.year() - self.from.year()) * 12
+                + self.until.month() as i32
+                - self.from.month() as i32;
+            from.checked_add_months(Months::new(months as u32))
+                .and_then(|d| d.end_of_month())
+        } else {
+            from.checked_add_signed(self.until - self.from)
+        };
+        Self::new(from, until.expect("Date out of range"))
+    }
+
+    /// The weeks, Monday to Sunday, of the period with the first and last
+    /// clipped to it
+    pub fn weeks(&self) -> Vec<Period> {
+        let Some(monday) = self.from.start_of_week() else {
+            return Vec::new();
+        };
+        iter::once(self.from)
+            .chain(monday.iter_weeks().skip(1))
+            .take_while(|from| *from <= self.until)
+            .map(|from| {
+                let until = from
+                    .end_of_week()
+                    .map_or(self.until, |end| end.min(self.until));
+                Period::new(from, until)
+            })
+            .collect()
+    }
+
+    /// Reads a month like 2024-04, a range of days like
+    /// 2024-04-01..2024-04-15, last-month, this-month, or a quarter like
+    /// q1-2024, relative months counted from today
+    pub fn parse(
+        text: &str,
+        today: NaiveDate,
+    ) -> Result<Self, PeriodParseError> {
+        let format = || PeriodParseError::Format(text.to_string());
+        let text = text.trim();
+        let day =
+            |text: &str| NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d");
+        let month = |start: NaiveDate| {
+            Some(Self::new(start.start_of_month()?, start.end_of_month()?))
+        };
+
+        let range = text.split_once("..").or_else(|| text.split_once('—'));
+        let period = if let Some((from, until)) = range {
+            let (from, until) = (day(from), day(until));
+            let period = Self::new(
+                from.map_err(|_| format())?,
+                until.map_err(|_| format())?,
+            );
+            if period.until < period.from {
+                return Err(PeriodParseError::Reversed(period));
+            }
+            Some(period)
+        } else if text == "this-month" {
+            month(today)
+        } else if text == "last-month" {
+            today
+                .start_of_month()
+                .and_then(|d| d.checked_sub_months(Months::new(1)))
+                .and_then(month)
+        } else if let Some(quarter) = text.to_lowercase().strip_prefix('q') {
+            let (quarter, year) = quarter.split_once('-').ok_or_else(format)?;
+            let quarter: u32 = quarter.parse().map_err(|_| format())?;
+            let year: i32 = year.parse().map_err(|_| format())?;
+            (1..=4)
+                .contains(&quarter)
+                .then(|| NaiveDate::from_ymd_opt(year, quarter * 3 - 2, 1))
+                .flatten()
+                .and_then(|start| {
+                    Some(Self::new(start, start.end_of_quarter()?))
+                })
+        } else {
+            day(&format!("{text}-01")).ok().and_then(month)
+        };
+        period.ok_or_else(format)
+    }
+
+    /// Weekdays from the start to the end of the period, inclusive
+    pub fn working_days(&self) -> Decimal {
         Decimal::from(
             self.from
                 .iter_days()
@@ -36,16 +130,35 @@ impl Period {
         Decimal::from(f(self.until) - f(self.from) + 1)
     }
 
-    fn num_units(&self, unit: &Unit) -> Decimal {
+    /// Quantity of a unit billed for the period, see `explain` for the
+    /// working
+    pub fn num_units(&self, unit: &Unit) -> Decimal {
+        self.num_units_with(unit, WeekProration::WorkingDays)
+    }
+
+    /// Quantity billed with a choice of week proration, the envelope
+    /// reproduces invoices made before weeks were counted in working days
+    pub fn num_units_with(&self, unit: &Unit, weeks: WeekProration) -> Decimal {
+        let num_weeks = || match weeks {
+            WeekProration::WorkingDays => self.num_weeks(),
+            WeekProration::Envelope => self.num_envelope_weeks(),
+        };
         match unit {
             Unit::Month => self.num_months(),
-            Unit::Week => self.num_weeks(),
+            Unit::Week => num_weeks(),
             Unit::Day => self.working_days(),
             Unit::Hour => Decimal::from(0),
+            Unit::Quarter => self.num_quarters(),
+            Unit::Year => self.num_years(),
+            Unit::EveryNWeeks(span) => {
+                num_weeks() / Decimal::from((*span).max(1))
+            }
         }
     }
 
-    fn num_months(&self) -> Decimal {
+    /// Working days billed over the working days of the whole months the
+    /// period touches, times the number of months
+    pub fn num_months(&self) -> Decimal {
         let full_period = Self::new(
             self.from.start_of_month().expect("Error in chorno-utils"),
             self.until.end_of_month().expect("Error in chorno-utils"),
@@ -55,7 +168,40 @@ impl Period {
                 * self.count_distinct(|d| d.month()))
     }
 
-    fn num_weeks(&self) -> Decimal {
+    /// Prorated like months, with quarters counted across year boundaries
+    fn num_quarters(&self) -> Decimal {
+        let full_period = Self::new(
+            self.from.start_of_quarter().expect("Error in chrono utils"),
+            self.until.end_of_quarter().expect("Error in chrono utils"),
+        );
+        let quarter = |d: NaiveDate| d.year() * 4 + (d.month0() / 3) as i32;
+        let distinct_quarters =
+            Decimal::from(quarter(self.until) - quarter(self.from) + 1);
+        self.working_days() / full_period.working_days() * distinct_quarters
+    }
+
+    fn num_years(&self) -> Decimal {
+        let full_period = Self::new(
+            self.from.start_of_year().expect("Error in chrono utils"),
+            self.until.end_of_year().expect("Error in chrono utils"),
+        );
+        let distinct_years =
+            Decimal::from(self.until.year() - self.from.year() + 1);
+        self.working_days() / full_period.working_days() * distinct_years
+    }
+
+    /// A week is five working days wherever the period starts or ends, so
+    /// Monday to Friday is one week, a single weekday is 0.2, and a period
+    /// over the new year counts the same as any other
+    pub fn num_weeks(&self) -> Decimal {
+        self.working_days() / Decimal::from(WORKING_DAYS_PER_WEEK)
+    }
+
+    /// The earlier week proration, the working days billed over those of the
+    /// Monday to Sunday weeks the period touches, times the number of seven
+    /// day steps from its start. Periods starting late in a week count too
+    /// few weeks for the envelope and come out short.
+    fn num_envelope_weeks(&self) -> Decimal {
         let full_period = Self::new(
             self.from.start_of_week().expect("Error in chrono utils"),
             self.until.end_of_week().expect("Error in chrono utils"),
@@ -68,6 +214,144 @@ impl Period {
         );
         distinct_weeks * self.working_days() / full_period.working_days()
     }
+
+    /// The derivation of the quantity billed for the period, none for hours
+    /// which aren't prorated
+    pub fn explain(&self, unit: &Unit) -> Option<Proration> {
+        let boundary = |from: Option<NaiveDate>, until: Option<NaiveDate>| {
+            Self::new(
+                from.expect("Error in chrono utils"),
+                until.expect("Error in chrono utils"),
+            )
+        };
+        let (boundary, distinct, whole) = match unit {
+            Unit::Hour => return None,
+            Unit::Day => (self.clone(), Decimal::ONE, Decimal::ZERO),
+            Unit::Month => (
+                boundary(self.from.start_of_month(), self.until.end_of_month()),
+                self.count_distinct(|d| d.month()),
+                Decimal::from((self.until.year() - self.from.year()) * 12),
+            ),
+            Unit::Quarter => {
+                let quarter =
+                    |d: NaiveDate| d.year() * 4 + (d.month0() / 3) as i32;
+                (
+                    boundary(
+                        self.from.start_of_quarter(),
+                        self.until.end_of_quarter(),
+                    ),
+                    Decimal::from(quarter(self.until) - quarter(self.from) + 1),
+                    Decimal::ZERO,
+                )
+            }
+            Unit::Year => (
+                boundary(self.from.start_of_year(), self.until.end_of_year()),
+                Decimal::from(self.until.year() - self.from.year() + 1),
+                Decimal::ZERO,
+            ),
+            Unit::Week | Unit::EveryNWeeks(_) => {
+                (self.clone(), Decimal::ONE, Decimal::ZERO)
+            }
+        };
+        let span = match unit {
+            Unit::EveryNWeeks(weeks) => Decimal::from((*weeks).max(1)),
+            _ => Decimal::ONE,
+        };
+        let boundary_days = match unit {
+            Unit::Day => Decimal::ONE,
+            Unit::Week | Unit::EveryNWeeks(_) => {
+                Decimal::from(WORKING_DAYS_PER_WEEK)
+            }
+            _ => boundary.working_days(),
+        };
+
+        Some(Proration {
+            unit: unit.clone(),
+            period: self.clone(),
+            boundary,
+            boundary_days,
+            billed_days: self.working_days(),
+            distinct,
+            whole,
+            span,
+            quantity: self.num_units(unit),
+        })
+    }
+}
+
+/// Weeks are prorated as a share of a standard five day working week
+pub const WORKING_DAYS_PER_WEEK: u8 = 5;
+
+/// How weeks are counted, see `Period::num_weeks`
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum WeekProration {
+    #[default]
+    WorkingDays,
+    /// The earlier proration, kept to reproduce invoices made with it
+    Envelope,
+}
+
+/// The working behind a prorated quantity: the billed share of the working
+/// days in the whole units the period touches, times the number of units
+#[derive(Debug, PartialEq)]
+pub struct Proration {
+    pub unit: Unit,
+    pub period: Period,
+    /// Whole units around the period, e.g. the months it starts and ends in
+    pub boundary: Period,
+    pub boundary_days: Decimal,
+    pub billed_days: Decimal,
+    /// Distinct units the period touches
+    pub distinct: Decimal,
+    /// Units counted outright, the months of the years between the dates
+    pub whole: Decimal,
+    /// Weeks per unit for multi-week spans, otherwise one
+    pub span: Decimal,
+    pub quantity: Decimal,
+}
+
+impl fmt::Display for Proration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Billed period: {}", self.period)?;
+        if self.unit == Unit::Day {
+            writeln!(f, "Working days billed: {}", self.billed_days)?;
+            return write!(f, "Quantity: {}", self.quantity);
+        }
+        if let Unit::Week | Unit::EveryNWeeks(_) = self.unit {
+            writeln!(f, "Working days billed: {}", self.billed_days)?;
+            writeln!(f, "Working days per week: {}", self.boundary_days)?;
+            let mut working =
+                format!("{} / {}", self.billed_days, self.boundary_days);
+            if self.span != Decimal::ONE {
+                working = format!("({}) / {}", working, self.span);
+            }
+            return write!(
+                f,
+                "Quantity: {} = {}",
+                working,
+                self.quantity.round_dp(4)
+            );
+        }
+        writeln!(f, "Whole {} period: {}", self.unit, self.boundary)?;
+        writeln!(
+            f,
+            "Working days in the whole period: {}",
+            self.boundary_days
+        )?;
+        writeln!(f, "Working days billed: {}", self.billed_days)?;
+        writeln!(f, "Distinct units counted: {}", self.distinct)?;
+        let mut working = format!(
+            "{} / {} × {}",
+            self.billed_days, self.boundary_days, self.distinct
+        );
+        if !self.whole.is_zero() {
+            working = format!("{} + {}", self.whole, working);
+        }
+        if self.span != Decimal::ONE {
+            working = format!("({}) / {}", working, self.span);
+        }
+        write!(f, "Quantity: {} = {}", working, self.quantity.round_dp(4))
+    }
 }
 
 impl fmt::Display for Period {
@@ -80,6 +364,19 @@ impl fmt::Display for Period {
 pub struct Service {
     pub name: String,
     pub rates: Historical<Rate>,
+    /// Hours pre-filled when invoicing, e.g. for a monthly retainer
+    #[serde(default)]
+    pub default_quantity: Option<Decimal>,
+    /// Day of the month the service is invoiced on, the last day of shorter
+    /// months
+    #[serde(default)]
+    pub schedule: Option<u8>,
+    /// Grouping of the service's revenue in reports, e.g. development
+    #[serde(default)]
+    pub category: Option<String>,
+    /// Hours agreed to be billed every month
+    #[serde(default)]
+    pub target_hours: Option<Decimal>,
 }
 
 impl Service {
@@ -87,35 +384,156 @@ impl Service {
         Self {
             name,
             rates: Historical::new(),
+            default_quantity: None,
+            schedule: None,
+            category: None,
+            target_hours: None,
         }
     }
 }
 
+/// Age after which a rate is likely out of date, unless a client sets its own
+pub const DEFAULT_STALE_RATE_MONTHS: u32 = 12;
+
+/// Whether a rate effective on a date was set more than a number of months
+/// before billing a period starting on `from`
+pub fn rate_is_stale(
+    effective: NaiveDate,
+    from: NaiveDate,
+    months: u32,
+) -> bool {
+    effective
+        .checked_add_months(Months::new(months))
+        .is_some_and(|limit| limit < from)
+}
+
+/// The days between the last billed date and the start of a new period that
+/// no invoice covers, None when the period follows on or overlaps
+pub fn billing_gap(billed_until: NaiveDate, from: NaiveDate) -> Option<Period> {
+    let first = billed_until.succ_opt()?;
+    let last = from.pred_opt()?;
+    (first <= last).then(|| Period::new(first, last))
+}
+
+/// The start suggested for a service's next period, its first rate's
+/// effective date when that falls in the month after the last billed date,
+/// or any time up to then when nothing has been billed, so a service that
+/// started mid-month is prorated from its start
+pub fn suggested_start(
+    today: NaiveDate,
+    billed_until: Option<NaiveDate>,
+    first_effective: NaiveDate,
+) -> Option<NaiveDate> {
+    let from = match billed_until {
+        Some(until) => until.succ_opt()?,
+        None => today.start_of_month()?,
+    };
+    let until = from.end_of_month()?;
+    let started = match billed_until {
+        Some(_) => from <= first_effective,
+        None => true,
+    };
+    (started && first_effective <= until).then_some(first_effective)
+}
+
+/// The period extended back to start on the first day of the gap
+pub fn cover_gap(period: &Period, gap: &Period) -> Period {
+    Period::new(cmp::min(gap.from, period.from), period.until)
+}
+
 impl fmt::Display for Service {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{} ", self.name)?;
-        match self.rates.current() {
-            None => write!(f, "(No current rate set) "),
-            Some(rate) => write!(f, "{}", rate),
+        match (self.rates.current(), self.rates.first()) {
+            (Some(rate), _) => write!(f, "{}", rate),
+            (None, Some((date, rate))) => write!(f, "{} from {}", rate, date),
+            (None, None) => write!(f, "(No current rate set) "),
+        }
+    }
+}
+
+/// Hours of a service paid for up front for a period, drawn down by the
+/// hours worked in it
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Retainer {
+    pub period: Period,
+    pub hours: Decimal,
+    pub consumed: Decimal,
+}
+
+impl Retainer {
+    pub fn new(period: Period, hours: Decimal) -> Self {
+        Self {
+            period,
+            hours,
+            consumed: Decimal::ZERO,
         }
     }
+
+    pub fn remaining(&self) -> Decimal {
+        (self.hours - self.consumed).max(Decimal::ZERO)
+    }
+
+    pub fn covers(&self, date: NaiveDate) -> bool {
+        self.period.from <= date && date <= self.period.until
+    }
+}
+
+/// Splits a period at the service's rate changes, pairing each part with the
+/// rate effective at its start, parts before the first rate are left out
+pub fn split_item_at_rate_changes(
+    service: &Service,
+    period: &Period,
+) -> Vec<(Period, Rate)> {
+    let mut starts = vec![period.from];
+    starts.extend(
+        service
+            .rates
+            .effective_dates()
+            .filter(|date| period.from < **date && **date <= period.until),
+    );
+    let ends = starts
+        .iter()
+        .skip(1)
+        .map(|start| start.pred_opt().expect("Date out of range"))
+        .chain([period.until]);
+
+    starts
+        .iter()
+        .zip(ends)
+        .filter_map(|(from, until)| {
+            let rate = service.rates.as_of(*from)?;
+            Some((Period::new(*from, until), rate.clone()))
+        })
+        .collect()
 }
 
 #[derive(
-    Display,
-    EnumString,
-    VariantNames,
-    Serialize,
-    Deserialize,
-    Debug,
-    PartialEq,
-    Clone,
+    EnumString, VariantNames, Serialize, Deserialize, Debug, PartialEq, Clone,
 )]
 pub enum Unit {
     Month,
     Week,
     Day,
     Hour,
+    Quarter,
+    Year,
+    /// Spans of a fixed number of weeks, e.g. two week sprints
+    EveryNWeeks(u8),
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Unit::Month => write!(f, "Month"),
+            Unit::Week => write!(f, "Week"),
+            Unit::Day => write!(f, "Day"),
+            Unit::Hour => write!(f, "Hour"),
+            Unit::Quarter => write!(f, "Quarter"),
+            Unit::Year => write!(f, "Year"),
+            Unit::EveryNWeeks(weeks) => write!(f, "{} weeks", weeks),
+        }
+    }
 }
 
 #[derive(
@@ -141,8 +559,52 @@ pub enum Currency {
     Eur,
 }
 
+impl Currency {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Currency::Cad => "CAD",
+            Currency::Usd => "USD",
+            Currency::Eur => "EUR",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Currency> {
+        match code.to_uppercase().as_str() {
+            "CAD" => Some(Currency::Cad),
+            "USD" => Some(Currency::Usd),
+            "EUR" => Some(Currency::Eur),
+            _ => None,
+        }
+    }
+
+    /// Decimal places amounts in the currency are written with
+    pub fn decimals(&self) -> u32 {
+        2
+    }
+
+    /// The currency of a code or symbol as written by `Display` or
+    /// `LedgerDisplay`, None for a bare `$` which any of the dollars could be
+    fn from_commodity(commodity: &str) -> Option<Currency> {
+        let commodity: String =
+            commodity.chars().filter(|c| !c.is_whitespace()).collect();
+        match commodity.to_uppercase().as_str() {
+            "CAD$" => Some(Currency::Cad),
+            "USD$" => Some(Currency::Usd),
+            "EUR€" | "€" => Some(Currency::Eur),
+            code => Currency::from_code(code),
+        }
+    }
+}
+
 impl LedgerDisplay for Currency {
-    fn ledger_fmt(&self, buf: &mut (dyn fmt::Write)) -> fmt::Result {
+    fn ledger_fmt(
+        &self,
+        style: &LedgerStyle,
+        buf: &mut (dyn fmt::Write),
+    ) -> fmt::Result {
+        if style.iso_code {
+            return write!(buf, "{}", self.code());
+        }
         match self {
             Currency::Cad => write!(buf, "$"),
             Currency::Usd => write!(buf, "USD$"),
@@ -158,13 +620,202 @@ impl Money {
     pub fn new(currency: Currency, amount: Decimal) -> Self {
         Self(currency, amount)
     }
+
+    pub fn currency(&self) -> Currency {
+        self.0
+    }
+
+    pub fn amount(&self) -> Decimal {
+        self.1
+    }
+
+    pub fn checked_add(self, other: Self) -> Result<Self, CurrencyMismatch> {
+        if self.0 != other.0 {
+            return Err(CurrencyMismatch(self.0, other.0));
+        }
+        Ok(Self(self.0, self.1 + other.1))
+    }
+
+    pub fn checked_sub(self, other: Self) -> Result<Self, CurrencyMismatch> {
+        self.checked_add(-other)
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.1 < Decimal::ZERO
+    }
+
+    /// Reads an amount like `CAD 1500.00`, `$99.95`, or `1.200,50 EUR`, with
+    /// the default currency for a bare `$` or a number without a currency
+    pub fn parse_lenient(
+        text: &str,
+        default: Option<Currency>,
+    ) -> Result<Self, MoneyParseError> {
+        let format = || MoneyParseError::Format(text.to_string());
+        let (start, end) = text
+            .find(|c: char| c.is_ascii_digit())
+            .zip(text.rfind(|c: char| c.is_ascii_digit()))
+            .ok_or_else(format)?;
+        let (before, number, after) =
+            (&text[..start], &text[start..=end], &text[end + 1..]);
+
+        // The sign comes before the commodity or the number
+        let before = before.trim();
+        let (negative, before) = match before.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => match before.strip_suffix('-') {
+                Some(rest) => (true, rest),
+                None => (false, before),
+            },
+        };
+        let commodity = match (before.trim(), after.trim()) {
+            ("", "") => None,
+            (commodity, "") | ("", commodity) => Some(commodity),
+            _ => return Err(format()),
+        };
+        let currency = match commodity {
+            Some("$") | None => default
+                .ok_or_else(|| MoneyParseError::NoCurrency(text.to_string()))?,
+            Some(commodity) => {
+                Currency::from_commodity(commodity).ok_or_else(format)?
+            }
+        };
+
+        let decimals = currency.decimals();
+        let amount =
+            parse_amount(number, decimals).ok_or_else(|| {
+                match number.rsplit_once(['.', ',']) {
+                    Some((_, frac)) if frac.len() > decimals as usize => {
+                        MoneyParseError::Decimals(text.to_string(), decimals)
+                    }
+                    _ => format(),
+                }
+            })?;
+        Ok(Self(currency, if negative { -amount } else { amount }))
+    }
+
+    /// Multiplies and rounds to the cent with the given strategy, `*`
+    /// rounds half to even
+    pub fn mul_rounded(
+        self,
+        other: Decimal,
+        strategy: RoundingStrategy,
+    ) -> Self {
+        Self(self.0, (self.1 * other).round_dp_with_strategy(2, strategy))
+    }
+}
+
+/// Reads an unsigned number with up to `decimals` decimal places, grouped
+/// by spaces, apostrophes, or whichever of `.` and `,` isn't the decimal
+/// mark. A lone `.` or `,` followed by three digits groups thousands, as
+/// no currency has that many decimal places.
+pub fn parse_amount(number: &str, decimals: u32) -> Option<Decimal> {
+    let number = number.trim();
+    let lone = |mark: char| {
+        number.matches(mark).count() == 1
+            && number.rsplit_once(mark).is_some_and(|(_, f)| f.len() != 3)
+    };
+    let mark = match (number.rfind('.'), number.rfind(',')) {
+        (Some(point), Some(comma)) if point > comma => Some('.'),
+        (Some(_), Some(_)) => Some(','),
+        (Some(_), None) => Some('.').filter(|_| lone('.')),
+        (None, Some(_)) => Some(',').filter(|_| lone(',')),
+        (None, None) => None,
+    };
+    let (int, frac) = match mark {
+        Some(mark) => number.rsplit_once(mark)?,
+        None => (number, ""),
+    };
+    let places = 1..=decimals as usize;
+    if mark.is_some() && !places.contains(&frac.len()) {
+        return None;
+    }
+
+    let groups: Vec<&str> =
+        int.split(['.', ',', ' ', '\'', '\u{a0}']).collect();
+    let grouped = match groups.split_first()? {
+        (first, []) => !first.is_empty(),
+        (first, rest) => {
+            (1..=3).contains(&first.len())
+                && !first.starts_with('0')
+                && rest.iter().all(|g| g.len() == 3)
+        }
+    };
+    let digits = |text: &str| text.chars().all(|c| c.is_ascii_digit());
+    if !grouped || !groups.iter().all(|g| digits(g)) || !digits(frac) {
+        return None;
+    }
+    let number = match frac {
+        "" => groups.concat(),
+        frac => format!("{}.{}", groups.concat(), frac),
+    };
+    Decimal::from_str(&number).ok()
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum MoneyParseError {
+    #[error(
+        "'{0}' isn't an amount, e.g. CAD 1500.00, $99.95, or 1.200,50 EUR"
+    )]
+    Format(String),
+    #[error("'{0}' has more than {1} decimal places")]
+    Decimals(String, u32),
+    #[error("'{0}' needs a currency code, e.g. CAD 1500.00")]
+    NoCurrency(String),
+}
+
+/// The forms [`Period::parse`] accepts, for error messages
+pub const PERIOD_FORMATS: &str =
+    "2024-04, 2024-04-01..2024-04-15, last-month, this-month, or q1-2024";
+
+#[derive(Debug, Error, PartialEq)]
+pub enum PeriodParseError {
+    #[error("'{0}' isn't a period, use {PERIOD_FORMATS}")]
+    Format(String),
+    #[error("the period {0} ends before it starts")]
+    Reversed(Period),
+}
+
+#[derive(Debug, Error, PartialEq)]
+#[error("cannot combine {} and {} amounts", .0.code(), .1.code())]
+pub struct CurrencyMismatch(pub Currency, pub Currency);
+
+#[derive(Debug, Error, PartialEq)]
+pub enum NewInvoiceError {
+    #[error("{0}")]
+    Currency(#[from] CurrencyMismatch),
+    #[error("the subtotal {0} must be more than zero")]
+    Subtotal(Money),
+    #[error(
+        "{0} {1} spans more than one month, tiered hours are counted per \
+         calendar month so bill each month as its own item"
+    )]
+    TieredMonths(String, Period),
 }
 
 impl Add<Money> for Money {
     type Output = Self;
 
+    /// Panics when the currencies differ, use `checked_add` for amounts
+    /// that haven't been validated to share a currency
     fn add(self, other: Self) -> Self {
-        Self(self.0, self.1 + other.1)
+        self.checked_add(other).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+impl Sub<Money> for Money {
+    type Output = Self;
+
+    /// Panics when the currencies differ, like `add`
+    fn sub(self, other: Self) -> Self {
+        self.checked_sub(other).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+impl Neg for Money {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self(self.0, -self.1)
     }
 }
 
@@ -172,32 +823,62 @@ impl Mul<Decimal> for Money {
     type Output = Self;
 
     fn mul(self, other: Decimal) -> Self {
-        Self(
-            self.0,
-            (self.1 * other).round_dp_with_strategy(
-                2,
-                RoundingStrategy::MidpointNearestEven,
-            ),
-        )
+        self.mul_rounded(other, RoundingStrategy::MidpointNearestEven)
     }
 }
 
 impl fmt::Display for Money {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}{:.2}", self.0, self.1)
+        let sign = if self.is_negative() { "-" } else { "" };
+        write!(f, "{}{}{:.2}", sign, self.0, self.1.abs())
+    }
+}
+
+/// Reads amounts as `Display` writes them, or with the forms
+/// `Money::parse_lenient` accepts except a bare `$`
+impl FromStr for Money {
+    type Err = MoneyParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Self::parse_lenient(text, None)
     }
 }
 
+/// Negative amounts put the sign before the commodity, e.g. `-$123.45`
 impl LedgerDisplay for Money {
-    fn ledger_fmt(&self, buf: &mut (dyn fmt::Write)) -> fmt::Result {
-        self.0.ledger_fmt(buf)?;
-        self.1.ledger_fmt(buf)
+    fn ledger_fmt(
+        &self,
+        style: &LedgerStyle,
+        buf: &mut (dyn fmt::Write),
+    ) -> fmt::Result {
+        if self.is_negative() {
+            write!(buf, "-")?;
+        }
+        let commodity = ledger_fmt_with(style, self.0);
+        let amount = ledger_fmt_with(style, self.1.abs());
+        let separator = style.commodity_separator();
+        match style.position {
+            CommodityPosition::Before => {
+                write!(buf, "{}{}{}", commodity, separator, amount)
+            }
+            CommodityPosition::After => {
+                write!(buf, "{}{}{}", amount, separator, commodity)
+            }
+        }
     }
 }
 
 impl LedgerDisplay for Decimal {
-    fn ledger_fmt(&self, buf: &mut (dyn fmt::Write)) -> fmt::Result {
-        write! {buf, "{:.2}", self}
+    fn ledger_fmt(
+        &self,
+        style: &LedgerStyle,
+        buf: &mut (dyn fmt::Write),
+    ) -> fmt::Result {
+        if *self < Decimal::ZERO {
+            write!(buf, "-")?;
+        }
+        let digits = format!("{:.2}", self.abs());
+        write!(buf, "{}", style.format_number(&digits))
     }
 }
 
@@ -205,20 +886,198 @@ impl LedgerDisplay for Decimal {
 pub struct Rate {
     pub amount: Money,
     pub per: Unit,
+    /// Hourly amounts from a number of hours in a period on, e.g. $120
+    /// after the first 40 hours, by increasing threshold
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tiers: Vec<(Decimal, Money)>,
+}
+
+/// Length of a working day when comparing rates, unless the profile sets one
+pub const DEFAULT_HOURS_PER_DAY: u32 = 8;
+
+impl Rate {
+    /// The rate per hour, day, week, and month, other than its own unit,
+    /// for working days of the hours and months of the period's working
+    /// days. Only a rough comparison, weeks of rates are five working days.
+    pub fn equivalents(
+        &self,
+        hours_per_day: Decimal,
+        days_source: &Period,
+    ) -> Vec<Rate> {
+        let month_days = days_source.working_days();
+        let hours = |unit: &Unit| unit_hours(unit, hours_per_day, month_days);
+        let own = hours(&self.per);
+        if own.is_zero() {
+            return Vec::new();
+        }
+        [Unit::Hour, Unit::Day, Unit::Week, Unit::Month]
+            .into_iter()
+            .filter(|unit| *unit != self.per)
+            .map(|per| Rate {
+                amount: self.amount * (hours(&per) / own),
+                per,
+                tiers: Vec::new(),
+            })
+            .collect()
+    }
+
+    /// The hours billed at each amount, the rate's own amount up to the
+    /// first threshold and each tier's from its threshold on, counting the
+    /// hours already billed in the same month towards the thresholds.
+    /// Empty without tiers.
+    pub fn split_hours(
+        &self,
+        billed: Decimal,
+        hours: Decimal,
+    ) -> Vec<(Decimal, Money)> {
+        if self.tiers.is_empty() {
+            return Vec::new();
+        }
+        let end = billed + hours;
+        let mut bounds = iter::once((Decimal::ZERO, self.amount))
+            .chain(self.tiers.iter().copied())
+            .peekable();
+        let mut split = Vec::new();
+        while let Some((threshold, amount)) = bounds.next() {
+            let from = cmp::max(threshold, billed);
+            let until =
+                bounds.peek().map_or(end, |(next, _)| cmp::min(*next, end));
+            if until > from {
+                split.push((until - from, amount));
+            }
+        }
+        split
+    }
+
+    /// Percentage change from an earlier rate, to one decimal place. None
+    /// when the unit or currency differs, or the earlier rate was zero.
+    pub fn change_from(&self, earlier: &Rate) -> Option<Decimal> {
+        let before = earlier.amount.amount();
+        if self.per != earlier.per
+            || self.amount.currency() != earlier.amount.currency()
+            || before.is_zero()
+        {
+            return None;
+        }
+        let change = (self.amount.amount() - before) / before;
+        Some((change * Decimal::ONE_HUNDRED).round_dp(1))
+    }
+}
+
+/// Working hours in one of a unit
+fn unit_hours(
+    unit: &Unit,
+    hours_per_day: Decimal,
+    month_days: Decimal,
+) -> Decimal {
+    let week = hours_per_day * Decimal::from(5);
+    let month = hours_per_day * month_days;
+    match unit {
+        Unit::Hour => Decimal::ONE,
+        Unit::Day => hours_per_day,
+        Unit::Week => week,
+        Unit::EveryNWeeks(weeks) => week * Decimal::from(*weeks),
+        Unit::Month => month,
+        Unit::Quarter => month * Decimal::from(3),
+        Unit::Year => month * Decimal::from(12),
+    }
 }
 
 impl fmt::Display for Rate {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}/{}", self.amount, self.per)
+        write!(f, "{}/{}", self.amount, self.per)?;
+        for (threshold, amount) in self.tiers.iter() {
+            write!(f, ", {} after {}h", amount, threshold)?;
+        }
+        Ok(())
     }
 }
 
+/// A named tax as a fraction of the subtotal, and how its amount is
+/// rounded. Rates rounded half to even are stored as just the name and rate.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
-pub struct TaxRate(pub String, pub Decimal);
+pub struct TaxRate(
+    pub String,
+    pub Decimal,
+    #[serde(default, skip_serializing_if = "TaxRounding::is_default")]
+    pub  TaxRounding,
+);
 
 impl TaxRate {
     pub fn new(name: String, percentage: i64) -> Self {
-        Self(name, Decimal::new(percentage, 2))
+        Self(name, Decimal::new(percentage, 2), TaxRounding::HalfEven)
+    }
+
+    pub fn rounded(self, rounding: TaxRounding) -> Self {
+        Self(self.0, self.1, rounding)
+    }
+}
+
+/// How a tax amount ending in half a cent is rounded
+#[derive(
+    Display,
+    EnumString,
+    VariantNames,
+    Serialize,
+    Deserialize,
+    Debug,
+    PartialEq,
+    Clone,
+    Copy,
+    Default,
+)]
+pub enum TaxRounding {
+    /// Banker's rounding, $0.525 is $0.52 and $0.535 is $0.54
+    #[default]
+    #[strum(serialize = "Half to even")]
+    HalfEven,
+    /// $0.525 is $0.53
+    #[strum(serialize = "Half up")]
+    HalfUp,
+}
+
+impl TaxRounding {
+    pub fn strategy(&self) -> RoundingStrategy {
+        match self {
+            TaxRounding::HalfEven => RoundingStrategy::MidpointNearestEven,
+            TaxRounding::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+        }
+    }
+
+    fn is_default(&self) -> bool {
+        *self == TaxRounding::HalfEven
+    }
+}
+
+/// The date heading an invoice's ledger transaction, the period end or
+/// start recognizes revenue when the work was done
+#[derive(
+    Display,
+    EnumString,
+    VariantNames,
+    Serialize,
+    Deserialize,
+    Debug,
+    PartialEq,
+    Clone,
+    Copy,
+    Default,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum PostingDate {
+    #[default]
+    Issue,
+    PeriodEnd,
+    PeriodStart,
+}
+
+impl PostingDate {
+    pub fn of(&self, invoice: &Invoice) -> NaiveDate {
+        match self {
+            PostingDate::Issue => invoice.date,
+            PostingDate::PeriodEnd => invoice.overall_period().until,
+            PostingDate::PeriodStart => invoice.overall_period().from,
+        }
     }
 }
 
@@ -232,6 +1091,11 @@ impl fmt::Display for TaxRate {
 pub struct InvoiceTotal {
     pub subtotal: Money,
     pub taxes: Vec<(TaxRate, Money)>,
+    /// Negative amounts of the deposits deducted by a final invoice
+    pub deductions: Vec<(usize, Money)>,
+    /// Difference between the rounded total and the sum of the rounded
+    /// taxes, when only the total is rounded
+    pub adjustment: Option<Money>,
     pub total: Money,
 }
 
@@ -241,6 +1105,12 @@ impl fmt::Display for InvoiceTotal {
         for (tax_rate, amount) in self.taxes.iter() {
             writeln!(f, "{}: {}", tax_rate, amount)?;
         }
+        if let Some(adjustment) = self.adjustment {
+            writeln!(f, "Rounding: {}", adjustment)?;
+        }
+        for (number, amount) in self.deductions.iter() {
+            writeln!(f, "Deposit #{}: {}", number, amount)?;
+        }
 
         write!(f, "\nTotal: {}", self.total)
     }
@@ -253,6 +1123,13 @@ pub struct InvoiceItem {
     pub period: Period,
     pub quantity: Decimal,
     pub amount: Money,
+    /// Hours worked in each week of the period, by the week's first day,
+    /// when they were entered week by week
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub weeks: Vec<(NaiveDate, Decimal)>,
+    /// Hours billed at each amount of a tiered hourly rate
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tiers: Vec<(Decimal, Money)>,
 }
 
 impl InvoiceItem {
@@ -265,6 +1142,8 @@ impl InvoiceItem {
             period,
             quantity,
             amount,
+            weeks: Vec::new(),
+            tiers: Vec::new(),
         }
     }
 
@@ -274,72 +1153,561 @@ impl InvoiceItem {
         period: Period,
         quantity: Decimal,
     ) -> Self {
-        let amount = rate.amount * quantity;
+        Self::hourly_after(name, rate, period, quantity, Decimal::ZERO)
+    }
+
+    /// An hourly item billed after hours of the same service earlier in
+    /// the month, see [`tier_by_month`]
+    fn hourly_after(
+        name: String,
+        rate: Rate,
+        period: Period,
+        quantity: Decimal,
+        billed: Decimal,
+    ) -> Self {
+        let tiers = rate.split_hours(billed, quantity);
+        // The tiers are entered in the rate's currency
+        let amount = tiers
+            .iter()
+            .map(|(hours, amount)| *amount * *hours)
+            .reduce(|total, amount| total + amount)
+            .unwrap_or(rate.amount * quantity);
+        Self {
+            name,
+            rate,
+            period,
+            quantity,
+            amount,
+            weeks: Vec::new(),
+            tiers,
+        }
+    }
+
+    /// Hours at each amount of a tiered rate, e.g. 40h @ $150, 12.5h @ $120
+    pub fn tier_breakdown(&self) -> Option<String> {
+        let tiers: Vec<String> = self
+            .tiers
+            .iter()
+            .map(|(hours, amount)| {
+                format!("{}h @ {}", hours.normalize(), amount)
+            })
+            .collect();
+        (!tiers.is_empty()).then(|| tiers.join(", "))
+    }
+
+    /// Why the item bills nothing or a negative amount, a zero amount can
+    /// still be kept to show work done without charge
+    pub fn check_amount(&self) -> Result<(), ItemAmountError> {
+        if self.quantity < Decimal::ZERO {
+            return Err(ItemAmountError::NegativeQuantity(self.quantity));
+        }
+        if self.amount.is_negative() {
+            return Err(ItemAmountError::Negative(self.amount));
+        }
+        if !self.amount.amount().is_zero() {
+            return Ok(());
+        }
+        if self.quantity.is_zero() && self.rate.per != Unit::Hour {
+            return Err(ItemAmountError::NoWorkingDays(self.period.clone()));
+        }
+        Err(ItemAmountError::Zero)
+    }
+
+    /// Item with an amount taken as is rather than computed from the rate,
+    /// e.g. for invoices made before using invogen
+    pub fn raw(
+        name: String,
+        rate: Rate,
+        period: Period,
+        quantity: Decimal,
+        amount: Money,
+    ) -> Self {
         Self {
             name,
             rate,
             period,
             quantity,
             amount,
+            weeks: Vec::new(),
+            tiers: Vec::new(),
         }
     }
+
+    /// The amount in another currency at the rate, to the cent, keeping the
+    /// rate billed in its own currency
+    pub fn converted(self, currency: Currency, rate: Decimal) -> Self {
+        let amount = Money::new(currency, self.amount.amount()) * rate;
+        Self { amount, ..self }
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ItemAmountError {
+    #[error("{0} contains no working days, so nothing is billed")]
+    NoWorkingDays(Period),
+    #[error("the amount is zero")]
+    Zero,
+    #[error("the quantity {0} is negative")]
+    NegativeQuantity(Decimal),
+    #[error("the amount {0} is negative")]
+    Negative(Money),
+}
+
+impl ItemAmountError {
+    /// Zero amounts can be kept for work done without charge, negative ones
+    /// can't
+    pub fn is_zero(&self) -> bool {
+        matches!(
+            self,
+            ItemAmountError::NoWorkingDays(_) | ItemAmountError::Zero
+        )
+    }
 }
 
 impl fmt::Display for InvoiceItem {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "{} {}, {:.2} @ {}: {}",
-            self.name, self.period, self.quantity, self.rate, self.amount
-        )
+        match self.tier_breakdown() {
+            Some(tiers) => write!(
+                f,
+                "{} {}, {}: {}",
+                self.name, self.period, tiers, self.amount
+            ),
+            None => write!(
+                f,
+                "{} {}, {:.2} @ {}: {}",
+                self.name, self.period, self.quantity, self.rate, self.amount
+            ),
+        }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+/// Share of an item's quantity in the month of a date, split by the working
+/// days of its period in the month. Items without working days count in the
+/// month they start.
+pub fn quantity_in_month(item: &InvoiceItem, month: NaiveDate) -> Decimal {
+    let (Some(start), Some(end)) =
+        (month.start_of_month(), month.end_of_month())
+    else {
+        return Decimal::ZERO;
+    };
+    let period = &item.period;
+    let overlap = Period::new(period.from.max(start), period.until.min(end));
+    if overlap.from > overlap.until {
+        return Decimal::ZERO;
+    }
+    if overlap == *period {
+        return item.quantity;
+    }
+    let working_days = period.working_days();
+    if working_days.is_zero() {
+        return if overlap.from == period.from {
+            item.quantity
+        } else {
+            Decimal::ZERO
+        };
+    }
+    item.quantity * overlap.working_days() / working_days
+}
+
+/// Applies the thresholds of tiered hourly rates to each service's hours
+/// per calendar month across the items rather than to each item on its
+/// own, counting the items of a month in order of their periods. Items
+/// have to be tiered before they're converted to another currency.
+pub fn tier_by_month(items: &mut [InvoiceItem]) -> Result<(), NewInvoiceError> {
+    let tiered = |item: &InvoiceItem| !item.tiers.is_empty();
+    if let Some(item) = items.iter().filter(|item| tiered(item)).find(|item| {
+        let (from, until) = (item.period.from, item.period.until);
+        (from.year(), from.month()) != (until.year(), until.month())
+    }) {
+        return Err(NewInvoiceError::TieredMonths(
+            item.name.clone(),
+            item.period.clone(),
+        ));
+    }
+
+    let mut order: Vec<usize> = (0..items.len()).collect();
+    order.sort_by_key(|i| items[*i].period.from);
+    let mut billed: BTreeMap<(&str, i32, u32), Decimal> = BTreeMap::new();
+    let mut retiered = Vec::new();
+    for i in order {
+        let item = &items[i];
+        if !tiered(item) {
+            continue;
+        }
+        let from = item.period.from;
+        let hours = billed
+            .entry((&item.name, from.year(), from.month()))
+            .or_default();
+        let mut item_after = InvoiceItem::hourly_after(
+            item.name.clone(),
+            item.rate.clone(),
+            item.period.clone(),
+            item.quantity,
+            *hours,
+        );
+        *hours += item.quantity;
+        item_after.weeks = item.weeks.clone();
+        retiered.push((i, item_after));
+    }
+    for (i, item) in retiered {
+        items[i] = item;
+    }
+    Ok(())
+}
+
+/// Separates items into groups sharing a currency, in order of appearance
+pub fn group_by_currency(items: Vec<InvoiceItem>) -> Vec<Vec<InvoiceItem>> {
+    let mut groups: Vec<Vec<InvoiceItem>> = Vec::new();
+    for item in items {
+        let currency = item.amount.currency();
+        match groups
+            .iter_mut()
+            .find(|group| group[0].amount.currency() == currency)
+        {
+            Some(group) => group.push(item),
+            None => groups.push(vec![item]),
+        }
+    }
+    groups
+}
+
+/// Payment terms assumed for invoices, counted from the issue date
+pub const DEFAULT_TERMS: Days = Days::new(30);
+
+/// Deposits are billed up front and deducted from a later final invoice,
+/// which keeps each applied deposit's number and total after tax
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub enum InvoiceKind {
+    #[default]
+    Standard,
+    Deposit,
+    Final {
+        applies: Vec<(usize, Money)>,
+    },
+}
+
+impl InvoiceKind {
+    pub fn is_standard(&self) -> bool {
+        *self == InvoiceKind::Standard
+    }
+}
+
+/// How an invoice's taxes are rounded to the cent
+#[derive(
+    Display,
+    EnumString,
+    VariantNames,
+    Serialize,
+    Deserialize,
+    Debug,
+    PartialEq,
+    Clone,
+    Copy,
+    Default,
+)]
+pub enum RoundingPolicy {
+    /// Each tax is rounded and the total is their sum
+    #[default]
+    #[strum(serialize = "Round each tax")]
+    EachTax,
+    /// Only the total is rounded, an adjustment line makes up any cent of
+    /// difference from the rounded taxes
+    #[strum(serialize = "Round the total")]
+    Total,
+}
+
+impl RoundingPolicy {
+    fn is_default(&self) -> bool {
+        *self == RoundingPolicy::EachTax
+    }
+}
+
+/// A payment received in another currency than the invoice's, with the
+/// exchange rate the invoice's amounts were booked at
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Received {
+    pub amount: Money,
+    pub booked_rate: Decimal,
+}
+
+/// Amounts owed in the invoice currency valued in the received currency,
+/// and the realized gain (positive) or loss (negative) on the exchange
+#[derive(Debug, PartialEq)]
+pub struct Settlement {
+    pub costs: Vec<Money>,
+    pub difference: Money,
+}
+
+/// Splits a total in proportion to the weights, rounded to the cent, with
+/// the rounding residual on the largest weight (the first of equals) so the
+/// shares add up to the total exactly
+pub fn allocate(total: Money, weights: &[Money]) -> Vec<Money> {
+    let sum: Decimal = weights.iter().map(|w| w.amount()).sum();
+    if sum.is_zero() {
+        return weights
+            .iter()
+            .map(|w| Money::new(w.currency(), Decimal::ZERO))
+            .collect();
+    }
+    let mut shares: Vec<Money> =
+        weights.iter().map(|w| total * (w.amount() / sum)).collect();
+    let allocated = shares
+        .iter()
+        .fold(Money::new(total.currency(), Decimal::ZERO), |a, x| a + *x);
+    let largest = weights
+        .iter()
+        .enumerate()
+        .rev()
+        .max_by_key(|(_, w)| w.amount().abs())
+        .map(|(i, _)| i);
+    if let Some(i) = largest {
+        shares[i] = shares[i] + (total - allocated);
+    }
+    shares
+}
+
+/// Values each amount in another currency at a rate rounded to the cent,
+/// with any rounding residual on the last one so the values add up to the
+/// rounded total exactly
+pub fn value_at(
+    amounts: &[Money],
+    currency: Currency,
+    rate: Decimal,
+) -> Vec<Money> {
+    let convert = |amount: Decimal| Money::new(currency, amount) * rate;
+    let mut values: Vec<Money> =
+        amounts.iter().map(|a| convert(a.amount())).collect();
+    let total = convert(amounts.iter().map(|a| a.amount()).sum());
+    let sum = values
+        .iter()
+        .fold(Money::new(currency, Decimal::ZERO), |a, x| a + *x);
+    if let Some(last) = values.last_mut() {
+        *last = *last + (total - sum);
+    }
+    values
+}
+
+/// Settles the amounts owed at the rate they were booked at, see `value_at`
+pub fn settle(amounts: &[Money], received: &Received) -> Settlement {
+    let currency = received.amount.currency();
+    let costs = value_at(amounts, currency, received.booked_rate);
+    let booked = costs
+        .iter()
+        .fold(Money::new(currency, Decimal::ZERO), |a, x| a + *x);
+
+    Settlement {
+        costs,
+        difference: received.amount - booked,
+    }
+}
+
+/// An invoice given up on as uncollectable
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct WriteOff {
+    pub date: NaiveDate,
+    pub reason: Option<String>,
+}
+
+/// An issued invoice cancelled before it was paid, its number stays used
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Void {
+    pub date: NaiveDate,
+    pub reason: Option<String>,
+}
+
+/// An invoice the client disputes, it's not treated as overdue until the
+/// dispute is resolved
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Dispute {
+    pub date: NaiveDate,
+    pub reason: Option<String>,
+    pub resolved: Option<NaiveDate>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Invoice {
     pub date: NaiveDate,
     pub number: usize,
     pub items: Vec<InvoiceItem>,
     pub tax_rates: Vec<TaxRate>,
     pub paid: Option<NaiveDate>,
+    #[serde(default, skip_serializing_if = "InvoiceKind::is_standard")]
+    pub kind: InvoiceKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub written_off: Option<WriteOff>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub voided: Option<Void>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub received: Option<Received>,
+    #[serde(default, skip_serializing_if = "RoundingPolicy::is_default")]
+    pub rounding: RoundingPolicy,
+    /// When the invoice was sent to the client, if after it was created
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sent: Option<NaiveDate>,
+    /// Currency items were converted from and the rate used, the converted
+    /// amounts are stored on the items
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fx: Option<(Currency, Decimal)>,
+    /// The latest dispute of the invoice, resolved or not
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disputed: Option<Dispute>,
 }
 
 impl Invoice {
+    /// An invoice with its items in the canonical order, see
+    /// [`Invoice::sort_items`]
     pub fn new(
         number: usize,
         items: Vec<InvoiceItem>,
         tax_rates: Vec<TaxRate>,
-    ) -> Self {
+    ) -> Result<Self, NewInvoiceError> {
+        let mut invoice = Self::unsorted(number, items, tax_rates)?;
+        invoice.sort_items();
+        Ok(invoice)
+    }
+
+    /// An invoice keeping its items in the order given
+    pub fn unsorted(
+        number: usize,
+        items: Vec<InvoiceItem>,
+        tax_rates: Vec<TaxRate>,
+    ) -> Result<Self, NewInvoiceError> {
         let date = Local::now().date_naive();
 
-        Self {
+        let mut currencies = items.iter().map(|i| i.amount.currency());
+        if let Some(first) = currencies.next() {
+            if let Some(other) = currencies.find(|c| *c != first) {
+                return Err(CurrencyMismatch(first, other).into());
+            }
+        }
+        let subtotal = items.iter().map(|i| i.amount).reduce(|a, x| a + x);
+        if let Some(subtotal) = subtotal.filter(|s| s.amount() <= Decimal::ZERO)
+        {
+            return Err(NewInvoiceError::Subtotal(subtotal));
+        }
+
+        Ok(Self {
             date,
             number,
             items,
             tax_rates,
             paid: None,
+            kind: InvoiceKind::Standard,
+            written_off: None,
+            voided: None,
+            received: None,
+            rounding: RoundingPolicy::EachTax,
+            sent: None,
+            fx: None,
+            disputed: None,
+        })
+    }
+
+    /// Orders the items by the start of their period then service name, so
+    /// the same items serialize and render the same however they were
+    /// entered
+    pub fn sort_items(&mut self) {
+        self.items.sort_by(|a, b| {
+            (a.period.from, &a.name, a.period.until, a.quantity).cmp(&(
+                b.period.from,
+                &b.name,
+                b.period.until,
+                b.quantity,
+            ))
+        });
+    }
+
+    /// The number as printed on the invoice
+    pub fn display_number(&self) -> String {
+        format!("#{}", self.number)
+    }
+
+    /// Neither paid, written off, nor voided
+    pub fn is_open(&self) -> bool {
+        self.paid.is_none()
+            && self.written_off.is_none()
+            && self.voided.is_none()
+    }
+
+    /// Disputed by the client and not yet resolved
+    pub fn is_disputed(&self) -> bool {
+        self.disputed.as_ref().is_some_and(|d| d.resolved.is_none())
+    }
+
+    pub fn currency(&self) -> Currency {
+        self.items
+            .first()
+            .map(|i| i.amount.currency())
+            .expect("Invoice should have at least one item")
+    }
+
+    /// Items grouped by service name and sorted by period, each group with
+    /// the subtotal of its amounts, a service billed in more than one
+    /// currency is grouped separately by currency
+    pub fn items_by_service(&self) -> Vec<(String, Vec<&InvoiceItem>, Money)> {
+        let mut items: Vec<&InvoiceItem> = self.items.iter().collect();
+        items.sort_by(|a, b| {
+            a.name.cmp(&b.name).then(a.period.from.cmp(&b.period.from))
+        });
+        let mut groups: Vec<(String, Vec<&InvoiceItem>, Money)> = Vec::new();
+        for item in items {
+            match groups.last_mut() {
+                Some((name, items, subtotal))
+                    if *name == item.name
+                        && subtotal.currency() == item.amount.currency() =>
+                {
+                    *subtotal = *subtotal + item.amount;
+                    items.push(item);
+                }
+                _ => groups.push((item.name.clone(), vec![item], item.amount)),
+            }
         }
+        groups
     }
 
-    pub fn calculate(&self) -> InvoiceTotal {
+    /// The subtotal, taxes, and total, or the currencies that can't be
+    /// combined for invoices made before items were split by currency
+    pub fn calculate(&self) -> Result<InvoiceTotal, CurrencyMismatch> {
         let subtotal = self
             .items
             .iter()
-            .map(|i| i.amount)
-            .reduce(|acc, x| acc + x)
-            .expect("Invoice should have at least one item");
+            .map(|i| Ok(i.amount))
+            .reduce(|acc, x| acc?.checked_add(x?))
+            .expect("Invoice should have at least one item")?;
         let taxes: Vec<(TaxRate, Money)> = self
             .tax_rates
             .iter()
-            .map(|tr| (tr.clone(), subtotal * tr.1))
+            .map(|tr| (tr.clone(), subtotal.mul_rounded(tr.1, tr.2.strategy())))
             .collect();
-        let total = taxes.iter().fold(subtotal, |a, (_, x)| a + *x);
+        let deductions: Vec<(usize, Money)> = match &self.kind {
+            InvoiceKind::Final { applies } => applies
+                .iter()
+                .map(|(number, total)| (*number, -*total))
+                .collect(),
+            _ => Vec::new(),
+        };
+        let taxed = taxes.iter().fold(subtotal, |a, (_, x)| a + *x);
+        let adjustment = match self.rounding {
+            RoundingPolicy::EachTax => None,
+            RoundingPolicy::Total => {
+                let rates: Decimal = self.tax_rates.iter().map(|tr| tr.1).sum();
+                Some(subtotal * (Decimal::ONE + rates) - taxed)
+                    .filter(|a| !a.amount().is_zero())
+            }
+        };
+        let total = deductions
+            .iter()
+            .map(|(_, x)| *x)
+            .chain(adjustment)
+            .try_fold(taxed, |a, x| a.checked_add(x))?;
 
-        InvoiceTotal {
+        Ok(InvoiceTotal {
             subtotal,
             taxes,
+            deductions,
+            adjustment,
             total,
-        }
+        })
     }
 
     pub fn overall_period(&self) -> Period {
@@ -355,21 +1723,1343 @@ impl Invoice {
             );
         Period::new(min, max)
     }
+
+    /// When payment terms start, the sent date if recorded
+    pub fn issued(&self) -> NaiveDate {
+        self.sent.unwrap_or(self.date)
+    }
+
+    pub fn due_date(&self) -> NaiveDate {
+        self.issued() + DEFAULT_TERMS
+    }
 }
 
 impl fmt::Display for Invoice {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            InvoiceKind::Deposit => " (deposit)",
+            _ => "",
+        };
         write!(
             f,
-            "Invoice: #{}\n\
+            "Invoice: #{}{}\n\
              Date: {}\n\n",
-            self.number, self.date,
+            self.number, kind, self.date,
         )?;
 
-        for item in self.items.iter() {
-            writeln!(f, "{}", item)?;
+        // The alternate form lists the items as entered, without grouping
+        if f.alternate() {
+            for item in self.items.iter() {
+                writeln!(f, "{}", item)?;
+            }
+        } else {
+            let groups = self.items_by_service();
+            for (i, (name, items, subtotal)) in groups.iter().enumerate() {
+                if i > 0 {
+                    writeln!(f)?;
+                }
+                for item in items {
+                    writeln!(f, "{}", item)?;
+                }
+                if groups.len() > 1 {
+                    writeln!(f, "{} subtotal: {}", name, subtotal)?;
+                }
+            }
+        }
+        if let Some((from, rate)) = self.fx {
+            write!(f, "\nConverted from {} at {}", from.code(), rate)?;
+        }
+
+        match self.calculate() {
+            Ok(total) => write!(f, "\n\n{}", total),
+            Err(error) => write!(f, "\n\nNo total, {}", error),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn ledger_fmt(item: impl LedgerDisplay) -> String {
+        ledger_fmt_with(&LedgerStyle::default(), item)
+    }
+
+    pub fn ymd(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    pub fn monthly(currency: Currency, amount: i64) -> Rate {
+        Rate {
+            amount: Money::new(currency, Decimal::from(amount)),
+            per: Unit::Month,
+            tiers: Vec::new(),
+        }
+    }
+
+    fn april_item(name: &str, currency: Currency) -> InvoiceItem {
+        InvoiceItem::new(
+            name.to_string(),
+            monthly(currency, 1000),
+            Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30)),
+        )
+    }
+
+    fn quarters(from: NaiveDate, until: NaiveDate) -> Decimal {
+        Period::new(from, until)
+            .num_units(&Unit::Quarter)
+            .round_dp(4)
+    }
+
+    #[test]
+    fn whole_quarters() {
+        assert_eq!(quarters(ymd(2024, 4, 1), ymd(2024, 6, 30)), Decimal::ONE);
+        assert_eq!(
+            quarters(ymd(2024, 4, 1), ymd(2024, 9, 30)),
+            Decimal::from(2)
+        );
+        assert_eq!(
+            quarters(ymd(2024, 10, 1), ymd(2025, 3, 31)),
+            Decimal::from(2)
+        );
+    }
+
+    #[test]
+    fn partial_quarters() {
+        // 23 of Q1 2024's 65 working days
+        assert_eq!(
+            quarters(ymd(2024, 1, 1), ymd(2024, 1, 31)),
+            Decimal::new(3538, 4)
+        );
+        // 66 of the 131 working days in Q2 and Q3
+        assert_eq!(
+            quarters(ymd(2024, 5, 16), ymd(2024, 8, 15)),
+            Decimal::new(10076, 4)
+        );
+        // 66 of the 130 working days in Q4 2024 and Q1 2025
+        assert_eq!(
+            quarters(ymd(2024, 11, 1), ymd(2025, 1, 31)),
+            Decimal::new(10154, 4)
+        );
+    }
+
+    fn years(from: NaiveDate, until: NaiveDate) -> Decimal {
+        Period::new(from, until).num_units(&Unit::Year).round_dp(4)
+    }
+
+    #[test]
+    fn yearly_rates() {
+        assert_eq!(years(ymd(2024, 1, 1), ymd(2024, 12, 31)), Decimal::ONE);
+        assert_eq!(years(ymd(2023, 1, 1), ymd(2024, 12, 31)), Decimal::from(2));
+        // 132 of 2024's 262 working days
+        assert_eq!(
+            years(ymd(2024, 7, 1), ymd(2024, 12, 31)),
+            Decimal::new(5038, 4)
+        );
+        // 130 of 2023's 260 working days
+        assert_eq!(
+            years(ymd(2023, 7, 1), ymd(2023, 12, 31)),
+            Decimal::new(5, 1)
+        );
+    }
+
+    #[test]
+    fn weeks_clipped_to_the_period() {
+        let weeks = |from, until| -> Vec<(NaiveDate, NaiveDate)> {
+            Period::new(from, until)
+                .weeks()
+                .into_iter()
+                .map(|week| (week.from, week.until))
+                .collect()
+        };
+        // Wednesday to Tuesday
+        assert_eq!(
+            weeks(ymd(2024, 4, 3), ymd(2024, 4, 30)),
+            vec![
+                (ymd(2024, 4, 3), ymd(2024, 4, 7)),
+                (ymd(2024, 4, 8), ymd(2024, 4, 14)),
+                (ymd(2024, 4, 15), ymd(2024, 4, 21)),
+                (ymd(2024, 4, 22), ymd(2024, 4, 28)),
+                (ymd(2024, 4, 29), ymd(2024, 4, 30)),
+            ]
+        );
+        // Over the new year
+        assert_eq!(
+            weeks(ymd(2024, 12, 30), ymd(2025, 1, 12)),
+            vec![
+                (ymd(2024, 12, 30), ymd(2025, 1, 5)),
+                (ymd(2025, 1, 6), ymd(2025, 1, 12)),
+            ]
+        );
+        assert_eq!(
+            weeks(ymd(2024, 4, 7), ymd(2024, 4, 8)),
+            vec![
+                (ymd(2024, 4, 7), ymd(2024, 4, 7)),
+                (ymd(2024, 4, 8), ymd(2024, 4, 8)),
+            ]
+        );
+        assert_eq!(
+            weeks(ymd(2024, 4, 6), ymd(2024, 4, 6)),
+            vec![(ymd(2024, 4, 6), ymd(2024, 4, 6))]
+        );
+    }
+
+    #[test]
+    fn weeks_are_five_working_days() {
+        let weeks =
+            |from, until| Period::new(from, until).num_units(&Unit::Week);
+        let cases = [
+            // Over the new year, Monday to Friday
+            (ymd(2024, 12, 30), ymd(2025, 1, 3), Decimal::ONE),
+            // Single weekday and a single Saturday
+            (ymd(2024, 4, 3), ymd(2024, 4, 3), Decimal::new(2, 1)),
+            (ymd(2024, 4, 6), ymd(2024, 4, 6), Decimal::ZERO),
+            // Exact Monday to Sunday week
+            (ymd(2024, 4, 1), ymd(2024, 4, 7), Decimal::ONE),
+            // Friday to Thursday, short of a full week under the envelope
+            (ymd(2025, 1, 3), ymd(2025, 1, 9), Decimal::ONE),
+            // Ten days from a Monday, eight working days
+            (ymd(2024, 4, 1), ymd(2024, 4, 10), Decimal::new(16, 1)),
+            // Ten days from a Thursday
+            (ymd(2024, 4, 4), ymd(2024, 4, 13), Decimal::new(14, 1)),
+        ];
+        for (from, until, expected) in cases {
+            assert_eq!(weeks(from, until), expected, "{} to {}", from, until);
+        }
+    }
+
+    #[test]
+    fn envelope_weeks_reproduce_earlier_quantities() {
+        let weeks = |from, until| {
+            Period::new(from, until)
+                .num_units_with(&Unit::Week, WeekProration::Envelope)
+        };
+        assert_eq!(weeks(ymd(2024, 12, 30), ymd(2025, 1, 3)), Decimal::ONE);
+        assert_eq!(weeks(ymd(2025, 1, 3), ymd(2025, 1, 9)), Decimal::new(5, 1));
+        assert_eq!(
+            weeks(ymd(2024, 4, 1), ymd(2024, 4, 10)),
+            Decimal::new(16, 1)
+        );
+    }
+
+    #[test]
+    fn multi_week_spans() {
+        let sprint = Unit::EveryNWeeks(2);
+        let weeks = |from, until| Period::new(from, until).num_units(&sprint);
+
+        assert_eq!(weeks(ymd(2024, 4, 1), ymd(2024, 4, 12)), Decimal::ONE);
+        assert_eq!(weeks(ymd(2024, 4, 1), ymd(2024, 4, 26)), Decimal::from(2));
+        assert_eq!(weeks(ymd(2024, 4, 1), ymd(2024, 4, 5)), Decimal::new(5, 1));
+    }
+
+    #[test]
+    fn multi_week_rate_display() {
+        let rate = Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(4000)),
+            per: Unit::EveryNWeeks(2),
+            tiers: Vec::new(),
+        };
+        assert_eq!(rate.to_string(), "CAD $4000.00/2 weeks");
+        assert_eq!(monthly(Currency::Cad, 1).to_string(), "CAD $1.00/Month");
+    }
+
+    fn service_with_rates(changes: &[(NaiveDate, i64)]) -> Service {
+        let mut service = Service::new("Stuff".to_string());
+        for (effective, amount) in changes {
+            service
+                .rates
+                .insert(effective, &monthly(Currency::Cad, *amount));
+        }
+        service
+    }
+
+    #[test]
+    fn no_rate_change_in_period() {
+        let service = service_with_rates(&[(ymd(2024, 1, 1), 1000)]);
+        let april = Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30));
+
+        assert_eq!(
+            split_item_at_rate_changes(&service, &april),
+            vec![(april, monthly(Currency::Cad, 1000))]
+        );
+    }
+
+    #[test]
+    fn one_rate_change_in_period() {
+        let service = service_with_rates(&[
+            (ymd(2024, 1, 1), 1000),
+            (ymd(2024, 4, 15), 1200),
+            (ymd(2024, 5, 1), 1500),
+        ]);
+        let april = Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30));
+
+        assert_eq!(
+            split_item_at_rate_changes(&service, &april),
+            vec![
+                (
+                    Period::new(ymd(2024, 4, 1), ymd(2024, 4, 14)),
+                    monthly(Currency::Cad, 1000)
+                ),
+                (
+                    Period::new(ymd(2024, 4, 15), ymd(2024, 4, 30)),
+                    monthly(Currency::Cad, 1200)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn two_rate_changes_in_period() {
+        let service = service_with_rates(&[
+            (ymd(2024, 1, 1), 1000),
+            (ymd(2024, 4, 10), 1200),
+            (ymd(2024, 4, 30), 1500),
+        ]);
+        let april = Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30));
+
+        assert_eq!(
+            split_item_at_rate_changes(&service, &april),
+            vec![
+                (
+                    Period::new(ymd(2024, 4, 1), ymd(2024, 4, 9)),
+                    monthly(Currency::Cad, 1000)
+                ),
+                (
+                    Period::new(ymd(2024, 4, 10), ymd(2024, 4, 29)),
+                    monthly(Currency::Cad, 1200)
+                ),
+                (
+                    Period::new(ymd(2024, 4, 30), ymd(2024, 4, 30)),
+                    monthly(Currency::Cad, 1500)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn change_on_period_start_is_not_a_split() {
+        let service = service_with_rates(&[
+            (ymd(2024, 1, 1), 1000),
+            (ymd(2024, 4, 1), 1200),
+        ]);
+        let april = Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30));
+
+        assert_eq!(
+            split_item_at_rate_changes(&service, &april),
+            vec![(april, monthly(Currency::Cad, 1200))]
+        );
+    }
+
+    fn next_period(from: NaiveDate, until: NaiveDate) -> Period {
+        Period::new(from, until).next()
+    }
+
+    #[test]
+    fn next_whole_months() {
+        assert_eq!(
+            next_period(ymd(2024, 1, 1), ymd(2024, 1, 31)),
+            Period::new(ymd(2024, 2, 1), ymd(2024, 2, 29))
+        );
+        assert_eq!(
+            next_period(ymd(2023, 1, 1), ymd(2023, 1, 31)),
+            Period::new(ymd(2023, 2, 1), ymd(2023, 2, 28))
+        );
+        assert_eq!(
+            next_period(ymd(2024, 2, 1), ymd(2024, 2, 29)),
+            Period::new(ymd(2024, 3, 1), ymd(2024, 3, 31))
+        );
+        assert_eq!(
+            next_period(ymd(2024, 11, 1), ymd(2024, 12, 31)),
+            Period::new(ymd(2025, 1, 1), ymd(2025, 2, 28))
+        );
+    }
+
+    #[test]
+    fn next_partial_period_keeps_length() {
+        assert_eq!(
+            next_period(ymd(2024, 1, 15), ymd(2024, 2, 14)),
+            Period::new(ymd(2024, 2, 15), ymd(2024, 3, 16))
+        );
+        assert_eq!(
+            next_period(ymd(2024, 4, 1), ymd(2024, 4, 14)),
+            Period::new(ymd(2024, 4, 15), ymd(2024, 4, 28))
+        );
+    }
+
+    #[test]
+    fn suggested_start_from_first_rate() {
+        let today = ymd(2024, 5, 10);
+        let billed = Some(ymd(2024, 4, 30));
+        // Month start, mid-month, and before the period after the last one
+        assert_eq!(
+            suggested_start(today, billed, ymd(2024, 5, 1)),
+            Some(ymd(2024, 5, 1))
+        );
+        assert_eq!(
+            suggested_start(today, billed, ymd(2024, 5, 18)),
+            Some(ymd(2024, 5, 18))
+        );
+        assert_eq!(suggested_start(today, billed, ymd(2024, 3, 18)), None);
+        assert_eq!(suggested_start(today, billed, ymd(2024, 6, 3)), None);
+        // With nothing billed the first rate is where billing starts
+        assert_eq!(
+            suggested_start(today, None, ymd(2024, 3, 18)),
+            Some(ymd(2024, 3, 18))
+        );
+        assert_eq!(
+            suggested_start(today, None, ymd(2024, 5, 18)),
+            Some(ymd(2024, 5, 18))
+        );
+        assert_eq!(suggested_start(today, None, ymd(2024, 6, 3)), None);
+    }
+
+    #[test]
+    fn parse_periods() {
+        let today = ymd(2024, 3, 10);
+        let period = |from, until| Ok(Period::new(from, until));
+        let format = |text: &str| Err(PeriodParseError::Format(text.into()));
+        let cases = [
+            ("2024-04", period(ymd(2024, 4, 1), ymd(2024, 4, 30))),
+            ("2024-02", period(ymd(2024, 2, 1), ymd(2024, 2, 29))),
+            ("2023-02", period(ymd(2023, 2, 1), ymd(2023, 2, 28))),
+            (
+                "2024-04-01..2024-04-15",
+                period(ymd(2024, 4, 1), ymd(2024, 4, 15)),
+            ),
+            (
+                " 2024-02-29 .. 2024-02-29 ",
+                period(ymd(2024, 2, 29), ymd(2024, 2, 29)),
+            ),
+            (
+                "2024-04-01 — 2024-04-15",
+                period(ymd(2024, 4, 1), ymd(2024, 4, 15)),
+            ),
+            ("last-month", period(ymd(2024, 2, 1), ymd(2024, 2, 29))),
+            ("this-month", period(ymd(2024, 3, 1), ymd(2024, 3, 31))),
+            ("q1-2024", period(ymd(2024, 1, 1), ymd(2024, 3, 31))),
+            ("Q4-2023", period(ymd(2023, 10, 1), ymd(2023, 12, 31))),
+            ("2024-13", format("2024-13")),
+            ("2024-00", format("2024-00")),
+            ("2023-02-29..2023-03-01", format("2023-02-29..2023-03-01")),
+            ("2024-04-01..", format("2024-04-01..")),
+            ("q5-2024", format("q5-2024")),
+            ("q0-2024", format("q0-2024")),
+            ("q1", format("q1")),
+            ("next-month", format("next-month")),
+            ("", format("")),
+            (
+                "2024-04-15..2024-04-01",
+                Err(PeriodParseError::Reversed(Period::new(
+                    ymd(2024, 4, 15),
+                    ymd(2024, 4, 1),
+                ))),
+            ),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(Period::parse(text, today), expected, "{text}");
         }
+        let january = Period::parse("last-month", ymd(2025, 1, 31));
+        assert_eq!(january, period(ymd(2024, 12, 1), ymd(2024, 12, 31)));
+    }
 
-        write!(f, "\n\n{}", self.calculate())
+    #[test]
+    fn parse_display_round_trip() {
+        let today = ymd(2024, 3, 10);
+        for text in ["2024-04-01..2024-04-15", "2024-02", "q3-2024"] {
+            let period = Period::parse(text, today).unwrap();
+            let shown = period.to_string();
+            assert_eq!(Period::parse(&shown, today), Ok(period));
+        }
+        let error = Period::parse("2024-13", today).unwrap_err();
+        assert!(error.to_string().contains(PERIOD_FORMATS));
+    }
+
+    #[test]
+    fn checked_add_rejects_mixed_currencies() {
+        let usd = Money::new(Currency::Usd, Decimal::from(10));
+        let cad = Money::new(Currency::Cad, Decimal::from(5));
+
+        assert_eq!(
+            usd.checked_add(usd),
+            Ok(Money::new(Currency::Usd, Decimal::from(20)))
+        );
+        assert_eq!(
+            usd.checked_add(cad),
+            Err(CurrencyMismatch(Currency::Usd, Currency::Cad))
+        );
+    }
+
+    #[test]
+    fn mixed_currency_invoices_have_no_total() {
+        let april = Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30));
+        let item = |currency| {
+            InvoiceItem::new(
+                "Stuff".to_string(),
+                monthly(currency, 1000),
+                april.clone(),
+            )
+        };
+        // Invoices made before items were split by currency
+        let mut invoice =
+            Invoice::new(1, vec![item(Currency::Cad)], vec![]).unwrap();
+        invoice.items.push(item(Currency::Usd));
+
+        assert_eq!(
+            invoice.calculate().unwrap_err(),
+            CurrencyMismatch(Currency::Cad, Currency::Usd)
+        );
+        assert!(invoice
+            .to_string()
+            .ends_with("No total, cannot combine CAD and USD amounts"));
+    }
+
+    #[test]
+    fn subtraction_and_negation() {
+        let a = Money::new(Currency::Cad, Decimal::new(12345, 2));
+        let b = Money::new(Currency::Cad, Decimal::new(2345, 2));
+
+        assert_eq!(a - b, Money::new(Currency::Cad, Decimal::from(100)));
+        assert_eq!(b - a, Money::new(Currency::Cad, Decimal::from(-100)));
+        assert_eq!(-a, Money::new(Currency::Cad, Decimal::new(-12345, 2)));
+        assert_eq!(-(-a), a);
+        assert_eq!(
+            a.checked_sub(Money::new(Currency::Eur, Decimal::ONE)),
+            Err(CurrencyMismatch(Currency::Cad, Currency::Eur))
+        );
+    }
+
+    #[test]
+    fn negative_amounts_display_sign_first() {
+        let amount = -Money::new(Currency::Cad, Decimal::new(12345, 2));
+
+        assert_eq!(amount.to_string(), "-CAD $123.45");
+        assert_eq!(ledger_fmt(amount), "-$123.45");
+        assert_eq!(
+            ledger_fmt(-Money::new(Currency::Usd, Decimal::from(5))),
+            "-USD$5.00"
+        );
+        assert_eq!(
+            ledger_fmt(Money::new(Currency::Eur, Decimal::new(99, 1))),
+            "EUR€9.90"
+        );
+        assert_eq!(ledger_fmt(Decimal::new(-5, 1)), "-0.50");
+    }
+
+    #[test]
+    fn negative_total_displays_without_double_sign() {
+        let credit = -Money::new(Currency::Cad, Decimal::from(100));
+        let total = InvoiceTotal {
+            subtotal: credit,
+            taxes: vec![],
+            deductions: vec![],
+            adjustment: None,
+            total: credit,
+        };
+
+        assert_eq!(
+            total.to_string(),
+            "Subtotal: -CAD $100.00\n\nTotal: -CAD $100.00"
+        );
+    }
+
+    #[test]
+    fn final_invoice_deducts_deposits_after_tax() {
+        let mut invoice = Invoice::new(
+            3,
+            vec![april_item("Project", Currency::Cad)],
+            vec![TaxRate::new("GST".to_string(), 5)],
+        )
+        .unwrap();
+        let deposit = Money::new(Currency::Cad, Decimal::new(31500, 2));
+        invoice.kind = InvoiceKind::Final {
+            applies: vec![(1, deposit)],
+        };
+
+        let total = invoice.calculate().unwrap();
+        assert_eq!(total.deductions, vec![(1, -deposit)]);
+        assert_eq!(
+            total.total,
+            Money::new(Currency::Cad, Decimal::new(73500, 2))
+        );
+        assert_eq!(
+            total.to_string(),
+            "Subtotal: CAD $1000.00\n\
+             GST @ 5.00%: CAD $50.00\n\
+             Deposit #1: -CAD $315.00\n\
+             \nTotal: CAD $735.00"
+        );
+    }
+
+    fn usd(cents: i64) -> Money {
+        Money::new(Currency::Usd, Decimal::new(cents, 2))
+    }
+
+    fn cad(cents: i64) -> Money {
+        Money::new(Currency::Cad, Decimal::new(cents, 2))
+    }
+
+    #[test]
+    fn settlement_gain() {
+        let received = Received {
+            amount: cad(140000),
+            booked_rate: Decimal::new(13333, 4),
+        };
+        // 1000.00 and 50.00 USD at 1.3333 are 1333.30 and 66.665 CAD, the
+        // total of 1399.965 rounds to 1399.96
+        let settlement = settle(&[usd(100000), usd(5000)], &received);
+        assert_eq!(settlement.costs, vec![cad(133330), cad(6666)]);
+        assert_eq!(settlement.difference, cad(4));
+    }
+
+    #[test]
+    fn settlement_loss() {
+        let received = Received {
+            amount: cad(130000),
+            booked_rate: Decimal::new(135, 2),
+        };
+        let settlement = settle(&[usd(100000)], &received);
+        assert_eq!(settlement.costs, vec![cad(135000)]);
+        assert_eq!(settlement.difference, cad(-5000));
+    }
+
+    #[test]
+    fn settlement_costs_balance_to_the_cent() {
+        let received = Received {
+            amount: cad(10000),
+            booked_rate: Decimal::new(1, 0) / Decimal::from(3),
+        };
+        let amounts = [usd(10001), usd(10001), usd(10001)];
+        let settlement = settle(&amounts, &received);
+        let costs = settlement.costs.iter().fold(cad(0), |a, x| a + *x);
+        assert_eq!(costs + settlement.difference, received.amount);
+        assert_eq!(costs, cad(10001));
+    }
+
+    #[test]
+    fn allocation_residual_goes_to_the_largest_share() {
+        let shares = allocate(cad(10000), &[cad(100), cad(100), cad(100)]);
+        assert_eq!(shares, vec![cad(3334), cad(3333), cad(3333)]);
+
+        let shares = allocate(cad(10500), &[cad(3333), cad(6667)]);
+        assert_eq!(shares, vec![cad(3500), cad(7000)]);
+        let weights = [cad(100), cad(100), cad(100), cad(300)];
+        let shares = allocate(cad(10000), &weights);
+        assert_eq!(shares, vec![cad(1667), cad(1667), cad(1667), cad(4999)]);
+    }
+
+    #[test]
+    fn invoice_requires_single_currency() {
+        let items = vec![
+            april_item("Legacy", Currency::Usd),
+            april_item("New", Currency::Cad),
+        ];
+        assert_eq!(
+            Invoice::new(1, items, vec![]),
+            Err(CurrencyMismatch(Currency::Usd, Currency::Cad).into())
+        );
+
+        let invoice =
+            Invoice::new(1, vec![april_item("New", Currency::Cad)], vec![])
+                .unwrap();
+        assert_eq!(invoice.currency(), Currency::Cad);
+    }
+
+    #[test]
+    fn zero_and_negative_items() {
+        let daily = Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(500)),
+            per: Unit::Day,
+            tiers: Vec::new(),
+        };
+        let weekend = Period::new(ymd(2024, 4, 6), ymd(2024, 4, 7));
+        let item = InvoiceItem::new("Support".to_string(), daily, weekend);
+        let error = item.check_amount().unwrap_err();
+        assert_eq!(error, ItemAmountError::NoWorkingDays(item.period.clone()));
+        assert!(error.is_zero());
+        assert_eq!(
+            Invoice::new(1, vec![item.clone()], vec![]),
+            Err(NewInvoiceError::Subtotal(Money::new(
+                Currency::Cad,
+                Decimal::ZERO
+            )))
+        );
+        let invoice = Invoice::new(
+            1,
+            vec![april_item("Stuff", Currency::Cad), item],
+            vec![],
+        );
+        assert!(invoice.is_ok());
+
+        let hourly = Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(80)),
+            per: Unit::Hour,
+            tiers: Vec::new(),
+        };
+        let april = Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30));
+        let typo = Decimal::from(-8);
+        let item =
+            InvoiceItem::new_hourly("Support".to_string(), hourly, april, typo);
+        let error = item.check_amount().unwrap_err();
+        assert_eq!(error, ItemAmountError::NegativeQuantity(typo));
+        assert!(!error.is_zero());
+        assert!(matches!(
+            Invoice::new(1, vec![item], vec![]),
+            Err(NewInvoiceError::Subtotal(_))
+        ));
+
+        assert_eq!(april_item("Stuff", Currency::Cad).check_amount(), Ok(()));
+    }
+
+    #[test]
+    fn grouping_by_currency_keeps_order() {
+        let groups = group_by_currency(vec![
+            april_item("A", Currency::Usd),
+            april_item("B", Currency::Cad),
+            april_item("C", Currency::Usd),
+        ]);
+        let names: Vec<Vec<&str>> = groups
+            .iter()
+            .map(|g| g.iter().map(|i| i.name.as_str()).collect())
+            .collect();
+
+        assert_eq!(names, vec![vec!["A", "C"], vec!["B"]]);
+    }
+
+    #[test]
+    fn quantity_in_month_by_working_days() {
+        let hourly = Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(100)),
+            per: Unit::Hour,
+            tiers: Vec::new(),
+        };
+        let item = |from, until, hours| {
+            let period = Period::new(from, until);
+            InvoiceItem::new_hourly(
+                "Support".to_string(),
+                hourly.clone(),
+                period,
+                Decimal::from(hours),
+            )
+        };
+
+        let april = item(ymd(2024, 4, 1), ymd(2024, 4, 30), 40);
+        assert_eq!(quantity_in_month(&april, ymd(2024, 4, 15)), 40.into());
+        assert!(quantity_in_month(&april, ymd(2024, 3, 31)).is_zero());
+        assert!(quantity_in_month(&april, ymd(2024, 5, 1)).is_zero());
+
+        // 12 working days in April and 10 in May
+        let straddling = item(ymd(2024, 4, 15), ymd(2024, 5, 14), 44);
+        assert_eq!(quantity_in_month(&straddling, ymd(2024, 4, 1)), 24.into());
+        assert_eq!(quantity_in_month(&straddling, ymd(2024, 5, 31)), 20.into());
+        assert!(quantity_in_month(&straddling, ymd(2024, 6, 1)).is_zero());
+
+        let weekend = item(ymd(2024, 8, 31), ymd(2024, 9, 1), 3);
+        assert_eq!(quantity_in_month(&weekend, ymd(2024, 8, 1)), 3.into());
+        assert!(quantity_in_month(&weekend, ymd(2024, 9, 1)).is_zero());
+    }
+
+    #[test]
+    fn stale_rate_boundary() {
+        let effective = ymd(2023, 4, 15);
+        assert!(!rate_is_stale(effective, ymd(2024, 4, 15), 12));
+        assert!(rate_is_stale(effective, ymd(2024, 4, 16), 12));
+        assert!(!rate_is_stale(effective, ymd(2023, 10, 15), 6));
+        assert!(rate_is_stale(effective, ymd(2023, 10, 16), 6));
+        assert!(!rate_is_stale(ymd(2024, 1, 31), ymd(2024, 2, 29), 1));
+        assert!(rate_is_stale(ymd(2024, 1, 31), ymd(2024, 3, 1), 1));
+    }
+
+    #[test]
+    fn gaps_before_new_periods() {
+        let april = Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30));
+        assert_eq!(billing_gap(ymd(2024, 3, 31), april.from), None);
+        assert_eq!(billing_gap(ymd(2024, 4, 15), april.from), None);
+
+        let gap = billing_gap(ymd(2024, 2, 29), april.from);
+        assert_eq!(gap, Some(Period::new(ymd(2024, 3, 1), ymd(2024, 3, 31))));
+        assert_eq!(
+            cover_gap(&april, &gap.unwrap()),
+            Period::new(ymd(2024, 3, 1), ymd(2024, 4, 30))
+        );
+
+        let day = billing_gap(ymd(2024, 3, 30), april.from);
+        assert_eq!(day, Some(Period::new(ymd(2024, 3, 31), ymd(2024, 3, 31))));
+    }
+
+    fn mixed_services_invoice() -> Invoice {
+        let hourly = Rate {
+            amount: cad(10000),
+            per: Unit::Hour,
+            tiers: Vec::new(),
+        };
+        let week =
+            |from, until| Period::new(ymd(2024, 4, from), ymd(2024, 4, until));
+        let support = |period, hours| {
+            InvoiceItem::new_hourly(
+                "Support".to_string(),
+                hourly.clone(),
+                period,
+                Decimal::from(hours),
+            )
+        };
+        Invoice::new(
+            1,
+            vec![
+                support(week(15, 21), 5),
+                april_item("Design", Currency::Cad),
+                support(week(1, 7), 10),
+            ],
+            vec![],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn items_grouped_by_service() {
+        let invoice = mixed_services_invoice();
+        let groups: Vec<(String, Vec<NaiveDate>, Money)> = invoice
+            .items_by_service()
+            .into_iter()
+            .map(|(name, items, subtotal)| {
+                let starts = items.iter().map(|i| i.period.from).collect();
+                (name, starts, subtotal)
+            })
+            .collect();
+
+        assert_eq!(
+            groups,
+            vec![
+                ("Design".to_string(), vec![ymd(2024, 4, 1)], cad(100000)),
+                (
+                    "Support".to_string(),
+                    vec![ymd(2024, 4, 1), ymd(2024, 4, 15)],
+                    cad(150000)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn invoice_display_subtotals_services() {
+        let invoice = mixed_services_invoice();
+        let grouped = invoice.to_string();
+        let flat = format!("{:#}", invoice);
+
+        assert!(grouped.contains("Design subtotal: CAD $1000.00\n\nSupport"));
+        assert!(grouped.contains("Support subtotal: CAD $1500.00"));
+        assert!(!flat.contains("subtotal"));
+        // Flat items are in period order, service by service within a day
+        assert!(
+            flat.find("Design").unwrap() < flat.find("2024-04-15").unwrap()
+        );
+    }
+
+    #[test]
+    fn items_in_canonical_order() {
+        let items = mixed_services_invoice().items;
+        let serialized = |items: Vec<InvoiceItem>| {
+            let mut invoice = Invoice::new(1, items, vec![]).unwrap();
+            invoice.date = ymd(2024, 5, 1);
+            serde_lexpr::to_string(&invoice).unwrap()
+        };
+        let expected = serialized(items.clone());
+        let mut reversed = items.clone();
+        reversed.reverse();
+        assert_eq!(serialized(reversed.clone()), expected);
+        let mut rotated = items.clone();
+        rotated.rotate_left(1);
+        assert_eq!(serialized(rotated), expected);
+
+        let invoice = Invoice::new(1, items.clone(), vec![]).unwrap();
+        let order: Vec<(&str, NaiveDate)> = invoice
+            .items
+            .iter()
+            .map(|i| (i.name.as_str(), i.period.from))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                ("Design", ymd(2024, 4, 1)),
+                ("Support", ymd(2024, 4, 1)),
+                ("Support", ymd(2024, 4, 15)),
+            ]
+        );
+
+        let manual = Invoice::unsorted(1, reversed.clone(), vec![]).unwrap();
+        assert_eq!(manual.items, reversed);
+    }
+
+    fn quebec_taxes() -> Vec<TaxRate> {
+        vec![
+            TaxRate::new("GST".to_string(), 5),
+            TaxRate(
+                "QST".to_string(),
+                Decimal::new(9975, 5),
+                TaxRounding::HalfEven,
+            ),
+        ]
+    }
+
+    #[test]
+    fn rate_change_percentages() {
+        let rate = |amount: i64, per| Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(amount)),
+            per,
+            tiers: Vec::new(),
+        };
+        let hourly = rate(120, Unit::Hour);
+        assert_eq!(
+            hourly.change_from(&rate(100, Unit::Hour)),
+            Some(Decimal::new(200, 1))
+        );
+        assert_eq!(
+            rate(100, Unit::Hour).change_from(&rate(300, Unit::Hour)),
+            Some(Decimal::new(-667, 1))
+        );
+        assert_eq!(hourly.change_from(&rate(900, Unit::Day)), None);
+        assert_eq!(hourly.change_from(&rate(0, Unit::Hour)), None);
+        let usd = Rate {
+            amount: Money::new(Currency::Usd, Decimal::from(100)),
+            per: Unit::Hour,
+            tiers: Vec::new(),
+        };
+        assert_eq!(hourly.change_from(&usd), None);
+    }
+
+    #[test]
+    fn rate_equivalents() {
+        // 22 working days
+        let april = Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30));
+        let eight = Decimal::from(8);
+        let show = |rate: Rate, hours| {
+            rate.equivalents(hours, &april)
+                .iter()
+                .map(Rate::to_string)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            show(monthly(Currency::Usd, 8000), eight),
+            vec!["USD $45.45/Hour", "USD $363.64/Day", "USD $1818.18/Week"]
+        );
+        let hourly = Rate {
+            amount: Money::new(Currency::Usd, Decimal::from(100)),
+            per: Unit::Hour,
+            tiers: Vec::new(),
+        };
+        assert_eq!(
+            show(hourly.clone(), eight),
+            vec![
+                "USD $800.00/Day",
+                "USD $4000.00/Week",
+                "USD $17600.00/Month"
+            ]
+        );
+        assert_eq!(
+            show(hourly, Decimal::new(75, 1)),
+            vec![
+                "USD $750.00/Day",
+                "USD $3750.00/Week",
+                "USD $16500.00/Month"
+            ]
+        );
+        let sprint = Rate {
+            amount: Money::new(Currency::Usd, Decimal::from(8000)),
+            per: Unit::EveryNWeeks(2),
+            tiers: Vec::new(),
+        };
+        assert_eq!(show(sprint, eight)[0], "USD $100.00/Hour");
+        assert!(show(monthly(Currency::Usd, 8000), Decimal::ZERO).is_empty());
+    }
+
+    #[test]
+    fn hours_split_across_tiers() {
+        let rate = Rate {
+            amount: cad(15000),
+            per: Unit::Hour,
+            tiers: vec![(Decimal::from(40), cad(12000))],
+        };
+        let april = Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30));
+        let item = |hours: Decimal| {
+            InvoiceItem::new_hourly(
+                "Work".to_string(),
+                rate.clone(),
+                april.clone(),
+                hours,
+            )
+        };
+
+        let below = item(Decimal::from(30));
+        assert_eq!(below.tiers, vec![(Decimal::from(30), cad(15000))]);
+        assert_eq!(below.amount, cad(450000));
+        assert_eq!(below.tier_breakdown().unwrap(), "30h @ CAD $150.00");
+
+        let at = item(Decimal::from(40));
+        assert_eq!(at.tiers, vec![(Decimal::from(40), cad(15000))]);
+        assert_eq!(at.amount, cad(600000));
+
+        let above = item(Decimal::new(525, 1));
+        assert_eq!(
+            above.tiers,
+            vec![
+                (Decimal::from(40), cad(15000)),
+                (Decimal::new(125, 1), cad(12000)),
+            ]
+        );
+        assert_eq!(above.amount, cad(750000));
+        assert_eq!(
+            above.tier_breakdown().unwrap(),
+            "40h @ CAD $150.00, 12.5h @ CAD $120.00"
+        );
+
+        let flat = Rate {
+            tiers: Vec::new(),
+            ..rate.clone()
+        };
+        assert!(flat
+            .split_hours(Decimal::ZERO, Decimal::from(50))
+            .is_empty());
+        let item = InvoiceItem::new_hourly(
+            "Work".to_string(),
+            flat,
+            april,
+            Decimal::from(50),
+        );
+        assert_eq!(item.amount, cad(750000));
+        assert_eq!(item.tier_breakdown(), None);
+    }
+
+    #[test]
+    fn tiers_count_a_months_hours_across_items() {
+        let rate = Rate {
+            amount: cad(15000),
+            per: Unit::Hour,
+            tiers: vec![(Decimal::from(40), cad(12000))],
+        };
+        let item = |from: NaiveDate, until: NaiveDate| {
+            InvoiceItem::new_hourly(
+                "Work".to_string(),
+                rate.clone(),
+                Period::new(from, until),
+                Decimal::from(30),
+            )
+        };
+        let mut items = vec![
+            item(ymd(2024, 4, 16), ymd(2024, 4, 30)),
+            item(ymd(2024, 5, 1), ymd(2024, 5, 15)),
+            item(ymd(2024, 4, 1), ymd(2024, 4, 15)),
+        ];
+        tier_by_month(&mut items).unwrap();
+
+        // The first half of April reaches the threshold first
+        assert_eq!(items[2].tiers, vec![(Decimal::from(30), cad(15000))]);
+        assert_eq!(
+            items[0].tiers,
+            vec![
+                (Decimal::from(10), cad(15000)),
+                (Decimal::from(20), cad(12000)),
+            ]
+        );
+        assert_eq!(items[0].amount, cad(390000));
+        // May counts its own hours
+        assert_eq!(items[1].tiers, vec![(Decimal::from(30), cad(15000))]);
+        assert_eq!(items[1].amount, cad(450000));
+
+        let mut spanning = vec![item(ymd(2024, 4, 16), ymd(2024, 5, 15))];
+        assert_eq!(
+            tier_by_month(&mut spanning),
+            Err(NewInvoiceError::TieredMonths(
+                "Work".to_string(),
+                Period::new(ymd(2024, 4, 16), ymd(2024, 5, 15))
+            ))
+        );
+        assert_eq!(spanning[0].amount, cad(450000));
+    }
+
+    #[test]
+    fn converted_items_round_to_cents() {
+        let item = InvoiceItem::new_hourly(
+            "Stuff".to_string(),
+            Rate {
+                amount: Money::new(Currency::Usd, Decimal::new(101, 2)),
+                per: Unit::Hour,
+                tiers: Vec::new(),
+            },
+            Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30)),
+            Decimal::ONE,
+        );
+        // 2.525 rounds half to even
+        let converted = item.converted(Currency::Eur, Decimal::new(25, 1));
+        assert_eq!(
+            converted.amount,
+            Money::new(Currency::Eur, Decimal::new(252, 2))
+        );
+        assert_eq!(converted.rate.amount.currency(), Currency::Usd);
+
+        let item = april_item("Stuff", Currency::Usd);
+        let mut invoice = Invoice::new(
+            1,
+            vec![item.converted(Currency::Eur, Decimal::new(92, 2))],
+            vec![],
+        )
+        .unwrap();
+        invoice.fx = Some((Currency::Usd, Decimal::new(92, 2)));
+        assert_eq!(invoice.currency(), Currency::Eur);
+        assert_eq!(
+            invoice.calculate().unwrap().total.to_string(),
+            "EUR €920.00"
+        );
+        assert!(invoice.to_string().contains("Converted from USD at 0.92"));
+    }
+
+    #[test]
+    fn zero_rated_tax_is_kept() {
+        let gst = TaxRate::new("GST".to_string(), 0);
+        let item = prorated_item(1050, 1000);
+        let invoice = Invoice::new(1, vec![item], vec![gst.clone()]).unwrap();
+
+        let total = invoice.calculate().unwrap();
+        assert_eq!(total.taxes, vec![(gst, cad(0))]);
+        assert_eq!(total.total, cad(1050));
+        assert!(total.to_string().contains("GST @ 0%: CAD $0.00\n"));
+    }
+
+    #[test]
+    fn tax_rounding_at_half_cents() {
+        let strategies = [TaxRounding::HalfEven, TaxRounding::HalfUp];
+        // 5% of $10.50 is $0.525 and of $0.30 is $0.015
+        for (subtotal, rounded) in [(1050, [52, 53]), (30, [2, 2])] {
+            for (rounding, cents) in strategies.iter().zip(rounded) {
+                let gst = TaxRate::new("GST".to_string(), 5).rounded(*rounding);
+                let item = prorated_item(subtotal, 1000);
+                let invoice = Invoice::new(1, vec![item], vec![gst]).unwrap();
+                let total = invoice.calculate().unwrap();
+                assert_eq!(total.taxes[0].1, cad(cents), "{:?}", rounding);
+                assert_eq!(total.total, cad(subtotal + cents));
+            }
+        }
+        let half_cent = Decimal::new(5, 3);
+        let half_up = TaxRounding::HalfUp.strategy();
+        assert_eq!(cad(100).mul_rounded(half_cent, half_up), cad(1));
+        assert_eq!(cad(100) * half_cent, cad(0));
+    }
+
+    /// An item of a prorated quantity of a monthly rate
+    fn prorated_item(rate_cents: i64, thousandths: i64) -> InvoiceItem {
+        let rate = Rate {
+            amount: cad(rate_cents),
+            per: Unit::Month,
+            tiers: Vec::new(),
+        };
+        let quantity = Decimal::new(thousandths, 3);
+        InvoiceItem::raw(
+            "Prorated".to_string(),
+            rate.clone(),
+            Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30)),
+            quantity,
+            rate.amount * quantity,
+        )
+    }
+
+    #[test]
+    fn total_rounding_adds_an_adjustment() {
+        let items = vec![prorated_item(10010, 1000)];
+        let mut invoice = Invoice::new(1, items, quebec_taxes()).unwrap();
+
+        let total = invoice.calculate().unwrap();
+        assert_eq!(total.adjustment, None);
+        assert_eq!(total.total, cad(11508));
+
+        invoice.rounding = RoundingPolicy::Total;
+        let total = invoice.calculate().unwrap();
+        assert_eq!(total.adjustment, Some(cad(1)));
+        assert_eq!(total.total, cad(11509));
+        assert!(total.to_string().contains("Rounding: CAD $0.01\n"));
+
+        let items = vec![prorated_item(10000, 1000)];
+        let mut invoice = Invoice::new(2, items, quebec_taxes()).unwrap();
+        invoice.rounding = RoundingPolicy::Total;
+        assert_eq!(invoice.calculate().unwrap().adjustment, None);
+    }
+
+    proptest! {
+        #[test]
+        fn totals_add_up_under_either_policy(
+            items in prop::collection::vec((1i64..5_000_000, 1i64..3000), 1..8),
+            round_total in any::<bool>(),
+        ) {
+            let items = items
+                .into_iter()
+                .map(|(rate, quantity)| prorated_item(rate, quantity))
+                .collect();
+            let mut invoice = Invoice::new(1, items, quebec_taxes()).unwrap();
+            if round_total {
+                invoice.rounding = RoundingPolicy::Total;
+            }
+
+            let total = invoice.calculate().unwrap();
+            let sum = total
+                .taxes
+                .iter()
+                .map(|(_, x)| *x)
+                .chain(total.adjustment)
+                .fold(total.subtotal, |a, x| a + x);
+            prop_assert_eq!(sum, total.total);
+            if round_total {
+                let rates = Decimal::new(114975, 5);
+                prop_assert_eq!(total.total, total.subtotal * rates);
+            } else {
+                prop_assert_eq!(total.adjustment, None);
+            }
+        }
+    }
+
+    #[test]
+    fn parse_money() {
+        use Currency::{Cad, Eur, Usd};
+        let amount =
+            |currency, cents| Ok(Money::new(currency, Decimal::new(cents, 2)));
+        let format =
+            |text: &str| Err(MoneyParseError::Format(text.to_string()));
+        let no_currency =
+            |text: &str| Err(MoneyParseError::NoCurrency(text.to_string()));
+        let cases = [
+            ("CAD 1500.00", None, amount(Cad, 150000)),
+            ("CAD $1500.00", None, amount(Cad, 150000)),
+            ("cad 1500", None, amount(Cad, 150000)),
+            ("1500.5 CAD", None, amount(Cad, 150050)),
+            ("USD$99.95", None, amount(Usd, 9995)),
+            ("USD 1,234,567.89", None, amount(Usd, 123456789)),
+            ("$99.95", Some(Usd), amount(Usd, 9995)),
+            ("$99.95", None, no_currency("$99.95")),
+            ("99.95", Some(Eur), amount(Eur, 9995)),
+            ("99.95", None, no_currency("99.95")),
+            ("EUR€12", None, amount(Eur, 1200)),
+            ("EUR € 12,5", None, amount(Eur, 1250)),
+            ("1.200,50 EUR", None, amount(Eur, 120050)),
+            ("1 200,50 €", None, amount(Eur, 120050)),
+            ("€1'200", None, amount(Eur, 120000)),
+            ("1.200 EUR", None, amount(Eur, 120000)),
+            ("1,200 $", Some(Cad), amount(Cad, 120000)),
+            ("-CAD $5.00", None, amount(Cad, -500)),
+            ("CAD -5", None, amount(Cad, -500)),
+            ("-$0.05", Some(Cad), amount(Cad, -5)),
+            ("CAD 0", None, amount(Cad, 0)),
+            (
+                "CAD 1.2345",
+                None,
+                Err(MoneyParseError::Decimals("CAD 1.2345".to_string(), 2)),
+            ),
+            (
+                "CAD 0.001",
+                None,
+                Err(MoneyParseError::Decimals("CAD 0.001".to_string(), 2)),
+            ),
+            ("CAD 1,2,3", None, format("CAD 1,2,3")),
+            ("CAD 12,34.56", None, format("CAD 12,34.56")),
+            ("CAD 5.", None, format("CAD 5.")),
+            ("CAD 5 USD", None, format("CAD 5 USD")),
+            ("GBP 5", None, format("GBP 5")),
+            ("CAD", None, format("CAD")),
+            ("", Some(Cad), format("")),
+        ];
+        for (text, default, expected) in cases {
+            assert_eq!(
+                Money::parse_lenient(text, default),
+                expected,
+                "{}",
+                text
+            );
+        }
+        assert_eq!("CAD 1500.00".parse(), amount(Cad, 150000));
+        assert_eq!("$1500.00".parse::<Money>(), no_currency("$1500.00"));
+    }
+
+    proptest! {
+        #[test]
+        fn money_round_trips(
+            currency in prop_oneof![
+                Just(Currency::Cad),
+                Just(Currency::Usd),
+                Just(Currency::Eur),
+            ],
+            cents in -100_000_000_000i64..100_000_000_000,
+            style in prop_oneof![
+                Just(""),
+                Just("code"),
+                Just("after,comma,grouped,code"),
+                Just("grouped"),
+                Just("after,comma,grouped"),
+            ],
+        ) {
+            let money = Money::new(currency, Decimal::new(cents, 2));
+            prop_assert_eq!(money.to_string().parse::<Money>(), Ok(money));
+            let style: LedgerStyle = style.parse().unwrap();
+            // A bare $ in postings is Canadian
+            let posted = ledger_fmt_with(&style, money);
+            prop_assert_eq!(
+                Money::parse_lenient(&posted, Some(Currency::Cad)),
+                Ok(money)
+            );
+        }
+    }
+
+    /// The quantity recomputed from the parts of an explanation
+    fn derived(proration: &Proration) -> Decimal {
+        (proration.whole
+            + proration.billed_days / proration.boundary_days
+                * proration.distinct)
+            / proration.span
+    }
+
+    #[test]
+    fn explanations_agree_with_quantities() {
+        let units = [
+            Unit::Month,
+            Unit::Week,
+            Unit::Day,
+            Unit::Quarter,
+            Unit::Year,
+            Unit::EveryNWeeks(2),
+        ];
+        let periods = [
+            (ymd(2024, 4, 1), ymd(2024, 4, 30)),
+            (ymd(2024, 4, 3), ymd(2024, 4, 29)),
+            (ymd(2024, 3, 15), ymd(2024, 6, 10)),
+            (ymd(2024, 2, 7), ymd(2024, 2, 9)),
+            (ymd(2023, 1, 1), ymd(2024, 12, 31)),
+        ];
+        for (from, until) in periods {
+            let period = Period::new(from, until);
+            for unit in units.iter() {
+                let explained = period.explain(unit).unwrap();
+                assert_eq!(explained.quantity, period.num_units(unit));
+                assert_eq!(
+                    derived(&explained).round_dp(20),
+                    explained.quantity.round_dp(20),
+                    "{} {}",
+                    period,
+                    unit
+                );
+            }
+        }
+        assert_eq!(
+            Period::new(ymd(2024, 4, 1), ymd(2024, 4, 3)).explain(&Unit::Hour),
+            None
+        );
+    }
+
+    #[test]
+    fn explanation_text() {
+        let period = Period::new(ymd(2024, 4, 3), ymd(2024, 4, 30));
+        assert_eq!(
+            period.explain(&Unit::Month).unwrap().to_string(),
+            "Billed period: 2024-04-03 — 2024-04-30\n\
+             Whole Month period: 2024-04-01 — 2024-04-30\n\
+             Working days in the whole period: 22\n\
+             Working days billed: 20\n\
+             Distinct units counted: 1\n\
+             Quantity: 20 / 22 × 1 = 0.9091"
+        );
     }
 }
@@ -16,6 +16,22 @@ pub trait DateBoundaries {
     fn end_of_week(&self) -> Option<Self>
     where
         Self: Sized;
+
+    fn start_of_quarter(&self) -> Option<Self>
+    where
+        Self: Sized;
+
+    fn end_of_quarter(&self) -> Option<Self>
+    where
+        Self: Sized;
+
+    fn start_of_year(&self) -> Option<Self>
+    where
+        Self: Sized;
+
+    fn end_of_year(&self) -> Option<Self>
+    where
+        Self: Sized;
 }
 
 impl DateBoundaries for NaiveDate {
@@ -39,6 +55,31 @@ impl DateBoundaries for NaiveDate {
         let num_days = max_days - self.weekday().num_days_from_monday();
         self.checked_add_days(Days::new(num_days.into()))
     }
+
+    fn start_of_quarter(&self) -> Option<Self> {
+        let first_month = self.month0() / 3 * 3 + 1;
+        self.with_day(1).and_then(|d| d.with_month(first_month))
+    }
+
+    fn end_of_quarter(&self) -> Option<Self> {
+        self.start_of_quarter()
+            .and_then(|d| d.checked_add_months(Months::new(3)))
+            .and_then(|d| d.checked_sub_days(Days::new(1)))
+    }
+
+    fn start_of_year(&self) -> Option<Self> {
+        self.with_ordinal(1)
+    }
+
+    fn end_of_year(&self) -> Option<Self> {
+        NaiveDate::from_ymd_opt(self.year(), 12, 31)
+    }
+}
+
+/// The day in the date's month, the month's last day when it's shorter,
+/// e.g. the 31st is the 30th in April
+pub fn day_of_month(date: NaiveDate, day: u8) -> Option<NaiveDate> {
+    date.with_day(day.into()).or_else(|| date.end_of_month())
 }
 
 #[cfg(test)]
@@ -68,6 +109,15 @@ pub mod tests {
         assert_expected_date(ymd(2023, 12, 24).end_of_month(), 2023, 12, 31);
     }
 
+    #[test]
+    fn day_of_month_clamps() {
+        assert_expected_date(day_of_month(ymd(2023, 1, 9), 31), 2023, 1, 31);
+        assert_expected_date(day_of_month(ymd(2023, 2, 9), 31), 2023, 2, 28);
+        assert_expected_date(day_of_month(ymd(2024, 2, 9), 30), 2024, 2, 29);
+        assert_expected_date(day_of_month(ymd(2023, 4, 30), 31), 2023, 4, 30);
+        assert_expected_date(day_of_month(ymd(2023, 4, 30), 1), 2023, 4, 1);
+    }
+
     #[test]
     fn start_of_month() {
         assert_expected_date(ymd(2023, 1, 30).start_of_month(), 2023, 1, 1);
@@ -92,4 +142,36 @@ pub mod tests {
         assert_expected_date(ymd(2023, 12, 31).start_of_week(), 2023, 12, 25);
         assert_expected_date(ymd(2025, 1, 4).start_of_week(), 2024, 12, 30);
     }
+
+    #[test]
+    fn end_of_quarter() {
+        assert_expected_date(ymd(2023, 1, 1).end_of_quarter(), 2023, 3, 31);
+        assert_expected_date(ymd(2024, 2, 29).end_of_quarter(), 2024, 3, 31);
+        assert_expected_date(ymd(2023, 6, 30).end_of_quarter(), 2023, 6, 30);
+        assert_expected_date(ymd(2023, 8, 31).end_of_quarter(), 2023, 9, 30);
+        assert_expected_date(ymd(2023, 10, 1).end_of_quarter(), 2023, 12, 31);
+    }
+
+    #[test]
+    fn start_of_quarter() {
+        assert_expected_date(ymd(2023, 3, 31).start_of_quarter(), 2023, 1, 1);
+        assert_expected_date(ymd(2024, 2, 29).start_of_quarter(), 2024, 1, 1);
+        assert_expected_date(ymd(2023, 5, 31).start_of_quarter(), 2023, 4, 1);
+        assert_expected_date(ymd(2023, 7, 1).start_of_quarter(), 2023, 7, 1);
+        assert_expected_date(ymd(2023, 12, 31).start_of_quarter(), 2023, 10, 1);
+    }
+
+    #[test]
+    fn end_of_year() {
+        assert_expected_date(ymd(2023, 1, 1).end_of_year(), 2023, 12, 31);
+        assert_expected_date(ymd(2024, 2, 29).end_of_year(), 2024, 12, 31);
+        assert_expected_date(ymd(2024, 12, 31).end_of_year(), 2024, 12, 31);
+    }
+
+    #[test]
+    fn start_of_year() {
+        assert_expected_date(ymd(2023, 12, 31).start_of_year(), 2023, 1, 1);
+        assert_expected_date(ymd(2024, 2, 29).start_of_year(), 2024, 1, 1);
+        assert_expected_date(ymd(2024, 1, 1).start_of_year(), 2024, 1, 1);
+    }
 }
@@ -3,24 +3,96 @@ use std::path::PathBuf;
 
 /* Argument Stucture
  *
- * list [clients | invoices <client> | services <client>]
- * add [client | service <client>]
- * show <client> ( taxes |
- *      invoice <num> (posting | payment | markdown)
- * set <client> [rate | taxes | address | name ]
- * invoice <client>
- * mark-paid <client> <number>
+ * [--file <path>] [--dry-run] [--idempotency-key <key>] [-v | -vv]
+ *     [<command>]
+ *
+ * With no command a menu picks a client and what to do with it
+ *
+ * list [clients [--convert-to <currency>] [--key-only | --porcelain] |
+ *      invoices <client> [--porcelain] |
+ *      services <client> [--porcelain] [--history [--json]] |
+ *      taxes <client> [--as-of <date>] | templates]
+ * add [client | service <client> | retainer <client> | template |
+ *      from-template <client>] [--timestamp <rfc3339>]
+ * show <client> ( taxes | rates | retainer | payments [--year <year>] |
+ *      invoice <num> [--flat] (posting [--value-in <currency>
+ *      [--price-directive]]
+ *      [--by-service] [--date <date>] [--reverse-accrual] |
+ *      payment [--date <date>] | write-off | latex [--output [<path>]] |
+ *      csv | ubl)
+ * set <client> [rate | default-hours | taxes | address [--name <name>] |
+ *      invoice-address <name> | name | template |
+ *      footer | rounding | payment-account <account> | language <code> |
+ *      stale-rate-age <months> | schedule <service> <day> |
+ *      posting-date <date> | category <service> <category> |
+ *      target-hours <service> <hours>]
+ * invoice <client> [--repeat-last] [--deposit] [--advance]
+ *      [--period <period>] [--weekly] [--currency <code>] [--preview]
+ *      [--confirm-key <amount>] [--timestamp <rfc3339>]
+ * mark-paid <client> <number> [--other-currency] [--timestamp <rfc3339>]
+ * mark-sent <client> <number> [--timestamp <rfc3339>]
+ * write-off <client> <number>
+ * void <client> <number>
+ * dispute <client> <number>
+ * resolve-dispute <client> <number>
  * remove <client>
+ * remove-template <name>
+ * normalize-services <client>
+ * path <client> <number>
+ * ical <output> [--reminders] [--include-disputed]
+ * profile [sender | footer | ledger-style <style> |
+ *      payment-account <account> | invoice-path <pattern> |
+ *      hours-per-day <hours> | global-numbering <enabled> |
+ *      registration <label> [<number>] [--force]]
+ * init [<path>]
+ * config
+ * snapshot
+ * export (toml | sqlite) [--output <path>]
+ * find <query> [--key-only]
+ * grep <query> [--raw]
+ * log [--origin <name>]
+ * unbilled [--convert-to <currency>]
+ * revenue [--year <year>]
+ * hours <client> [--year <year>]
+ * accrual <client> <period> [--ledger-style <style>]
+ * due [--generate] [--confirm-key <amount>]
+ * remind <client> <number> [--level (first | second | final)]
+ *      [--format (text | tex | html)] [--output <path>]
+ * invoices [<number>] [--json]
+ * audit <client> <number> [--legacy-weeks]
+ * verify [--legacy-weeks]
+ * import-invoices <client> <path>
+ * explain <from> <until> <unit>
  */
 
 #[derive(Parser)]
 pub struct Opts {
-    #[clap(short, long, default_value="client.history",
-        value_hint=ValueHint::FilePath)]
-    pub file: PathBuf,
+    /// History file, otherwise $INVOGEN_FILE or the nearest client.history
+    /// in this or a parent directory
+    #[clap(short, long, value_hint=ValueHint::FilePath)]
+    pub file: Option<PathBuf>,
+
+    /// Print the events that would be added instead of writing them
+    #[clap(long, global = true)]
+    pub dry_run: bool,
+
+    /// Skip the command if one with this key was already applied, so
+    /// scripts can safely retry it
+    #[clap(long, global = true)]
+    pub idempotency_key: Option<String>,
+
+    /// Log what invogen is doing to stderr, -vv for more detail
+    #[clap(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
 
+    /// Describe the commands and their arguments as JSON for tools that
+    /// wrap invogen
+    #[clap(long, hide = true)]
+    pub help_json: bool,
+
+    /// Without a command, an interactive menu starts in a terminal
     #[clap(subcommand)]
-    pub subcommand: Command,
+    pub subcommand: Option<Command>,
 }
 
 #[derive(Parser)]
@@ -29,12 +101,19 @@ pub enum Command {
     List {
         #[clap(subcommand)]
         listing: Listable,
+        /// Don't colour the output, also disabled by NO_COLOR or piping
+        #[clap(long, global = true)]
+        no_color: bool,
     },
 
     /// Add a new client or service
     Add {
         #[clap(subcommand)]
         property: Addable,
+        /// Record the changes at this time (RFC 3339), e.g. when entering
+        /// past invoices
+        #[clap(long)]
+        timestamp: Option<String>,
     },
 
     /// Show clients and invoices
@@ -57,13 +136,101 @@ pub enum Command {
     Invoice {
         /// key name to identify the client
         client: String,
+        /// Bill the items of the last invoice again for the following period
+        #[clap(long)]
+        repeat_last: bool,
+        /// Bill a deposit to be deducted from a later final invoice
+        #[clap(long, conflicts_with = "repeat_last")]
+        deposit: bool,
+        /// Allow periods until the end of next month, billing in advance
+        #[clap(long, conflicts_with = "repeat_last")]
+        advance: bool,
+        /// Bill every item for this period instead of prompting, e.g.
+        /// 2024-04, 2024-04-01..2024-04-15, last-month, or q1-2024
+        #[clap(long, conflicts_with = "repeat_last")]
+        period: Option<String>,
+        /// Enter the hours of hourly services week by week, listing them on
+        /// the invoice
+        #[clap(long, conflicts_with = "repeat_last")]
+        weekly: bool,
+        /// Bill in this currency, e.g. EUR, converting items in another at
+        /// a rate entered while invoicing
+        #[clap(long, conflicts_with = "repeat_last")]
+        currency: Option<String>,
+        /// Offer to show the invoice as text, LaTeX, or a posting before
+        /// confirming it
+        #[clap(long, conflicts_with = "repeat_last")]
+        preview: bool,
+        /// Require typing the client's key to confirm invoices totalling
+        /// more than this amount, e.g. 5000
+        #[clap(long, value_name = "amount")]
+        confirm_key: Option<String>,
+        /// Record the changes at this time (RFC 3339), e.g. when entering
+        /// past invoices
+        #[clap(long)]
+        timestamp: Option<String>,
     },
 
     /// Record an invoice as paid
     MarkPaid {
         /// key name to identify the client
         client: String,
-        /// Invoice number to show
+        /// Invoice to mark, e.g. 7, #7, or its display number
+        number: String,
+        /// Record the amount received in another currency
+        #[clap(long)]
+        other_currency: bool,
+        /// Record the changes at this time (RFC 3339), e.g. when entering
+        /// past invoices
+        #[clap(long)]
+        timestamp: Option<String>,
+    },
+
+    /// Record when an invoice was sent, payment terms start from then
+    MarkSent {
+        /// key name to identify the client
+        client: String,
+        /// Invoice to mark, e.g. 7, #7, or its display number
+        number: String,
+        /// Record the change at this time (RFC 3339)
+        #[clap(long)]
+        timestamp: Option<String>,
+    },
+
+    /// Record an invoice as uncollectable
+    WriteOff {
+        /// key name to identify the client
+        client: String,
+        /// Invoice number to write off, e.g. 7 or #7
+        #[clap(value_parser = parse_invoice_number)]
+        number: usize,
+    },
+
+    /// Cancel an unpaid invoice, its number is not reused
+    Void {
+        /// key name to identify the client
+        client: String,
+        /// Invoice number to void, e.g. 7 or #7
+        #[clap(value_parser = parse_invoice_number)]
+        number: usize,
+    },
+
+    /// Record that the client disputes an unpaid invoice, it's not shown as
+    /// overdue until resolved or paid
+    Dispute {
+        /// key name to identify the client
+        client: String,
+        /// Invoice number disputed, e.g. 7 or #7
+        #[clap(value_parser = parse_invoice_number)]
+        number: usize,
+    },
+
+    /// Record that a disputed invoice is to be paid after all
+    ResolveDispute {
+        /// key name to identify the client
+        client: String,
+        /// Invoice number of the dispute, e.g. 7 or #7
+        #[clap(value_parser = parse_invoice_number)]
         number: usize,
     },
 
@@ -72,6 +239,210 @@ pub enum Command {
         /// key name to identify the client
         client: String,
     },
+
+    /// Print the conventional path of an invoice's file, set with
+    /// `profile invoice-path`
+    Path {
+        /// key name to identify the client
+        client: String,
+        /// Invoice to locate, e.g. 7, #7, or its display number
+        number: String,
+    },
+
+    /// Remove a service template, clients keep the services it added
+    RemoveTemplate {
+        /// Name of the template
+        name: String,
+    },
+
+    /// Merge services named the same except for case or spacing
+    NormalizeServices {
+        /// key name to identify the client
+        client: String,
+    },
+
+    /// Export unpaid invoice due dates as an iCalendar file
+    Ical {
+        /// Path of the .ics file to write
+        #[clap(value_hint=ValueHint::FilePath)]
+        output: PathBuf,
+        /// Add a monthly reminder to invoice each active client
+        #[clap(long)]
+        reminders: bool,
+        /// Also add due dates of disputed invoices
+        #[clap(long)]
+        include_disputed: bool,
+    },
+
+    /// Export the clients with their services, taxes, and invoices
+    Export {
+        format: ExportFormat,
+        /// Write to a file instead of printing, required for sqlite
+        #[clap(long, value_hint=ValueHint::FilePath)]
+        output: Option<PathBuf>,
+    },
+
+    /// Show or set the sender profile shared by all clients
+    Profile {
+        #[clap(subcommand)]
+        property: Option<ProfileSetable>,
+    },
+
+    /// Find clients by key, name, or address
+    Find {
+        /// Text to search for, case and accents are ignored
+        query: String,
+        /// Print only the key of a single matching client
+        #[clap(long)]
+        key_only: bool,
+    },
+
+    /// Search the whole history, e.g. for an invoice item or an address
+    Grep {
+        /// Text to search for, case and accents are ignored
+        query: String,
+        /// Search the event lines of the history file instead, printing
+        /// their line numbers
+        #[clap(long)]
+        raw: bool,
+    },
+
+    /// List the events of the history with who added them
+    Log {
+        /// Only list events added with this origin, e.g. alice@laptop
+        #[clap(long)]
+        origin: Option<String>,
+    },
+
+    /// Estimate what hasn't been billed up to the end of last month
+    Unbilled {
+        /// Total the estimates in this currency, e.g. CAD, using the rates
+        /// in rates.toml beside the history file
+        #[clap(long)]
+        convert_to: Option<String>,
+    },
+
+    /// Total the invoiced revenue of every client by service category
+    Revenue {
+        /// Only invoices issued in this year
+        #[clap(long)]
+        year: Option<i32>,
+    },
+
+    /// Compare the hours billed each month with the services' targets
+    Hours {
+        /// key name to identify the client
+        client: String,
+        /// Year to report, this year's months to date by default
+        #[clap(long)]
+        year: Option<i32>,
+    },
+
+    /// Revenue earned but not invoiced yet in ledger format, at the
+    /// services' current rates
+    Accrual {
+        /// key name to identify the client
+        client: String,
+        /// Period the revenue was earned in, e.g. 2024-04,
+        /// 2024-04-01..2024-04-15, last-month, or q1-2024
+        period: String,
+        /// Override the profile's amount style, e.g. after,comma,code
+        #[clap(long)]
+        ledger_style: Option<String>,
+    },
+
+    /// List scheduled invoices that haven't been issued yet
+    Due {
+        /// Create the due invoices, only asking for hours without a default
+        #[clap(long)]
+        generate: bool,
+        /// Require typing the client's key to confirm invoices totalling
+        /// more than this amount, e.g. 5000
+        #[clap(long, value_name = "amount", requires = "generate")]
+        confirm_key: Option<String>,
+    },
+
+    /// Write a payment reminder for an overdue invoice
+    Remind {
+        /// key name to identify the client
+        client: String,
+        /// Invoice to chase, e.g. 7, #7, or its display number
+        number: String,
+        /// How firm the reminder is
+        #[clap(long, value_enum, default_value = "first")]
+        level: ReminderLevel,
+        #[clap(long, value_enum, default_value = "text")]
+        format: ReminderFormat,
+        /// Write to a file instead of printing
+        #[clap(long, value_hint=ValueHint::FilePath)]
+        output: Option<PathBuf>,
+    },
+
+    /// List the invoices of every client, e.g. to find the client of an
+    /// invoice number
+    Invoices {
+        /// Only list invoices with this number, e.g. 7, #7, or a display
+        /// number, failing when several clients have one
+        number: Option<String>,
+        /// Print a JSON array instead of a table
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Check an invoice against the client's rate and tax history
+    Audit {
+        /// key name to identify the client
+        client: String,
+        /// Invoice number to audit, e.g. 7 or #7
+        #[clap(value_parser = parse_invoice_number)]
+        number: usize,
+        /// Recompute weekly items with the proration used before weeks were
+        /// counted in working days
+        #[clap(long)]
+        legacy_weeks: bool,
+    },
+
+    /// Audit every invoice in the history
+    Verify {
+        /// Recompute weekly items with the proration used before weeks were
+        /// counted in working days
+        #[clap(long)]
+        legacy_weeks: bool,
+    },
+
+    /// Show how the quantity billed for a period is prorated
+    Explain {
+        /// First day billed, e.g. 2024-04-03
+        from: String,
+        /// Last day billed
+        until: String,
+        /// month, week, day, quarter, year, or a span of weeks like 2-weeks
+        unit: String,
+    },
+
+    /// Add invoices made before using invogen from a CSV file
+    ImportInvoices {
+        /// key name to identify the client
+        client: String,
+        /// CSV with number,date,service,from,until,quantity,amount,paid_date
+        #[clap(value_hint=ValueHint::FilePath)]
+        path: PathBuf,
+    },
+
+    /// Create an empty history file, then optionally set the sender and add
+    /// a first client
+    Init {
+        /// Defaults to --file, INVOGEN_FILE, or client.history
+        #[clap(value_hint=ValueHint::FilePath)]
+        path: Option<PathBuf>,
+    },
+
+    /// Print the settings from invogen.toml files in effect
+    Config,
+
+    /// Save the replayed history beside it, so commands only replay the
+    /// events added since
+    Snapshot,
 }
 
 #[derive(Parser)]
@@ -83,32 +454,94 @@ pub enum Addable {
         /// key name to identify the client
         client: String,
     },
+    /// Add prepaid hours of a service for a period
+    Retainer {
+        /// key name to identify the client
+        client: String,
+    },
+    /// Add a template of services and taxes for onboarding clients
+    Template,
+    /// Add the services and taxes of a template to a client
+    FromTemplate {
+        /// key name to identify the client
+        client: String,
+    },
 }
 
 #[derive(Parser)]
 pub enum Listable {
     /// List current client
-    Clients,
+    Clients {
+        /// Total outstanding amounts in this currency, e.g. CAD, using the
+        /// rates in rates.toml beside the history file
+        #[clap(long)]
+        convert_to: Option<String>,
+        /// Print only the client keys, one per line
+        #[clap(long, conflicts_with = "porcelain")]
+        key_only: bool,
+        /// Print tab separated key, name, outstanding, and billed until
+        /// fields without a header
+        #[clap(long)]
+        porcelain: bool,
+    },
     /// List invoices for a client
     Invoices {
         /// key name to identify the client
         client: String,
+        /// Print tab separated number, date, period, total, and status
+        /// fields without a header
+        #[clap(long)]
+        porcelain: bool,
     },
     /// List services billable to a client
     Services {
         /// key name to identify the client
         client: String,
+        /// Print tab separated service, rate, effective, default hours, and
+        /// category fields without a header
+        #[clap(long)]
+        porcelain: bool,
+        /// List every rate of each service with the change from the one
+        /// before
+        #[clap(long)]
+        history: bool,
+        /// Print the rate history as a JSON array
+        #[clap(long, requires = "history", conflicts_with = "porcelain")]
+        json: bool,
+    },
+    /// List tax changes for a client, one tax per line
+    Taxes {
+        /// key name to identify the client
+        client: String,
+        /// Only list the taxes in effect on a date (YYYY-MM-DD)
+        #[clap(long)]
+        as_of: Option<String>,
     },
+    /// List service templates for onboarding clients
+    Templates,
 }
 
 #[derive(Parser)]
 pub enum Showable {
     /// Show taxes applied to client invoices
     Taxes,
+    /// Show the rate history of each service
+    Rates,
+    /// Show retainer hours purchased, consumed, and remaining
+    Retainer,
+    /// Show payments received in date order with totals per year
+    Payments {
+        /// Only payments received in this year
+        #[clap(long)]
+        year: Option<i32>,
+    },
     /// Show an invoice or in specialized formats
     Invoice {
-        /// Invoice number to show
-        number: usize,
+        /// Invoice to show, e.g. 7, #7, or its display number
+        number: String,
+        /// List items as entered instead of grouped by service
+        #[clap(long)]
+        flat: bool,
         #[clap(subcommand)]
         view: Option<InvoiceView>,
     },
@@ -118,20 +551,234 @@ pub enum Showable {
 pub enum Setable {
     /// Set the billing rate for a client service
     Rate,
+    /// Set the hours pre-filled when invoicing an hourly service
+    DefaultHours,
     /// Set the tax rate(s) for a client
     Taxes,
-    /// Change a client's address
-    Address,
+    /// Change a client's billing address or another named address
+    Address {
+        /// Name of the address, e.g. registered, billing by default
+        #[clap(long)]
+        name: Option<String>,
+    },
+    /// Choose which named address is printed on invoices
+    InvoiceAddress {
+        /// Name of the address, billing to go back to the default
+        name: String,
+    },
     /// Change a client's name
     Name,
+    /// Set the accent colour, logo, and document class of invoices
+    Template,
+    /// Set the terms text at the bottom of a client's invoices
+    Footer,
+    /// Set whether new invoices round each tax or only the total
+    Rounding,
+    /// Set the account the client's payments are deposited to
+    PaymentAccount {
+        /// Ledger account, e.g. assets:stripe
+        account: String,
+    },
+    /// Set how many months old a rate can be before invoicing with it asks
+    /// for confirmation
+    StaleRateAge {
+        /// Age in months, 12 unless set
+        months: u32,
+    },
+    /// Set the language of a client's invoices
+    Language {
+        /// Language code: en, fr, or de
+        code: String,
+    },
+    /// Invoice a service every month on a day, the last day of shorter
+    /// months
+    Schedule {
+        /// Name of the service
+        service: String,
+        /// Day of the month, 1 to 31
+        #[clap(value_parser = clap::value_parser!(u8).range(1..=31))]
+        day: u8,
+    },
+    /// Set the date heading the client's invoice postings
+    PostingDate {
+        /// issue, period-end, or period-start
+        date: String,
+    },
+    /// Group a service's revenue under a category, e.g. development
+    Category {
+        /// Name of the service
+        service: String,
+        /// Category in revenue reports
+        category: String,
+    },
+    /// Set the hours of a service agreed to be billed every month
+    TargetHours {
+        /// Name of the service
+        service: String,
+        /// Hours a month, e.g. 40
+        hours: String,
+    },
+}
+
+#[derive(Parser)]
+pub enum ProfileSetable {
+    /// Set the business name and address invoices are sent from
+    Sender,
+    /// Set the default footer used for clients without one
+    Footer,
+    /// Set how amounts are written in postings
+    LedgerStyle {
+        /// Comma separated options, e.g. after,comma,grouped,code
+        style: String,
+    },
+    /// Set the account payments are deposited to unless a client has one
+    PaymentAccount {
+        /// Ledger account, e.g. assets:bank:chequing
+        account: String,
+    },
+    /// Set where invoice files go, used by `path` and `latex --output`
+    InvoicePath {
+        /// Path with {year}, {month}, {key}, {number}, or {name}, e.g.
+        /// invoices/{year}/{key}/{year}-{month}-{key}-{number}.pdf
+        pattern: String,
+    },
+    /// Set the length of a working day used to compare rates in other units
+    HoursPerDay {
+        /// Hours, e.g. 7.5
+        hours: String,
+    },
+    /// Set whether all clients share one sequence of invoice numbers,
+    /// invoices from before keep their numbers
+    GlobalNumbering {
+        /// true or false
+        #[clap(action = clap::ArgAction::Set)]
+        enabled: bool,
+    },
+    /// Set a tax registration number shown on invoices, e.g. GST
+    /// 123456782RT0001 or VAT DE136695976
+    Registration {
+        /// What the number is shown as, e.g. GST/HST or VAT
+        label: String,
+        /// Canadian business number or EU VAT number, leave out to remove
+        /// the registration
+        number: Option<String>,
+        /// Store a number that doesn't look valid
+        #[clap(long)]
+        force: bool,
+    },
 }
 
 #[derive(Parser)]
 pub enum InvoiceView {
     /// Invoice in ledger format
-    Posting,
+    Posting {
+        /// Override the profile's amount style, e.g. after,comma,code
+        #[clap(long)]
+        ledger_style: Option<String>,
+        /// Value the amounts in this currency, e.g. CAD, with `@@` costs
+        /// at the invoice date's rate from rates.toml
+        #[clap(long)]
+        value_in: Option<String>,
+        /// With --value-in, write a P price directive instead of costs
+        #[clap(long, requires = "value_in")]
+        price_directive: bool,
+        /// Split the revenue into a sub-account per service
+        #[clap(long)]
+        by_service: bool,
+        /// Head the transaction with the issue date, period-end, or
+        /// period-start instead of the client's setting
+        #[clap(long)]
+        date: Option<String>,
+        /// Follow with a transaction reversing the revenue `accrual` booked
+        /// for the invoice's period
+        #[clap(long)]
+        reverse_accrual: bool,
+    },
     /// Payment in ledger format
-    Payment,
+    Payment {
+        /// Override the profile's amount style, e.g. after,comma,code
+        #[clap(long)]
+        ledger_style: Option<String>,
+        /// With period-end or period-start, add that date of the invoice as
+        /// the secondary date, overriding the client's setting
+        #[clap(long)]
+        date: Option<String>,
+    },
+    /// Bad debt in ledger format for a written off invoice
+    WriteOff {
+        /// Override the profile's amount style, e.g. after,comma,code
+        #[clap(long)]
+        ledger_style: Option<String>,
+    },
     /// Latex format of the invoice
-    Latex,
+    Latex {
+        /// Write to a file instead of printing, by default at the invoice's
+        /// conventional path with a .tex extension, creating directories
+        #[clap(long, value_hint=ValueHint::FilePath)]
+        output: Option<Option<PathBuf>>,
+    },
+    /// UBL 2.1 XML e-invoice following Peppol BIS Billing 3.0
+    Ubl,
+    /// Line items, taxes, and totals as CSV
+    Csv,
+}
+
+/// Invoice numbers are displayed as `#7`, so accept them with the hash
+fn parse_invoice_number(arg: &str) -> Result<usize, String> {
+    let digits = arg.trim().trim_start_matches('#');
+    digits
+        .parse()
+        .map_err(|_| format!("'{}' is not an invoice number", arg))
+}
+
+/// How firmly a reminder asks for payment
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ReminderLevel {
+    First,
+    Second,
+    Final,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ReminderFormat {
+    /// Plain text for an email
+    Text,
+    /// LaTeX paragraphs for a letter
+    Tex,
+    Html,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+pub enum ExportFormat {
+    /// TOML with dates and amounts as strings
+    Toml,
+    /// SQLite database with amounts in cents, when built with the sqlite
+    /// feature
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invoice_number_with_or_without_hash() {
+        assert_eq!(parse_invoice_number("7"), Ok(7));
+        assert_eq!(parse_invoice_number("#7"), Ok(7));
+        assert_eq!(parse_invoice_number(" #12 "), Ok(12));
+        assert!(parse_invoice_number("#").is_err());
+        assert!(parse_invoice_number("seven").is_err());
+        assert!(parse_invoice_number("-1").is_err());
+    }
+
+    #[test]
+    fn invoice_number_argument() {
+        let opts = Opts::try_parse_from(["invogen", "write-off", "acme", "#7"])
+            .unwrap_or_else(|e| panic!("{}", e));
+        assert!(matches!(
+            opts.subcommand,
+            Some(Command::WriteOff { number: 7, .. })
+        ));
+    }
 }
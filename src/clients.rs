@@ -1,43 +1,115 @@
 use std::collections::BTreeMap;
 use std::fmt;
 use std::fs::{self, File};
-use std::io::{self, BufRead, BufReader, Seek, Write};
-use std::path::PathBuf;
+use std::io::{self, BufRead, BufReader, Read, Seek, Write};
+use std::path::{Path, PathBuf};
 
-use chrono::{DateTime, NaiveDate, Utc};
+use chrono::{DateTime, Local, Months, NaiveDate, Utc};
 use serde::ser::Error;
-use serde::{Deserialize, Serialize};
+use serde::ser::SerializeTupleStruct;
+use serde::{Deserialize, Serialize, Serializer};
 use thiserror::Error;
 
-use crate::billing::{Invoice, Rate, Service, TaxRate};
+use crate::billing::{
+    Currency, CurrencyMismatch, Dispute, Invoice, InvoiceKind, Money, Period,
+    PostingDate, Rate, Received, Retainer, RoundingPolicy, Service, TaxRate,
+    Void, WriteOff, DEFAULT_HOURS_PER_DAY, DEFAULT_STALE_RATE_MONTHS,
+};
 use crate::historical::Historical;
+use crate::labels::{Labels, ENGLISH};
+use crate::ledger_fmt::LedgerStyle;
+use crate::logging;
+use crate::paths;
+use crate::registration;
+use rust_decimal::Decimal;
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Client {
     pub key: String,
     pub name: String,
-    pub address: String,
+    addresses: Historical<String>,
+    /// Addresses besides the billing address, e.g. a registered office
+    #[serde(default)]
+    other_addresses: BTreeMap<String, Historical<String>>,
+    /// Name of the address printed on invoices from a date, billing until
+    /// another is chosen
+    #[serde(default = "Historical::new")]
+    invoice_address: Historical<String>,
     pub services: BTreeMap<String, Service>,
+    retainers: BTreeMap<String, Vec<Retainer>>,
     invoices: BTreeMap<usize, Invoice>,
     taxes: Historical<Vec<TaxRate>>,
+    pub template: TemplateOptions,
+    pub footer: Option<String>,
+    pub payment_account: Option<String>,
+    /// Rounding of new invoices' taxes
+    pub rounding: RoundingPolicy,
+    /// Language code of the client's invoices, English when unset
+    pub language: Option<String>,
+    /// Months after which invoicing with a rate asks for confirmation
+    pub stale_rate_months: Option<u32>,
+    /// Date heading the client's invoice postings, the issue date unless set
+    #[serde(default)]
+    pub posting_date: Option<PostingDate>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub struct TemplateOptions {
+    pub accent: Option<String>,
+    pub logo_path: Option<String>,
+    pub template: Option<String>,
 }
 
 impl Client {
+    /// The initial address also applies to anything dated before the
+    /// client was added
     pub fn new(key: &str, name: &str, address: &str) -> Self {
+        let mut addresses = Historical::new();
+        addresses.insert(&NaiveDate::MIN, &address.to_string());
         Self {
             key: key.to_string(),
             name: name.to_string(),
-            address: address.to_string(),
+            addresses,
+            other_addresses: BTreeMap::new(),
+            invoice_address: Historical::new(),
             services: BTreeMap::new(),
+            retainers: BTreeMap::new(),
             invoices: BTreeMap::new(),
             taxes: Historical::new(),
+            template: TemplateOptions::default(),
+            footer: None,
+            payment_account: None,
+            rounding: RoundingPolicy::default(),
+            language: None,
+            stale_rate_months: None,
+            posting_date: None,
         }
     }
 
-    pub fn update(&mut self, update: &Update) -> Result<(), ClientError> {
+    /// Applies an update made today, with warnings about anything unusual
+    /// in it
+    #[cfg(test)]
+    pub fn update(
+        &mut self,
+        update: &Update,
+    ) -> Result<Vec<Warning>, ClientError> {
+        self.update_made(update, Local::now().date_naive())
+    }
+
+    /// Applies an update made on a date, which address changes without an
+    /// effective date of their own take effect from
+    pub fn update_made(
+        &mut self,
+        update: &Update,
+        made: NaiveDate,
+    ) -> Result<Vec<Warning>, ClientError> {
         use InvoiceError::*;
+        let mut warnings = Vec::new();
         match update {
-            Update::Address(addr) => self.address = addr.clone(),
+            Update::Address(addr) => self.addresses.insert(&made, addr),
+            Update::EffectiveAddress(effective, addr) => {
+                self.addresses.insert(effective, addr)
+            }
             Update::Name(name) => self.name = name.clone(),
             Update::ServiceRate(name, effective, rate) => {
                 let service = self
@@ -46,34 +118,350 @@ impl Client {
                     .or_insert(Service::new(name.clone()));
                 service.rates.insert(effective, rate);
             }
+            Update::ServiceDefaultQuantity(name, quantity) => {
+                let service = self
+                    .services
+                    .get_mut(name)
+                    .ok_or(ClientError::NoService(name.clone()))?;
+                service.default_quantity = Some(*quantity);
+            }
+            Update::Schedule {
+                service,
+                day_of_month,
+            } => {
+                self.services
+                    .get_mut(service)
+                    .ok_or(ClientError::NoService(service.clone()))?
+                    .schedule = Some(*day_of_month);
+            }
+            Update::ServiceCategory(service, category) => {
+                self.services
+                    .get_mut(service)
+                    .ok_or(ClientError::NoService(service.clone()))?
+                    .category = Some(category.clone());
+            }
+            Update::ServiceTargetHours(service, hours) => {
+                self.services
+                    .get_mut(service)
+                    .ok_or(ClientError::NoService(service.clone()))?
+                    .target_hours = Some(*hours);
+            }
+            Update::RetainerPurchased(name, period, hours) => {
+                if !self.services.contains_key(name) {
+                    return Err(ClientError::NoService(name.clone()));
+                }
+                self.retainers
+                    .entry(name.clone())
+                    .or_default()
+                    .push(Retainer::new(period.clone(), *hours));
+            }
+            Update::RetainerConsumed(name, from, hours) => {
+                let retainer = self
+                    .retainers
+                    .get_mut(name)
+                    .and_then(|r| r.iter_mut().find(|r| r.period.from == *from))
+                    .ok_or(ClientError::NoRetainer(name.clone(), *from))?;
+                retainer.consumed += *hours;
+            }
             Update::Invoiced(invoice) => {
-                if invoice.number != self.next_invoice_num() {
+                let expected = self.next_invoice_num();
+                if invoice.number < expected {
+                    return Err(ClientError::Invoice(
+                        invoice.number,
+                        OutOfSequence(expected),
+                    ));
+                }
+                if let Err(mismatch) = invoice.calculate() {
                     return Err(ClientError::Invoice(
                         invoice.number,
-                        OutOfSequence(self.invoices.len()),
+                        MixedCurrencies(mismatch),
                     ));
                 }
+                if let InvoiceKind::Final { applies } = &invoice.kind {
+                    for (number, _) in applies {
+                        let deposit = self.invoice(number)?;
+                        let error = if deposit.kind != InvoiceKind::Deposit {
+                            Some(NotDeposit)
+                        } else if deposit.paid.is_none() {
+                            Some(NotPaid)
+                        } else {
+                            self.applied_to(*number).map(AlreadyApplied)
+                        };
+                        if let Some(error) = error {
+                            return Err(ClientError::Invoice(*number, error));
+                        }
+                    }
+                }
                 self.invoices.insert(invoice.number, invoice.clone());
             }
-            Update::Paid(num, when) => {
-                let invoice = self
-                    .invoices
-                    .get_mut(num)
-                    .ok_or(ClientError::Invoice(*num, NotFound))?;
+            Update::Paid(num, when, received) => {
+                let invoice = self.invoice(num)?;
+                if invoice.paid.is_some() {
+                    return Err(ClientError::Invoice(*num, AlreadyPaid));
+                }
+                if invoice.written_off.is_some() {
+                    return Err(ClientError::Invoice(*num, WrittenOff));
+                }
+                if invoice.voided.is_some() {
+                    return Err(ClientError::Invoice(*num, Voided));
+                }
+                if *when < invoice.date {
+                    return Err(ClientError::Invoice(
+                        *num,
+                        PaidBeforeIssued(*when, invoice.date),
+                    ));
+                }
+                if let Some(sent) = invoice.sent.filter(|sent| when < sent) {
+                    return Err(ClientError::Invoice(
+                        *num,
+                        PaidBeforeSent(*when, sent),
+                    ));
+                }
+                let late = Months::new(12 * LATE_PAYMENT_YEARS);
+                if invoice
+                    .date
+                    .checked_add_months(late)
+                    .is_some_and(|late| late < *when)
+                {
+                    warnings.push(Warning::LatePayment(*num, *when));
+                }
+                if let Some(invoice) = self.invoices.get_mut(num) {
+                    invoice.paid = Some(*when);
+                    invoice.received = received.clone();
+                    // Payment settles a dispute
+                    if let Some(dispute) = invoice
+                        .disputed
+                        .as_mut()
+                        .filter(|d| d.resolved.is_none())
+                    {
+                        dispute.resolved = Some(*when);
+                    }
+                }
+            }
+            Update::WrittenOff(num, date, reason) => {
+                let invoice = self.invoice(num)?;
+                if invoice.paid.is_some() {
+                    return Err(ClientError::Invoice(*num, AlreadyPaid));
+                }
+                if invoice.written_off.is_some() {
+                    return Err(ClientError::Invoice(*num, WrittenOff));
+                }
+                if invoice.voided.is_some() {
+                    return Err(ClientError::Invoice(*num, Voided));
+                }
+                if let Some(invoice) = self.invoices.get_mut(num) {
+                    invoice.written_off = Some(WriteOff {
+                        date: *date,
+                        reason: reason.clone(),
+                    });
+                }
+            }
+            Update::Voided(num, date, reason) => {
+                let invoice = self.invoice(num)?;
+                if invoice.paid.is_some() {
+                    return Err(ClientError::Invoice(*num, PaidNotVoidable));
+                }
+                if invoice.written_off.is_some() {
+                    return Err(ClientError::Invoice(*num, WrittenOff));
+                }
+                if invoice.voided.is_some() {
+                    return Err(ClientError::Invoice(*num, Voided));
+                }
+                if let Some(invoice) = self.invoices.get_mut(num) {
+                    invoice.voided = Some(Void {
+                        date: *date,
+                        reason: reason.clone(),
+                    });
+                }
+            }
+            Update::Disputed(num, date, reason) => {
+                let invoice = self.invoice(num)?;
                 if invoice.paid.is_some() {
                     return Err(ClientError::Invoice(*num, AlreadyPaid));
                 }
-                invoice.paid = Some(*when)
+                if invoice.written_off.is_some() {
+                    return Err(ClientError::Invoice(*num, WrittenOff));
+                }
+                if invoice.voided.is_some() {
+                    return Err(ClientError::Invoice(*num, Voided));
+                }
+                if let Some(dispute) =
+                    invoice.disputed.as_ref().filter(|d| d.resolved.is_none())
+                {
+                    return Err(ClientError::Invoice(
+                        *num,
+                        AlreadyDisputed(dispute.date),
+                    ));
+                }
+                if let Some(invoice) = self.invoices.get_mut(num) {
+                    invoice.disputed = Some(Dispute {
+                        date: *date,
+                        reason: reason.clone(),
+                        resolved: None,
+                    });
+                }
+            }
+            Update::DisputeResolved(num, date) => {
+                let invoice = self.invoice(num)?;
+                if !invoice.is_disputed() {
+                    return Err(ClientError::Invoice(*num, NotDisputed));
+                }
+                if let Some(dispute) =
+                    self.invoices.get_mut(num).and_then(|i| i.disputed.as_mut())
+                {
+                    dispute.resolved = Some(*date);
+                }
             }
             Update::Taxes(effective, taxes) => {
                 self.taxes.insert(effective, taxes);
             }
+            Update::TemplateOptions {
+                accent,
+                logo_path,
+                template,
+            } => {
+                self.template = TemplateOptions {
+                    accent: accent.clone(),
+                    logo_path: logo_path.clone(),
+                    template: template.clone(),
+                }
+            }
+            Update::Footer(footer) => self.footer = Some(footer.clone()),
+            Update::PaymentAccount(account) => {
+                self.payment_account = Some(account.clone())
+            }
+            Update::Rounding(policy) => self.rounding = *policy,
+            Update::Language(code) => self.language = Some(code.clone()),
+            Update::StaleRateAge(months) => {
+                self.stale_rate_months = Some(*months)
+            }
+            Update::PostingDate(date) => self.posting_date = Some(*date),
+            Update::Sent(num, when) => {
+                let invoice = self.invoice(num)?;
+                if let Some(sent) = invoice.sent {
+                    return Err(ClientError::Invoice(*num, AlreadySent(sent)));
+                }
+                if invoice.voided.is_some() {
+                    return Err(ClientError::Invoice(*num, Voided));
+                }
+                if let Some(invoice) = self.invoices.get_mut(num) {
+                    invoice.sent = Some(*when);
+                }
+            }
+            Update::NamedAddress(name, address) if name == BILLING_ADDRESS => {
+                self.addresses.insert(&made, address)
+            }
+            Update::NamedAddress(name, address) => self
+                .other_addresses
+                .entry(name.clone())
+                .or_insert_with(Historical::new)
+                .insert(&made, address),
+            Update::InvoiceAddress(name) => {
+                if self.named_address_as_of(name, made).is_none() {
+                    return Err(ClientError::NoAddress(name.clone()));
+                }
+                self.invoice_address.insert(&made, name);
+            }
+            Update::ServicesMerged(from, into) => {
+                if !self.services.contains_key(into) {
+                    return Err(ClientError::NoService(into.clone()));
+                }
+                let merged = self
+                    .services
+                    .remove(from)
+                    .ok_or(ClientError::NoService(from.clone()))?;
+                let service = self.services.get_mut(into).unwrap();
+                for (effective, rate) in merged.rates.iter() {
+                    if !service.rates.effective_dates().any(|d| d == effective)
+                    {
+                        service.rates.insert(effective, rate);
+                    }
+                }
+                service.default_quantity =
+                    service.default_quantity.or(merged.default_quantity);
+                service.schedule = service.schedule.or(merged.schedule);
+                service.category = service.category.take().or(merged.category);
+                service.target_hours =
+                    service.target_hours.or(merged.target_hours);
+                if let Some(retainers) = self.retainers.remove(from) {
+                    self.retainers
+                        .entry(into.clone())
+                        .or_default()
+                        .extend(retainers);
+                }
+            }
         };
-        Ok(())
+        Ok(warnings)
+    }
+
+    pub fn current_address(&self) -> &str {
+        self.address_as_of(Local::now().date_naive())
+    }
+
+    /// The current address of the name, the billing address by default
+    pub fn address_named(&self, name: &str) -> Option<&str> {
+        self.named_address_as_of(name, Local::now().date_naive())
+    }
+
+    /// The address of the name on a date
+    pub fn named_address_as_of(
+        &self,
+        name: &str,
+        date: NaiveDate,
+    ) -> Option<&str> {
+        match name {
+            BILLING_ADDRESS => Some(self.address_as_of(date)),
+            _ => self
+                .other_addresses
+                .get(name)?
+                .as_of(date)
+                .map(String::as_str),
+        }
+    }
+
+    /// Names and current addresses, billing first
+    pub fn addresses(&self) -> impl Iterator<Item = (&str, &str)> {
+        let other = self.other_addresses.keys().filter_map(|name| {
+            Some((name.as_str(), self.address_named(name)?))
+        });
+        [(BILLING_ADDRESS, self.current_address())]
+            .into_iter()
+            .chain(other)
+    }
+
+    /// Name of the address printed on invoices from today, when it isn't
+    /// the billing address
+    pub fn invoice_address_name(&self) -> Option<&str> {
+        self.invoice_address
+            .current()
+            .map(String::as_str)
+            .filter(|name| *name != BILLING_ADDRESS)
+    }
+
+    /// Address printed on invoices of a date, the billing address as of the
+    /// date unless another was chosen by then
+    pub fn invoice_address(&self, date: NaiveDate) -> &str {
+        self.invoice_address
+            .as_of(date)
+            .and_then(|name| self.named_address_as_of(name, date))
+            .unwrap_or_else(|| self.address_as_of(date))
+    }
+
+    /// Address on a date, e.g. to reprint an invoice as it was issued
+    pub fn address_as_of(&self, date: NaiveDate) -> &str {
+        self.addresses
+            .as_of(date)
+            .or_else(|| self.addresses.first().map(|(_, address)| address))
+            .map_or("", String::as_str)
     }
 
+    /// One more than the highest number so far, numbers missing from a
+    /// hand-edited history are never reused
     pub fn next_invoice_num(&self) -> usize {
-        self.invoices.len() + 1
+        self.invoices
+            .keys()
+            .next_back()
+            .map_or(1, |number| number + 1)
     }
 
     pub fn taxes_as_of(&self, date: NaiveDate) -> Vec<TaxRate> {
@@ -85,6 +473,10 @@ impl Client {
             .collect()
     }
 
+    pub fn tax_history(&self) -> &Historical<Vec<TaxRate>> {
+        &self.taxes
+    }
+
     pub fn current_taxes(&self) -> Vec<TaxRate> {
         self.taxes
             .current()
@@ -94,17 +486,42 @@ impl Client {
             .collect()
     }
 
+    /// End of the last invoice's period, voided invoices left the period
+    /// unbilled
     pub fn billed_until(&self) -> Option<NaiveDate> {
         self.invoices
             .values()
-            .last()
+            .rev()
+            .find(|i| i.voided.is_none())
             .map(|i| i.overall_period().until)
     }
 
     pub fn invoice(&self, num: &usize) -> Result<&Invoice, ClientError> {
-        self.invoices
-            .get(num)
-            .ok_or(ClientError::Invoice(*num, InvoiceError::NotFound))
+        self.invoices.get(num).ok_or_else(|| {
+            let known = self.invoices.keys().copied().collect();
+            ClientError::Invoice(*num, InvoiceError::NotFound(known))
+        })
+    }
+
+    /// An invoice by its display number, or its sequence number with or
+    /// without a leading #
+    pub fn find_invoice(&self, id: &str) -> Result<&Invoice, ClientError> {
+        let id = id.trim();
+        if let Some(invoice) =
+            self.invoices().find(|i| i.display_number() == id)
+        {
+            return Ok(invoice);
+        }
+        match id.trim_start_matches('#').parse() {
+            Ok(number) => self.invoice(&number),
+            Err(_) => {
+                let known = self.invoices.keys().copied().collect();
+                Err(ClientError::InvoiceId(
+                    id.to_string(),
+                    InvoiceError::NotFound(known),
+                ))
+            }
+        }
     }
 
     pub fn service_names(&self) -> Vec<&str> {
@@ -114,77 +531,594 @@ impl Client {
             .collect::<Vec<&str>>()
     }
 
+    /// An existing service named the same except for case or spacing
+    pub fn similar_service(&self, name: &str) -> Option<&str> {
+        let wanted = service_match_key(name);
+        self.service_names().into_iter().find(|existing| {
+            *existing != name && service_match_key(existing) == wanted
+        })
+    }
+
+    /// Groups of services named the same except for case or spacing
+    pub fn duplicate_services(&self) -> Vec<Vec<&str>> {
+        let mut groups: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+        for name in self.service_names() {
+            groups
+                .entry(service_match_key(name))
+                .or_default()
+                .push(name);
+        }
+        groups
+            .into_values()
+            .filter(|names| names.len() > 1)
+            .collect()
+    }
+
     pub fn service(&self, name: String) -> Option<&Service> {
         self.services.get(&name)
     }
 
+    /// The service's rate in effect on a date, with an error telling apart
+    /// a service without rates from a date before its first rate
+    pub fn rate_as_of(
+        &self,
+        name: &str,
+        date: NaiveDate,
+    ) -> Result<&Rate, ClientError> {
+        let rates = &self
+            .services
+            .get(name)
+            .ok_or_else(|| ClientError::NoService(name.to_string()))?
+            .rates;
+        rates.as_of(date).ok_or_else(|| match rates.first() {
+            Some((earliest, _)) => ClientError::BeforeFirstRate {
+                service: name.to_string(),
+                earliest: *earliest,
+                from: date,
+            },
+            None => ClientError::NoRates(name.to_string()),
+        })
+    }
+
+    /// Retainers of each service, in the order they were purchased
+    pub fn retainers(&self) -> impl Iterator<Item = (&str, &Retainer)> {
+        self.retainers.iter().flat_map(|(name, retainers)| {
+            retainers.iter().map(move |r| (name.as_str(), r))
+        })
+    }
+
+    /// The retainer of a service covering a date, if it has one
+    pub fn retainer_for(
+        &self,
+        name: &str,
+        date: NaiveDate,
+    ) -> Option<&Retainer> {
+        self.retainers
+            .get(name)
+            .and_then(|r| r.iter().find(|r| r.covers(date)))
+    }
+
+    /// The final invoice a deposit was deducted from
+    pub fn applied_to(&self, deposit: usize) -> Option<usize> {
+        self.invoices
+            .values()
+            .find(|i| match &i.kind {
+                InvoiceKind::Final { applies } => {
+                    applies.iter().any(|(number, _)| *number == deposit)
+                }
+                _ => false,
+            })
+            .map(|i| i.number)
+    }
+
+    /// Paid deposits not yet deducted from a final invoice
+    pub fn unapplied_deposits(&self) -> impl Iterator<Item = &Invoice> {
+        self.invoices.values().filter(|i| {
+            i.kind == InvoiceKind::Deposit
+                && i.paid.is_some()
+                && self.applied_to(i.number).is_none()
+        })
+    }
+
     pub fn invoices(&self) -> impl Iterator<Item = &Invoice> {
         self.invoices.values()
     }
 
     pub fn unpaid_invoices(&self) -> impl Iterator<Item = &usize> {
+        self.invoices().filter(|i| i.is_open()).map(|i| &i.number)
+    }
+
+    pub fn written_off_invoices(&self) -> impl Iterator<Item = &Invoice> {
+        self.invoices().filter(|i| i.written_off.is_some())
+    }
+
+    pub fn voided_invoices(&self) -> impl Iterator<Item = &Invoice> {
+        self.invoices().filter(|i| i.voided.is_some())
+    }
+
+    /// Latest end of the periods a service was invoiced for
+    pub fn service_billed_until(&self, name: &str) -> Option<NaiveDate> {
         self.invoices()
-            .filter(|i| i.paid.is_none())
-            .map(|i| &i.number)
+            .filter(|i| i.voided.is_none())
+            .flat_map(|i| i.items.iter())
+            .filter(|item| item.name == name)
+            .map(|item| item.period.until)
+            .max()
+    }
+
+    /// Total of unpaid invoices per currency, in order of first appearance
+    pub fn outstanding(
+        &self,
+    ) -> Result<Vec<(Currency, Decimal)>, CurrencyMismatch> {
+        let mut totals: Vec<(Currency, Decimal)> = Vec::new();
+        for invoice in self.invoices().filter(|i| i.is_open()) {
+            let total = invoice.calculate()?.total;
+            match totals.iter_mut().find(|(c, _)| *c == total.currency()) {
+                Some((_, sum)) => *sum += total.amount(),
+                None => totals.push((total.currency(), total.amount())),
+            }
+        }
+        Ok(totals)
+    }
+
+    /// Payments received for the client's invoices, oldest first. Payments
+    /// in another currency are recorded at the amount received.
+    pub fn payments(&self) -> Result<Vec<PaymentRecord>, CurrencyMismatch> {
+        let mut payments: Vec<PaymentRecord> = self
+            .invoices()
+            .filter_map(|invoice| {
+                let date = invoice.paid?;
+                let amount = match &invoice.received {
+                    Some(received) => Ok(received.amount),
+                    None => invoice.calculate().map(|total| total.total),
+                };
+                Some(amount.map(|amount| PaymentRecord {
+                    number: invoice.number,
+                    date,
+                    amount,
+                }))
+            })
+            .collect::<Result<_, _>>()?;
+        payments.sort_by_key(|payment| (payment.date, payment.number));
+        Ok(payments)
+    }
+
+    /// Footer text for invoices, an explicitly empty footer suppresses the
+    /// profile's default
+    pub fn footer<'a>(&'a self, profile: &'a Profile) -> Option<&'a str> {
+        match &self.footer {
+            Some(footer) => Some(footer.as_str()),
+            None => profile.footer.as_deref(),
+        }
+        .filter(|footer| !footer.is_empty())
+    }
+
+    /// Account the client's payments are deposited to
+    /// Labels of the client's language, codes are checked when set so an
+    /// unknown one falls back to English
+    pub fn labels(&self) -> &'static Labels {
+        self.language
+            .as_deref()
+            .and_then(Labels::for_language)
+            .unwrap_or(&ENGLISH)
+    }
+
+    pub fn stale_rate_months(&self) -> u32 {
+        self.stale_rate_months.unwrap_or(DEFAULT_STALE_RATE_MONTHS)
+    }
+
+    pub fn posting_date(&self) -> PostingDate {
+        self.posting_date.unwrap_or_default()
+    }
+
+    pub fn payment_account<'a>(&'a self, profile: &'a Profile) -> &'a str {
+        self.payment_account
+            .as_deref()
+            .or(profile.payment_account.as_deref())
+            .unwrap_or(DEFAULT_PAYMENT_ACCOUNT)
+    }
+}
+
+/// A payment received for an invoice
+#[derive(Debug, PartialEq, Clone)]
+pub struct PaymentRecord {
+    pub number: usize,
+    pub date: NaiveDate,
+    pub amount: Money,
+}
+
+/// Sender details shared by all clients, stored under [`PROFILE_KEY`]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub struct Profile {
+    pub footer: Option<String>,
+    pub ledger_style: LedgerStyle,
+    pub payment_account: Option<String>,
+    /// Name and address of the business sending invoices
+    pub sender: Option<(String, String)>,
+    /// Services and taxes for onboarding clients, by template name
+    pub service_templates: BTreeMap<String, ServiceTemplate>,
+    /// Pattern of invoice file paths, see [`crate::paths::invoice_path`]
+    pub invoice_path: Option<String>,
+    /// Length of a working day when showing equivalent rates
+    pub hours_per_day: Option<Decimal>,
+    /// First number of the invoice sequence shared by all clients, set
+    /// while clients aren't numbered separately
+    #[serde(default)]
+    pub global_numbering: Option<usize>,
+    /// Tax registrations shown on invoices, label and number, e.g. GST and
+    /// 123456782RT0001
+    #[serde(default)]
+    pub registrations: Vec<(String, String)>,
+}
+
+/// Services with their rates and default taxes added to a client together
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub struct ServiceTemplate {
+    pub services: Vec<(String, Rate)>,
+    pub taxes: Vec<TaxRate>,
+}
+
+impl ServiceTemplate {
+    /// Updates setting a client's services and, when there are any, taxes
+    /// from a date
+    pub fn updates(&self, effective: NaiveDate) -> Vec<Update> {
+        let mut updates: Vec<Update> = self
+            .services
+            .iter()
+            .map(|(name, rate)| {
+                Update::ServiceRate(name.clone(), effective, rate.clone())
+            })
+            .collect();
+        if !self.taxes.is_empty() {
+            updates.push(Update::Taxes(effective, self.taxes.clone()));
+        }
+        updates
+    }
+}
+
+impl fmt::Display for ServiceTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, rate) in self.services.iter() {
+            writeln!(f, "  {}: {}", name, rate)?;
+        }
+        let taxes: Vec<String> =
+            self.taxes.iter().map(|t| t.to_string()).collect();
+        match taxes.is_empty() {
+            true => writeln!(f, "  Taxes: (none)"),
+            false => writeln!(f, "  Taxes: {}", taxes.join(", ")),
+        }
+    }
+}
+
+/// Payments are deposited here unless the profile or client says otherwise
+pub const DEFAULT_PAYMENT_ACCOUNT: &str = "assets:bank";
+
+impl Profile {
+    pub fn hours_per_day(&self) -> Decimal {
+        self.hours_per_day
+            .unwrap_or(Decimal::from(DEFAULT_HOURS_PER_DAY))
+    }
+
+    pub fn invoice_path_pattern(&self) -> &str {
+        self.invoice_path
+            .as_deref()
+            .unwrap_or(paths::DEFAULT_PATTERN)
+    }
+
+    pub fn update(&mut self, update: &ProfileUpdate) {
+        match update {
+            ProfileUpdate::Footer(footer) => {
+                self.footer = Some(footer.clone()).filter(|f| !f.is_empty())
+            }
+            ProfileUpdate::LedgerStyle(style) => self.ledger_style = *style,
+            ProfileUpdate::PaymentAccount(account) => {
+                self.payment_account = Some(account.clone())
+            }
+            ProfileUpdate::Sender(name, address) => {
+                self.sender = Some((name.clone(), address.clone()))
+            }
+            ProfileUpdate::ServiceTemplate(name, template) => {
+                self.service_templates
+                    .insert(name.clone(), template.clone());
+            }
+            ProfileUpdate::RemoveServiceTemplate(name) => {
+                self.service_templates.remove(name);
+            }
+            ProfileUpdate::InvoicePath(pattern) => {
+                self.invoice_path = Some(pattern.clone())
+            }
+            ProfileUpdate::HoursPerDay(hours) => {
+                self.hours_per_day = Some(*hours)
+            }
+            ProfileUpdate::GlobalNumbering(on) => {
+                self.global_numbering =
+                    on.then(|| self.global_numbering.unwrap_or(1))
+            }
+            ProfileUpdate::Registration(label, number) => {
+                match self
+                    .registrations
+                    .iter_mut()
+                    .find(|(other, _)| other == label)
+                {
+                    Some(existing) => existing.1 = number.clone(),
+                    None => {
+                        self.registrations.push((label.clone(), number.clone()))
+                    }
+                }
+            }
+            ProfileUpdate::RemoveRegistration(label) => {
+                self.registrations.retain(|(other, _)| other != label);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Profile:\n")?;
+        match &self.sender {
+            Some((name, address)) => {
+                writeln!(f, "Sender:\n{}\n{}", name, address)?
+            }
+            None => writeln!(f, "Sender: (none)")?,
+        }
+        for (label, number) in self.registrations.iter() {
+            writeln!(f, "{}: {}", label, registration::display(number))?;
+        }
+        writeln!(f, "Ledger style: {}", self.ledger_style)?;
+        writeln!(f, "Invoice path: {}", self.invoice_path_pattern())?;
+        writeln!(f, "Hours per day: {}", self.hours_per_day())?;
+        match self.global_numbering {
+            Some(from) => {
+                writeln!(f, "Invoice numbers: shared from #{}", from)?
+            }
+            None => writeln!(f, "Invoice numbers: per client")?,
+        }
+        writeln!(
+            f,
+            "Payment account: {}",
+            self.payment_account
+                .as_deref()
+                .unwrap_or(DEFAULT_PAYMENT_ACCOUNT)
+        )?;
+        match &self.footer {
+            Some(footer) => writeln!(f, "Footer:\n{}", footer),
+            None => writeln!(f, "Footer: (none)"),
+        }
     }
 }
 
 impl fmt::Display for Client {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}:\n\n{}\n{}\n", self.key, self.name, self.address)
+        write!(
+            f,
+            "{}:\n\n{}\n{}\n",
+            self.key,
+            self.name,
+            self.current_address()
+        )?;
+        for (name, address) in self.addresses().skip(1) {
+            write!(f, "\n{} address:\n{}\n", name, address)?;
+        }
+        if let Some(name) = self.invoice_address_name() {
+            write!(f, "\nInvoices show the {} address\n", name)?;
+        }
+        Ok(())
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
-pub struct Event(pub String, pub DateTime<Utc>, pub Change);
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+pub struct Event(
+    pub String,
+    pub DateTime<Utc>,
+    pub Change,
+    /// Set when the timestamp was given explicitly, e.g. for imported data,
+    /// so it can be earlier than the events before it
+    #[serde(default)]
+    pub bool,
+    /// Idempotency key of the command that added this event, so running it
+    /// again with the same key doesn't add it twice
+    #[serde(default)]
+    pub Option<String>,
+    /// Who or which machine added the event, e.g. alice@laptop, when a
+    /// history is shared
+    #[serde(default)]
+    pub Option<String>,
+);
 
 impl Event {
     pub fn new(key: &str, change: Change) -> Self {
-        Self(key.to_string(), Utc::now(), change)
+        Self::new_with_origin(key, change, None)
+    }
+    pub fn new_with_origin(
+        key: &str,
+        change: Change,
+        origin: Option<String>,
+    ) -> Self {
+        Self(key.to_string(), Utc::now(), change, false, None, origin)
+    }
+    pub fn new_at(key: &str, timestamp: DateTime<Utc>, change: Change) -> Self {
+        Self(key.to_string(), timestamp, change, true, None, None)
     }
     pub fn new_update(key: &str, update: Update) -> Self {
-        Self(key.to_string(), Utc::now(), Change::Updated(update))
+        Self::new_update_with_origin(key, update, None)
+    }
+    pub fn new_update_with_origin(
+        key: &str,
+        update: Update,
+        origin: Option<String>,
+    ) -> Self {
+        Self::new_with_origin(key, Change::Updated(update), origin)
+    }
+    pub fn new_profile(update: ProfileUpdate) -> Self {
+        Self::new(PROFILE_KEY, Change::Profile(update))
+    }
+
+    pub fn origin(&self) -> Option<&str> {
+        self.5.as_deref()
+    }
+
+    pub fn is_backdated(&self) -> bool {
+        self.3
+    }
+}
+
+/// Unset trailing fields are left out, but earlier fields are written
+/// whenever there is one after them to keep it in its position
+impl Serialize for Event {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let len = match (self.3, &self.4, &self.5) {
+            (_, _, Some(_)) => 6,
+            (_, Some(_), None) => 5,
+            (true, None, None) => 4,
+            (false, None, None) => 3,
+        };
+        let mut tuple = serializer.serialize_tuple_struct("Event", len)?;
+        tuple.serialize_field(&self.0)?;
+        tuple.serialize_field(&self.1)?;
+        tuple.serialize_field(&self.2)?;
+        if len > 3 {
+            tuple.serialize_field(&self.3)?;
+        }
+        if len > 4 {
+            tuple.serialize_field(&self.4)?;
+        }
+        if len > 5 {
+            tuple.serialize_field(&self.5)?;
+        }
+        tuple.end()
     }
 }
 
+/// Reserved event key for changes that are not specific to a client
+pub const PROFILE_KEY: &str = "@profile";
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum Change {
     Added { name: String, address: String },
     Updated(Update),
     Removed,
+    Profile(ProfileUpdate),
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum Update {
+    /// Address change from older histories, effective on the event's date
     Address(String),
+    EffectiveAddress(NaiveDate, String),
     Name(String),
     ServiceRate(String, NaiveDate, Rate),
+    ServiceDefaultQuantity(String, Decimal),
+    /// Hours of a service prepaid for a period
+    RetainerPurchased(String, Period, Decimal),
+    /// Hours drawn from the service's retainer starting on the date
+    RetainerConsumed(String, NaiveDate, Decimal),
     Invoiced(Invoice),
-    Paid(usize, NaiveDate),
+    /// Paid, with the amount when it was received in another currency
+    Paid(
+        usize,
+        NaiveDate,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        Option<Received>,
+    ),
+    /// Uncollectable invoice, with the reason if one was given
+    WrittenOff(usize, NaiveDate, Option<String>),
     Taxes(NaiveDate, Vec<TaxRate>),
+    TemplateOptions {
+        accent: Option<String>,
+        logo_path: Option<String>,
+        template: Option<String>,
+    },
+    Footer(String),
+    /// Account payments from the client are deposited to
+    PaymentAccount(String),
+    Rounding(RoundingPolicy),
+    /// Language code of invoices, e.g. fr
+    Language(String),
+    /// Months after which a rate is considered out of date
+    StaleRateAge(u32),
+    /// Cancelled before payment, with the reason if one was given
+    Voided(usize, NaiveDate, Option<String>),
+    /// Rates and retainers of the first service moved to the second, the
+    /// second's rate is kept where both have one on the same date
+    ServicesMerged(String, String),
+    /// Sent to the client, payment terms start from this date
+    Sent(usize, NaiveDate),
+    /// Address under a name, the billing name changes the billing address
+    /// from today
+    NamedAddress(String, String),
+    /// Name of the address printed on invoices
+    InvoiceAddress(String),
+    /// Disputed by the client, with the reason if one was given
+    Disputed(usize, NaiveDate, Option<String>),
+    DisputeResolved(usize, NaiveDate),
+    /// Service invoiced monthly on the day, the last day of shorter months
+    Schedule {
+        service: String,
+        day_of_month: u8,
+    },
+    /// Date heading the client's invoice postings
+    PostingDate(PostingDate),
+    /// Category the service's revenue is reported under
+    ServiceCategory(String, String),
+    /// Hours of the service agreed to be billed every month
+    ServiceTargetHours(String, Decimal),
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub enum ProfileUpdate {
+    Footer(String),
+    LedgerStyle(LedgerStyle),
+    PaymentAccount(String),
+    /// Name and address of the sender
+    Sender(String, String),
+    /// Services and taxes added to clients together, replacing any
+    /// template of the same name
+    ServiceTemplate(String, ServiceTemplate),
+    RemoveServiceTemplate(String),
+    /// Pattern of invoice file paths with placeholders like {year}
+    InvoicePath(String),
+    /// Length of a working day when showing equivalent rates
+    HoursPerDay(Decimal),
+    /// Whether all clients share one sequence of invoice numbers
+    GlobalNumbering(bool),
+    /// Tax registration number under a label, replacing any with the same
+    /// label
+    Registration(String, String),
+    RemoveRegistration(String),
 }
 
-pub struct Clients(BTreeMap<String, Client>);
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Clients {
+    clients: BTreeMap<String, Client>,
+    pub profile: Profile,
+}
 
 impl Clients {
     pub fn new() -> Self {
-        Self(BTreeMap::new())
+        Self {
+            clients: BTreeMap::new(),
+            profile: Profile::default(),
+        }
     }
     pub fn add(
         &mut self,
         key: &str,
         client: Client,
     ) -> Result<(), ClientError> {
-        self.0.insert(key.to_owned(), client);
+        self.clients.insert(key.to_owned(), client);
         Ok(())
     }
     pub fn get(&self, key: &String) -> Result<&Client, ClientError> {
-        self.0
+        self.clients
             .get(key)
             .ok_or(ClientError::NotFound(key.to_string()))
     }
     pub fn remove(&mut self, key: &String) -> Result<(), ClientError> {
-        self.0
+        self.clients
             .remove(key)
             .map(|_| ())
             .ok_or(ClientError::NotFound(key.to_string()))
@@ -193,55 +1127,155 @@ impl Clients {
         &mut self,
         key: &String,
         update: &Update,
-    ) -> Result<(), ClientError> {
+    ) -> Result<Vec<Warning>, ClientError> {
+        self.update_made(key, update, Local::now().date_naive())
+    }
+
+    /// Applies an update to a client as made on a date, see
+    /// `Client::update_made`
+    pub fn update_made(
+        &mut self,
+        key: &String,
+        update: &Update,
+        made: NaiveDate,
+    ) -> Result<Vec<Warning>, ClientError> {
+        if let Update::Invoiced(invoice) = update {
+            if self.profile.global_numbering.is_some() {
+                let expected = self.max_invoice_number().map_or(1, |n| n + 1);
+                if invoice.number < expected {
+                    return Err(ClientError::Invoice(
+                        invoice.number,
+                        InvoiceError::OutOfSequence(expected),
+                    ));
+                }
+            }
+        }
         let client = self
-            .0
+            .clients
             .get_mut(key)
             .ok_or(ClientError::NotFound(key.to_string()))?;
-        client.update(update)?;
-        Ok(())
+        client.update_made(update, made)
     }
     pub fn iter(&self) -> impl Iterator<Item = &Client> {
-        self.0.values()
+        self.clients.values()
     }
 
-    pub fn from_events(events: &[Event]) -> Result<Self, ClientError> {
-        let mut clients = Self::new();
-        for event in events.iter() {
-            clients.apply_event(event)?;
+    /// Highest invoice number of any client
+    pub fn max_invoice_number(&self) -> Option<usize> {
+        self.iter()
+            .filter_map(|client| client.invoices().map(|i| i.number).max())
+            .max()
+    }
+
+    /// Number of a client's next invoice, following every client's
+    /// invoices when they share one sequence
+    pub fn next_invoice_num(&self, client: &Client) -> usize {
+        match self.profile.global_numbering {
+            Some(_) => self.max_invoice_number().map_or(1, |n| n + 1),
+            None => client.next_invoice_num(),
         }
+    }
+
+    /// Clients replayed from events as they're read, so a history doesn't
+    /// have to be held in memory to load it
+    pub fn from_events(
+        events: impl IntoIterator<Item = Result<Event, EventError>>,
+    ) -> Result<Self, ClientError> {
+        let mut clients = Self::new();
+        clients.apply_events(events)?;
         Ok(clients)
     }
 
-    pub fn apply_event(&mut self, event: &Event) -> Result<(), ClientError> {
-        let Event(ref key, _, change) = event;
-        match change {
-            Change::Added { name, address } => {
-                self.add(key, Client::new(key, name, address))
-            }
-            Change::Updated(update) => self.update(key, update),
-            Change::Removed => self.remove(key),
+    /// Applies events as they're read, stopping at the first that can't be
+    /// read or applied
+    pub fn apply_events(
+        &mut self,
+        events: impl IntoIterator<Item = Result<Event, EventError>>,
+    ) -> Result<(), ClientError> {
+        for event in events {
+            self.apply_event(&event?)?;
         }
+        Ok(())
     }
-}
-
-type FormatParser = fn(&mut BufReader<File>) -> Result<Vec<Event>, EventError>;
 
-pub fn events_from_file(path: &PathBuf) -> Result<Vec<Event>, EventError> {
-    if !path.as_path().exists() {
+    /// Applies an event, with warnings about anything unusual in it
+    pub fn apply_event(
+        &mut self,
+        event: &Event,
+    ) -> Result<Vec<Warning>, ClientError> {
+        let Event(ref key, _, change, ..) = event;
+        match change {
+            Change::Added { name, address } => self
+                .add(key, Client::new(key, name, address))
+                .map(|_| Vec::new()),
+            Change::Updated(Update::Address(address)) => {
+                let effective = event.1.date_naive();
+                let update =
+                    Update::EffectiveAddress(effective, address.clone());
+                self.update(key, &update)
+            }
+            Change::Updated(Update::NamedAddress(name, address))
+                if name == BILLING_ADDRESS =>
+            {
+                let effective = event.1.date_naive();
+                let update =
+                    Update::EffectiveAddress(effective, address.clone());
+                self.update(key, &update)
+            }
+            Change::Updated(update) => {
+                self.update_made(key, update, event.1.date_naive())
+            }
+            Change::Removed => self.remove(key).map(|_| Vec::new()),
+            Change::Profile(ProfileUpdate::GlobalNumbering(true))
+                if self.profile.global_numbering.is_none() =>
+            {
+                // Numbers before switching stay per client
+                let from = self.max_invoice_number().map_or(1, |n| n + 1);
+                self.profile.global_numbering = Some(from);
+                Ok(Vec::new())
+            }
+            Change::Profile(update) => {
+                self.profile.update(update);
+                Ok(Vec::new())
+            }
+        }
+    }
+}
+
+type FormatParser = fn(&str) -> Result<Vec<Event>, EventError>;
+
+/// History formats, newest first, by the version that introduced them
+const FORMATS: [(&str, FormatParser); 2] = [
+    ("current", read_current_format),
+    ("0.1.3", read_0_1_3_format),
+];
+
+pub fn events_from_file(path: &PathBuf) -> Result<Vec<Event>, EventError> {
+    if !path.as_path().exists() {
+        logging::info!("No history at {}, starting empty", path.display());
         Ok(Vec::new())
     } else {
-        let file = File::open(path)?;
-        let mut reader = BufReader::new(file);
+        let mut file = File::open(path)
+            .map_err(EventError::file(FileOperation::Open, path))?;
+        let mut text = String::new();
+        file.read_to_string(&mut text)
+            .map_err(EventError::file(FileOperation::Read, path))?;
 
-        let funcs: Vec<FormatParser> =
-            vec![read_current_format, read_0_1_3_format];
-
-        for func in &funcs {
-            reader.rewind()?;
-            if let Ok(events) = func(&mut reader) {
-                return Ok(events);
-            };
+        for (format, func) in FORMATS {
+            match func(&text) {
+                Ok(events) => {
+                    logging::info!(
+                        "Read {} events from {} in the {} format",
+                        events.len(),
+                        path.display(),
+                        format
+                    );
+                    return Ok(events);
+                }
+                Err(error) => {
+                    logging::debug!("Not the {} format: {}", format, error)
+                }
+            }
         }
         Err(EventError::from(serde_lexpr::Error::custom(
             "No existing or previous formats match the history file format",
@@ -249,35 +1283,142 @@ pub fn events_from_file(path: &PathBuf) -> Result<Vec<Event>, EventError> {
     }
 }
 
-fn read_current_format(
-    reader: &mut BufReader<File>,
-) -> Result<Vec<Event>, EventError> {
+fn read_current_format(text: &str) -> Result<Vec<Event>, EventError> {
     let mut events: Vec<Event> = Vec::new();
-    for line in reader.lines() {
-        events.push(serde_lexpr::from_str(line?.as_str())?);
+    for line in text.lines() {
+        events.push(serde_lexpr::from_str(line)?);
     }
     Ok(events)
 }
 
-fn read_0_1_3_format(
-    reader: &mut BufReader<File>,
-) -> Result<Vec<Event>, EventError> {
-    Ok(serde_lexpr::from_reader(reader)?)
+pub type EventStream = Box<dyn Iterator<Item = Result<Event, EventError>>>;
+
+/// The events of a history read as they're needed rather than all at once.
+/// Histories in a previous format are read in full and then streamed.
+pub fn stream_events(path: &PathBuf) -> Result<EventStream, EventError> {
+    if !path.as_path().exists() {
+        return Ok(Box::new(std::iter::empty()));
+    }
+    if !is_current_format(path)? {
+        return Ok(Box::new(events_from_file(path)?.into_iter().map(Ok)));
+    }
+    let file = File::open(path)
+        .map_err(EventError::file(FileOperation::Open, path))?;
+    logging::info!("Streaming events from {}", path.display());
+    let path = path.clone();
+    Ok(Box::new(BufReader::new(file).lines().map(move |line| {
+        let line =
+            line.map_err(EventError::file(FileOperation::Read, &path))?;
+        Ok(serde_lexpr::from_str(&line)?)
+    })))
+}
+
+/// Whether a history has an event per line, judged by its first line.
+/// Missing and empty histories are in the current format.
+fn is_current_format(path: &Path) -> Result<bool, EventError> {
+    if !path.exists() {
+        return Ok(true);
+    }
+    let file = File::open(path)
+        .map_err(EventError::file(FileOperation::Open, path))?;
+    let mut first = String::new();
+    BufReader::new(file)
+        .read_line(&mut first)
+        .map_err(EventError::file(FileOperation::Read, path))?;
+    Ok(first.trim().is_empty()
+        || serde_lexpr::from_str::<Event>(first.trim_end()).is_ok())
+}
+
+fn read_0_1_3_format(text: &str) -> Result<Vec<Event>, EventError> {
+    Ok(serde_lexpr::from_str(text)?)
+}
+
+/// An event as it's written to the history file
+pub fn event_line(event: &Event) -> Result<String, EventError> {
+    Ok(serde_lexpr::to_string(event)? + "\n")
 }
 
 pub fn events_to_file(
     path: &PathBuf,
     events: &[Event],
 ) -> Result<(), EventError> {
-    let updated_path = path.with_extension("updated");
+    replace_file(path, |f, updated_path| {
+        for event in events.iter() {
+            f.write_all(event_line(event)?.as_bytes()).map_err(
+                EventError::file(FileOperation::Write, updated_path),
+            )?;
+        }
+        Ok(())
+    })
+}
+
+/// Adds events to the end of a history. Its existing lines are copied
+/// rather than read as events, unless it's in a previous format, which is
+/// rewritten in the current one.
+pub fn append_to_file(
+    path: &PathBuf,
+    events: &[Event],
+) -> Result<(), EventError> {
+    if !is_current_format(path)? {
+        let mut all = events_from_file(path)?;
+        all.extend(events.iter().cloned());
+        return events_to_file(path, &all);
+    }
+    replace_file(path, |f, updated_path| {
+        if path.exists() {
+            let mut history = File::open(path)
+                .map_err(EventError::file(FileOperation::Open, path))?;
+            let ended = ends_with_newline(&mut history)
+                .map_err(EventError::file(FileOperation::Read, path))?;
+            io::copy(&mut history, f)
+                .and_then(|_| if ended { Ok(()) } else { f.write_all(b"\n") })
+                .map_err(EventError::file(
+                    FileOperation::Write,
+                    updated_path,
+                ))?;
+        }
+        for event in events.iter() {
+            f.write_all(event_line(event)?.as_bytes()).map_err(
+                EventError::file(FileOperation::Write, updated_path),
+            )?;
+        }
+        Ok(())
+    })
+}
 
-    let mut f = File::create(&updated_path)?;
-    for event in events.iter() {
-        serde_lexpr::to_writer(&mut f, &event)?;
-        f.write_all(b"\n")?;
+/// Whether a file is empty or ends a line, leaving it at its start
+fn ends_with_newline(file: &mut File) -> io::Result<bool> {
+    if file.metadata()?.len() == 0 {
+        return Ok(true);
     }
+    file.seek(io::SeekFrom::End(-1))?;
+    let mut last = [0u8];
+    file.read_exact(&mut last)?;
+    file.rewind()?;
+    Ok(last[0] == b'\n')
+}
+
+/// Writes a file beside the history and renames it over the history once
+/// it's complete, so the history is never left half written
+fn replace_file(
+    path: &PathBuf,
+    write: impl FnOnce(&mut File, &Path) -> Result<(), EventError>,
+) -> Result<(), EventError> {
+    let updated_path = path.with_extension("updated");
+
+    let mut f = File::create(&updated_path)
+        .map_err(EventError::file(FileOperation::Create, &updated_path))?;
+    write(&mut f, &updated_path)?;
 
-    fs::rename(updated_path, path)?;
+    logging::debug!(
+        "Renaming {} to {}",
+        updated_path.display(),
+        path.display()
+    );
+    fs::rename(&updated_path, path).map_err(EventError::file(
+        FileOperation::Rename(path.clone()),
+        &updated_path,
+    ))?;
     Ok(())
 }
 
@@ -286,18 +1427,122 @@ pub enum ClientError {
     #[error("Client Error: No client found for: '{0}'")]
     NotFound(String),
 
-    #[error("Client Error: No effective rate found for: '{0}' as of {1}")]
-    NoRate(String, NaiveDate),
+    #[error("Client Error: Service '{0}' has no rates configured")]
+    NoRates(String),
+
+    #[error(
+        "Client Error: {service}'s earliest rate is effective {earliest}, but \
+         the period starts {from}, set an earlier rate or adjust the period"
+    )]
+    BeforeFirstRate {
+        service: String,
+        earliest: NaiveDate,
+        from: NaiveDate,
+    },
 
     #[error("Invoice #{0} {1}")]
     Invoice(usize, InvoiceError),
+
+    #[error("Invoice {0} {1}")]
+    InvoiceId(String, InvoiceError),
+
+    #[error("Client Error: No invoices found for: '{0}'")]
+    NoInvoices(String),
+
+    #[error("Client Error: No service named: '{0}'")]
+    NoService(String),
+
+    #[error("Client Error: No retainer for '{0}' starting {1}")]
+    NoRetainer(String, NaiveDate),
+
+    #[error(
+        "Client Error: Service '{0}' differs from the existing '{1}' only by \
+         case or spacing, use that name instead"
+    )]
+    SimilarService(String, String),
+
+    #[error("Client Error: No address named: '{0}'")]
+    NoAddress(String),
+
+    #[error("Error processing event history: {source}")]
+    History {
+        #[from]
+        source: EventError,
+    },
+}
+
+/// Name of the address from older histories and new clients
+pub const BILLING_ADDRESS: &str = "billing";
+
+/// Trims a service name and collapses the whitespace inside it
+pub fn normalize_service_name(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn service_match_key(name: &str) -> String {
+    normalize_service_name(name).to_lowercase()
+}
+
+const MAX_KEY_LEN: usize = 32;
+
+/// Checks the key of a new client is usable in file and ledger account
+/// names, keys already in a history aren't validated
+pub fn validate_key<'a>(
+    key: &str,
+    mut taken: impl Iterator<Item = &'a str>,
+) -> Result<(), KeyError> {
+    if key.is_empty() {
+        return Err(KeyError::Empty);
+    }
+    if key.len() > MAX_KEY_LEN {
+        return Err(KeyError::TooLong);
+    }
+    if let Some(c) = key
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || *c == '-' || *c == '_'))
+    {
+        return Err(KeyError::InvalidChar(c));
+    }
+    if taken.any(|existing| existing == key) {
+        return Err(KeyError::Taken(key.to_string()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum KeyError {
+    #[error("Client key can't be empty")]
+    Empty,
+
+    #[error("Client key can't be longer than {} characters", MAX_KEY_LEN)]
+    TooLong,
+
+    #[error(
+        "Client key can't contain '{0}', use letters, numbers, '-', or '_'"
+    )]
+    InvalidChar(char),
+
+    #[error("Client key '{0}' is already used")]
+    Taken(String),
+}
+
+/// What was being done with a file when it failed
+#[derive(Debug, PartialEq, Clone)]
+pub enum FileOperation {
+    Open,
+    Read,
+    Create,
+    Write,
+    /// Renaming an updated file over the file at this path
+    Rename(PathBuf),
 }
 
 #[derive(Debug, Error)]
 pub enum EventError {
-    #[error("IO Error: {source}")]
+    #[error("{}", file_error_message(.operation, .path, .source))]
     Io {
-        #[from]
+        operation: FileOperation,
+        path: PathBuf,
         source: io::Error,
     },
 
@@ -308,36 +1553,181 @@ pub enum EventError {
     },
 }
 
+impl EventError {
+    /// Adds the file and what was being done with it to IO errors
+    pub fn file(
+        operation: FileOperation,
+        path: &Path,
+    ) -> impl FnOnce(io::Error) -> Self + '_ {
+        move |source| Self::Io {
+            operation,
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+}
+
+fn file_error_message(
+    operation: &FileOperation,
+    path: &Path,
+    source: &io::Error,
+) -> String {
+    let file = path.display();
+    let reason = io_reason(source);
+    match operation {
+        FileOperation::Open => format!(
+            "Could not open history file '{}' for reading: {}",
+            file, reason
+        ),
+        FileOperation::Read => {
+            format!("Could not read history file '{}': {}", file, reason)
+        }
+        FileOperation::Create => {
+            format!("Could not create '{}' for writing: {}", file, reason)
+        }
+        FileOperation::Write => {
+            format!("Could not write to '{}': {}", file, reason)
+        }
+        FileOperation::Rename(to) => format!(
+            "Could not replace '{}' with '{}': {}\n\
+             The changes were left in '{}', e.g. because the directory is \
+             read-only or on another device, move it over '{}' to keep them",
+            to.display(),
+            file,
+            reason,
+            file,
+            to.display()
+        ),
+    }
+}
+
+/// An IO error's description without the OS error code, e.g. "permission
+/// denied"
+fn io_reason(error: &io::Error) -> String {
+    let text = error.to_string();
+    let text = match text.find(" (os error") {
+        Some(i) => &text[..i],
+        None => &text,
+    };
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum InvoiceError {
-    #[error("found after {0}")]
+    #[error("is out of sequence, expected #{0} or later")]
     OutOfSequence(usize),
 
-    #[error("not found")]
-    NotFound,
+    #[error("not found, {}", known_numbers(.0))]
+    NotFound(Vec<usize>),
 
     #[error("was previously paid")]
     AlreadyPaid,
+
+    #[error("was already sent on {0}")]
+    AlreadySent(NaiveDate),
+
+    #[error("has not been paid")]
+    NotPaid,
+
+    #[error("is not a deposit")]
+    NotDeposit,
+
+    #[error("was already applied to invoice #{0}")]
+    AlreadyApplied(usize),
+
+    #[error("was written off")]
+    WrittenOff,
+
+    #[error("has not been written off")]
+    NotWrittenOff,
+
+    #[error("was already disputed on {0}")]
+    AlreadyDisputed(NaiveDate),
+
+    #[error("is not disputed")]
+    NotDisputed,
+
+    #[error("was voided")]
+    Voided,
+
+    #[error("was paid and can't be voided, credit the client instead")]
+    PaidNotVoidable,
+
+    #[error("can't be paid on {0}, before it was issued on {1}")]
+    PaidBeforeIssued(NaiveDate, NaiveDate),
+
+    #[error("can't be paid on {0}, before it was sent on {1}")]
+    PaidBeforeSent(NaiveDate, NaiveDate),
+
+    #[error("is not overdue until after {0}")]
+    NotOverdue(NaiveDate),
+
+    #[error(
+        "has {} and {} items, invoices made before items were split by \
+         currency need to be split by hand",
+        .0.0.code(),
+        .0.1.code()
+    )]
+    MixedCurrencies(CurrencyMismatch),
+}
+
+/// Years after an invoice's date past which its payment is likely a
+/// mistyped date
+pub const LATE_PAYMENT_YEARS: u32 = 2;
+
+/// Something unusual about an update that was still applied
+#[derive(Debug, PartialEq)]
+pub enum Warning {
+    /// Invoice paid more than [`LATE_PAYMENT_YEARS`] after its date
+    LatePayment(usize, NaiveDate),
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::LatePayment(number, date) => write!(
+                f,
+                "Invoice #{} paid on {}, more than {} years after its date",
+                number, date, LATE_PAYMENT_YEARS
+            ),
+        }
+    }
+}
+
+fn known_numbers(numbers: &[usize]) -> String {
+    if numbers.is_empty() {
+        return "there are no invoices".to_string();
+    }
+    let numbers: Vec<String> =
+        numbers.iter().map(|n| format!("#{}", n)).collect();
+    format!("invoices are {}", numbers.join(", "))
 }
 
 #[cfg(test)]
 pub mod tests {
 
     use super::*;
-    use crate::billing::{Currency, Money, Rate, Unit};
-    use chrono::{NaiveDate, TimeZone, Utc};
+    use crate::billing::{InvoiceItem, TaxRounding, Unit};
+    use crate::ledger_fmt::{CommodityPosition, DecimalMark};
+    use chrono::{NaiveTime, TimeZone, Utc};
     use const_format::formatcp;
-    use rust_decimal::Decimal;
+    use proptest::prelude::*;
     use serde_lexpr::{from_str, to_string, Error};
+    use std::slice;
 
     fn billing_rate() -> Rate {
         Rate {
             amount: Money::new(Currency::Usd, Decimal::from(1000)),
             per: Unit::Month,
+            tiers: Vec::new(),
         }
     }
 
-    const RATE_RAW: &str = "(amount . #(USD 1000.0)) \
+    const RATE_RAW: &str = "(amount . #(USD \"1000\")) \
          (per . Month)";
 
     const CLIENT_ADD_STR: &str = formatcp!(
@@ -357,12 +1747,90 @@ pub mod tests {
                 .single()
                 .unwrap(),
             change,
+            false,
+            None,
+            None,
         );
         let sexpr = to_string(&event)?;
         assert_eq!(sexpr, CLIENT_ADD_STR);
         Ok(())
     }
 
+    #[test]
+    fn backdated_event_round_trip() -> Result<(), Error> {
+        let change = Change::Added {
+            name: "Innotech".to_string(),
+            address: "Some Place".to_string(),
+        };
+        let timestamp = Utc
+            .with_ymd_and_hms(2021, 4, 15, 10, 30, 0)
+            .single()
+            .unwrap();
+        let event = Event::new_at("innotech", timestamp, change);
+
+        let sexpr = to_string(&event)?;
+        assert_eq!(
+            sexpr,
+            format!("{} #t)", CLIENT_ADD_STR.strip_suffix(')').unwrap())
+        );
+        let parsed: Event = from_str(&sexpr)?;
+        assert_eq!(parsed, event);
+        assert!(parsed.is_backdated());
+
+        let current: Event = from_str(CLIENT_ADD_STR)?;
+        assert!(!current.is_backdated());
+        Ok(())
+    }
+
+    #[test]
+    fn idempotency_key_round_trip() -> Result<(), Error> {
+        let mut event: Event = from_str(CLIENT_ADD_STR)?;
+        assert_eq!(event.4, None);
+        event.4 = Some("nightly-2024-05".to_string());
+
+        let sexpr = to_string(&event)?;
+        assert!(sexpr.contains("#f"), "{}", sexpr);
+        let parsed: Event = from_str(&sexpr)?;
+        assert_eq!(parsed, event);
+        assert!(!parsed.is_backdated());
+        Ok(())
+    }
+
+    #[test]
+    fn origin_round_trip() -> Result<(), Error> {
+        let prefix = CLIENT_ADD_STR.strip_suffix(')').unwrap();
+        // Lines written before origins were recorded
+        for line in [
+            CLIENT_ADD_STR.to_string(),
+            format!("{} #t)", prefix),
+            format!("{} #f (\"nightly\"))", prefix),
+        ] {
+            let event: Event = from_str(&line)?;
+            assert_eq!(event.origin(), None);
+            assert_eq!(to_string(&event)?, line);
+        }
+
+        let mut event: Event = from_str(CLIENT_ADD_STR)?;
+        event.5 = Some("alice@laptop".to_string());
+        let sexpr = to_string(&event)?;
+        assert_eq!(sexpr, format!("{} #f () (\"alice@laptop\"))", prefix));
+        let parsed: Event = from_str(&sexpr)?;
+        assert_eq!(parsed, event);
+        assert_eq!(parsed.origin(), Some("alice@laptop"));
+        assert_eq!(parsed.4, None);
+
+        let update = Update::Name("Innotech Inc".to_string());
+        let event = Event::new_update_with_origin(
+            "innotech",
+            update,
+            Some("bob@desktop".to_string()),
+        );
+        let parsed: Event = from_str(&to_string(&event)?)?;
+        assert_eq!(parsed.origin(), Some("bob@desktop"));
+        assert_eq!(Event::new("innotech", Change::Removed).origin(), None);
+        Ok(())
+    }
+
     const RATE_UPDATE_STR: &str = formatcp!(
         "#(\"innotech\" \"2021-04-16T09:30:00Z\" \
            (Updated ServiceRate \"Stuff\" \"2021-04-15\" ({})))",
@@ -383,27 +1851,1423 @@ pub mod tests {
                 .single()
                 .unwrap(),
             change,
+            false,
+            None,
+            None,
         );
         let sexpr = to_string(&event)?;
         assert_eq!(sexpr, RATE_UPDATE_STR);
         Ok(())
     }
 
+    #[test]
+    fn tax_rounding_round_trip() -> Result<(), Error> {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let gst = TaxRate::new("GST".to_string(), 5);
+        let sexpr = to_string(&Update::Taxes(date, vec![gst.clone()]))?;
+        // Taxes rounded half to even keep the two field form of older
+        // histories
+        assert!(sexpr.contains("#(\"GST\" \"0.05\")"), "{}", sexpr);
+        assert_eq!(
+            from_str::<Update>(&sexpr)?,
+            Update::Taxes(date, vec![gst.clone()])
+        );
+
+        let half_up =
+            Update::Taxes(date, vec![gst.rounded(TaxRounding::HalfUp)]);
+        let sexpr = to_string(&half_up)?;
+        assert!(sexpr.contains("HalfUp"), "{}", sexpr);
+        assert_eq!(from_str::<Update>(&sexpr)?, half_up);
+        Ok(())
+    }
+
+    #[test]
+    fn converted_amounts_replay_as_stored() -> Result<(), Error> {
+        let rate = billing_rate();
+        let mut invoice = invoice(1, &rate, false);
+        invoice.items = invoice
+            .items
+            .into_iter()
+            .map(|item| item.converted(Currency::Eur, Decimal::new(92, 2)))
+            .collect();
+        invoice.fx = Some((Currency::Usd, Decimal::new(92, 2)));
+        // An amount that no longer matches the rate, e.g. rounded differently
+        // by an older version, is kept rather than recomputed
+        invoice.items[0].amount =
+            Money::new(Currency::Eur, Decimal::new(91999, 2));
+
+        let sexpr = to_string(&Update::Invoiced(invoice.clone()))?;
+        let mut client = Client::new("innotech", "Innotech", "Some Place");
+        client.update(&from_str(&sexpr)?).unwrap();
+        let replayed = client.invoice(&1).unwrap();
+        assert_eq!(replayed, &invoice);
+        assert_eq!(replayed.items[0].amount.to_string(), "EUR €919.99");
+        Ok(())
+    }
+
+    #[test]
+    fn multi_week_rate_round_trip() -> Result<(), Error> {
+        let rate = Rate {
+            amount: Money::new(Currency::Usd, Decimal::from(4000)),
+            per: Unit::EveryNWeeks(2),
+            tiers: Vec::new(),
+        };
+        let update = Update::ServiceRate(
+            "Sprints".to_string(),
+            NaiveDate::from_ymd_opt(2021, 4, 15).unwrap(),
+            rate,
+        );
+        let sexpr = to_string(&update)?;
+        assert_eq!(from_str::<Update>(&sexpr)?, update);
+        Ok(())
+    }
+
+    #[test]
+    fn plain_units_still_parse() -> Result<(), Error> {
+        let rate: Rate = from_str(&format!("({})", RATE_RAW))?;
+        assert_eq!(rate, billing_rate());
+        Ok(())
+    }
+
     pub const EVENTS_STR: &str =
         formatcp!("({}\n{})", CLIENT_ADD_STR, RATE_UPDATE_STR);
 
     #[test]
     fn client_from_events() -> Result<(), ClientError> {
         let events: Vec<Event> = from_str(EVENTS_STR).unwrap();
-        let clients = Clients::from_events(&events)?;
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
 
         let client = clients.get(&"innotech".to_string())?;
         let query_date = NaiveDate::from_ymd_opt(2021, 4, 17).unwrap();
         let service = client.services.get("Stuff").unwrap();
 
-        assert_eq!(&client.address, "Some Place");
+        assert_eq!(client.current_address(), "Some Place");
         assert_eq!(&service.name, "Stuff");
         assert_eq!(service.rates.as_of(query_date), Some(&billing_rate()));
         Ok(())
     }
+
+    fn invoice(number: usize, rate: &Rate, paid: bool) -> Invoice {
+        let april = Period::new(
+            NaiveDate::from_ymd_opt(2024, 4, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2024, 4, 30).unwrap(),
+        );
+        let item = InvoiceItem::new("Stuff".to_string(), rate.clone(), april);
+        let mut invoice = Invoice::new(number, vec![item], vec![]).unwrap();
+        invoice.date = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        invoice.paid = paid.then_some(invoice.date);
+        invoice
+    }
+
+    #[test]
+    fn outstanding_by_currency() {
+        let cad = Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(500)),
+            per: Unit::Month,
+            tiers: Vec::new(),
+        };
+        let mut client = Client::new("innotech", "Innotech", "Some Place");
+        assert!(client.outstanding().unwrap().is_empty());
+
+        for (number, rate, paid) in [
+            (1, &billing_rate(), false),
+            (2, &cad, true),
+            (3, &cad, false),
+            (4, &billing_rate(), false),
+            (5, &cad, false),
+        ] {
+            client
+                .update(&Update::Invoiced(invoice(number, rate, paid)))
+                .unwrap();
+        }
+
+        assert_eq!(
+            client.outstanding().unwrap(),
+            vec![
+                (Currency::Usd, Decimal::from(2000)),
+                (Currency::Cad, Decimal::from(1000)),
+            ]
+        );
+    }
+
+    #[test]
+    fn accepted_keys() {
+        for key in ["acme", "acme-2", "big_co", "a", "x1y2", &"k".repeat(32)] {
+            assert_eq!(
+                validate_key(key, ["other"].into_iter()),
+                Ok(()),
+                "{key}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejected_keys() {
+        let cases = [
+            ("", KeyError::Empty),
+            (&"k".repeat(33), KeyError::TooLong),
+            ("acme corp", KeyError::InvalidChar(' ')),
+            ("acme/corp", KeyError::InvalidChar('/')),
+            ("acme.corp", KeyError::InvalidChar('.')),
+            ("müller", KeyError::InvalidChar('ü')),
+            ("acme:corp", KeyError::InvalidChar(':')),
+            ("innotech", KeyError::Taken("innotech".to_string())),
+        ];
+        for (key, error) in cases {
+            assert_eq!(
+                validate_key(key, ["innotech"].into_iter()),
+                Err(error),
+                "{key}"
+            );
+        }
+    }
+
+    #[test]
+    fn unusual_existing_keys_still_load() -> Result<(), ClientError> {
+        let events = [Event::new(
+            "Acme Corp/West",
+            Change::Added {
+                name: "Acme".to_string(),
+                address: "Some Place".to_string(),
+            },
+        )];
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        assert!(clients.get(&"Acme Corp/West".to_string()).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn legacy_address_update_uses_event_date() -> Result<(), ClientError> {
+        let mut events: Vec<Event> = from_str(EVENTS_STR).unwrap();
+        events.push(Event(
+            "innotech".to_string(),
+            Utc.with_ymd_and_hms(2022, 3, 1, 12, 0, 0).single().unwrap(),
+            Change::Updated(Update::Address("New Place".to_string())),
+            false,
+            None,
+            None,
+        ));
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+
+        assert_eq!(client.address_as_of(date(2021, 6, 1)), "Some Place");
+        assert_eq!(client.address_as_of(date(2022, 3, 1)), "New Place");
+        assert_eq!(client.current_address(), "New Place");
+        Ok(())
+    }
+
+    #[test]
+    fn billing_address_by_name_uses_event_date() -> Result<(), ClientError> {
+        let mut events: Vec<Event> = from_str(EVENTS_STR).unwrap();
+        events.push(Event(
+            "innotech".to_string(),
+            Utc.with_ymd_and_hms(2022, 3, 1, 12, 0, 0).single().unwrap(),
+            Change::Updated(Update::NamedAddress(
+                BILLING_ADDRESS.to_string(),
+                "New Place".to_string(),
+            )),
+            false,
+            None,
+            None,
+        ));
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+
+        assert_eq!(client.address_as_of(date(2022, 2, 28)), "Some Place");
+        assert_eq!(client.address_as_of(date(2022, 3, 1)), "New Place");
+        Ok(())
+    }
+
+    #[test]
+    fn named_addresses_replay() -> Result<(), ClientError> {
+        let mut events: Vec<Event> = from_str(EVENTS_STR).unwrap();
+        let made = |month, update: Update| {
+            let update = to_string(&update).unwrap();
+            Event(
+                "innotech".to_string(),
+                Utc.with_ymd_and_hms(2024, month, 1, 12, 0, 0)
+                    .single()
+                    .unwrap(),
+                Change::Updated(from_str(&update).unwrap()),
+                false,
+                None,
+                None,
+            )
+        };
+        let named = |name: &str, address: &str| {
+            Update::NamedAddress(name.to_string(), address.to_string())
+        };
+        events.extend([
+            made(1, named("registered", "9 Registered Way")),
+            made(2, Update::InvoiceAddress("registered".to_string())),
+            made(3, named("registered", "10 Registered Way")),
+            made(4, named(BILLING_ADDRESS, "Billing Place")),
+            made(
+                5,
+                Update::TemplateOptions {
+                    accent: Some("1A2B3C".to_string()),
+                    logo_path: None,
+                    template: None,
+                },
+            ),
+        ]);
+        let mut clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let date = |m, d| NaiveDate::from_ymd_opt(2024, m, d).unwrap();
+
+        assert_eq!(
+            client.addresses().collect::<Vec<_>>(),
+            vec![
+                (BILLING_ADDRESS, "Billing Place"),
+                ("registered", "10 Registered Way")
+            ]
+        );
+        // Addresses and the choice of address apply from the day they were
+        // made, older invoices keep the address they were issued with
+        assert_eq!(client.address_as_of(date(3, 31)), "Some Place");
+        assert_eq!(client.address_as_of(date(4, 1)), "Billing Place");
+        assert_eq!(client.invoice_address(date(1, 15)), "Some Place");
+        assert_eq!(client.invoice_address(date(2, 15)), "9 Registered Way");
+        assert_eq!(client.invoice_address(date(3, 15)), "10 Registered Way");
+        assert_eq!(client.invoice_address_name(), Some("registered"));
+        assert!(client.to_string().contains("registered address:"));
+
+        let key = "innotech".to_string();
+        assert!(matches!(
+            clients.update(&key, &Update::InvoiceAddress("home".to_string())),
+            Err(ClientError::NoAddress(name)) if name == "home"
+        ));
+        clients.update(&key, &Update::InvoiceAddress("billing".to_string()))?;
+        let client = clients.get(&key)?;
+        assert_eq!(client.invoice_address_name(), None);
+        assert_eq!(client.invoice_address(date(3, 15)), "10 Registered Way");
+        Ok(())
+    }
+
+    #[test]
+    fn template_options_from_events() -> Result<(), ClientError> {
+        let mut events: Vec<Event> = from_str(EVENTS_STR).unwrap();
+        events.push(Event::new_update(
+            "innotech",
+            Update::TemplateOptions {
+                accent: Some("1A2B3C".to_string()),
+                logo_path: None,
+                template: Some("brand".to_string()),
+            },
+        ));
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+
+        assert_eq!(client.template.accent.as_deref(), Some("1A2B3C"));
+        assert_eq!(client.template.logo_path, None);
+        assert_eq!(client.template.template.as_deref(), Some("brand"));
+        Ok(())
+    }
+
+    #[test]
+    fn footer_falls_back_to_profile() -> Result<(), ClientError> {
+        let mut events: Vec<Event> = from_str(EVENTS_STR).unwrap();
+        events.push(Event::new_profile(ProfileUpdate::Footer(
+            "Net 30".to_string(),
+        )));
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+
+        assert_eq!(client.footer(&clients.profile), Some("Net 30"));
+        assert!(clients.iter().all(|c| c.key != PROFILE_KEY));
+        Ok(())
+    }
+
+    #[test]
+    fn service_templates_replay() -> Result<(), ClientError> {
+        let mut events: Vec<Event> = from_str(EVENTS_STR).unwrap();
+        let template = ServiceTemplate {
+            services: vec![("Stuff".to_string(), billing_rate())],
+            taxes: vec![TaxRate::new("GST".to_string(), 5)],
+        };
+        for update in [
+            ProfileUpdate::ServiceTemplate(
+                "small".to_string(),
+                template.clone(),
+            ),
+            ProfileUpdate::ServiceTemplate(
+                "large".to_string(),
+                template.clone(),
+            ),
+            ProfileUpdate::RemoveServiceTemplate("large".to_string()),
+        ] {
+            events.push(Event::new_profile(update));
+        }
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+
+        assert_eq!(
+            clients.profile.service_templates.keys().collect::<Vec<_>>(),
+            vec!["small"]
+        );
+        assert!(clients.iter().all(|c| c.key != PROFILE_KEY));
+
+        let effective = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        assert_eq!(
+            template.updates(effective),
+            vec![
+                Update::ServiceRate(
+                    "Stuff".to_string(),
+                    effective,
+                    billing_rate()
+                ),
+                Update::Taxes(effective, template.taxes.clone()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn registrations_replay() -> Result<(), ClientError> {
+        let mut events: Vec<Event> = from_str(EVENTS_STR).unwrap();
+        for update in [
+            ProfileUpdate::Registration(
+                "GST/HST".to_string(),
+                "123456782RT0001".to_string(),
+            ),
+            ProfileUpdate::Registration(
+                "VAT".to_string(),
+                "DE136695977".to_string(),
+            ),
+            ProfileUpdate::Registration(
+                "VAT".to_string(),
+                "DE136695976".to_string(),
+            ),
+            ProfileUpdate::RemoveRegistration("GST/HST".to_string()),
+        ] {
+            events.push(Event::new_profile(update));
+        }
+        let lines = events
+            .iter()
+            .map(event_line)
+            .collect::<Result<String, _>>()
+            .unwrap();
+        let read = lines.lines().map(|line| from_str(line).map_err(Into::into));
+        let clients = Clients::from_events(read)?;
+
+        assert_eq!(
+            clients.profile.registrations,
+            vec![("VAT".to_string(), "DE136695976".to_string())]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn client_footer_overrides_profile() -> Result<(), ClientError> {
+        let mut events: Vec<Event> = from_str(EVENTS_STR).unwrap();
+        events.push(Event::new_profile(ProfileUpdate::Footer(
+            "Net 30".to_string(),
+        )));
+        events.push(Event::new_update(
+            "innotech",
+            Update::Footer("Per contract 12-A".to_string()),
+        ));
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        assert_eq!(client.footer(&clients.profile), Some("Per contract 12-A"));
+
+        events
+            .push(Event::new_update("innotech", Update::Footer(String::new())));
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        assert_eq!(client.footer(&clients.profile), None);
+        Ok(())
+    }
+
+    #[test]
+    fn payment_account_overrides() -> Result<(), ClientError> {
+        let mut events: Vec<Event> = from_str(EVENTS_STR).unwrap();
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        assert_eq!(client.payment_account(&clients.profile), "assets:bank");
+
+        events.push(Event::new_profile(ProfileUpdate::PaymentAccount(
+            "assets:wise".to_string(),
+        )));
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        assert_eq!(client.payment_account(&clients.profile), "assets:wise");
+
+        events.push(Event::new_update(
+            "innotech",
+            Update::PaymentAccount("assets:stripe".to_string()),
+        ));
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        assert_eq!(client.payment_account(&clients.profile), "assets:stripe");
+        Ok(())
+    }
+
+    #[test]
+    fn default_quantity_needs_a_service() {
+        let mut client = Client::new("innotech", "Innotech", "Some Place");
+        let update = Update::ServiceDefaultQuantity(
+            "Stuff".to_string(),
+            Decimal::from(40),
+        );
+        assert!(matches!(
+            client.update(&update),
+            Err(ClientError::NoService(name)) if name == "Stuff"
+        ));
+
+        client
+            .update(&Update::ServiceRate(
+                "Stuff".to_string(),
+                NaiveDate::from_ymd_opt(2021, 4, 15).unwrap(),
+                billing_rate(),
+            ))
+            .unwrap();
+        client.update(&update).unwrap();
+        assert_eq!(
+            client
+                .service("Stuff".to_string())
+                .unwrap()
+                .default_quantity,
+            Some(Decimal::from(40))
+        );
+    }
+
+    #[test]
+    fn retainer_consumption_replay() {
+        let mut client = Client::new("innotech", "Innotech", "Some Place");
+        let date = |d| NaiveDate::from_ymd_opt(2021, 5, d).unwrap();
+        let may = Period::new(date(1), date(31));
+        let purchase = Update::RetainerPurchased(
+            "Stuff".to_string(),
+            may.clone(),
+            Decimal::from(20),
+        );
+        assert!(client.update(&purchase).is_err());
+
+        for update in [
+            Update::ServiceRate("Stuff".to_string(), date(1), billing_rate()),
+            purchase,
+            Update::RetainerConsumed(
+                "Stuff".to_string(),
+                date(1),
+                Decimal::from(12),
+            ),
+        ] {
+            client.update(&update).unwrap();
+        }
+        let retainer = client.retainer_for("Stuff", date(31)).unwrap();
+        assert_eq!(retainer.remaining(), Decimal::from(8));
+        assert_eq!(client.retainers().count(), 1);
+
+        let unknown = Update::RetainerConsumed(
+            "Stuff".to_string(),
+            date(2),
+            Decimal::from(1),
+        );
+        assert!(matches!(
+            client.update(&unknown),
+            Err(ClientError::NoRetainer(_, from)) if from == date(2)
+        ));
+    }
+
+    #[test]
+    fn similar_services_merge() {
+        let mut client = Client::new("innotech", "Innotech", "Some Place");
+        let date = |d| NaiveDate::from_ymd_opt(2021, 5, d).unwrap();
+        let rate = |amount: i64| Rate {
+            amount: Money::new(Currency::Usd, Decimal::from(amount)),
+            per: Unit::Month,
+            tiers: Vec::new(),
+        };
+        for update in [
+            Update::ServiceRate("consulting".to_string(), date(1), rate(100)),
+            Update::ServiceRate("consulting".to_string(), date(20), rate(300)),
+            Update::ServiceRate("Consulting ".to_string(), date(1), rate(150)),
+            Update::ServiceRate("Consulting ".to_string(), date(10), rate(200)),
+            Update::RetainerPurchased(
+                "Consulting ".to_string(),
+                Period::new(date(1), date(31)),
+                Decimal::from(20),
+            ),
+            Update::ServiceRate("Design".to_string(), date(1), rate(50)),
+        ] {
+            client.update(&update).unwrap();
+        }
+        assert_eq!(normalize_service_name("  Big \t data  "), "Big data");
+        assert_eq!(client.similar_service(" CONSULTING"), Some("Consulting "));
+        assert_eq!(client.similar_service("consulting"), Some("Consulting "));
+        assert_eq!(client.similar_service("Design"), None);
+        assert_eq!(
+            client.duplicate_services(),
+            vec![vec!["Consulting ", "consulting"]]
+        );
+
+        let merge = Update::ServicesMerged(
+            "Consulting ".to_string(),
+            "consulting".to_string(),
+        );
+        let replayed: Update = from_str(&to_string(&merge).unwrap()).unwrap();
+        client.update(&replayed).unwrap();
+        assert!(client.duplicate_services().is_empty());
+        let rates = &client.services["consulting"].rates;
+        let amounts: Vec<_> = rates
+            .iter()
+            .map(|(effective, rate)| (*effective, rate.amount))
+            .collect();
+        assert_eq!(
+            amounts,
+            vec![
+                (date(1), rate(100).amount),
+                (date(10), rate(200).amount),
+                (date(20), rate(300).amount),
+            ]
+        );
+        assert!(client.retainer_for("consulting", date(15)).is_some());
+        assert!(matches!(
+            client.update(&merge),
+            Err(ClientError::NoService(name)) if name == "Consulting "
+        ));
+    }
+
+    #[test]
+    fn final_invoices_apply_paid_deposits_once() {
+        let mut client = Client::new("innotech", "Innotech", "Some Place");
+        let rate = billing_rate();
+        for (number, kind, paid) in [
+            (1, InvoiceKind::Deposit, false),
+            (2, InvoiceKind::Deposit, true),
+            (3, InvoiceKind::Standard, true),
+        ] {
+            let mut invoice = invoice(number, &rate, paid);
+            invoice.kind = kind;
+            client.update(&Update::Invoiced(invoice)).unwrap();
+        }
+        let unapplied: Vec<usize> =
+            client.unapplied_deposits().map(|i| i.number).collect();
+        assert_eq!(unapplied, vec![2]);
+
+        let applying = |number: usize, deposit: usize| {
+            let mut invoice = invoice(number, &rate, false);
+            invoice.kind = InvoiceKind::Final {
+                applies: vec![(
+                    deposit,
+                    Money::new(Currency::Usd, 1000.into()),
+                )],
+            };
+            Update::Invoiced(invoice)
+        };
+        let error = |client: &mut Client, update| match client.update(&update) {
+            Err(ClientError::Invoice(number, error)) => Some((number, error)),
+            _ => None,
+        };
+        assert!(matches!(
+            error(&mut client, applying(4, 1)),
+            Some((1, InvoiceError::NotPaid))
+        ));
+        assert!(matches!(
+            error(&mut client, applying(4, 3)),
+            Some((3, InvoiceError::NotDeposit))
+        ));
+        assert!(matches!(
+            error(&mut client, applying(4, 9)),
+            Some((9, InvoiceError::NotFound(_)))
+        ));
+
+        client.update(&applying(4, 2)).unwrap();
+        assert_eq!(client.applied_to(2), Some(4));
+        assert_eq!(client.unapplied_deposits().count(), 0);
+        assert!(matches!(
+            error(&mut client, applying(5, 2)),
+            Some((2, InvoiceError::AlreadyApplied(4)))
+        ));
+    }
+
+    #[test]
+    fn rate_errors_before_and_without_rates() {
+        let mut client = Client::new("innotech", "Innotech", "Some Place");
+        let jan = NaiveDate::from_ymd_opt(2022, 1, 1).unwrap();
+        let before = NaiveDate::from_ymd_opt(2021, 12, 15).unwrap();
+        assert!(matches!(
+            client.rate_as_of("Stuff", jan),
+            Err(ClientError::NoService(_))
+        ));
+
+        client
+            .services
+            .insert("Stuff".to_string(), Service::new("Stuff".to_string()));
+        assert_eq!(
+            client.rate_as_of("Stuff", jan).unwrap_err().to_string(),
+            "Client Error: Service 'Stuff' has no rates configured"
+        );
+
+        client
+            .update(&Update::ServiceRate(
+                "Stuff".to_string(),
+                jan,
+                billing_rate(),
+            ))
+            .unwrap();
+        assert_eq!(client.rate_as_of("Stuff", jan).unwrap(), &billing_rate());
+        assert!(matches!(
+            client.rate_as_of("Stuff", before),
+            Err(ClientError::BeforeFirstRate { earliest, from, .. })
+                if earliest == jan && from == before
+        ));
+    }
+
+    #[test]
+    fn find_invoice_by_any_number_form() {
+        let mut client = Client::new("innotech", "Innotech", "Some Place");
+        let rate = billing_rate();
+        for number in [1, 2] {
+            client
+                .update(&Update::Invoiced(invoice(number, &rate, false)))
+                .unwrap();
+        }
+
+        for id in ["2", "#2", " #2 "] {
+            assert_eq!(client.find_invoice(id).unwrap().number, 2, "{id}");
+        }
+        assert!(matches!(
+            client.find_invoice("#9"),
+            Err(ClientError::Invoice(9, InvoiceError::NotFound(_)))
+        ));
+        assert_eq!(
+            client.find_invoice("2024-003").unwrap_err().to_string(),
+            "Invoice 2024-003 not found, invoices are #1, #2"
+        );
+    }
+
+    #[test]
+    fn sparse_invoice_numbers() {
+        let mut client = Client::new("innotech", "Innotech", "Some Place");
+        let rate = billing_rate();
+        assert_eq!(client.next_invoice_num(), 1);
+        for number in [1, 2, 4] {
+            client
+                .update(&Update::Invoiced(invoice(number, &rate, false)))
+                .unwrap();
+        }
+        assert_eq!(client.next_invoice_num(), 5);
+
+        for number in [3, 4] {
+            assert!(matches!(
+                client.update(&Update::Invoiced(invoice(number, &rate, false))),
+                Err(ClientError::Invoice(n, InvoiceError::OutOfSequence(5)))
+                    if n == number
+            ));
+        }
+        client
+            .update(&Update::Invoiced(invoice(5, &rate, false)))
+            .unwrap();
+    }
+
+    #[test]
+    fn voided_invoices_keep_their_numbers() {
+        let mut client = Client::new("innotech", "Innotech", "Some Place");
+        let rate = billing_rate();
+        let may = NaiveDate::from_ymd_opt(2024, 5, 3).unwrap();
+        for update in [
+            Update::Invoiced(invoice(1, &rate, true)),
+            Update::Invoiced(invoice(2, &rate, false)),
+            Update::Voided(2, may, Some("Cancelled".to_string())),
+        ] {
+            client.update(&update).unwrap();
+        }
+
+        assert!(client.outstanding().unwrap().is_empty());
+        assert_eq!(client.unpaid_invoices().count(), 0);
+        assert_eq!(client.voided_invoices().count(), 1);
+        assert_eq!(client.next_invoice_num(), 3);
+        client
+            .update(&Update::Invoiced(invoice(3, &rate, false)))
+            .unwrap();
+
+        assert!(matches!(
+            client.update(&Update::Voided(1, may, None)),
+            Err(ClientError::Invoice(1, InvoiceError::PaidNotVoidable))
+        ));
+        assert!(matches!(
+            client.update(&Update::Paid(2, may, None)),
+            Err(ClientError::Invoice(2, InvoiceError::Voided))
+        ));
+        assert!(matches!(
+            client.update(&Update::Voided(2, may, None)),
+            Err(ClientError::Invoice(2, InvoiceError::Voided))
+        ));
+    }
+
+    #[test]
+    fn payments_in_date_order() {
+        let mut client = Client::new("innotech", "Innotech", "Some Place");
+        let rate = billing_rate();
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        let eur = Money::new(Currency::Eur, Decimal::from(300));
+        for update in [
+            Update::Invoiced(invoice(1, &rate, false)),
+            Update::Invoiced(invoice(2, &rate, false)),
+            Update::Invoiced(invoice(3, &rate, false)),
+            Update::Invoiced(invoice(4, &rate, false)),
+            Update::Paid(2, date(2025, 1, 10), None),
+            Update::Paid(
+                3,
+                date(2024, 12, 20),
+                Some(Received {
+                    amount: eur,
+                    booked_rate: Decimal::new(65, 2),
+                }),
+            ),
+            Update::Paid(1, date(2024, 6, 1), None),
+        ] {
+            client.update(&update).unwrap();
+        }
+
+        let total = invoice(1, &rate, false).calculate().unwrap().total;
+        assert_eq!(
+            client.payments().unwrap(),
+            vec![
+                PaymentRecord {
+                    number: 1,
+                    date: date(2024, 6, 1),
+                    amount: total,
+                },
+                PaymentRecord {
+                    number: 3,
+                    date: date(2024, 12, 20),
+                    amount: eur,
+                },
+                PaymentRecord {
+                    number: 2,
+                    date: date(2025, 1, 10),
+                    amount: total,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn written_off_invoices_are_not_outstanding() {
+        let mut client = Client::new("innotech", "Innotech", "Some Place");
+        let rate = billing_rate();
+        let may = NaiveDate::from_ymd_opt(2024, 5, 15).unwrap();
+        for update in [
+            Update::Invoiced(invoice(1, &rate, true)),
+            Update::Invoiced(invoice(2, &rate, false)),
+            Update::WrittenOff(2, may, Some("Bankrupt".to_string())),
+        ] {
+            client.update(&update).unwrap();
+        }
+
+        assert!(client.outstanding().unwrap().is_empty());
+        assert_eq!(client.unpaid_invoices().count(), 0);
+        assert_eq!(client.written_off_invoices().count(), 1);
+
+        assert!(matches!(
+            client.update(&Update::WrittenOff(1, may, None)),
+            Err(ClientError::Invoice(1, InvoiceError::AlreadyPaid))
+        ));
+        assert!(matches!(
+            client.update(&Update::Paid(2, may, None)),
+            Err(ClientError::Invoice(2, InvoiceError::WrittenOff))
+        ));
+        assert!(matches!(
+            client.update(&Update::WrittenOff(2, may, None)),
+            Err(ClientError::Invoice(2, InvoiceError::WrittenOff))
+        ));
+    }
+
+    #[test]
+    fn sent_dates_replay() {
+        let mut client = Client::new("innotech", "Innotech", "Some Place");
+        let rate = billing_rate();
+        let may = NaiveDate::from_ymd_opt(2024, 5, 15).unwrap();
+        client
+            .update(&Update::Invoiced(invoice(1, &rate, false)))
+            .unwrap();
+        assert_eq!(client.invoice(&1).unwrap().sent, None);
+
+        let sent: Update =
+            from_str(&to_string(&Update::Sent(1, may)).unwrap()).unwrap();
+        client.update(&sent).unwrap();
+        let invoice = client.invoice(&1).unwrap();
+        assert_eq!(invoice.issued(), may);
+        assert_eq!(invoice.due_date(), may + crate::billing::DEFAULT_TERMS);
+        assert!(matches!(
+            client.update(&sent),
+            Err(ClientError::Invoice(1, InvoiceError::AlreadySent(date)))
+                if date == may
+        ));
+        assert!(matches!(
+            client.update(&Update::Sent(2, may)),
+            Err(ClientError::Invoice(2, InvoiceError::NotFound(_)))
+        ));
+    }
+
+    #[test]
+    fn paid_dates_follow_the_invoice() {
+        let mut client = Client::new("innotech", "Innotech", "Some Place");
+        let rate = billing_rate();
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        for number in [1, 2, 3] {
+            client
+                .update(&Update::Invoiced(invoice(number, &rate, false)))
+                .unwrap();
+        }
+
+        assert!(matches!(
+            client.update(&Update::Paid(1, date(2024, 4, 30), None)),
+            Err(ClientError::Invoice(1, InvoiceError::PaidBeforeIssued(..)))
+        ));
+        assert_eq!(
+            client
+                .update(&Update::Paid(1, date(2024, 5, 1), None))
+                .unwrap(),
+            vec![]
+        );
+
+        client.update(&Update::Sent(2, date(2024, 5, 10))).unwrap();
+        assert!(matches!(
+            client.update(&Update::Paid(2, date(2024, 5, 5), None)),
+            Err(ClientError::Invoice(2, InvoiceError::PaidBeforeSent(..)))
+        ));
+        let paid = client.update(&Update::Paid(2, date(2024, 5, 10), None));
+        assert_eq!(paid.unwrap(), vec![]);
+
+        let late = date(2026, 5, 2);
+        assert_eq!(
+            client.update(&Update::Paid(3, late, None)).unwrap(),
+            vec![Warning::LatePayment(3, late)]
+        );
+        assert_eq!(client.invoice(&3).unwrap().paid, Some(late));
+        assert_eq!(
+            Warning::LatePayment(3, late).to_string(),
+            "Invoice #3 paid on 2026-05-02, more than 2 years after its date"
+        );
+    }
+
+    #[test]
+    fn dispute_resolve_and_pay() {
+        let mut client = Client::new("innotech", "Innotech", "Some Place");
+        let rate = billing_rate();
+        let date = |d| NaiveDate::from_ymd_opt(2024, 6, d).unwrap();
+        for number in [1, 2] {
+            client
+                .update(&Update::Invoiced(invoice(number, &rate, false)))
+                .unwrap();
+        }
+
+        let disputed: Update = from_str(
+            &to_string(&Update::Disputed(1, date(3), Some("Hours".into())))
+                .unwrap(),
+        )
+        .unwrap();
+        client.update(&disputed).unwrap();
+        assert!(client.invoice(&1).unwrap().is_disputed());
+        assert!(matches!(
+            client.update(&disputed),
+            Err(ClientError::Invoice(1, InvoiceError::AlreadyDisputed(d)))
+                if d == date(3)
+        ));
+        client
+            .update(&Update::DisputeResolved(1, date(10)))
+            .unwrap();
+        let invoice = client.invoice(&1).unwrap();
+        assert!(!invoice.is_disputed());
+        assert!(invoice.is_open());
+        assert!(matches!(
+            client.update(&Update::DisputeResolved(1, date(11))),
+            Err(ClientError::Invoice(1, InvoiceError::NotDisputed))
+        ));
+        client.update(&Update::Paid(1, date(20), None)).unwrap();
+        let dispute = client.invoice(&1).unwrap().disputed.clone().unwrap();
+        assert_eq!(dispute.resolved, Some(date(10)));
+
+        // Paying a disputed invoice resolves the dispute
+        client.update(&Update::Disputed(2, date(3), None)).unwrap();
+        client.update(&Update::Paid(2, date(15), None)).unwrap();
+        let invoice = client.invoice(&2).unwrap();
+        assert!(!invoice.is_disputed());
+        assert_eq!(invoice.disputed.as_ref().unwrap().resolved, Some(date(15)));
+        assert!(matches!(
+            client.update(&Update::Disputed(2, date(16), None)),
+            Err(ClientError::Invoice(2, InvoiceError::AlreadyPaid))
+        ));
+    }
+
+    #[test]
+    fn file_errors_name_the_file() {
+        let root = std::env::temp_dir().join("invogen-file-errors");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("history/inside")).unwrap();
+        let dir = root.join("history");
+
+        let error = events_from_file(&dir).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Could not read history file '{}': is a directory",
+                dir.display()
+            )
+        );
+
+        let missing = root.join("missing/client.history");
+        let error = events_to_file(&missing, &[]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "Could not create '{}' for writing: no such file or directory",
+                root.join("missing/client.updated").display()
+            )
+        );
+
+        // Renaming over a directory fails even where permissions don't
+        let error = events_to_file(&dir, &[]).unwrap_err();
+        let updated = root.join("history.updated");
+        assert!(matches!(
+            &error,
+            EventError::Io {
+                operation: FileOperation::Rename(to),
+                path,
+                ..
+            } if to == &dir && path == &updated
+        ));
+        assert!(error.to_string().contains(&format!(
+            "The changes were left in '{}'",
+            updated.display()
+        )));
+        assert!(updated.exists());
+    }
+
+    #[test]
+    fn io_reasons_without_error_codes() {
+        let denied = io::Error::from_raw_os_error(13);
+        assert_eq!(io_reason(&denied), "permission denied");
+        let custom = io::Error::other("Disk on fire");
+        assert_eq!(io_reason(&custom), "disk on fire");
+    }
+
+    /// Text as typed or pasted into prompts, with tabs and line breaks
+    fn text() -> impl Strategy<Value = String> {
+        "(\\PC|[\t\r\n])*"
+    }
+
+    fn date() -> impl Strategy<Value = NaiveDate> {
+        prop_oneof![
+            Just(NaiveDate::MIN),
+            Just(NaiveDate::MAX),
+            (-262_000i32..262_000, 1u32..=365).prop_map(|(year, day)| {
+                NaiveDate::from_yo_opt(year, day).unwrap()
+            }),
+        ]
+    }
+
+    fn timestamp() -> impl Strategy<Value = DateTime<Utc>> {
+        (date(), 0u32..86_400, 0u32..1_000_000_000).prop_map(
+            |(date, seconds, nanos)| {
+                let time = NaiveTime::from_num_seconds_from_midnight_opt(
+                    seconds, nanos,
+                );
+                date.and_time(time.unwrap()).and_utc()
+            },
+        )
+    }
+
+    /// Any decimal, from the smallest fractions to the largest amounts
+    fn decimal() -> impl Strategy<Value = Decimal> {
+        prop_oneof![
+            Just(Decimal::ZERO),
+            Just(Decimal::MAX),
+            Just(Decimal::MIN),
+            Just(Decimal::new(1, 28)),
+            (any::<i64>(), 0u32..=28)
+                .prop_map(|(mantissa, scale)| Decimal::new(mantissa, scale)),
+            (
+                any::<u32>(),
+                any::<u32>(),
+                any::<u32>(),
+                any::<bool>(),
+                0u32..=28
+            )
+                .prop_map(|(lo, mid, hi, negative, scale)| {
+                    Decimal::from_parts(lo, mid, hi, negative, scale)
+                }),
+        ]
+    }
+
+    fn currency() -> impl Strategy<Value = Currency> {
+        prop_oneof![
+            Just(Currency::Cad),
+            Just(Currency::Usd),
+            Just(Currency::Eur)
+        ]
+    }
+
+    fn money() -> impl Strategy<Value = Money> {
+        (currency(), decimal())
+            .prop_map(|(currency, amount)| Money::new(currency, amount))
+    }
+
+    fn unit() -> impl Strategy<Value = Unit> {
+        prop_oneof![
+            Just(Unit::Month),
+            Just(Unit::Week),
+            Just(Unit::Day),
+            Just(Unit::Hour),
+            Just(Unit::Quarter),
+            Just(Unit::Year),
+            any::<u8>().prop_map(Unit::EveryNWeeks),
+        ]
+    }
+
+    fn rate() -> impl Strategy<Value = Rate> {
+        let tiers = prop::collection::vec((decimal(), money()), 0..3);
+        (money(), unit(), tiers).prop_map(|(amount, per, tiers)| Rate {
+            amount,
+            per,
+            tiers,
+        })
+    }
+
+    fn period() -> impl Strategy<Value = Period> {
+        (date(), date()).prop_map(|(from, until)| Period::new(from, until))
+    }
+
+    fn tax_rates() -> impl Strategy<Value = Vec<TaxRate>> {
+        let rounding =
+            prop_oneof![Just(TaxRounding::HalfEven), Just(TaxRounding::HalfUp)];
+        let tax = (text(), decimal(), rounding)
+            .prop_map(|(name, rate, rounding)| TaxRate(name, rate, rounding));
+        prop::collection::vec(tax, 0..3)
+    }
+
+    fn item() -> impl Strategy<Value = InvoiceItem> {
+        let weeks = prop::collection::vec((date(), decimal()), 0..3);
+        let tiers = prop::collection::vec((decimal(), money()), 0..3);
+        (text(), rate(), period(), decimal(), money(), weeks, tiers).prop_map(
+            |(name, rate, period, quantity, amount, weeks, tiers)| {
+                InvoiceItem {
+                    name,
+                    rate,
+                    period,
+                    quantity,
+                    amount,
+                    weeks,
+                    tiers,
+                }
+            },
+        )
+    }
+
+    fn kind() -> impl Strategy<Value = InvoiceKind> {
+        let applies = prop::collection::vec((any::<usize>(), money()), 0..3);
+        prop_oneof![
+            Just(InvoiceKind::Standard),
+            Just(InvoiceKind::Deposit),
+            applies.prop_map(|applies| InvoiceKind::Final { applies }),
+        ]
+    }
+
+    fn received() -> impl Strategy<Value = Received> {
+        (money(), decimal()).prop_map(|(amount, booked_rate)| Received {
+            amount,
+            booked_rate,
+        })
+    }
+
+    fn rounding() -> impl Strategy<Value = RoundingPolicy> {
+        prop_oneof![Just(RoundingPolicy::EachTax), Just(RoundingPolicy::Total)]
+    }
+
+    fn any_invoice() -> impl Strategy<Value = Invoice> {
+        let items = prop::collection::vec(item(), 0..3);
+        let written_off = prop::option::of(
+            (date(), prop::option::of(text()))
+                .prop_map(|(date, reason)| WriteOff { date, reason }),
+        );
+        let voided = prop::option::of(
+            (date(), prop::option::of(text()))
+                .prop_map(|(date, reason)| Void { date, reason }),
+        );
+        let disputed = prop::option::of(
+            (date(), prop::option::of(text()), prop::option::of(date()))
+                .prop_map(|(date, reason, resolved)| Dispute {
+                    date,
+                    reason,
+                    resolved,
+                }),
+        );
+        let issued = (
+            date(),
+            any::<usize>(),
+            items,
+            tax_rates(),
+            prop::option::of(date()),
+            kind(),
+        );
+        let settled = (
+            written_off,
+            voided,
+            prop::option::of(received()),
+            rounding(),
+            prop::option::of(date()),
+            prop::option::of((currency(), decimal())),
+            disputed,
+        );
+        (issued, settled).prop_map(
+            |(
+                (date, number, items, tax_rates, paid, kind),
+                (written_off, voided, received, rounding, sent, fx, disputed),
+            )| Invoice {
+                date,
+                number,
+                items,
+                tax_rates,
+                paid,
+                kind,
+                written_off,
+                voided,
+                received,
+                rounding,
+                sent,
+                fx,
+                disputed,
+            },
+        )
+    }
+
+    fn update() -> impl Strategy<Value = Update> {
+        let reason = || prop::option::of(text());
+        let posting_date = prop_oneof![
+            Just(PostingDate::Issue),
+            Just(PostingDate::PeriodEnd),
+            Just(PostingDate::PeriodStart),
+        ];
+        prop_oneof![
+            text().prop_map(Update::Address),
+            (date(), text()).prop_map(|(d, a)| Update::EffectiveAddress(d, a)),
+            text().prop_map(Update::Name),
+            (text(), date(), rate())
+                .prop_map(|(s, d, r)| Update::ServiceRate(s, d, r)),
+            (text(), decimal())
+                .prop_map(|(s, q)| Update::ServiceDefaultQuantity(s, q)),
+            (text(), period(), decimal())
+                .prop_map(|(s, p, q)| Update::RetainerPurchased(s, p, q)),
+            (text(), date(), decimal())
+                .prop_map(|(s, d, q)| Update::RetainerConsumed(s, d, q)),
+            any_invoice().prop_map(Update::Invoiced),
+            (any::<usize>(), date(), prop::option::of(received()))
+                .prop_map(|(n, d, r)| Update::Paid(n, d, r)),
+            (any::<usize>(), date(), reason())
+                .prop_map(|(n, d, r)| Update::WrittenOff(n, d, r)),
+            (date(), tax_rates()).prop_map(|(d, t)| Update::Taxes(d, t)),
+            (reason(), reason(), reason()).prop_map(
+                |(accent, logo_path, template)| Update::TemplateOptions {
+                    accent,
+                    logo_path,
+                    template,
+                }
+            ),
+            text().prop_map(Update::Footer),
+            text().prop_map(Update::PaymentAccount),
+            rounding().prop_map(Update::Rounding),
+            text().prop_map(Update::Language),
+            any::<u32>().prop_map(Update::StaleRateAge),
+            (any::<usize>(), date(), reason())
+                .prop_map(|(n, d, r)| Update::Voided(n, d, r)),
+            (text(), text()).prop_map(|(a, b)| Update::ServicesMerged(a, b)),
+            (any::<usize>(), date()).prop_map(|(n, d)| Update::Sent(n, d)),
+            (text(), text()).prop_map(|(n, a)| Update::NamedAddress(n, a)),
+            text().prop_map(Update::InvoiceAddress),
+            (any::<usize>(), date(), reason())
+                .prop_map(|(n, d, r)| Update::Disputed(n, d, r)),
+            (any::<usize>(), date())
+                .prop_map(|(n, d)| Update::DisputeResolved(n, d)),
+            (text(), any::<u8>()).prop_map(|(service, day_of_month)| {
+                Update::Schedule {
+                    service,
+                    day_of_month,
+                }
+            }),
+            posting_date.prop_map(Update::PostingDate),
+            (text(), text()).prop_map(|(s, c)| Update::ServiceCategory(s, c)),
+            (text(), decimal())
+                .prop_map(|(s, h)| Update::ServiceTargetHours(s, h)),
+        ]
+    }
+
+    fn profile_update() -> impl Strategy<Value = ProfileUpdate> {
+        let position = prop_oneof![
+            Just(CommodityPosition::Before),
+            Just(CommodityPosition::After)
+        ];
+        let mark =
+            prop_oneof![Just(DecimalMark::Point), Just(DecimalMark::Comma)];
+        let style = (position, mark, any::<bool>(), any::<bool>()).prop_map(
+            |(position, decimal_mark, grouping, iso_code)| LedgerStyle {
+                position,
+                decimal_mark,
+                grouping,
+                iso_code,
+            },
+        );
+        let template =
+            (prop::collection::vec((text(), rate()), 0..3), tax_rates())
+                .prop_map(|(services, taxes)| ServiceTemplate {
+                    services,
+                    taxes,
+                });
+        prop_oneof![
+            text().prop_map(ProfileUpdate::Footer),
+            style.prop_map(ProfileUpdate::LedgerStyle),
+            text().prop_map(ProfileUpdate::PaymentAccount),
+            (text(), text()).prop_map(|(n, a)| ProfileUpdate::Sender(n, a)),
+            (text(), template)
+                .prop_map(|(n, t)| ProfileUpdate::ServiceTemplate(n, t)),
+            text().prop_map(ProfileUpdate::RemoveServiceTemplate),
+            text().prop_map(ProfileUpdate::InvoicePath),
+            decimal().prop_map(ProfileUpdate::HoursPerDay),
+            any::<bool>().prop_map(ProfileUpdate::GlobalNumbering),
+            (text(), text())
+                .prop_map(|(l, n)| ProfileUpdate::Registration(l, n)),
+            text().prop_map(ProfileUpdate::RemoveRegistration),
+        ]
+    }
+
+    fn event() -> impl Strategy<Value = Event> {
+        let change = prop_oneof![
+            (text(), text())
+                .prop_map(|(name, address)| Change::Added { name, address }),
+            update().prop_map(Change::Updated),
+            Just(Change::Removed),
+            profile_update().prop_map(Change::Profile),
+        ];
+        (
+            text(),
+            timestamp(),
+            change,
+            any::<bool>(),
+            prop::option::of(text()),
+            prop::option::of(text()),
+        )
+            .prop_map(
+                |(key, at, change, backdated, idempotency, origin)| {
+                    Event(key, at, change, backdated, idempotency, origin)
+                },
+            )
+    }
+
+    proptest! {
+        #[test]
+        fn events_round_trip(event in event()) {
+            let line = to_string(&event).unwrap();
+            prop_assert!(!line.contains('\n'), "{} spans lines", line);
+            prop_assert_eq!(from_str::<Event>(&line).expect(&line), event);
+        }
+
+        #[test]
+        fn histories_round_trip(
+            events in prop::collection::vec(event(), 0..8),
+        ) {
+            let path = std::env::temp_dir().join("invogen-round-trip.history");
+            events_to_file(&path, &events).unwrap();
+            let text = fs::read_to_string(&path).unwrap();
+            let read = events_from_file(&path).unwrap();
+            prop_assert_eq!(&read, &events);
+
+            // Rewriting what was read changes nothing
+            events_to_file(&path, &read).unwrap();
+            prop_assert_eq!(fs::read_to_string(&path).unwrap(), text);
+        }
+    }
+
+    /// Histories as earlier versions wrote them, in fixtures/ by format
+    fn fixture(format: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join(format!("{}.history", format))
+    }
+
+    #[test]
+    fn current_format_fixture() {
+        let path = fixture("current");
+        let text = fs::read_to_string(&path).unwrap();
+        let events = events_from_file(&path).unwrap();
+
+        assert_eq!(events.len(), text.lines().count());
+        // Rewriting a history leaves the lines already in it unchanged
+        for (event, line) in events.iter().zip(text.lines()) {
+            assert_eq!(event_line(event).unwrap(), format!("{}\n", line));
+        }
+    }
+
+    #[test]
+    fn float_decimals_fixture() -> Result<(), ClientError> {
+        // Decimals were written as floats, they read as the exact amounts
+        let events = events_from_file(&fixture("float-decimals")).unwrap();
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+
+        let client = clients.get(&"innotech".to_string())?;
+        assert_eq!(
+            client.invoice(&1)?.items[0].amount,
+            Money::new(Currency::Cad, Decimal::new(123456, 2))
+        );
+        let invoice = client.invoice(&2)?;
+        assert_eq!(invoice.items[0].quantity, Decimal::new(455, 1));
+        assert_eq!(invoice.tax_rates[1].1, Decimal::new(9975, 5));
+        assert_eq!(
+            invoice.received.as_ref().map(|r| r.booked_rate),
+            Some(Decimal::new(1372, 3))
+        );
+        assert_eq!(clients.profile.hours_per_day(), Decimal::new(75, 1));
+        // Rewriting them keeps the amounts exact
+        assert!(event_line(&events[1])
+            .unwrap()
+            .contains(r#"#(CAD "1234.56")"#));
+        Ok(())
+    }
+
+    #[test]
+    fn appending_keeps_existing_lines() -> Result<(), EventError> {
+        let root = std::env::temp_dir().join("invogen-append");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let path = root.join("client.history");
+        let renamed = Event::new_update(
+            "innotech",
+            Update::Name("Innotech Ltd.".to_string()),
+        );
+
+        // A hand edited history may be missing its last newline
+        let text = fs::read_to_string(fixture("current")).unwrap();
+        fs::write(&path, text.trim_end()).unwrap();
+        append_to_file(&path, slice::from_ref(&renamed))?;
+        let appended = fs::read_to_string(&path).unwrap();
+        assert_eq!(appended, text.clone() + &event_line(&renamed)?);
+
+        // Previous formats are rewritten with the events in the current one
+        fs::copy(fixture("0.1.3"), &path).unwrap();
+        append_to_file(&path, slice::from_ref(&renamed))?;
+        let mut expected = events_from_file(&fixture("0.1.3"))?;
+        expected.push(renamed);
+        assert_eq!(events_from_file(&path)?, expected);
+        assert!(is_current_format(&path)?);
+        Ok(())
+    }
+
+    #[test]
+    fn format_0_1_3_fixture() -> Result<(), ClientError> {
+        let events = events_from_file(&fixture("0.1.3")).unwrap();
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+
+        assert_eq!(events.len(), 10);
+        let client = clients.get(&"innotech".to_string())?;
+        assert_eq!(client.name, "Innotech Inc.");
+        assert_eq!(
+            client.invoice(&1)?.paid,
+            NaiveDate::from_ymd_opt(2023, 2, 20)
+        );
+        assert!(clients.get(&"bigco".to_string()).is_err());
+        Ok(())
+    }
 }
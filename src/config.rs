@@ -0,0 +1,354 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::cli::{Command, Listable};
+
+/// Config file read from the user's config directory and beside the history
+pub const CONFIG_FILE: &str = "invogen.toml";
+
+/// Defaults for command line flags, read from TOML files of settings:
+///
+/// ```toml
+/// file = "invoicing/client.history"
+/// convert-to = "CAD"
+/// no-color = true
+/// idempotency-days = 90
+/// confirm-key = 5000
+/// ```
+///
+/// Flags given on the command line and environment variables take
+/// precedence over these.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Config {
+    /// History file used without --file or $INVOGEN_FILE
+    pub file: Option<PathBuf>,
+    /// Reporting currency used without --convert-to
+    pub convert_to: Option<String>,
+    /// Don't colour listings, like --no-color
+    pub no_color: Option<bool>,
+    /// How many days back --idempotency-key looks for commands already
+    /// applied, otherwise the whole history
+    pub idempotency_days: Option<u32>,
+    /// Invoice total above which the client's key is typed to confirm, like
+    /// --confirm-key
+    pub confirm_key: Option<String>,
+}
+
+impl Config {
+    /// Reads a config file, a missing file sets nothing. Relative history
+    /// paths are relative to the config file and unknown settings are
+    /// warned about.
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                return Ok(Self::default());
+            }
+            Err(source) => return Err(ConfigError::Io(source)),
+        };
+        let (mut config, unknown) = Self::parse(&text)?;
+        for key in unknown {
+            eprintln!(
+                "Warning: unknown setting '{}' in {}",
+                key,
+                path.display()
+            );
+        }
+        if let Some(dir) = path.parent() {
+            config.file = config.file.map(|file| dir.join(file));
+        }
+        Ok(config)
+    }
+
+    /// The settings in a config file and the keys that aren't settings
+    pub fn parse(text: &str) -> Result<(Self, Vec<String>), ConfigError> {
+        let settings: Settings = toml::from_str(text)?;
+        let confirm_key = match settings.confirm_key {
+            None => None,
+            Some(Amount::Text(amount)) => Some(amount),
+            Some(Amount::Integer(amount)) => Some(amount.to_string()),
+            Some(Amount::Float(amount)) => Some(amount.to_string()),
+        };
+        let config = Config {
+            file: settings.file,
+            convert_to: settings.convert_to,
+            no_color: settings.no_color,
+            idempotency_days: settings.idempotency_days,
+            confirm_key,
+        };
+        Ok((config, settings.unknown.into_keys().collect()))
+    }
+
+    /// These settings with those set in another config taking precedence
+    pub fn merge(self, over: Config) -> Config {
+        Config {
+            file: over.file.or(self.file),
+            convert_to: over.convert_to.or(self.convert_to),
+            no_color: over.no_color.or(self.no_color),
+            idempotency_days: over.idempotency_days.or(self.idempotency_days),
+            confirm_key: over.confirm_key.or(self.confirm_key),
+        }
+    }
+
+    /// The command with the flags it was given, or the configured defaults
+    /// for those it wasn't
+    pub fn apply(&self, cmd: Command) -> Command {
+        let no_color = |flag: bool| flag || self.no_color.unwrap_or(false);
+        match cmd {
+            Command::List {
+                listing:
+                    Listable::Clients {
+                        convert_to,
+                        key_only,
+                        porcelain,
+                    },
+                no_color: flag,
+            } => Command::List {
+                listing: Listable::Clients {
+                    convert_to: convert_to.or_else(|| self.convert_to.clone()),
+                    key_only,
+                    porcelain,
+                },
+                no_color: no_color(flag),
+            },
+            Command::List {
+                listing,
+                no_color: flag,
+            } => Command::List {
+                listing,
+                no_color: no_color(flag),
+            },
+            Command::Unbilled { convert_to } => Command::Unbilled {
+                convert_to: convert_to.or_else(|| self.convert_to.clone()),
+            },
+            Command::Invoice {
+                client,
+                repeat_last,
+                deposit,
+                advance,
+                period,
+                weekly,
+                currency,
+                preview,
+                confirm_key,
+                timestamp,
+            } => Command::Invoice {
+                client,
+                repeat_last,
+                deposit,
+                advance,
+                period,
+                weekly,
+                currency,
+                preview,
+                confirm_key: confirm_key.or_else(|| self.confirm_key.clone()),
+                timestamp,
+            },
+            Command::Due {
+                generate,
+                confirm_key,
+            } => Command::Due {
+                generate,
+                confirm_key: confirm_key.or_else(|| self.confirm_key.clone()),
+            },
+            cmd => cmd,
+        }
+    }
+}
+
+/// A config file as written, with the keys that aren't settings
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Settings {
+    file: Option<PathBuf>,
+    convert_to: Option<String>,
+    no_color: Option<bool>,
+    idempotency_days: Option<u32>,
+    confirm_key: Option<Amount>,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, toml::Value>,
+}
+
+/// A --confirm-key amount, as a number or a string like "5,000"
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Amount {
+    Integer(i64),
+    Float(f64),
+    Text(String),
+}
+
+/// A string as a quoted and escaped TOML value
+fn quoted(text: &str) -> toml::Value {
+    toml::Value::String(text.to_string())
+}
+
+/// The user's config file, in $XDG_CONFIG_HOME or ~/.config
+pub fn user_config_path(env: &HashMap<String, String>) -> Option<PathBuf> {
+    let dir = match env.get("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env.get("HOME")?).join(".config"),
+    };
+    Some(dir.join("invogen").join(CONFIG_FILE))
+}
+
+/// The settings as a config file, unset settings are commented out
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.file {
+            Some(file) => {
+                let file = file.display().to_string();
+                writeln!(f, "file = {}", quoted(&file))?
+            }
+            None => writeln!(f, "# file =")?,
+        }
+        match &self.convert_to {
+            Some(code) => writeln!(f, "convert-to = {}", quoted(code))?,
+            None => writeln!(f, "# convert-to =")?,
+        }
+        match self.no_color {
+            Some(no_color) => writeln!(f, "no-color = {}", no_color)?,
+            None => writeln!(f, "# no-color =")?,
+        }
+        match self.idempotency_days {
+            Some(days) => writeln!(f, "idempotency-days = {}", days)?,
+            None => writeln!(f, "# idempotency-days =")?,
+        }
+        match &self.confirm_key {
+            Some(amount) => writeln!(f, "confirm-key = {}", quoted(amount)),
+            None => writeln!(f, "# confirm-key ="),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Config {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("Reading config: {0}")]
+    Io(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_settings() {
+        let (config, unknown) = Config::parse(
+            "# defaults\n\
+             file = \"books#2/client.history\"\n\
+             convert-to = 'CAD' # reporting\n\
+             no-color = true\n\
+             idempotency-days = 90\n\
+             confirm-key = 5000\n\
+             pdf-engine = \"lualatex\"\n\
+             [latex]\n\
+             engine = \"xelatex\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                file: Some(PathBuf::from("books#2/client.history")),
+                convert_to: Some("CAD".to_string()),
+                no_color: Some(true),
+                idempotency_days: Some(90),
+                confirm_key: Some("5000".to_string()),
+            }
+        );
+        assert_eq!(unknown, vec!["latex", "pdf-engine"]);
+
+        let (config, _) = Config::parse("confirm-key = \"5,000\"").unwrap();
+        assert_eq!(config.confirm_key.as_deref(), Some("5,000"));
+
+        for text in [
+            "\nconvert-to CAD",
+            "no-color = \"yes\"",
+            "idempotency-days = -1",
+        ] {
+            assert!(matches!(Config::parse(text), Err(ConfigError::Toml(_))));
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        let user = Config {
+            convert_to: Some("CAD".to_string()),
+            no_color: Some(true),
+            ..Config::default()
+        };
+        let local = Config {
+            convert_to: Some("USD".to_string()),
+            ..Config::default()
+        };
+        let config = user.merge(local);
+        assert_eq!(config.convert_to.as_deref(), Some("USD"));
+        assert_eq!(config.no_color, Some(true));
+
+        let unbilled = |convert_to: Option<&str>| Command::Unbilled {
+            convert_to: convert_to.map(String::from),
+        };
+        assert!(matches!(
+            config.apply(unbilled(None)),
+            Command::Unbilled { convert_to: Some(code) } if code == "USD"
+        ));
+        assert!(matches!(
+            config.apply(unbilled(Some("EUR"))),
+            Command::Unbilled { convert_to: Some(code) } if code == "EUR"
+        ));
+        assert!(matches!(
+            Config::default().apply(unbilled(None)),
+            Command::Unbilled { convert_to: None }
+        ));
+    }
+
+    #[test]
+    fn user_config_location() {
+        let env = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            user_config_path(&env(&[
+                ("XDG_CONFIG_HOME", "/xdg"),
+                ("HOME", "/h")
+            ])),
+            Some(PathBuf::from("/xdg/invogen/invogen.toml"))
+        );
+        assert_eq!(
+            user_config_path(&env(&[("HOME", "/h")])),
+            Some(PathBuf::from("/h/.config/invogen/invogen.toml"))
+        );
+        assert_eq!(user_config_path(&env(&[])), None);
+    }
+
+    #[test]
+    fn display_as_config_file() {
+        let mut config = Config {
+            convert_to: Some("CAD".to_string()),
+            ..Config::default()
+        };
+        assert_eq!(
+            config.to_string(),
+            "# file =\nconvert-to = \"CAD\"\n# no-color =\n\
+             # idempotency-days =\n# confirm-key =\n"
+        );
+        let (parsed, _) = Config::parse(&config.to_string()).unwrap();
+        assert_eq!(parsed, config);
+
+        config.file =
+            Some(PathBuf::from("C:\\books\\\"2024\"\\client.history"));
+        config.confirm_key = Some("5,000".to_string());
+        let (parsed, _) = Config::parse(&config.to_string()).unwrap();
+        assert_eq!(parsed, config);
+    }
+}
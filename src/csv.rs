@@ -0,0 +1,217 @@
+use std::borrow::Cow;
+
+use rust_decimal::Decimal;
+
+use crate::billing::{CurrencyMismatch, Invoice, Money};
+
+const HEADER: [&str; 10] = [
+    "invoice_number",
+    "date",
+    "service",
+    "period_from",
+    "period_until",
+    "quantity",
+    "unit",
+    "rate",
+    "amount",
+    "currency",
+];
+
+/// Line items of an invoice as CSV, one row per item followed by rows for
+/// the subtotal, each tax, and the total
+pub fn invoice_items(invoice: &Invoice) -> Result<String, CurrencyMismatch> {
+    let number = invoice.number.to_string();
+    let date = invoice.date.to_string();
+    let mut rows = vec![HEADER.map(String::from)];
+
+    for item in invoice.items.iter() {
+        rows.push([
+            number.clone(),
+            date.clone(),
+            item.name.clone(),
+            item.period.from.to_string(),
+            item.period.until.to_string(),
+            format!("{:.2}", item.quantity),
+            item.rate.per.to_string(),
+            format!("{:.2}", item.rate.amount.amount()),
+            format!("{:.2}", item.amount.amount()),
+            item.amount.currency().code().to_string(),
+        ]);
+    }
+
+    let summary = |label: &str, rate: String, amount: &Money| {
+        [
+            number.clone(),
+            date.clone(),
+            label.to_string(),
+            String::new(),
+            String::new(),
+            String::new(),
+            String::new(),
+            rate,
+            format!("{:.2}", amount.amount()),
+            amount.currency().code().to_string(),
+        ]
+    };
+    let total = invoice.calculate()?;
+    rows.push(summary("Subtotal", String::new(), &total.subtotal));
+    for (tax_rate, amount) in total.taxes.iter() {
+        let percent = (tax_rate.1 * Decimal::from(100)).normalize();
+        rows.push(summary(&tax_rate.0, format!("{}%", percent), amount));
+    }
+    if let Some(adjustment) = &total.adjustment {
+        rows.push(summary("Rounding", String::new(), adjustment));
+    }
+    for (number, amount) in total.deductions.iter() {
+        let label = format!("Deposit #{}", number);
+        rows.push(summary(&label, String::new(), amount));
+    }
+    rows.push(summary("Total", String::new(), &total.total));
+
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let fields: Vec<Cow<str>> =
+                row.iter().map(|field| quote(field)).collect();
+            fields.join(",") + "\r\n"
+        })
+        .collect())
+}
+
+/// Quotes a field per RFC 4180 when it contains a delimiter, quote, or
+/// line break, doubling any embedded quotes
+fn quote(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\r', '\n']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// Splits RFC 4180 text into records, each with the line it starts on. An
+/// unterminated quote is an error with the line the quote opened on
+pub fn records(text: &str) -> Result<Vec<(usize, Vec<String>)>, usize> {
+    let mut records = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while chars.peek().is_some() {
+        let start = line;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        loop {
+            match chars.next() {
+                Some('"') => {
+                    let opened = line;
+                    loop {
+                        match chars.next() {
+                            Some('"') if chars.peek() == Some(&'"') => {
+                                chars.next();
+                                field.push('"');
+                            }
+                            Some('"') => break,
+                            Some(c) => {
+                                if c == '\n' {
+                                    line += 1;
+                                }
+                                field.push(c);
+                            }
+                            None => return Err(opened),
+                        }
+                    }
+                }
+                Some(',') => fields.push(std::mem::take(&mut field)),
+                Some('\r') if chars.peek() == Some(&'\n') => {}
+                Some('\n') | None => {
+                    line += 1;
+                    fields.push(field);
+                    break;
+                }
+                Some(c) => field.push(c),
+            }
+        }
+        if fields.iter().any(|f| !f.is_empty()) {
+            records.push((start, fields));
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::billing::tests::{monthly, ymd};
+    use crate::billing::{Currency, InvoiceItem, Period, TaxRate};
+
+    fn invoice_with(name: &str) -> Invoice {
+        let item = InvoiceItem::new(
+            name.to_string(),
+            monthly(Currency::Cad, 1000),
+            Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30)),
+        );
+        let mut invoice = Invoice::new(
+            7,
+            vec![item],
+            vec![
+                TaxRate::new("GST".to_string(), 5),
+                TaxRate::new("PST, BC".to_string(), 7),
+            ],
+        )
+        .unwrap();
+        invoice.date = ymd(2024, 5, 1);
+        invoice
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote("plain"), "plain");
+        assert_eq!(quote("a,b"), "\"a,b\"");
+        assert_eq!(quote("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(quote("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn items_and_summary_rows() {
+        assert_eq!(
+            invoice_items(&invoice_with("Design, development")).unwrap(),
+            "invoice_number,date,service,period_from,period_until,quantity,\
+             unit,rate,amount,currency\r\n\
+             7,2024-05-01,\"Design, development\",2024-04-01,2024-04-30,\
+             1.00,Month,1000.00,1000.00,CAD\r\n\
+             7,2024-05-01,Subtotal,,,,,,1000.00,CAD\r\n\
+             7,2024-05-01,GST,,,,,5%,50.00,CAD\r\n\
+             7,2024-05-01,\"PST, BC\",,,,,7%,70.00,CAD\r\n\
+             7,2024-05-01,Total,,,,,,1120.00,CAD\r\n"
+        );
+    }
+
+    #[test]
+    fn multi_line_service_name() {
+        let csv = invoice_items(&invoice_with("Support\n\"on call\"")).unwrap();
+        assert!(csv
+            .contains("7,2024-05-01,\"Support\n\"\"on call\"\"\",2024-04-01,"));
+    }
+
+    #[test]
+    fn reading_records() {
+        let text = "a,\"b,c\"\r\n\r\n\"two\nlines\",\"\"\"q\"\"\"\nlast";
+        assert_eq!(
+            records(text),
+            Ok(vec![
+                (1, vec!["a".to_string(), "b,c".to_string()]),
+                (3, vec!["two\nlines".to_string(), "\"q\"".to_string()]),
+                (5, vec!["last".to_string()]),
+            ])
+        );
+        assert_eq!(records("a\n\"open,b\n"), Err(2));
+    }
+
+    #[test]
+    fn written_items_read_back() {
+        let csv = invoice_items(&invoice_with("Support\n\"on call\"")).unwrap();
+        let rows = records(&csv).unwrap();
+        assert_eq!(rows.len(), 6);
+        assert_eq!(rows[1].1[2], "Support\n\"on call\"");
+        assert_eq!(rows[2].0, 4);
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use crate::billing::{Currency, Money};
+
+/// Exchange rates file kept beside the history file
+pub const RATES_FILE: &str = "rates.toml";
+
+/// Dated exchange rates from one currency to another, read from a small
+/// TOML style file with a table per currency pair:
+///
+/// ```toml
+/// [USD.CAD]
+/// 2024-01-02 = 1.3316
+/// 2024-05-01 = 1.3751
+/// ```
+#[derive(Debug, Default, PartialEq)]
+pub struct ExchangeRates {
+    rates: BTreeMap<(&'static str, &'static str), BTreeMap<NaiveDate, Decimal>>,
+}
+
+impl ExchangeRates {
+    /// Reads the rates file, a missing file has no rates
+    pub fn from_file(path: &Path) -> Result<Self, RatesError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                Ok(Self::default())
+            }
+            Err(source) => Err(RatesError::Io(source)),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, RatesError> {
+        let mut rates = Self::default();
+        let mut pair = None;
+        for (i, line) in text.lines().enumerate() {
+            let line_num = i + 1;
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(table) =
+                line.strip_prefix('[').and_then(|l| l.strip_suffix(']'))
+            {
+                let (from, to) = table
+                    .split_once('.')
+                    .and_then(|(from, to)| {
+                        let from = Currency::from_code(from.trim())?;
+                        Some((from, Currency::from_code(to.trim())?))
+                    })
+                    .ok_or(RatesError::Pair(line_num))?;
+                pair = Some((from, to));
+                continue;
+            }
+
+            let (from, to) = pair.ok_or(RatesError::NoPair(line_num))?;
+            let (date, rate) =
+                line.split_once('=').ok_or(RatesError::Entry(line_num))?;
+            let date = date
+                .trim()
+                .trim_matches('"')
+                .parse()
+                .map_err(|_| RatesError::Entry(line_num))?;
+            let rate = rate
+                .trim()
+                .trim_matches('"')
+                .parse()
+                .map_err(|_| RatesError::Entry(line_num))?;
+            rates.insert(from, to, date, rate);
+        }
+        Ok(rates)
+    }
+
+    pub fn insert(
+        &mut self,
+        from: Currency,
+        to: Currency,
+        date: NaiveDate,
+        rate: Decimal,
+    ) {
+        self.rates
+            .entry((from.code(), to.code()))
+            .or_default()
+            .insert(date, rate);
+    }
+
+    /// The rate in effect on a date, the nearest earlier dated one, along
+    /// with the date it's from
+    pub fn rate(
+        &self,
+        from: Currency,
+        to: Currency,
+        date: NaiveDate,
+    ) -> Result<(NaiveDate, Decimal), MissingRate> {
+        if from == to {
+            return Ok((date, Decimal::ONE));
+        }
+        self.rates
+            .get(&(from.code(), to.code()))
+            .and_then(|rates| rates.range(..=date).next_back())
+            .map(|(date, rate)| (*date, *rate))
+            .ok_or(MissingRate(from, to, date))
+    }
+}
+
+/// Amounts converted to one currency, with the rates used and the amounts
+/// left out for lack of a rate
+#[derive(Debug, PartialEq)]
+pub struct Converted {
+    pub total: Money,
+    pub rates: Vec<(Currency, NaiveDate, Decimal)>,
+    pub missing: Vec<(String, MissingRate)>,
+}
+
+impl ExchangeRates {
+    /// Totals labelled amounts in one currency, each converted at the rate
+    /// in effect on its date
+    pub fn convert_all(
+        &self,
+        amounts: impl IntoIterator<Item = (String, Money, NaiveDate)>,
+        to: Currency,
+    ) -> Converted {
+        let mut converted = Converted {
+            total: Money::new(to, Decimal::ZERO),
+            rates: Vec::new(),
+            missing: Vec::new(),
+        };
+        for (label, amount, date) in amounts {
+            match self.rate(amount.currency(), to, date) {
+                Ok((rate_date, rate)) => {
+                    converted.total = converted.total
+                        + Money::new(to, amount.amount()) * rate;
+                    let used = (amount.currency(), rate_date, rate);
+                    if amount.currency() != to
+                        && !converted.rates.contains(&used)
+                    {
+                        converted.rates.push(used);
+                    }
+                }
+                Err(missing) => converted.missing.push((label, missing)),
+            }
+        }
+        converted
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+#[error("No {} to {} exchange rate on or before {2}", .0.code(), .1.code())]
+pub struct MissingRate(pub Currency, pub Currency, pub NaiveDate);
+
+#[derive(Debug, Error)]
+pub enum RatesError {
+    #[error("Rates line {0}: expected a currency pair like [USD.CAD]")]
+    Pair(usize),
+    #[error("Rates line {0}: rate before any [FROM.TO] currency pair")]
+    NoPair(usize),
+    #[error("Rates line {0}: expected a date and rate like 2024-05-01 = 1.37")]
+    Entry(usize),
+    #[error("Reading rates: {0}")]
+    Io(#[from] io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ymd(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn table() -> ExchangeRates {
+        ExchangeRates::parse(
+            "# monthly rates\n\
+             [USD.CAD]\n\
+             2024-01-02 = 1.3316\n\
+             2024-05-01 = 1.3751  # bank of canada\n\
+             \n\
+             [EUR.CAD]\n\
+             \"2024-03-01\" = 1.4702\n",
+        )
+        .unwrap()
+    }
+
+    /// One amount converted at the rate in effect on a date
+    fn convert(
+        amount: Money,
+        rates: &ExchangeRates,
+        to: Currency,
+        date: NaiveDate,
+    ) -> Result<Money, MissingRate> {
+        let mut converted =
+            rates.convert_all([(String::new(), amount, date)], to);
+        match converted.missing.pop() {
+            Some((_, missing)) => Err(missing),
+            None => Ok(converted.total),
+        }
+    }
+
+    #[test]
+    fn nearest_earlier_rate() {
+        let rates = table();
+        let usd = Money::new(Currency::Usd, Decimal::from(100));
+
+        assert_eq!(
+            rates.rate(Currency::Usd, Currency::Cad, ymd(2024, 4, 30)),
+            Ok((ymd(2024, 1, 2), Decimal::new(13316, 4)))
+        );
+        assert_eq!(
+            convert(usd, &rates, Currency::Cad, ymd(2024, 5, 1)),
+            Ok(Money::new(Currency::Cad, Decimal::new(13751, 2)))
+        );
+        assert_eq!(
+            convert(usd, &rates, Currency::Cad, ymd(2025, 1, 1)),
+            Ok(Money::new(Currency::Cad, Decimal::new(13751, 2)))
+        );
+    }
+
+    #[test]
+    fn missing_rates() {
+        let rates = table();
+        let eur = Money::new(Currency::Eur, Decimal::from(10));
+
+        assert_eq!(
+            convert(eur, &rates, Currency::Cad, ymd(2024, 2, 1)),
+            Err(MissingRate(Currency::Eur, Currency::Cad, ymd(2024, 2, 1)))
+        );
+        assert!(convert(eur, &rates, Currency::Usd, ymd(2024, 5, 1)).is_err());
+        assert_eq!(
+            convert(eur, &rates, Currency::Eur, ymd(2020, 1, 1)),
+            Ok(eur)
+        );
+    }
+
+    #[test]
+    fn convert_all_warns_per_item() {
+        let rates = table();
+        let cad = |cents| Money::new(Currency::Cad, Decimal::new(cents, 2));
+        let converted = rates.convert_all(
+            vec![
+                ("#1".to_string(), cad(10000), ymd(2024, 1, 15)),
+                (
+                    "#2".to_string(),
+                    Money::new(Currency::Usd, Decimal::from(100)),
+                    ymd(2024, 6, 1),
+                ),
+                (
+                    "#3".to_string(),
+                    Money::new(Currency::Eur, Decimal::from(100)),
+                    ymd(2024, 2, 1),
+                ),
+            ],
+            Currency::Cad,
+        );
+
+        assert_eq!(converted.total, cad(23751));
+        assert_eq!(
+            converted.rates,
+            vec![(Currency::Usd, ymd(2024, 5, 1), Decimal::new(13751, 4))]
+        );
+        assert_eq!(
+            converted.missing,
+            vec![(
+                "#3".to_string(),
+                MissingRate(Currency::Eur, Currency::Cad, ymd(2024, 2, 1))
+            )]
+        );
+    }
+
+    #[test]
+    fn malformed_rates() {
+        assert!(matches!(
+            ExchangeRates::parse("[USD.XYZ]\n"),
+            Err(RatesError::Pair(1))
+        ));
+        assert!(matches!(
+            ExchangeRates::parse("2024-01-01 = 1.3\n"),
+            Err(RatesError::NoPair(1))
+        ));
+        assert!(matches!(
+            ExchangeRates::parse("[USD.CAD]\n2024-01-01 = lots\n"),
+            Err(RatesError::Entry(2))
+        ));
+    }
+
+    #[test]
+    fn missing_file_has_no_rates() {
+        let path = Path::new("/nonexistent/invogen/rates.toml");
+        assert_eq!(
+            ExchangeRates::from_file(path).unwrap(),
+            ExchangeRates::default()
+        );
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use serde::{ser, Deserialize, Serialize};
+
+use crate::billing::{
+    CurrencyMismatch, Invoice, InvoiceItem, InvoiceKind, Rate, Service,
+};
+use crate::clients::{Client, Clients, BILLING_ADDRESS};
+
+/// The replayed clients as a TOML document, with dates and amounts as
+/// strings so nothing is rounded
+pub fn clients_toml(clients: &Clients) -> Result<String, toml::ser::Error> {
+    let export = Export::new(clients).map_err(ser::Error::custom)?;
+    toml::to_string(&export)
+}
+
+/// Everything known about the clients, ordered by key, date, and number so
+/// exports of the same history are identical
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Export {
+    pub clients: BTreeMap<String, ExportClient>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ExportClient {
+    pub name: String,
+    pub address: String,
+    /// Addresses besides the billing address, by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub addresses: BTreeMap<String, String>,
+    #[serde(default)]
+    pub services: BTreeMap<String, ExportService>,
+    #[serde(default)]
+    pub taxes: Vec<ExportTaxes>,
+    #[serde(default)]
+    pub retainers: Vec<ExportRetainer>,
+    #[serde(default)]
+    pub invoices: Vec<ExportInvoice>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ExportService {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_hours: Option<String>,
+    /// Day of the month the service is invoiced on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schedule: Option<u8>,
+    /// Category the service's revenue is reported under
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    /// Hours agreed to be billed every month
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_hours: Option<String>,
+    pub rates: Vec<ExportRate>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ExportRate {
+    pub effective: String,
+    pub amount: String,
+    pub currency: String,
+    pub per: String,
+}
+
+/// The taxes in effect from a date
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ExportTaxes {
+    pub effective: String,
+    pub taxes: Vec<ExportTax>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ExportTax {
+    pub name: String,
+    /// Fraction of the subtotal, e.g. 0.05
+    pub rate: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ExportRetainer {
+    pub service: String,
+    pub from: String,
+    pub until: String,
+    pub hours: String,
+    pub consumed: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ExportInvoice {
+    pub number: usize,
+    pub date: String,
+    /// standard, deposit, or final
+    pub kind: String,
+    pub currency: String,
+    pub subtotal: String,
+    pub total: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sent: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub written_off: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub voided: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disputed: Option<String>,
+    pub items: Vec<ExportItem>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ExportItem {
+    pub name: String,
+    pub from: String,
+    pub until: String,
+    pub quantity: String,
+    pub rate: String,
+    pub per: String,
+    pub amount: String,
+}
+
+impl Export {
+    pub fn new(clients: &Clients) -> Result<Self, CurrencyMismatch> {
+        let clients = clients
+            .iter()
+            .map(|client| Ok((client.key.clone(), ExportClient::new(client)?)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { clients })
+    }
+}
+
+impl ExportClient {
+    fn new(client: &Client) -> Result<Self, CurrencyMismatch> {
+        let addresses = client
+            .addresses()
+            .filter(|(name, _)| *name != BILLING_ADDRESS)
+            .map(|(name, address)| (name.to_string(), address.to_string()))
+            .collect();
+        let taxes = client
+            .tax_history()
+            .iter()
+            .map(|(effective, taxes)| ExportTaxes {
+                effective: effective.to_string(),
+                taxes: taxes
+                    .iter()
+                    .map(|tax| ExportTax {
+                        name: tax.0.clone(),
+                        rate: tax.1.to_string(),
+                    })
+                    .collect(),
+            })
+            .collect();
+        let retainers = client
+            .retainers()
+            .map(|(service, retainer)| ExportRetainer {
+                service: service.to_string(),
+                from: retainer.period.from.to_string(),
+                until: retainer.period.until.to_string(),
+                hours: retainer.hours.to_string(),
+                consumed: retainer.consumed.to_string(),
+            })
+            .collect();
+        Ok(Self {
+            name: client.name.clone(),
+            address: client.current_address().to_string(),
+            addresses,
+            services: client
+                .services
+                .values()
+                .map(|service| {
+                    (service.name.clone(), ExportService::new(service))
+                })
+                .collect(),
+            taxes,
+            retainers,
+            invoices: client
+                .invoices()
+                .map(ExportInvoice::new)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl ExportService {
+    fn new(service: &Service) -> Self {
+        Self {
+            default_hours: service.default_quantity.map(|h| h.to_string()),
+            schedule: service.schedule,
+            category: service.category.clone(),
+            target_hours: service.target_hours.map(|h| h.to_string()),
+            rates: service
+                .rates
+                .iter()
+                .map(|(effective, rate)| ExportRate::new(effective, rate))
+                .collect(),
+        }
+    }
+}
+
+impl ExportRate {
+    fn new(effective: &NaiveDate, rate: &Rate) -> Self {
+        Self {
+            effective: effective.to_string(),
+            amount: rate.amount.amount().to_string(),
+            currency: rate.amount.currency().code().to_string(),
+            per: rate.per.to_string(),
+        }
+    }
+}
+
+impl ExportInvoice {
+    fn new(invoice: &Invoice) -> Result<Self, CurrencyMismatch> {
+        let total = invoice.calculate()?;
+        let date = |date: Option<NaiveDate>| date.map(|d| d.to_string());
+        Ok(Self {
+            number: invoice.number,
+            date: invoice.date.to_string(),
+            kind: match invoice.kind {
+                InvoiceKind::Standard => "standard",
+                InvoiceKind::Deposit => "deposit",
+                InvoiceKind::Final { .. } => "final",
+            }
+            .to_string(),
+            currency: invoice.currency().code().to_string(),
+            subtotal: total.subtotal.amount().to_string(),
+            total: total.total.amount().to_string(),
+            sent: date(invoice.sent),
+            paid: date(invoice.paid),
+            written_off: date(invoice.written_off.as_ref().map(|w| w.date)),
+            voided: date(invoice.voided.as_ref().map(|v| v.date)),
+            disputed: date(invoice.disputed.as_ref().map(|d| d.date)),
+            items: invoice.items.iter().map(ExportItem::new).collect(),
+        })
+    }
+}
+
+impl ExportItem {
+    fn new(item: &InvoiceItem) -> Self {
+        Self {
+            name: item.name.clone(),
+            from: item.period.from.to_string(),
+            until: item.period.until.to_string(),
+            quantity: item.quantity.to_string(),
+            rate: item.rate.amount.amount().to_string(),
+            per: item.rate.per.to_string(),
+            amount: item.amount.amount().to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::billing::{Currency, Money, Period, TaxRate, TaxRounding, Unit};
+    use crate::clients::Update;
+    use rust_decimal::Decimal;
+
+    fn ymd(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn clients() -> Clients {
+        let key = "innotech".to_string();
+        let mut clients = Clients::new();
+        let client = Client::new(&key, "Innotech", "Some Place\nCanada");
+        clients.add(&key, client).unwrap();
+        let rate = Rate {
+            amount: Money::new(Currency::Cad, Decimal::new(123456, 2)),
+            per: Unit::Month,
+            tiers: Vec::new(),
+        };
+        let taxes = vec![TaxRate(
+            "QST".to_string(),
+            Decimal::new(9975, 5),
+            TaxRounding::HalfEven,
+        )];
+        let april = Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30));
+        let item = InvoiceItem::new("Stuff".to_string(), rate.clone(), april);
+        let mut invoice = Invoice::new(1, vec![item], taxes.clone()).unwrap();
+        invoice.date = ymd(2024, 5, 1);
+        for update in [
+            Update::ServiceRate("Stuff".to_string(), ymd(2024, 1, 1), rate),
+            Update::Taxes(ymd(2024, 1, 1), taxes),
+            Update::NamedAddress(
+                "registered".to_string(),
+                "1 \"Main\" St".to_string(),
+            ),
+            Update::Invoiced(invoice),
+            Update::Paid(1, ymd(2024, 5, 20), None),
+        ] {
+            clients.update(&key, &update).unwrap();
+        }
+        clients
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        let clients = clients();
+        let text = clients_toml(&clients).unwrap();
+        assert_eq!(text, clients_toml(&clients).unwrap());
+
+        let read: Export = toml::from_str(&text).unwrap();
+        assert_eq!(read, Export::new(&clients).unwrap());
+        let client = &read.clients["innotech"];
+        assert_eq!(client.address, "Some Place\nCanada");
+        assert_eq!(client.addresses["registered"], "1 \"Main\" St");
+        assert_eq!(client.taxes[0].taxes[0].rate, "0.09975");
+        assert_eq!(client.services["Stuff"].rates[0].amount, "1234.56");
+        let invoice = &client.invoices[0];
+        assert_eq!(invoice.paid.as_deref(), Some("2024-05-20"));
+        assert_eq!(invoice.items[0].from, "2024-04-01");
+        assert!(text.contains("rate = \"0.09975\""));
+    }
+}
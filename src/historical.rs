@@ -1,4 +1,5 @@
 use std::collections::BTreeMap;
+use std::ops::RangeBounds;
 
 use chrono::{Local, NaiveDate};
 use serde::{Deserialize, Serialize};
@@ -16,10 +17,7 @@ impl<T: Clone> Historical<T> {
     }
 
     pub fn as_of(&self, date: NaiveDate) -> Option<&T> {
-        self.history
-            .range(..=date)
-            .next_back()
-            .map(|(_, item)| item)
+        self.range(..=date).next_back().map(|(_, item)| item)
     }
 
     pub fn current(&self) -> Option<&T> {
@@ -29,4 +27,105 @@ impl<T: Clone> Historical<T> {
     pub fn insert(&mut self, effective: &NaiveDate, item: &T) {
         self.history.insert(*effective, item.clone());
     }
+
+    /// Entries in order of their effective dates
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&NaiveDate, &T)> {
+        self.history.iter()
+    }
+
+    /// Entries effective within the given dates, in order
+    pub fn range<R: RangeBounds<NaiveDate>>(
+        &self,
+        dates: R,
+    ) -> impl DoubleEndedIterator<Item = (&NaiveDate, &T)> {
+        self.history.range(dates)
+    }
+
+    pub fn len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.history.is_empty()
+    }
+
+    pub fn first(&self) -> Option<(&NaiveDate, &T)> {
+        self.history.first_key_value()
+    }
+
+    pub fn last(&self) -> Option<(&NaiveDate, &T)> {
+        self.history.last_key_value()
+    }
+
+    pub fn effective_dates(&self) -> impl Iterator<Item = &NaiveDate> {
+        self.history.keys()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ymd(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn history() -> Historical<&'static str> {
+        let mut history = Historical::new();
+        history.insert(&ymd(2024, 6, 1), &"second");
+        history.insert(&ymd(2024, 1, 1), &"first");
+        history.insert(&ymd(2025, 1, 1), &"third");
+        history
+    }
+
+    #[test]
+    fn as_of_boundaries() {
+        let history = history();
+
+        assert_eq!(history.as_of(ymd(2023, 12, 31)), None);
+        assert_eq!(history.as_of(ymd(2024, 1, 1)), Some(&"first"));
+        assert_eq!(history.as_of(ymd(2024, 5, 31)), Some(&"first"));
+        assert_eq!(history.as_of(ymd(2024, 6, 1)), Some(&"second"));
+        assert_eq!(history.as_of(ymd(2030, 1, 1)), Some(&"third"));
+    }
+
+    #[test]
+    fn ordered_access() {
+        let history = history();
+        let dates: Vec<&NaiveDate> = history.effective_dates().collect();
+
+        assert_eq!(
+            dates,
+            vec![&ymd(2024, 1, 1), &ymd(2024, 6, 1), &ymd(2025, 1, 1)]
+        );
+        assert_eq!(history.len(), 3);
+        assert!(!history.is_empty());
+        assert_eq!(history.first(), Some((&ymd(2024, 1, 1), &"first")));
+        assert_eq!(history.last(), Some((&ymd(2025, 1, 1), &"third")));
+        assert_eq!(
+            history.iter().map(|(_, item)| *item).collect::<Vec<_>>(),
+            vec!["first", "second", "third"]
+        );
+    }
+
+    #[test]
+    fn range_is_inclusive() {
+        let history = history();
+        let within: Vec<&str> = history
+            .range(ymd(2024, 1, 1)..=ymd(2024, 6, 1))
+            .map(|(_, item)| *item)
+            .collect();
+
+        assert_eq!(within, vec!["first", "second"]);
+        assert_eq!(history.range(ymd(2024, 1, 2)..ymd(2024, 6, 1)).count(), 0);
+    }
+
+    #[test]
+    fn empty_history() {
+        let history: Historical<&str> = Historical::new();
+
+        assert!(history.is_empty());
+        assert_eq!(history.first(), None);
+        assert_eq!(history.current(), None);
+    }
 }
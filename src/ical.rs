@@ -0,0 +1,166 @@
+use chrono::{DateTime, NaiveDate, Utc};
+
+/// RFC 5545 limits content lines to 75 octets excluding the line break
+const MAX_LINE_OCTETS: usize = 75;
+
+pub struct CalendarEvent {
+    pub uid: String,
+    pub start: NaiveDate,
+    pub summary: String,
+    pub rrule: Option<String>,
+}
+
+pub struct Calendar {
+    events: Vec<CalendarEvent>,
+}
+
+impl Calendar {
+    pub fn new() -> Self {
+        Self { events: Vec::new() }
+    }
+
+    pub fn push(&mut self, event: CalendarEvent) {
+        self.events.push(event);
+    }
+
+    pub fn render(&self, stamp: DateTime<Utc>) -> String {
+        let mut lines = vec![
+            "BEGIN:VCALENDAR".to_string(),
+            "VERSION:2.0".to_string(),
+            "PRODID:-//invogen//invogen//EN".to_string(),
+            "CALSCALE:GREGORIAN".to_string(),
+        ];
+
+        for event in self.events.iter() {
+            lines.push("BEGIN:VEVENT".to_string());
+            lines.push(format!("UID:{}", escape_text(&event.uid)));
+            lines.push(format!("DTSTAMP:{}", stamp.format("%Y%m%dT%H%M%SZ")));
+            lines.push(format!(
+                "DTSTART;VALUE=DATE:{}",
+                event.start.format("%Y%m%d")
+            ));
+            lines.push(format!("SUMMARY:{}", escape_text(&event.summary)));
+            if let Some(rule) = &event.rrule {
+                lines.push(format!("RRULE:{}", rule));
+            }
+            lines.push("END:VEVENT".to_string());
+        }
+        lines.push("END:VCALENDAR".to_string());
+
+        lines.iter().map(|l| fold_line(l) + "\r\n").collect()
+    }
+}
+
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > MAX_LINE_OCTETS {
+            folded.push_str("\r\n ");
+            octets = 1;
+        }
+        folded.push(c);
+        octets += c.len_utf8();
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn escapes_special_characters() {
+        assert_eq!(
+            escape_text("Smith, Jones; Co\\\nLtd"),
+            "Smith\\, Jones\\; Co\\\\\\nLtd"
+        );
+    }
+
+    #[test]
+    fn short_lines_are_not_folded() {
+        assert_eq!(fold_line("SUMMARY:short"), "SUMMARY:short");
+    }
+
+    #[test]
+    fn long_lines_fold_at_75_octets() {
+        let line = "X".repeat(160);
+        let folded = fold_line(&line);
+        let parts: Vec<&str> = folded.split("\r\n").collect();
+
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0].len(), 75);
+        assert_eq!(parts[1].len(), 75);
+        assert!(parts[1].starts_with(' '));
+        assert_eq!(parts.concat().replace(' ', ""), line);
+    }
+
+    #[test]
+    fn folding_keeps_multibyte_characters_whole() {
+        let line = "é".repeat(50);
+        let folded = fold_line(&line);
+
+        for part in folded.split("\r\n") {
+            assert!(part.len() <= 75);
+        }
+        assert_eq!(folded.replace("\r\n ", ""), line);
+    }
+
+    #[test]
+    fn renders_events() {
+        let mut calendar = Calendar::new();
+        calendar.push(CalendarEvent {
+            uid: "invoice-innotech-7@invogen".to_string(),
+            start: NaiveDate::from_ymd_opt(2024, 5, 12).unwrap(),
+            summary: "Invoice #7 for Innotech, Inc. due".to_string(),
+            rrule: None,
+        });
+        calendar.push(CalendarEvent {
+            uid: "billing-innotech@invogen".to_string(),
+            start: NaiveDate::from_ymd_opt(2024, 6, 1).unwrap(),
+            summary: "Generate invoice for Innotech".to_string(),
+            rrule: Some("FREQ=MONTHLY;BYMONTHDAY=1".to_string()),
+        });
+        let stamp = Utc
+            .with_ymd_and_hms(2024, 4, 15, 10, 30, 0)
+            .single()
+            .unwrap();
+
+        assert_eq!(
+            calendar.render(stamp),
+            "BEGIN:VCALENDAR\r\n\
+             VERSION:2.0\r\n\
+             PRODID:-//invogen//invogen//EN\r\n\
+             CALSCALE:GREGORIAN\r\n\
+             BEGIN:VEVENT\r\n\
+             UID:invoice-innotech-7@invogen\r\n\
+             DTSTAMP:20240415T103000Z\r\n\
+             DTSTART;VALUE=DATE:20240512\r\n\
+             SUMMARY:Invoice #7 for Innotech\\, Inc. due\r\n\
+             END:VEVENT\r\n\
+             BEGIN:VEVENT\r\n\
+             UID:billing-innotech@invogen\r\n\
+             DTSTAMP:20240415T103000Z\r\n\
+             DTSTART;VALUE=DATE:20240601\r\n\
+             SUMMARY:Generate invoice for Innotech\r\n\
+             RRULE:FREQ=MONTHLY;BYMONTHDAY=1\r\n\
+             END:VEVENT\r\n\
+             END:VCALENDAR\r\n"
+        );
+    }
+}
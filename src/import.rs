@@ -0,0 +1,330 @@
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use crate::billing::{
+    CurrencyMismatch, Invoice, InvoiceItem, Money, NewInvoiceError, Period,
+};
+use crate::clients::Client;
+use crate::csv;
+
+const COLUMNS: [&str; 8] = [
+    "number",
+    "date",
+    "service",
+    "from",
+    "until",
+    "quantity",
+    "amount",
+    "paid_date",
+];
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ImportError {
+    #[error("Line {0}: quoted field is never closed")]
+    Quote(usize),
+
+    #[error(
+        "Line {0}: found {1} columns, expected \
+         number,date,service,from,until,quantity,amount,paid_date"
+    )]
+    Columns(usize, usize),
+
+    #[error("Line {0}: invalid {1} '{2}'")]
+    Field(usize, &'static str, String),
+
+    #[error("Line {0}: invoice #{1} should be #{2} to continue the numbering")]
+    Number(usize, usize, usize),
+
+    #[error(
+        "Line {0}: date or paid date differs from invoice #{1}'s other rows"
+    )]
+    Mismatch(usize, usize),
+
+    #[error("Line {0}: no rate for '{1}' as of {2}")]
+    NoRate(usize, String, NaiveDate),
+
+    #[error("Line {0}: {1}")]
+    Currency(usize, CurrencyMismatch),
+
+    #[error("Line {0}: {1}")]
+    Invoice(usize, NewInvoiceError),
+}
+
+/// An invoice item row with the invoice it belongs to
+struct Row {
+    line: usize,
+    number: usize,
+    date: NaiveDate,
+    service: String,
+    period: Period,
+    quantity: Decimal,
+    /// Read once the currency of the service's rate is known
+    amount: String,
+    paid: Option<NaiveDate>,
+}
+
+impl Row {
+    fn parse(line: usize, fields: &[String]) -> Result<Self, ImportError> {
+        let [number, date, service, from, until, quantity, amount, paid] =
+            fields
+        else {
+            return Err(ImportError::Columns(line, fields.len()));
+        };
+        let number = parse(line, "number", number.trim_start_matches('#'))?;
+        let date = parse(line, "date", date)?;
+        let from: NaiveDate = parse(line, "from", from)?;
+        let until: NaiveDate = parse(line, "until", until)?;
+        if until < from {
+            return Err(ImportError::Field(line, "until", until.to_string()));
+        }
+        let paid = match paid.trim() {
+            "" => None,
+            paid => Some(parse(line, "paid_date", paid)?),
+        };
+
+        Ok(Self {
+            line,
+            number,
+            date,
+            service: service.trim().to_string(),
+            period: Period::new(from, until),
+            quantity: parse(line, "quantity", quantity)?,
+            amount: amount.trim().to_string(),
+            paid,
+        })
+    }
+
+    /// The item at the rate in effect at the start of its period, but with
+    /// the amount as it was invoiced, in the rate's currency unless the
+    /// amount names one
+    fn item(self, client: &Client) -> Result<InvoiceItem, ImportError> {
+        let from = self.period.from;
+        let rate = client
+            .service(self.service.clone())
+            .and_then(|s| s.rates.as_of(from))
+            .ok_or_else(|| {
+                ImportError::NoRate(self.line, self.service.clone(), from)
+            })?;
+        let currency = rate.amount.currency();
+        let amount = Money::parse_lenient(&self.amount, Some(currency))
+            .map_err(|_| {
+                ImportError::Field(self.line, "amount", self.amount)
+            })?;
+        if amount.currency() != currency {
+            let mismatch = CurrencyMismatch(currency, amount.currency());
+            return Err(ImportError::Currency(self.line, mismatch));
+        }
+        Ok(InvoiceItem::raw(
+            self.service,
+            rate.clone(),
+            self.period,
+            self.quantity,
+            amount,
+        ))
+    }
+}
+
+fn parse<T: FromStr>(
+    line: usize,
+    column: &'static str,
+    value: &str,
+) -> Result<T, ImportError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| ImportError::Field(line, column, value.to_string()))
+}
+
+/// Invoices from CSV rows of `number,date,service,from,until,quantity,amount,
+/// paid_date`, one row per item with an optional header row. The numbers
+/// have to continue the client's numbering in order, and the taxes are the
+/// ones in effect at the start of each invoice's period
+pub fn invoices_from_csv(
+    client: &Client,
+    text: &str,
+) -> Result<Vec<Invoice>, ImportError> {
+    let mut records = csv::records(text).map_err(ImportError::Quote)?;
+    if records
+        .first()
+        .is_some_and(|(_, fields)| fields.iter().map(|f| f.trim()).eq(COLUMNS))
+    {
+        records.remove(0);
+    }
+
+    let mut invoices: Vec<Invoice> = Vec::new();
+    for (line, fields) in records {
+        let row = Row::parse(line, &fields)?;
+        let (number, date, paid) = (row.number, row.date, row.paid);
+
+        match invoices.last_mut() {
+            Some(invoice) if invoice.number == number => {
+                if invoice.date != date || invoice.paid != paid {
+                    return Err(ImportError::Mismatch(line, number));
+                }
+                let item = row.item(client)?;
+                let currency = item.amount.currency();
+                if currency != invoice.currency() {
+                    let mismatch =
+                        CurrencyMismatch(invoice.currency(), currency);
+                    return Err(ImportError::Currency(line, mismatch));
+                }
+                invoice.items.push(item);
+            }
+            last => {
+                let expected =
+                    last.map_or(client.next_invoice_num(), |i| i.number + 1);
+                if number != expected {
+                    return Err(ImportError::Number(line, number, expected));
+                }
+                // Imported items keep the order of their rows
+                let mut invoice =
+                    Invoice::unsorted(number, vec![row.item(client)?], vec![])
+                        .map_err(|e| ImportError::Invoice(line, e))?;
+                invoice.date = date;
+                invoice.paid = paid;
+                invoices.push(invoice);
+            }
+        }
+    }
+
+    for invoice in invoices.iter_mut() {
+        invoice.tax_rates = client.taxes_as_of(invoice.overall_period().from);
+    }
+    Ok(invoices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::billing::tests::{monthly, ymd};
+    use crate::billing::{Currency, Rate, TaxRate, Unit};
+    use crate::clients::Update;
+
+    fn client() -> Client {
+        let mut client = Client::new("innotech", "Innotech", "Some Place");
+        let hourly = Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(100)),
+            per: Unit::Hour,
+            tiers: Vec::new(),
+        };
+        for update in [
+            Update::ServiceRate(
+                "Stuff".to_string(),
+                ymd(2019, 1, 1),
+                monthly(Currency::Cad, 1000),
+            ),
+            Update::ServiceRate("Support".to_string(), ymd(2019, 1, 1), hourly),
+            Update::Taxes(
+                ymd(2019, 1, 1),
+                vec![TaxRate::new("GST".to_string(), 5)],
+            ),
+        ] {
+            client.update(&update).unwrap();
+        }
+        client
+    }
+
+    const LEGACY: &str = "\
+        number,date,service,from,until,quantity,amount,paid_date\n\
+        1,2019-02-01,Stuff,2019-01-01,2019-01-31,1,950.00,2019-02-20\n\
+        1,2019-02-01,Support,2019-01-01,2019-01-31,2.5,250.00,2019-02-20\n\
+        2,2019-03-01,Stuff,2019-02-01,2019-02-28,1,950.00,\n";
+
+    #[test]
+    fn legacy_invoices() -> Result<(), ImportError> {
+        let invoices = invoices_from_csv(&client(), LEGACY)?;
+
+        assert_eq!(invoices.len(), 2);
+        let first = &invoices[0];
+        assert_eq!(first.number, 1);
+        assert_eq!(first.date, ymd(2019, 2, 1));
+        assert_eq!(first.paid, Some(ymd(2019, 2, 20)));
+        assert_eq!(first.items.len(), 2);
+        assert_eq!(first.items[1].quantity, Decimal::new(25, 1));
+        assert_eq!(
+            first.calculate().unwrap().subtotal,
+            Money::new(Currency::Cad, Decimal::from(1200))
+        );
+        assert_eq!(first.tax_rates, vec![TaxRate::new("GST".to_string(), 5)]);
+        assert_eq!(invoices[1].paid, None);
+        Ok(())
+    }
+
+    #[test]
+    fn numbering_continues_the_history() {
+        let mut client = client();
+        let mut invoices = invoices_from_csv(&client, LEGACY).unwrap();
+        client
+            .update(&Update::Invoiced(invoices.remove(0)))
+            .unwrap();
+
+        assert_eq!(
+            invoices_from_csv(&client, LEGACY).err(),
+            Some(ImportError::Number(2, 1, 2))
+        );
+    }
+
+    #[test]
+    fn amounts_with_currency_and_grouping() -> Result<(), ImportError> {
+        let invoices = invoices_from_csv(
+            &client(),
+            "1,2019-02-01,Stuff,2019-01-01,2019-01-31,1,\"CAD $1,250.00\",\n\
+             1,2019-02-01,Support,2019-01-01,2019-01-31,2.5,$250,\n",
+        )?;
+        assert_eq!(
+            invoices[0].calculate().unwrap().subtotal,
+            Money::new(Currency::Cad, Decimal::from(1500))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn errors_cite_lines() {
+        let error = |text: &str| invoices_from_csv(&client(), text).err();
+        let row = "1,2019-02-01,Stuff,2019-01-01,2019-01-31,1,950.00,";
+
+        assert_eq!(
+            error(&format!("{}\n2,2019-02-31,Stuff,,,,,", row)),
+            Some(ImportError::Field(2, "date", "2019-02-31".to_string()))
+        );
+        assert_eq!(
+            error(&format!("{}\n\n3,2019-03-01,Stuff", row)),
+            Some(ImportError::Columns(3, 3))
+        );
+        assert_eq!(
+            error(&format!(
+                "{}\n1,2019-02-01,Other,2019-01-01,2019-01-31,1,5,",
+                row
+            )),
+            Some(ImportError::NoRate(2, "Other".to_string(), ymd(2019, 1, 1)))
+        );
+        assert_eq!(
+            error(&format!(
+                "{}\n1,2019-02-01,Stuff,2019-02-01,2019-02-28,1,950,2019-03-01",
+                row
+            )),
+            Some(ImportError::Mismatch(2, 1))
+        );
+        assert_eq!(
+            error("1,2019-02-01,\"Stuff,2019-01-01"),
+            Some(ImportError::Quote(1))
+        );
+        assert_eq!(
+            error(&format!(
+                "{}\n1,2019-02-01,Stuff,2019-02-01,2019-02-28,1,USD 950,",
+                row
+            )),
+            Some(ImportError::Currency(
+                2,
+                CurrencyMismatch(Currency::Cad, Currency::Usd)
+            ))
+        );
+        assert_eq!(
+            error("1,2019-02-01,Stuff,2019-01-01,2019-01-31,1,9.5.0,"),
+            Some(ImportError::Field(1, "amount", "9.5.0".to_string()))
+        );
+    }
+}
@@ -1,21 +1,353 @@
-use crate::billing::{Currency, Money, Period, Rate, TaxRate, Unit};
+use crate::billing::{
+    Currency, Invoice, Money, Period, Rate, Received, RoundingPolicy, TaxRate,
+    TaxRounding, Unit,
+};
 use crate::calendar::DateBoundaries;
+use crate::clients;
+use crate::templates;
 
 use chrono::{Duration, Local, NaiveDate};
 use inquire::{
-    error::InquireError, formatter::CustomTypeFormatter, Confirm, CustomType,
-    DateSelect, Select, Text,
+    error::InquireError, formatter::CustomTypeFormatter, validator::Validation,
+    Confirm, CustomType, DateSelect, Editor, MultiSelect, Select, Text,
 };
 use rust_decimal::Decimal;
 use strum::VariantNames;
 
+use std::cmp;
 use std::str::FromStr;
 
-type InputResult<T> = Result<T, InquireError>;
+pub type InputResult<T> = Result<T, InquireError>;
+
+/// Choice when reviewing an invoice's items before it's confirmed, with
+/// the index of the item to edit or remove
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Review {
+    Done,
+    Add,
+    Edit(usize),
+    Remove(usize),
+}
+
+/// Choice when a new period starts after days the last invoice left unbilled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gap {
+    /// Start the period on the first unbilled day
+    Extend,
+    Leave,
+    Abort,
+}
+
+/// Source of interactive answers, abstracted so command flows can be driven
+/// by scripted answers in tests
+pub trait Prompter {
+    fn client(
+        &mut self,
+        taken: Vec<String>,
+    ) -> InputResult<(String, String, String)>;
+    fn name(&mut self) -> InputResult<String>;
+    fn address(&mut self) -> InputResult<String>;
+    fn effective(&mut self) -> InputResult<NaiveDate>;
+    fn period(
+        &mut self,
+        billed_until: Option<NaiveDate>,
+        per: &Unit,
+        advance: bool,
+        suggested: Option<NaiveDate>,
+    ) -> InputResult<Period>;
+    fn num_hours(&mut self, default: Option<Decimal>) -> InputResult<Decimal>;
+    fn default_hours(&mut self) -> InputResult<Decimal>;
+    fn week_hours(&mut self, week: &Period) -> InputResult<Decimal>;
+    fn paid_date(&mut self, issue_date: NaiveDate) -> InputResult<NaiveDate>;
+    fn sent_date(&mut self, created: NaiveDate) -> InputResult<NaiveDate>;
+    fn write_off(
+        &mut self,
+        issue_date: NaiveDate,
+    ) -> InputResult<(NaiveDate, Option<String>)>;
+    fn void(
+        &mut self,
+        issue_date: NaiveDate,
+    ) -> InputResult<(NaiveDate, Option<String>)>;
+    fn dispute(
+        &mut self,
+        issue_date: NaiveDate,
+    ) -> InputResult<(NaiveDate, Option<String>)>;
+    fn dispute_resolved(
+        &mut self,
+        disputed: NaiveDate,
+    ) -> InputResult<NaiveDate>;
+    fn received(&mut self, invoiced: Currency) -> InputResult<Received>;
+    /// Rate converting items in one currency to the invoice's
+    fn conversion_rate(
+        &mut self,
+        from: Currency,
+        to: Currency,
+    ) -> InputResult<Decimal>;
+    fn service_select(&mut self, services: Vec<&str>) -> InputResult<String>;
+    fn service(&mut self) -> InputResult<(String, Rate, NaiveDate)>;
+    fn rate(&mut self) -> InputResult<(Rate, NaiveDate)>;
+    fn taxes(&mut self) -> InputResult<(Vec<TaxRate>, NaiveDate)>;
+    /// Whether to set a new client's taxes while adding it
+    fn taxes_now(&mut self) -> InputResult<bool>;
+    /// Whether to set the sender and add a first client to a new history
+    fn set_up_now(&mut self) -> InputResult<bool>;
+    fn template_name(&mut self) -> InputResult<String>;
+    fn template_select(&mut self, names: Vec<&str>) -> InputResult<String>;
+    /// A service and its rate, without an effective date
+    fn template_service(&mut self) -> InputResult<(String, Rate)>;
+    /// Default taxes of a template, possibly none
+    fn template_taxes(&mut self) -> InputResult<Vec<TaxRate>>;
+    fn template_options(
+        &mut self,
+    ) -> InputResult<(Option<String>, Option<String>, Option<String>)>;
+    fn footer(&mut self, help: &str) -> InputResult<String>;
+    fn split_invoice(&mut self) -> InputResult<bool>;
+    fn split_at_rate_changes(&mut self) -> InputResult<bool>;
+    fn use_stale_rate(&mut self, effective: NaiveDate) -> InputResult<bool>;
+    fn keep_zero_item(&mut self) -> InputResult<bool>;
+    fn billing_gap(&mut self, gap: &Period) -> InputResult<Gap>;
+    fn rounding(&mut self) -> InputResult<RoundingPolicy>;
+    fn retainer_hours(&mut self) -> InputResult<Decimal>;
+    fn bill_overage(&mut self, remaining: Decimal) -> InputResult<bool>;
+    fn apply_deposits(
+        &mut self,
+        deposits: &[&Invoice],
+    ) -> InputResult<Vec<usize>>;
+    fn confirm(&mut self) -> InputResult<bool>;
+    /// Confirmation naming the client the change is for, e.g. [acme] Acme
+    fn confirm_for(&mut self, target: &str) -> InputResult<bool>;
+    /// What was typed to confirm a change for the client with this key
+    fn typed_key(&mut self, key: &str) -> InputResult<String>;
+    fn another(&mut self) -> InputResult<bool>;
+    fn review_items(&mut self, items: &[String]) -> InputResult<Review>;
+    /// Index of the chosen option, None when the menu is left with Esc
+    fn menu(
+        &mut self,
+        message: &str,
+        options: Vec<String>,
+    ) -> InputResult<Option<usize>>;
+}
 
-pub fn client() -> InputResult<(String, String, String)> {
+pub struct InquirePrompter;
+
+impl Prompter for InquirePrompter {
+    fn client(
+        &mut self,
+        taken: Vec<String>,
+    ) -> InputResult<(String, String, String)> {
+        client(taken)
+    }
+
+    fn name(&mut self) -> InputResult<String> {
+        name()
+    }
+
+    fn address(&mut self) -> InputResult<String> {
+        address()
+    }
+
+    fn effective(&mut self) -> InputResult<NaiveDate> {
+        effective()
+    }
+
+    fn period(
+        &mut self,
+        billed_until: Option<NaiveDate>,
+        per: &Unit,
+        advance: bool,
+        suggested: Option<NaiveDate>,
+    ) -> InputResult<Period> {
+        period(billed_until, per, advance, suggested)
+    }
+
+    fn num_hours(&mut self, default: Option<Decimal>) -> InputResult<Decimal> {
+        num_hours(default)
+    }
+
+    fn default_hours(&mut self) -> InputResult<Decimal> {
+        default_hours()
+    }
+
+    fn week_hours(&mut self, week: &Period) -> InputResult<Decimal> {
+        week_hours(week)
+    }
+
+    fn paid_date(&mut self, issue_date: NaiveDate) -> InputResult<NaiveDate> {
+        date_since("Paid on:", issue_date)
+    }
+
+    fn sent_date(&mut self, created: NaiveDate) -> InputResult<NaiveDate> {
+        date_since("Sent on:", created)
+    }
+
+    fn write_off(
+        &mut self,
+        issue_date: NaiveDate,
+    ) -> InputResult<(NaiveDate, Option<String>)> {
+        dated_reason("Written off on:", issue_date)
+    }
+
+    fn void(
+        &mut self,
+        issue_date: NaiveDate,
+    ) -> InputResult<(NaiveDate, Option<String>)> {
+        dated_reason("Voided on:", issue_date)
+    }
+
+    fn dispute(
+        &mut self,
+        issue_date: NaiveDate,
+    ) -> InputResult<(NaiveDate, Option<String>)> {
+        dated_reason("Disputed on:", issue_date)
+    }
+
+    fn dispute_resolved(
+        &mut self,
+        disputed: NaiveDate,
+    ) -> InputResult<NaiveDate> {
+        date_since("Resolved on:", disputed)
+    }
+
+    fn received(&mut self, invoiced: Currency) -> InputResult<Received> {
+        received(invoiced)
+    }
+
+    fn conversion_rate(
+        &mut self,
+        from: Currency,
+        to: Currency,
+    ) -> InputResult<Decimal> {
+        conversion_rate(from, to)
+    }
+
+    fn service_select(&mut self, services: Vec<&str>) -> InputResult<String> {
+        service_select(services)
+    }
+
+    fn service(&mut self) -> InputResult<(String, Rate, NaiveDate)> {
+        service()
+    }
+
+    fn rate(&mut self) -> InputResult<(Rate, NaiveDate)> {
+        rate()
+    }
+
+    fn taxes(&mut self) -> InputResult<(Vec<TaxRate>, NaiveDate)> {
+        taxes()
+    }
+
+    fn taxes_now(&mut self) -> InputResult<bool> {
+        taxes_now()
+    }
+
+    fn set_up_now(&mut self) -> InputResult<bool> {
+        set_up_now()
+    }
+
+    fn template_name(&mut self) -> InputResult<String> {
+        template_name()
+    }
+
+    fn template_select(&mut self, names: Vec<&str>) -> InputResult<String> {
+        template_select(names)
+    }
+
+    fn template_service(&mut self) -> InputResult<(String, Rate)> {
+        template_service()
+    }
+
+    fn template_taxes(&mut self) -> InputResult<Vec<TaxRate>> {
+        template_taxes()
+    }
+
+    fn template_options(
+        &mut self,
+    ) -> InputResult<(Option<String>, Option<String>, Option<String>)> {
+        template_options()
+    }
+
+    fn footer(&mut self, help: &str) -> InputResult<String> {
+        footer(help)
+    }
+
+    fn split_invoice(&mut self) -> InputResult<bool> {
+        split_invoice()
+    }
+
+    fn split_at_rate_changes(&mut self) -> InputResult<bool> {
+        split_at_rate_changes()
+    }
+
+    fn use_stale_rate(&mut self, effective: NaiveDate) -> InputResult<bool> {
+        use_stale_rate(effective)
+    }
+
+    fn keep_zero_item(&mut self) -> InputResult<bool> {
+        keep_zero_item()
+    }
+
+    fn billing_gap(&mut self, gap: &Period) -> InputResult<Gap> {
+        billing_gap(gap)
+    }
+
+    fn rounding(&mut self) -> InputResult<RoundingPolicy> {
+        rounding()
+    }
+
+    fn retainer_hours(&mut self) -> InputResult<Decimal> {
+        retainer_hours()
+    }
+
+    fn bill_overage(&mut self, remaining: Decimal) -> InputResult<bool> {
+        bill_overage(remaining)
+    }
+
+    fn apply_deposits(
+        &mut self,
+        deposits: &[&Invoice],
+    ) -> InputResult<Vec<usize>> {
+        apply_deposits(deposits)
+    }
+
+    fn confirm(&mut self) -> InputResult<bool> {
+        confirm()
+    }
+
+    fn confirm_for(&mut self, target: &str) -> InputResult<bool> {
+        confirm_for(target)
+    }
+
+    fn typed_key(&mut self, key: &str) -> InputResult<String> {
+        typed_key(key)
+    }
+
+    fn another(&mut self) -> InputResult<bool> {
+        another()
+    }
+
+    fn review_items(&mut self, items: &[String]) -> InputResult<Review> {
+        review_items(items)
+    }
+
+    fn menu(
+        &mut self,
+        message: &str,
+        options: Vec<String>,
+    ) -> InputResult<Option<usize>> {
+        menu(message, options)
+    }
+}
+
+fn client(taken: Vec<String>) -> InputResult<(String, String, String)> {
     let key = Text::new("Client key:")
         .with_help_message("This value cannot be changed once set")
+        .with_validator(move |key: &str| {
+            let taken = taken.iter().map(String::as_str);
+            match clients::validate_key(&key.to_lowercase(), taken) {
+                Ok(()) => Ok(Validation::Valid),
+                Err(e) => Ok(Validation::Invalid(e.to_string().into())),
+            }
+        })
         .prompt()?
         .to_lowercase();
     let name = name()?;
@@ -24,11 +356,11 @@ pub fn client() -> InputResult<(String, String, String)> {
     Ok((key, name, address))
 }
 
-pub fn name() -> InputResult<String> {
+fn name() -> InputResult<String> {
     Text::new("Name:").prompt()
 }
 
-pub fn address() -> InputResult<String> {
+fn address() -> InputResult<String> {
     let mut count = 0;
     let mut addr_lines: Vec<String> = Vec::new();
     loop {
@@ -47,13 +379,36 @@ pub fn address() -> InputResult<String> {
     Ok(addr_lines.join("\n").trim().to_string())
 }
 
-pub fn period(billed_until: Option<NaiveDate>) -> InputResult<Period> {
-    let today = Local::now().date_naive();
+/// The latest date an invoiced period can start on, the end of the current
+/// month or the end of the next one when billing in advance
+pub fn latest_period_date(today: NaiveDate, advance: bool) -> NaiveDate {
     let cur_eom = today
         .end_of_month()
         .expect("Error in chrono-utilities end_of_month");
+    if advance {
+        (cur_eom + Duration::days(1))
+            .end_of_month()
+            .expect("Error in chrono-utilities end_of_month")
+    } else {
+        cur_eom
+    }
+}
+
+/// The suggested end of the period follows the unit being billed, e.g. the
+/// end of the year for a yearly retainer
+fn period(
+    billed_until: Option<NaiveDate>,
+    per: &Unit,
+    advance: bool,
+    suggested: Option<NaiveDate>,
+) -> InputResult<Period> {
+    let latest = latest_period_date(Local::now().date_naive(), advance);
 
-    let from_select = DateSelect::new("Invoice from:").with_max_date(cur_eom);
+    let mut from_select =
+        DateSelect::new("Invoice from:").with_max_date(latest);
+    if let Some(suggested) = suggested.filter(|date| *date <= latest) {
+        from_select = from_select.with_default(suggested);
+    }
 
     let from = match billed_until {
         None => from_select,
@@ -62,38 +417,165 @@ pub fn period(billed_until: Option<NaiveDate>) -> InputResult<Period> {
     .prompt()?;
 
     let after_from = from + Duration::days(1);
-    let from_eom = from
-        .end_of_month()
-        .expect("Error in chrono-utilities end_of_month");
+    let default_until = match per {
+        Unit::Year => from.end_of_year(),
+        Unit::Quarter => from.end_of_quarter(),
+        _ => from.end_of_month(),
+    }
+    .expect("Error in chrono-utilities date boundaries");
+    if suggested == Some(from) && *per != Unit::Hour {
+        let first = Period::new(from, default_until);
+        println!(
+            "Prorated from {} until {}: {} × {}",
+            from,
+            default_until,
+            first.num_units(per).round_dp(2),
+            per
+        );
+    }
 
     let until = DateSelect::new("until:")
-        .with_default(from_eom)
+        .with_default(default_until)
         .with_min_date(after_from)
-        .with_max_date(cur_eom)
+        .with_max_date(cmp::max(latest, default_until))
         .prompt()?;
 
     Ok(Period::new(from, until))
 }
 
-pub fn num_hours() -> InputResult<Decimal> {
+/// Dates far from today are likely a mis-scroll, so they're confirmed
+fn effective() -> InputResult<NaiveDate> {
+    let today = Local::now().date_naive();
+    loop {
+        let date =
+            DateSelect::new("Effective:").with_default(today).prompt()?;
+        let Err(warning) = near_today(date, today) else {
+            return Ok(date);
+        };
+        if Confirm::new(&format!("{}, use it anyway?", warning))
+            .with_default(false)
+            .prompt()?
+        {
+            return Ok(date);
+        }
+    }
+}
+
+/// Many hours for one item are confirmed in case of a typo
+fn num_hours(default: Option<Decimal>) -> InputResult<Decimal> {
     let formatter: CustomTypeFormatter<Decimal> = &|i| format!("{:.0}", i);
-    let amount: Decimal = CustomType::new("Billable Hours:")
+    loop {
+        let mut prompt = CustomType::new("Billable Hours:")
+            .with_formatter(formatter)
+            .with_error_message("Please type a valid number")
+            .with_validator(|hours: &Decimal| validation(item_hours(hours)));
+        if let Some(default) = default {
+            prompt = prompt.with_default(default);
+        }
+        let hours = prompt.prompt()?;
+        if hours <= Decimal::from(CONFIRM_ITEM_HOURS)
+            || Confirm::new(&format!("Bill {} hours for one item?", hours))
+                .with_default(false)
+                .prompt()?
+        {
+            return Ok(hours);
+        }
+    }
+}
+
+fn default_hours() -> InputResult<Decimal> {
+    let formatter: CustomTypeFormatter<Decimal> = &|i| format!("{:.0}", i);
+    CustomType::new("Default Hours:")
         .with_formatter(formatter)
         .with_error_message("Please type a valid number")
-        .prompt()?;
-    Ok(amount)
+        .with_help_message("Pre-filled when invoicing the service")
+        .with_validator(|hours: &Decimal| validation(item_hours(hours)))
+        .prompt()
 }
 
-pub fn paid_date(issue_date: NaiveDate) -> InputResult<NaiveDate> {
+/// Hours worked in one week of an item, none when skipped
+fn week_hours(week: &Period) -> InputResult<Decimal> {
+    let formatter: CustomTypeFormatter<Decimal> = &|i| format!("{}", i);
+    let hours = CustomType::new(&format!("Hours {}:", week))
+        .with_formatter(formatter)
+        .with_default(Decimal::ZERO)
+        .with_error_message("Please type a valid number")
+        .with_help_message("Esc to skip the week")
+        .with_validator(|hours: &Decimal| validation(item_hours(hours)))
+        .prompt_skippable()?;
+    Ok(hours.unwrap_or(Decimal::ZERO))
+}
+
+/// A date from the invoice's issue date until today
+fn date_since(message: &str, issue_date: NaiveDate) -> InputResult<NaiveDate> {
     let today = Local::now().date_naive();
 
-    DateSelect::new("Paid on:")
+    DateSelect::new(message)
         .with_min_date(issue_date)
         .with_max_date(today)
         .prompt()
 }
 
-pub fn service_select(services: Vec<&str>) -> InputResult<String> {
+/// A date from the invoice's issue date until today, and an optional reason
+fn dated_reason(
+    message: &str,
+    issue_date: NaiveDate,
+) -> InputResult<(NaiveDate, Option<String>)> {
+    let today = Local::now().date_naive();
+
+    let date = DateSelect::new(message)
+        .with_min_date(issue_date)
+        .with_max_date(today)
+        .prompt()?;
+    let reason = Text::new("Reason:")
+        .with_help_message("Optional")
+        .prompt()?;
+    let reason = reason.trim();
+
+    Ok((date, (!reason.is_empty()).then(|| reason.to_string())))
+}
+
+fn received(invoiced: Currency) -> InputResult<Received> {
+    let currencies: Vec<&str> = Currency::VARIANTS
+        .iter()
+        .copied()
+        .filter(|c| *c != invoiced.to_string())
+        .collect();
+    let currency = Select::new("Received in:", currencies)
+        .with_vim_mode(true)
+        .prompt()?;
+    let currency = Currency::from_str(currency).expect("Currency select error");
+
+    let formatter: CustomTypeFormatter<Decimal> = &|i| format!("{:.2}", i);
+    let amount: Decimal = CustomType::new("Amount received:")
+        .with_formatter(formatter)
+        .with_error_message("Please type a valid number")
+        .with_validator(|amount: &Decimal| validation(amount_in_range(amount)))
+        .prompt()?;
+    let booked_rate: Decimal = CustomType::new(&format!(
+        "{} to {} rate when invoiced:",
+        invoiced.code(),
+        currency.code()
+    ))
+    .with_error_message("Please type a valid number")
+    .with_validator(|rate: &Decimal| validation(positive_rate(rate)))
+    .prompt()?;
+
+    Ok(Received {
+        amount: Money::new(currency, amount),
+        booked_rate,
+    })
+}
+
+fn conversion_rate(from: Currency, to: Currency) -> InputResult<Decimal> {
+    CustomType::new(&format!("{} to {} rate:", from.code(), to.code()))
+        .with_help_message("Items are billed in the converted amounts")
+        .with_error_message("Please type a valid number")
+        .with_validator(|rate: &Decimal| validation(positive_rate(rate)))
+        .prompt()
+}
+
+fn service_select(services: Vec<&str>) -> InputResult<String> {
     let service = Select::new("Service:", services)
         .with_vim_mode(true)
         .prompt()?;
@@ -101,18 +583,25 @@ pub fn service_select(services: Vec<&str>) -> InputResult<String> {
     Ok(service.to_string())
 }
 
-pub fn service() -> InputResult<(String, Rate, NaiveDate)> {
-    let name = Text::new("Service:").prompt()?;
+fn service() -> InputResult<(String, Rate, NaiveDate)> {
+    let name =
+        clients::normalize_service_name(&Text::new("Service:").prompt()?);
     let (rate, effective) = rate()?;
 
     Ok((name, rate, effective))
 }
 
-pub fn rate() -> InputResult<(Rate, NaiveDate)> {
+fn rate() -> InputResult<(Rate, NaiveDate)> {
+    let rate = billing_rate()?;
+    Ok((rate, effective()?))
+}
+
+fn billing_rate() -> InputResult<Rate> {
     let formatter: CustomTypeFormatter<Decimal> = &|i| format!("${:.2}", i);
     let amount: Decimal = CustomType::new("Amount:")
         .with_formatter(formatter)
         .with_error_message("Please type a valid number")
+        .with_validator(|amount: &Decimal| validation(amount_in_range(amount)))
         .prompt()?;
     let currency = Select::new("Currency:", Currency::VARIANTS.to_vec())
         .with_vim_mode(true)
@@ -121,19 +610,83 @@ pub fn rate() -> InputResult<(Rate, NaiveDate)> {
     let unit = Select::new("Per:", Unit::VARIANTS.to_vec())
         .with_vim_mode(true)
         .prompt()?;
-
-    let effective = DateSelect::new("Effective:").prompt()?;
-    let rate = Rate {
-        amount: Money::new(
-            Currency::from_str(currency).expect("only selecting from variants"),
-            amount,
-        ),
-        per: Unit::from_str(unit).expect("only selecting from variants"),
+    let per = match Unit::from_str(unit).expect("only selecting from variants")
+    {
+        Unit::EveryNWeeks(_) => Unit::EveryNWeeks(num_weeks()?),
+        per => per,
+    };
+    let currency =
+        Currency::from_str(currency).expect("only selecting from variants");
+    let tiers = match per {
+        Unit::Hour if hour_tiers()? => rate_tiers(currency)?,
+        _ => Vec::new(),
     };
-    Ok((rate, effective))
+
+    Ok(Rate {
+        amount: Money::new(currency, amount),
+        per,
+        tiers,
+    })
+}
+
+fn hour_tiers() -> InputResult<bool> {
+    Confirm::new("Charge a different amount after a number of hours?")
+        .with_default(false)
+        .prompt()
+}
+
+/// Amounts charged from increasing numbers of hours in an item on
+fn rate_tiers(currency: Currency) -> InputResult<Vec<(Decimal, Money)>> {
+    let formatter: CustomTypeFormatter<Decimal> = &|i| format!("${:.2}", i);
+    let mut tiers: Vec<(Decimal, Money)> = Vec::new();
+    loop {
+        let previous = tiers.last().map_or(Decimal::ZERO, |(hours, _)| *hours);
+        let hours: Decimal = CustomType::new("After hours:")
+            .with_error_message("Please type a valid number")
+            .with_validator(move |hours: &Decimal| {
+                validation(tier_threshold(hours, previous))
+            })
+            .prompt()?;
+        let amount: Decimal = CustomType::new("Amount:")
+            .with_formatter(formatter)
+            .with_error_message("Please type a valid number")
+            .with_validator(|amount: &Decimal| {
+                validation(amount_in_range(amount))
+            })
+            .prompt()?;
+        tiers.push((hours, Money::new(currency, amount)));
+        if !Confirm::new("Another tier?").with_default(false).prompt()? {
+            return Ok(tiers);
+        }
+    }
+}
+
+fn num_weeks() -> InputResult<u8> {
+    CustomType::new("Number of weeks:")
+        .with_error_message("Please type a whole number of weeks")
+        .with_validator(|weeks: &u8| validation(some_weeks(weeks)))
+        .prompt()
 }
 
-pub fn taxes() -> InputResult<(Vec<TaxRate>, NaiveDate)> {
+fn taxes() -> InputResult<(Vec<TaxRate>, NaiveDate)> {
+    let taxes = tax_rates()?;
+    Ok((taxes, effective()?))
+}
+
+fn taxes_now() -> InputResult<bool> {
+    Confirm::new("Set the client's taxes now?")
+        .with_default(true)
+        .with_help_message("Taxes apply to all of a client's services")
+        .prompt()
+}
+
+fn set_up_now() -> InputResult<bool> {
+    Confirm::new("Set your name and address and add a first client now?")
+        .with_default(true)
+        .prompt()
+}
+
+fn tax_rates() -> InputResult<Vec<TaxRate>> {
     let mut taxes: Vec<TaxRate> = Vec::new();
 
     let formatter: CustomTypeFormatter<i64> = &|i| format!("{}%", i);
@@ -142,23 +695,710 @@ pub fn taxes() -> InputResult<(Vec<TaxRate>, NaiveDate)> {
         let percentage: i64 = CustomType::new("Percentage:")
             .with_formatter(formatter)
             .with_error_message("Please type a valid number")
+            .with_validator(|percent: &i64| validation(tax_percentage(percent)))
+            .prompt()?;
+
+        let rounding = Select::new("Rounding:", TaxRounding::VARIANTS.to_vec())
+            .with_vim_mode(true)
+            .with_help_message("How a tax amount of half a cent is rounded")
             .prompt()?;
+        let rounding = TaxRounding::from_str(rounding)
+            .expect("only selecting from variants");
 
-        taxes.push(TaxRate::new(name, percentage));
+        taxes.push(TaxRate::new(name, percentage).rounded(rounding));
 
         if !Confirm::new("Add another").with_default(false).prompt()? {
             break;
         }
     }
+    Ok(taxes)
+}
+
+fn template_name() -> InputResult<String> {
+    Text::new("Template name:").prompt()
+}
+
+fn template_select(names: Vec<&str>) -> InputResult<String> {
+    let name = Select::new("Template:", names)
+        .with_vim_mode(true)
+        .prompt()?;
+    Ok(name.to_string())
+}
+
+fn template_service() -> InputResult<(String, Rate)> {
+    let name =
+        clients::normalize_service_name(&Text::new("Service:").prompt()?);
+    Ok((name, billing_rate()?))
+}
+
+fn template_taxes() -> InputResult<Vec<TaxRate>> {
+    if !Confirm::new("Add default taxes")
+        .with_default(true)
+        .prompt()?
+    {
+        return Ok(Vec::new());
+    }
+    tax_rates()
+}
+
+fn template_options(
+) -> InputResult<(Option<String>, Option<String>, Option<String>)> {
+    let help = "Leave empty to use the default";
+    let accent = Text::new("Accent colour (hex):")
+        .with_help_message(help)
+        .with_validator(|color: &str| {
+            if color.is_empty() || templates::parse_accent(color).is_some() {
+                Ok(Validation::Valid)
+            } else {
+                Ok(Validation::Invalid(
+                    "Please type a colour like #1A2B3C".into(),
+                ))
+            }
+        })
+        .prompt()?;
+    let logo_path = Text::new("Logo path:").with_help_message(help).prompt()?;
+    let template = Text::new("Document class:")
+        .with_help_message(help)
+        .prompt()?;
+
+    let non_empty = |s: String| (!s.trim().is_empty()).then_some(s);
+    Ok((
+        templates::parse_accent(&accent),
+        non_empty(logo_path),
+        non_empty(template),
+    ))
+}
 
-    let effective = DateSelect::new("Effective:").prompt()?;
-    Ok((taxes, effective))
+fn footer(help: &str) -> InputResult<String> {
+    let footer = Editor::new("Footer:").with_help_message(help).prompt()?;
+    Ok(footer.trim().to_string())
+}
+
+fn split_invoice() -> InputResult<bool> {
+    Confirm::new("Split into separate invoices per currency?")
+        .with_default(false)
+        .with_help_message("Otherwise the invoice is aborted")
+        .prompt()
+}
+
+fn split_at_rate_changes() -> InputResult<bool> {
+    Confirm::new("Split the item where the rate changes?")
+        .with_default(true)
+        .with_help_message(
+            "Otherwise the whole period is billed at its starting rate",
+        )
+        .prompt()
+}
+
+fn use_stale_rate(effective: NaiveDate) -> InputResult<bool> {
+    Confirm::new(&format!("Invoice with the rate set on {}?", effective))
+        .with_default(false)
+        .with_help_message(
+            "Otherwise the invoice is aborted to update the rate",
+        )
+        .prompt()
 }
 
-pub fn confirm() -> InputResult<bool> {
+fn keep_zero_item() -> InputResult<bool> {
+    Confirm::new("Keep the item at no charge?")
+        .with_default(false)
+        .with_help_message("e.g. to show work done without charge")
+        .prompt()
+}
+
+fn billing_gap(gap: &Period) -> InputResult<Gap> {
+    let choices = [Gap::Extend, Gap::Leave, Gap::Abort];
+    let options = vec![
+        format!("Extend the period back to {}", gap.from),
+        "Leave the gap unbilled".to_string(),
+        "Abort the invoice".to_string(),
+    ];
+    let selected = Select::new("Unbilled gap:", options)
+        .with_vim_mode(true)
+        .raw_prompt()?;
+    Ok(choices[selected.index])
+}
+
+fn rounding() -> InputResult<RoundingPolicy> {
+    let policy = Select::new("Rounding:", RoundingPolicy::VARIANTS.to_vec())
+        .with_vim_mode(true)
+        .with_help_message(
+            "Rounding only the total adds an adjustment line when the rounded \
+             taxes are a cent off",
+        )
+        .prompt()?;
+    Ok(RoundingPolicy::from_str(policy).expect("only selecting from variants"))
+}
+
+/// Invoice numbers of the deposits to deduct, none for a standard invoice
+fn apply_deposits(deposits: &[&Invoice]) -> InputResult<Vec<usize>> {
+    let options: Vec<String> = deposits
+        .iter()
+        .map(|d| match d.calculate() {
+            Ok(total) => format!("#{} {}", d.number, total.total),
+            Err(error) => format!("#{} {}", d.number, error),
+        })
+        .collect();
+    let selected = MultiSelect::new("Deduct deposits:", options)
+        .with_vim_mode(true)
+        .with_help_message("Leave empty unless this is the final invoice")
+        .raw_prompt()?;
+    Ok(selected.iter().map(|o| deposits[o.index].number).collect())
+}
+
+fn retainer_hours() -> InputResult<Decimal> {
+    let formatter: CustomTypeFormatter<Decimal> = &|i| format!("{:.0}", i);
+    CustomType::new("Retainer Hours:")
+        .with_formatter(formatter)
+        .with_error_message("Please type a valid number")
+        .with_validator(|hours: &Decimal| validation(retainer_size(hours)))
+        .prompt()
+}
+
+fn bill_overage(remaining: Decimal) -> InputResult<bool> {
+    Confirm::new(&format!(
+        "Draw from the {} hours left on the retainer?",
+        remaining
+    ))
+    .with_default(true)
+    .with_help_message("Only the hours over the balance are billed")
+    .prompt()
+}
+
+fn confirm() -> InputResult<bool> {
     Confirm::new("Confirm").with_default(true).prompt()
 }
 
-pub fn another() -> InputResult<bool> {
+fn confirm_for(target: &str) -> InputResult<bool> {
+    Confirm::new(&format!("Confirm for {}", target))
+        .with_default(true)
+        .prompt()
+}
+
+fn typed_key(key: &str) -> InputResult<String> {
+    Text::new(&format!("Type {} to confirm", key))
+        .with_help_message("The total is over the --confirm-key amount")
+        .prompt()
+}
+
+fn another() -> InputResult<bool> {
     Confirm::new("Add another").with_default(false).prompt()
 }
+
+fn menu(message: &str, options: Vec<String>) -> InputResult<Option<usize>> {
+    let selected = Select::new(message, options)
+        .with_vim_mode(true)
+        .with_help_message("Esc to go back")
+        .raw_prompt();
+    match selected {
+        Ok(option) => Ok(Some(option.index)),
+        Err(InquireError::OperationCanceled) => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+fn review_items(items: &[String]) -> InputResult<Review> {
+    let mut choices = vec![Review::Done, Review::Add];
+    choices.extend((0..items.len()).map(Review::Edit));
+    choices.extend((0..items.len()).map(Review::Remove));
+    let options: Vec<String> = choices
+        .iter()
+        .map(|choice| match choice {
+            Review::Done => "Looks good".to_string(),
+            Review::Add => "Add another".to_string(),
+            Review::Edit(i) => format!("Edit item {}: {}", i + 1, items[*i]),
+            Review::Remove(i) => {
+                format!("Remove item {}: {}", i + 1, items[*i])
+            }
+        })
+        .collect();
+    let selected = Select::new("Review items:", options)
+        .with_vim_mode(true)
+        .raw_prompt()?;
+    Ok(choices[selected.index])
+}
+
+/// Largest amount of a rate or payment, anything more is likely a typo
+const MAX_AMOUNT: i64 = 1_000_000;
+
+/// Most hours billed for one item, e.g. a month of long days
+const MAX_ITEM_HOURS: i64 = 400;
+
+/// Hours for one item above which they're confirmed
+const CONFIRM_ITEM_HOURS: i64 = 200;
+
+/// Most hours prepaid in one retainer
+const MAX_RETAINER_HOURS: i64 = 10_000;
+
+/// Years before or after today an effective date can be without confirming
+const EFFECTIVE_YEARS: u32 = 10;
+
+/// Adapts a check to an inquire validator
+fn validation(
+    check: Result<(), String>,
+) -> Result<Validation, inquire::CustomUserError> {
+    Ok(match check {
+        Ok(()) => Validation::Valid,
+        Err(message) => Validation::Invalid(message.into()),
+    })
+}
+
+fn amount_in_range(amount: &Decimal) -> Result<(), String> {
+    if *amount <= Decimal::ZERO {
+        Err("Must be more than zero".to_string())
+    } else if *amount > Decimal::from(MAX_AMOUNT) {
+        Err(format!("Must be at most {}", MAX_AMOUNT))
+    } else {
+        Ok(())
+    }
+}
+
+fn tier_threshold(hours: &Decimal, previous: Decimal) -> Result<(), String> {
+    if *hours <= previous {
+        Err(format!("Must be more than {}", previous))
+    } else if *hours > Decimal::from(MAX_ITEM_HOURS) {
+        Err(format!("Must be at most {}", MAX_ITEM_HOURS))
+    } else {
+        Ok(())
+    }
+}
+
+fn positive_rate(rate: &Decimal) -> Result<(), String> {
+    match *rate > Decimal::ZERO {
+        true => Ok(()),
+        false => Err("Must be more than zero".to_string()),
+    }
+}
+
+fn tax_percentage(percent: &i64) -> Result<(), String> {
+    match (0..=100).contains(percent) {
+        true => Ok(()),
+        false => Err("Must be from 0 to 100".to_string()),
+    }
+}
+
+fn item_hours(hours: &Decimal) -> Result<(), String> {
+    let range = Decimal::ZERO..=Decimal::from(MAX_ITEM_HOURS);
+    match range.contains(hours) {
+        true => Ok(()),
+        false => Err(format!("Must be from 0 to {}", MAX_ITEM_HOURS)),
+    }
+}
+
+fn retainer_size(hours: &Decimal) -> Result<(), String> {
+    if *hours <= Decimal::ZERO {
+        Err("Must be more than zero".to_string())
+    } else if *hours > Decimal::from(MAX_RETAINER_HOURS) {
+        Err(format!("Must be at most {}", MAX_RETAINER_HOURS))
+    } else {
+        Ok(())
+    }
+}
+
+fn some_weeks(weeks: &u8) -> Result<(), String> {
+    match *weeks > 0 {
+        true => Ok(()),
+        false => Err("Must be at least one week".to_string()),
+    }
+}
+
+/// Whether a date is within [`EFFECTIVE_YEARS`] of today
+fn near_today(date: NaiveDate, today: NaiveDate) -> Result<(), String> {
+    let years = chrono::Months::new(EFFECTIVE_YEARS * 12);
+    let earliest = today.checked_sub_months(years).unwrap_or(NaiveDate::MIN);
+    let latest = today.checked_add_months(years).unwrap_or(NaiveDate::MAX);
+    match (earliest..=latest).contains(&date) {
+        true => Ok(()),
+        false => Err(format!(
+            "{} is more than {} years from today",
+            date, EFFECTIVE_YEARS
+        )),
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// A canned answer for one call on the [`ScriptedPrompter`]
+    #[derive(Debug)]
+    pub enum Answer {
+        Client(String, String, String),
+        Text(String),
+        Period(Period),
+        Number(Decimal),
+        Date(NaiveDate),
+        Service(String, Rate, NaiveDate),
+        Rate(Rate, NaiveDate),
+        Taxes(Vec<TaxRate>, NaiveDate),
+        /// A service of a service template
+        ServiceRate(String, Rate),
+        TaxRates(Vec<TaxRate>),
+        TemplateOptions(Option<String>, Option<String>, Option<String>),
+        Bool(bool),
+        Numbers(Vec<usize>),
+        WriteOff(NaiveDate, Option<String>),
+        Void(NaiveDate, Option<String>),
+        Dispute(NaiveDate, Option<String>),
+        Received(Received),
+        Review(Review),
+        Gap(Gap),
+        /// Accept the prompt's default, like pressing enter
+        Default,
+        /// Leave the prompt, like pressing Esc
+        Cancel,
+    }
+
+    /// Answers prompts from a queue, panicking when the next answer doesn't
+    /// match the prompt asked so flows can't silently drift from the script
+    pub struct ScriptedPrompter(VecDeque<Answer>);
+
+    impl ScriptedPrompter {
+        pub fn new(answers: Vec<Answer>) -> Self {
+            Self(answers.into())
+        }
+
+        pub fn is_finished(&self) -> bool {
+            self.0.is_empty()
+        }
+
+        fn next(&mut self, prompt: &str) -> Answer {
+            self.0
+                .pop_front()
+                .unwrap_or_else(|| panic!("No answer left for {}", prompt))
+        }
+    }
+
+    macro_rules! expect_answer {
+        ($self:ident, $prompt:literal, $pattern:pat => $value:expr) => {
+            match $self.next($prompt) {
+                $pattern => Ok::<_, InquireError>($value),
+                other => panic!("Expected {} answer, got {:?}", $prompt, other),
+            }
+        };
+    }
+
+    impl Prompter for ScriptedPrompter {
+        fn client(
+            &mut self,
+            _: Vec<String>,
+        ) -> InputResult<(String, String, String)> {
+            expect_answer!(self, "client",
+                Answer::Client(key, name, address) => (key, name, address))
+        }
+
+        fn name(&mut self) -> InputResult<String> {
+            expect_answer!(self, "name", Answer::Text(name) => name)
+        }
+
+        fn address(&mut self) -> InputResult<String> {
+            expect_answer!(self, "address", Answer::Text(address) => address)
+        }
+
+        fn effective(&mut self) -> InputResult<NaiveDate> {
+            expect_answer!(self, "effective", Answer::Date(date) => date)
+        }
+
+        fn period(
+            &mut self,
+            _: Option<NaiveDate>,
+            _: &Unit,
+            _: bool,
+            _: Option<NaiveDate>,
+        ) -> InputResult<Period> {
+            expect_answer!(self, "period", Answer::Period(period) => period)
+        }
+
+        fn num_hours(
+            &mut self,
+            default: Option<Decimal>,
+        ) -> InputResult<Decimal> {
+            match self.next("hours") {
+                Answer::Number(hours) => Ok(hours),
+                Answer::Default => {
+                    Ok(default.expect("No default offered for hours"))
+                }
+                other => panic!("Expected hours answer, got {:?}", other),
+            }
+        }
+
+        fn default_hours(&mut self) -> InputResult<Decimal> {
+            expect_answer!(self, "default hours", Answer::Number(hours) => hours)
+        }
+
+        fn week_hours(&mut self, _: &Period) -> InputResult<Decimal> {
+            expect_answer!(self, "week hours", Answer::Number(hours) => hours)
+        }
+
+        fn paid_date(&mut self, _: NaiveDate) -> InputResult<NaiveDate> {
+            expect_answer!(self, "paid date", Answer::Date(date) => date)
+        }
+
+        fn sent_date(&mut self, _: NaiveDate) -> InputResult<NaiveDate> {
+            expect_answer!(self, "sent date", Answer::Date(date) => date)
+        }
+
+        fn write_off(
+            &mut self,
+            _: NaiveDate,
+        ) -> InputResult<(NaiveDate, Option<String>)> {
+            expect_answer!(self, "write off",
+                Answer::WriteOff(date, reason) => (date, reason))
+        }
+
+        fn void(
+            &mut self,
+            _: NaiveDate,
+        ) -> InputResult<(NaiveDate, Option<String>)> {
+            expect_answer!(self, "void",
+                Answer::Void(date, reason) => (date, reason))
+        }
+
+        fn dispute(
+            &mut self,
+            _: NaiveDate,
+        ) -> InputResult<(NaiveDate, Option<String>)> {
+            expect_answer!(self, "dispute",
+                Answer::Dispute(date, reason) => (date, reason))
+        }
+
+        fn dispute_resolved(&mut self, _: NaiveDate) -> InputResult<NaiveDate> {
+            expect_answer!(self, "resolved date", Answer::Date(date) => date)
+        }
+
+        fn received(&mut self, _: Currency) -> InputResult<Received> {
+            expect_answer!(self, "received", Answer::Received(r) => r)
+        }
+
+        fn conversion_rate(
+            &mut self,
+            _: Currency,
+            _: Currency,
+        ) -> InputResult<Decimal> {
+            expect_answer!(self, "conversion rate", Answer::Number(rate) => rate)
+        }
+
+        fn service_select(
+            &mut self,
+            services: Vec<&str>,
+        ) -> InputResult<String> {
+            let service = expect_answer!(self, "service selection",
+                Answer::Text(service) => service)?;
+            assert!(
+                services.contains(&service.as_str()),
+                "{} is not one of the options {:?}",
+                service,
+                services
+            );
+            Ok(service)
+        }
+
+        fn service(&mut self) -> InputResult<(String, Rate, NaiveDate)> {
+            expect_answer!(self, "service",
+                Answer::Service(name, rate, effective) => (name, rate, effective))
+        }
+
+        fn rate(&mut self) -> InputResult<(Rate, NaiveDate)> {
+            expect_answer!(self, "rate",
+                Answer::Rate(rate, effective) => (rate, effective))
+        }
+
+        fn taxes(&mut self) -> InputResult<(Vec<TaxRate>, NaiveDate)> {
+            expect_answer!(self, "taxes",
+                Answer::Taxes(taxes, effective) => (taxes, effective))
+        }
+
+        fn taxes_now(&mut self) -> InputResult<bool> {
+            expect_answer!(self, "taxes now", Answer::Bool(answer) => answer)
+        }
+
+        fn set_up_now(&mut self) -> InputResult<bool> {
+            expect_answer!(self, "set up now", Answer::Bool(answer) => answer)
+        }
+
+        fn template_name(&mut self) -> InputResult<String> {
+            expect_answer!(self, "template name", Answer::Text(name) => name)
+        }
+
+        fn template_select(&mut self, _: Vec<&str>) -> InputResult<String> {
+            expect_answer!(self, "template", Answer::Text(name) => name)
+        }
+
+        fn template_service(&mut self) -> InputResult<(String, Rate)> {
+            expect_answer!(self, "template service",
+                Answer::ServiceRate(name, rate) => (name, rate))
+        }
+
+        fn template_taxes(&mut self) -> InputResult<Vec<TaxRate>> {
+            expect_answer!(self, "template taxes",
+                Answer::TaxRates(taxes) => taxes)
+        }
+
+        fn template_options(
+            &mut self,
+        ) -> InputResult<(Option<String>, Option<String>, Option<String>)>
+        {
+            expect_answer!(self, "template options",
+                Answer::TemplateOptions(accent, logo, template) =>
+                    (accent, logo, template))
+        }
+
+        fn footer(&mut self, _: &str) -> InputResult<String> {
+            expect_answer!(self, "footer", Answer::Text(footer) => footer)
+        }
+
+        fn split_invoice(&mut self) -> InputResult<bool> {
+            expect_answer!(self, "split", Answer::Bool(answer) => answer)
+        }
+
+        fn split_at_rate_changes(&mut self) -> InputResult<bool> {
+            expect_answer!(self, "rate split", Answer::Bool(answer) => answer)
+        }
+
+        fn use_stale_rate(
+            &mut self,
+            _effective: NaiveDate,
+        ) -> InputResult<bool> {
+            expect_answer!(self, "stale rate", Answer::Bool(answer) => answer)
+        }
+
+        fn keep_zero_item(&mut self) -> InputResult<bool> {
+            expect_answer!(self, "zero item", Answer::Bool(answer) => answer)
+        }
+
+        fn billing_gap(&mut self, _gap: &Period) -> InputResult<Gap> {
+            expect_answer!(self, "billing gap", Answer::Gap(gap) => gap)
+        }
+
+        fn rounding(&mut self) -> InputResult<RoundingPolicy> {
+            expect_answer!(self, "rounding", Answer::Text(policy) => {
+                RoundingPolicy::from_str(&policy).expect("unknown policy")
+            })
+        }
+
+        fn retainer_hours(&mut self) -> InputResult<Decimal> {
+            expect_answer!(self, "retainer hours", Answer::Number(hours) => hours)
+        }
+
+        fn bill_overage(&mut self, _: Decimal) -> InputResult<bool> {
+            expect_answer!(self, "overage", Answer::Bool(answer) => answer)
+        }
+
+        fn apply_deposits(
+            &mut self,
+            _: &[&Invoice],
+        ) -> InputResult<Vec<usize>> {
+            expect_answer!(self, "deposits", Answer::Numbers(numbers) => numbers)
+        }
+
+        fn confirm(&mut self) -> InputResult<bool> {
+            expect_answer!(self, "confirm", Answer::Bool(answer) => answer)
+        }
+
+        fn confirm_for(&mut self, _target: &str) -> InputResult<bool> {
+            expect_answer!(self, "confirm", Answer::Bool(answer) => answer)
+        }
+
+        fn typed_key(&mut self, _key: &str) -> InputResult<String> {
+            expect_answer!(self, "typed key", Answer::Text(typed) => typed)
+        }
+
+        fn another(&mut self) -> InputResult<bool> {
+            expect_answer!(self, "another", Answer::Bool(answer) => answer)
+        }
+
+        fn review_items(&mut self, _items: &[String]) -> InputResult<Review> {
+            expect_answer!(self, "review", Answer::Review(review) => review)
+        }
+
+        /// Answered with the text of an option, or cancelled
+        fn menu(
+            &mut self,
+            message: &str,
+            options: Vec<String>,
+        ) -> InputResult<Option<usize>> {
+            match self.next("menu") {
+                Answer::Text(choice) => Ok(Some(
+                    options.iter().position(|o| *o == choice).unwrap_or_else(
+                        || panic!("No {} option {}", message, choice),
+                    ),
+                )),
+                Answer::Cancel => Ok(None),
+                other => panic!("Expected menu answer, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn period_dates_end_with_the_month_unless_in_advance() {
+        use crate::billing::tests::ymd;
+
+        assert_eq!(
+            latest_period_date(ymd(2024, 5, 1), false),
+            ymd(2024, 5, 31)
+        );
+        assert_eq!(latest_period_date(ymd(2024, 5, 1), true), ymd(2024, 6, 30));
+        assert_eq!(
+            latest_period_date(ymd(2024, 12, 31), true),
+            ymd(2025, 1, 31)
+        );
+    }
+
+    #[test]
+    fn numeric_validators() {
+        let dec = |n, scale| Decimal::new(n, scale);
+        assert!(amount_in_range(&dec(1, 2)).is_ok());
+        assert!(amount_in_range(&dec(1_000_000, 0)).is_ok());
+        assert!(amount_in_range(&Decimal::ZERO).is_err());
+        assert!(amount_in_range(&dec(-500, 0)).is_err());
+        assert_eq!(
+            amount_in_range(&dec(1_000_001, 0)),
+            Err("Must be at most 1000000".to_string())
+        );
+
+        assert!(positive_rate(&dec(138, 2)).is_ok());
+        assert!(positive_rate(&Decimal::ZERO).is_err());
+
+        assert!(tax_percentage(&0).is_ok());
+        assert!(tax_percentage(&100).is_ok());
+        assert!(tax_percentage(&250).is_err());
+        assert!(tax_percentage(&-5).is_err());
+
+        assert!(item_hours(&Decimal::ZERO).is_ok());
+        assert!(item_hours(&dec(4005, 1)).is_err());
+        assert!(item_hours(&dec(400, 0)).is_ok());
+        assert!(item_hours(&dec(-1, 0)).is_err());
+
+        assert!(retainer_size(&dec(1000, 0)).is_ok());
+        assert!(retainer_size(&Decimal::ZERO).is_err());
+        assert!(retainer_size(&dec(10_001, 0)).is_err());
+
+        assert!(some_weeks(&2).is_ok());
+        assert!(some_weeks(&0).is_err());
+    }
+
+    #[test]
+    fn effective_dates_near_today() {
+        use crate::billing::tests::ymd;
+
+        let today = ymd(2024, 5, 15);
+        assert!(near_today(today, today).is_ok());
+        assert!(near_today(ymd(2014, 5, 15), today).is_ok());
+        assert!(near_today(ymd(2034, 5, 15), today).is_ok());
+        assert_eq!(
+            near_today(ymd(1994, 5, 1), today),
+            Err("1994-05-01 is more than 10 years from today".to_string())
+        );
+        assert!(near_today(ymd(2034, 5, 16), today).is_err());
+    }
+
+    #[test]
+    fn validations_for_inquire() {
+        assert_eq!(validation(Ok(())).unwrap(), Validation::Valid);
+        assert_eq!(
+            validation(tax_percentage(&250)).unwrap(),
+            Validation::Invalid("Must be from 0 to 100".into())
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use std::any::TypeId;
+use std::path::PathBuf;
+
+use clap::{Arg, ArgAction, Command, ValueHint};
+use serde::Serialize;
+
+/// A command with its arguments and subcommands
+#[derive(Serialize)]
+struct CommandDescription {
+    name: String,
+    about: String,
+    arguments: Vec<ArgumentDescription>,
+    subcommands: Vec<CommandDescription>,
+}
+
+/// An argument, with the optional fields only when set
+#[derive(Serialize)]
+struct ArgumentDescription {
+    name: String,
+    #[serde(rename = "type")]
+    value_type: &'static str,
+    positional: bool,
+    required: bool,
+    client_key: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    long: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    short: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    default: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    values: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    help: Option<String>,
+}
+
+/// The commands with their arguments and subcommands as JSON, read from the
+/// clap definitions so tools wrapping invogen stay in step with it
+pub fn describe(command: &Command) -> Result<String, serde_json::Error> {
+    serde_json::to_string_pretty(&describe_command(command))
+}
+
+fn describe_command(command: &Command) -> CommandDescription {
+    CommandDescription {
+        name: command.get_name().to_string(),
+        about: command.get_about().map_or(String::new(), |a| a.to_string()),
+        arguments: command
+            .get_arguments()
+            .filter(|arg| !arg.is_hide_set())
+            .map(describe_argument)
+            .collect(),
+        subcommands: command
+            .get_subcommands()
+            .filter(|sub| !sub.is_hide_set())
+            .map(describe_command)
+            .collect(),
+    }
+}
+
+fn describe_argument(arg: &Arg) -> ArgumentDescription {
+    let id = arg.get_id();
+    ArgumentDescription {
+        name: id.to_string(),
+        value_type: value_type(arg),
+        positional: arg.is_positional(),
+        required: arg.is_required_set(),
+        client_key: id == "client",
+        long: arg.get_long().map(|long| format!("--{}", long)),
+        short: arg.get_short().map(|short| format!("-{}", short)),
+        value_name: arg.get_value_names().map(|names| {
+            let names: Vec<String> =
+                names.iter().map(|n| n.to_string()).collect();
+            names.join(" ")
+        }),
+        default: arg
+            .get_default_values()
+            .first()
+            .map(|default| default.to_string_lossy().into_owned()),
+        values: arg
+            .get_possible_values()
+            .iter()
+            .filter(|value| !value.is_hide_set())
+            .map(|value| value.get_name().to_string())
+            .collect(),
+        help: arg.get_help().map(|help| help.to_string()),
+    }
+}
+
+/// What an argument takes, flags and counts take no value
+fn value_type(arg: &Arg) -> &'static str {
+    match arg.get_action() {
+        ArgAction::SetTrue
+        | ArgAction::SetFalse
+        | ArgAction::Help
+        | ArgAction::HelpShort
+        | ArgAction::HelpLong
+        | ArgAction::Version => return "flag",
+        ArgAction::Count => return "count",
+        _ => {}
+    }
+    let id = arg.get_value_parser().type_id();
+    let integers = [
+        TypeId::of::<u8>(),
+        TypeId::of::<u32>(),
+        TypeId::of::<u64>(),
+        TypeId::of::<usize>(),
+        TypeId::of::<i32>(),
+        TypeId::of::<i64>(),
+    ];
+    let path_hint = matches!(
+        arg.get_value_hint(),
+        ValueHint::FilePath | ValueHint::DirPath | ValueHint::AnyPath
+    );
+    if id == TypeId::of::<bool>() {
+        "bool"
+    } else if integers.iter().any(|integer| id == *integer) {
+        "integer"
+    } else if !arg.get_possible_values().is_empty() {
+        "enum"
+    } else if id == TypeId::of::<PathBuf>() || path_hint {
+        "path"
+    } else {
+        "string"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Opts;
+    use clap::CommandFactory;
+    use serde_json::{json, Value};
+
+    fn description() -> Value {
+        serde_json::from_str(&describe(&Opts::command()).unwrap()).unwrap()
+    }
+
+    /// The entry of a list of commands or arguments with the given name
+    fn named<'a>(entries: &'a Value, name: &str) -> &'a Value {
+        entries
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|entry| entry["name"] == name)
+            .unwrap_or_else(|| panic!("{} is missing", name))
+    }
+
+    /// Every argument of a command and its subcommands
+    fn all_arguments(command: &Value) -> Vec<&Value> {
+        let mut arguments: Vec<&Value> =
+            command["arguments"].as_array().unwrap().iter().collect();
+        for sub in command["subcommands"].as_array().unwrap() {
+            arguments.extend(all_arguments(sub));
+        }
+        arguments
+    }
+
+    #[test]
+    fn every_command_described() {
+        let command = Opts::command();
+        let description = description();
+        let commands = [
+            "list",
+            "add",
+            "show",
+            "set",
+            "invoice",
+            "mark-paid",
+            "mark-sent",
+            "write-off",
+            "void",
+            "dispute",
+            "resolve-dispute",
+            "remove",
+            "path",
+            "remove-template",
+            "normalize-services",
+            "ical",
+            "export",
+            "profile",
+            "find",
+            "grep",
+            "log",
+            "unbilled",
+            "revenue",
+            "hours",
+            "accrual",
+            "due",
+            "remind",
+            "invoices",
+            "audit",
+            "verify",
+            "explain",
+            "import-invoices",
+            "init",
+            "config",
+            "snapshot",
+        ];
+        // A new command fails here until it's added to the list
+        assert_eq!(command.get_subcommands().count(), commands.len());
+        for name in commands {
+            named(&description["subcommands"], name);
+        }
+        let arguments = all_arguments(&description);
+        assert!(arguments.iter().all(|arg| arg["name"] != "help_json"));
+    }
+
+    #[test]
+    fn nested_subcommands_and_argument_types() {
+        let description = description();
+        // show <client> invoice <number> posting --value-in <currency>
+        let show = named(&description["subcommands"], "show");
+        let invoice = named(&show["subcommands"], "invoice");
+        let posting = named(&invoice["subcommands"], "posting");
+        let value_in = named(&posting["arguments"], "value_in");
+        assert_eq!(value_in["type"], "string");
+        assert_eq!(value_in["positional"], false);
+        assert_eq!(value_in["required"], false);
+        assert_eq!(value_in["client_key"], false);
+        assert_eq!(value_in["long"], "--value-in");
+        let client = named(&show["arguments"], "client");
+        assert_eq!(client["type"], "string");
+        assert_eq!(client["positional"], true);
+        assert_eq!(client["required"], true);
+        assert_eq!(client["client_key"], true);
+        assert_eq!(
+            named(&description["arguments"], "verbose")["type"],
+            "count"
+        );
+
+        let arguments = all_arguments(&description);
+        let has = |name: &str, value_type: &str| {
+            arguments
+                .iter()
+                .any(|arg| arg["name"] == name && arg["type"] == value_type)
+        };
+        assert!(has("number", "integer"));
+        assert!(has("enabled", "bool"));
+        assert!(arguments
+            .iter()
+            .any(|arg| arg["values"].get(0) == Some(&json!("toml"))));
+    }
+}
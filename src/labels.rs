@@ -0,0 +1,189 @@
+use chrono::{Datelike, NaiveDate};
+use rust_decimal::Decimal;
+
+use crate::billing::Period;
+
+/// Words and date formats printed on invoices in one language
+#[derive(Debug, PartialEq)]
+pub struct Labels {
+    pub code: &'static str,
+    pub invoice: &'static str,
+    pub bill_to: &'static str,
+    pub date: &'static str,
+    pub subtotal: &'static str,
+    pub total: &'static str,
+    pub rounding: &'static str,
+    pub deposit: &'static str,
+    /// Stamped on voided invoices
+    pub void: &'static str,
+    /// Label of a service's subtotal, `{}` is replaced by the service name
+    service_subtotal: &'static str,
+    /// Note on invoices with converted items, `{currency}` and `{rate}` are
+    /// replaced
+    converted: &'static str,
+    /// Hours of one week of an item, `{date}` and `{hours}` are replaced
+    week_hours: &'static str,
+    dates: DateStyle,
+}
+
+#[derive(Debug, PartialEq)]
+enum DateStyle {
+    /// 2024-04-15
+    Iso,
+    /// 15 avril 2024, with 1er for the first of the month
+    French([&'static str; 12]),
+    /// 15. April 2024
+    German([&'static str; 12]),
+}
+
+pub const ENGLISH: Labels = Labels {
+    code: "en",
+    invoice: "Invoice",
+    bill_to: "Bill to",
+    date: "Date",
+    subtotal: "Subtotal",
+    total: "Total",
+    rounding: "Rounding",
+    deposit: "Deposit",
+    void: "VOID",
+    service_subtotal: "{} subtotal",
+    converted: "Converted from {currency} at {rate}",
+    week_hours: "Week of {date}: {hours}h",
+    dates: DateStyle::Iso,
+};
+
+pub const FRENCH: Labels = Labels {
+    code: "fr",
+    invoice: "Facture",
+    bill_to: "Facturé à",
+    date: "Date",
+    subtotal: "Sous-total",
+    total: "Total",
+    rounding: "Arrondi",
+    deposit: "Acompte",
+    void: "ANNULÉE",
+    service_subtotal: "Sous-total {}",
+    converted: "Converti de {currency} au taux de {rate}",
+    week_hours: "Semaine du {date} : {hours} h",
+    dates: DateStyle::French([
+        "janvier",
+        "février",
+        "mars",
+        "avril",
+        "mai",
+        "juin",
+        "juillet",
+        "août",
+        "septembre",
+        "octobre",
+        "novembre",
+        "décembre",
+    ]),
+};
+
+pub const GERMAN: Labels = Labels {
+    code: "de",
+    invoice: "Rechnung",
+    bill_to: "Rechnung an",
+    date: "Datum",
+    subtotal: "Zwischensumme",
+    total: "Gesamtbetrag",
+    rounding: "Rundung",
+    deposit: "Anzahlung",
+    void: "STORNIERT",
+    service_subtotal: "Zwischensumme {}",
+    converted: "Umgerechnet aus {currency} zum Kurs {rate}",
+    week_hours: "Woche vom {date}: {hours} Std.",
+    dates: DateStyle::German([
+        "Januar",
+        "Februar",
+        "März",
+        "April",
+        "Mai",
+        "Juni",
+        "Juli",
+        "August",
+        "September",
+        "Oktober",
+        "November",
+        "Dezember",
+    ]),
+};
+
+const LANGUAGES: [&Labels; 3] = [&ENGLISH, &FRENCH, &GERMAN];
+
+impl Labels {
+    /// Labels for an ISO 639-1 language code, e.g. fr
+    pub fn for_language(code: &str) -> Option<&'static Labels> {
+        let code = code.trim().to_lowercase();
+        LANGUAGES.into_iter().find(|labels| labels.code == code)
+    }
+
+    pub fn date(&self, date: NaiveDate) -> String {
+        let (day, year) = (date.day(), date.year());
+        let month = date.month0() as usize;
+        match &self.dates {
+            DateStyle::Iso => date.to_string(),
+            DateStyle::French(months) if day == 1 => {
+                format!("1er {} {}", months[month], year)
+            }
+            DateStyle::French(months) => {
+                format!("{} {} {}", day, months[month], year)
+            }
+            DateStyle::German(months) => {
+                format!("{}. {} {}", day, months[month], year)
+            }
+        }
+    }
+
+    pub fn period(&self, period: &Period) -> String {
+        format!("{} — {}", self.date(period.from), self.date(period.until))
+    }
+
+    pub fn service_subtotal(&self, service: &str) -> String {
+        self.service_subtotal.replace("{}", service)
+    }
+
+    pub fn converted(&self, currency: &str, rate: &str) -> String {
+        self.converted
+            .replace("{currency}", currency)
+            .replace("{rate}", rate)
+    }
+
+    pub fn week_hours(&self, week: NaiveDate, hours: Decimal) -> String {
+        self.week_hours
+            .replace("{date}", &self.date(week))
+            .replace("{hours}", &hours.normalize().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::billing::tests::ymd;
+
+    #[test]
+    fn language_codes() {
+        assert_eq!(Labels::for_language("FR"), Some(&FRENCH));
+        assert_eq!(Labels::for_language("de"), Some(&GERMAN));
+        assert_eq!(Labels::for_language("es"), None);
+    }
+
+    #[test]
+    fn localized_dates() {
+        let april = Period::new(ymd(2024, 4, 1), ymd(2024, 4, 15));
+        assert_eq!(ENGLISH.period(&april), "2024-04-01 — 2024-04-15");
+        assert_eq!(FRENCH.period(&april), "1er avril 2024 — 15 avril 2024");
+        assert_eq!(GERMAN.period(&april), "1. April 2024 — 15. April 2024");
+        assert_eq!(FRENCH.date(ymd(2024, 8, 20)), "20 août 2024");
+        let hours = Decimal::new(3250, 2);
+        assert_eq!(
+            ENGLISH.week_hours(ymd(2024, 4, 1), hours),
+            "Week of 2024-04-01: 32.5h"
+        );
+        assert_eq!(
+            FRENCH.week_hours(ymd(2024, 4, 1), hours),
+            "Semaine du 1er avril 2024 : 32.5 h"
+        );
+    }
+}
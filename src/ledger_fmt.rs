@@ -1,11 +1,363 @@
 use std::fmt;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use unicode_width::UnicodeWidthStr;
+
+/// Postings wider than this aren't used to align the amount column, they
+/// get the minimum gap rather than pushing every other amount right
+const MAX_ALIGNED_WIDTH: usize = 72;
+const GAP: usize = 4;
+/// hledger needs at least two spaces between an account and its amount
+const MIN_GAP: usize = 2;
 
 pub trait LedgerDisplay {
-    fn ledger_fmt(&self, buf: &mut dyn fmt::Write) -> fmt::Result;
+    fn ledger_fmt(
+        &self,
+        style: &LedgerStyle,
+        buf: &mut dyn fmt::Write,
+    ) -> fmt::Result;
 }
 
-pub fn ledger_fmt(item: impl LedgerDisplay) -> String {
+pub fn ledger_fmt_with(
+    style: &LedgerStyle,
+    item: impl LedgerDisplay,
+) -> String {
     let mut buf = String::new();
-    item.ledger_fmt(&mut buf).expect("String formatting failed");
+    item.ledger_fmt(style, &mut buf)
+        .expect("String formatting failed");
     buf
 }
+
+/// An amount with the total it cost in another commodity, e.g.
+/// `-USD$1000.00 @@ $1350.00`, hledger takes the cost as unsigned
+pub fn ledger_fmt_with_cost(
+    style: &LedgerStyle,
+    amount: impl LedgerDisplay,
+    cost: impl LedgerDisplay,
+) -> String {
+    let cost = ledger_fmt_with(style, cost);
+    format!(
+        "{} @@ {}",
+        ledger_fmt_with(style, amount),
+        cost.trim_start_matches('-')
+    )
+}
+
+/// A market price directive valuing one unit of a commodity, e.g.
+/// `P 2024-05-01 USD$ $1.3751`, the rate keeps all of its decimal places
+pub fn price_directive(
+    style: &LedgerStyle,
+    date: NaiveDate,
+    commodity: impl LedgerDisplay,
+    price_commodity: impl LedgerDisplay,
+    rate: Decimal,
+) -> String {
+    let price_commodity = ledger_fmt_with(style, price_commodity);
+    let rate = style.format_number(&rate.normalize().to_string());
+    let separator = style.commodity_separator();
+    let price = match style.position {
+        CommodityPosition::Before => {
+            format!("{}{}{}", price_commodity, separator, rate)
+        }
+        CommodityPosition::After => {
+            format!("{}{}{}", rate, separator, price_commodity)
+        }
+    };
+    format!(
+        "P {} {} {}\n",
+        date,
+        ledger_fmt_with(style, commodity),
+        price
+    )
+}
+
+/// Lays out `(account, amount)` postings with the amounts right aligned,
+/// measuring display width so accented and wide characters line up
+pub fn align_postings(postings: &[(String, String)]) -> String {
+    let widths: Vec<usize> = postings
+        .iter()
+        .map(|(account, amount)| account.width() + amount.width())
+        .collect();
+    let column = widths
+        .iter()
+        .copied()
+        .filter(|width| *width <= MAX_ALIGNED_WIDTH)
+        .max()
+        .unwrap_or(0);
+
+    postings
+        .iter()
+        .zip(widths)
+        .map(|((account, amount), width)| {
+            let gap = (column + GAP).saturating_sub(width).max(MIN_GAP);
+            format!("    {}{}{}\n", account, " ".repeat(gap), amount)
+        })
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy, Default)]
+pub enum CommodityPosition {
+    #[default]
+    Before,
+    After,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy, Default)]
+pub enum DecimalMark {
+    #[default]
+    Point,
+    Comma,
+}
+
+/// How amounts are written for ledger and hledger journals, the default
+/// matches the original `$1234.56` output
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy, Default)]
+pub struct LedgerStyle {
+    pub position: CommodityPosition,
+    pub decimal_mark: DecimalMark,
+    pub grouping: bool,
+    pub iso_code: bool,
+}
+
+impl LedgerStyle {
+    /// Separator between commodity and number, symbols prefixing the amount
+    /// are written without one
+    pub fn commodity_separator(&self) -> &'static str {
+        if self.iso_code || self.position == CommodityPosition::After {
+            " "
+        } else {
+            ""
+        }
+    }
+
+    /// Writes the digits of an unsigned amount with the style's decimal mark
+    /// and grouping
+    pub fn format_number(&self, digits: &str) -> String {
+        let (int, frac) = digits.split_once('.').unwrap_or((digits, ""));
+        let (mark, separator) = match self.decimal_mark {
+            DecimalMark::Point => ('.', ','),
+            DecimalMark::Comma => (',', '.'),
+        };
+
+        let mut number = String::with_capacity(digits.len() + int.len() / 3);
+        for (i, c) in int.chars().enumerate() {
+            if self.grouping && i > 0 && (int.len() - i) % 3 == 0 {
+                number.push(separator);
+            }
+            number.push(c);
+        }
+        if !frac.is_empty() {
+            number.push(mark);
+            number.push_str(frac);
+        }
+        number
+    }
+}
+
+impl fmt::Display for LedgerStyle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let position = match self.position {
+            CommodityPosition::Before => "before",
+            CommodityPosition::After => "after",
+        };
+        let mark = match self.decimal_mark {
+            DecimalMark::Point => "point",
+            DecimalMark::Comma => "comma",
+        };
+        let grouping = if self.grouping {
+            "grouped"
+        } else {
+            "ungrouped"
+        };
+        let commodity = if self.iso_code { "code" } else { "symbol" };
+        write!(f, "{},{},{},{}", position, mark, grouping, commodity)
+    }
+}
+
+/// Parses a comma separated list of options, e.g. `after,comma,grouped,code`
+/// for `1.234,56 EUR`, unspecified options keep their defaults
+impl FromStr for LedgerStyle {
+    type Err = LedgerStyleError;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut style = Self::default();
+        for option in spec.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+            match option {
+                "before" => style.position = CommodityPosition::Before,
+                "after" => style.position = CommodityPosition::After,
+                "point" => style.decimal_mark = DecimalMark::Point,
+                "comma" => style.decimal_mark = DecimalMark::Comma,
+                "grouped" => style.grouping = true,
+                "ungrouped" => style.grouping = false,
+                "symbol" => style.iso_code = false,
+                "code" => style.iso_code = true,
+                _ => return Err(LedgerStyleError(option.to_string())),
+            }
+        }
+        Ok(style)
+    }
+}
+
+#[derive(Debug, Error)]
+#[error(
+    "Unknown ledger style option '{0}', expected a comma separated list of: \
+     before, after, point, comma, grouped, ungrouped, symbol, code"
+)]
+pub struct LedgerStyleError(String);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::billing::{Currency, Money};
+
+    fn styled(spec: &str, currency: Currency, cents: i64) -> String {
+        let style: LedgerStyle = spec.parse().unwrap();
+        ledger_fmt_with(&style, Money::new(currency, Decimal::new(cents, 2)))
+    }
+
+    #[test]
+    fn default_style() {
+        assert_eq!(styled("", Currency::Cad, 123456), "$1234.56");
+        assert_eq!(styled("", Currency::Cad, -123456), "-$1234.56");
+        assert_eq!(styled("", Currency::Eur, 5), "EUR€0.05");
+    }
+
+    #[test]
+    fn commodity_after_with_decimal_comma() {
+        let spec = "after,comma,grouped,code";
+        assert_eq!(styled(spec, Currency::Eur, 123456), "1.234,56 EUR");
+        assert_eq!(styled(spec, Currency::Eur, -123456), "-1.234,56 EUR");
+        assert_eq!(styled(spec, Currency::Eur, 99), "0,99 EUR");
+    }
+
+    #[test]
+    fn commodity_after_with_symbol() {
+        assert_eq!(styled("after", Currency::Eur, 123456), "1234.56 EUR€");
+        assert_eq!(styled("after", Currency::Cad, -100), "-1.00 $");
+    }
+
+    #[test]
+    fn code_before_with_grouping() {
+        let spec = "before,point,grouped,code";
+        assert_eq!(styled(spec, Currency::Usd, 123456789), "USD 1,234,567.89");
+        assert_eq!(
+            styled(spec, Currency::Usd, -123456789),
+            "-USD 1,234,567.89"
+        );
+        assert_eq!(styled(spec, Currency::Usd, 12345), "USD 123.45");
+    }
+
+    #[test]
+    fn grouping_with_symbol() {
+        assert_eq!(
+            styled("grouped", Currency::Cad, 100000000),
+            "$1,000,000.00"
+        );
+        assert_eq!(
+            styled("grouped,comma", Currency::Cad, -100000000),
+            "-$1.000.000,00"
+        );
+    }
+
+    #[test]
+    fn parse_round_trip() {
+        let style: LedgerStyle = "after,comma,grouped,code".parse().unwrap();
+        assert_eq!(style.to_string().parse::<LedgerStyle>().unwrap(), style);
+        assert_eq!(
+            LedgerStyle::default().to_string(),
+            "before,point,ungrouped,symbol"
+        );
+    }
+
+    #[test]
+    fn unknown_option() {
+        assert!("after,decimal".parse::<LedgerStyle>().is_err());
+    }
+
+    fn postings(accounts: &[&str]) -> Vec<(String, String)> {
+        accounts
+            .iter()
+            .map(|a| (a.to_string(), "$1050.00".to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn aligns_ascii_postings() {
+        let aligned = align_postings(&[
+            ("assets:bank".to_string(), "$1.00".to_string()),
+            ("revenues:clients:Acme".to_string(), "-$1.00".to_string()),
+        ]);
+
+        assert_eq!(
+            aligned,
+            "    assets:bank               $1.00\n    \
+             revenues:clients:Acme    -$1.00\n"
+        );
+    }
+
+    #[test]
+    fn aligns_accented_and_wide_names() {
+        let aligned = align_postings(&postings(&[
+            "assets:receivable:Müller & Söhne",
+            "assets:receivable:TVQ–Québec",
+            "assets:receivable:株式会社テスト",
+            "revenues:clients:Acme",
+        ]));
+        let widths: Vec<usize> = aligned.lines().map(|l| l.width()).collect();
+
+        assert_eq!(widths.len(), 4);
+        assert!(widths.iter().all(|w| *w == widths[0]), "{:?}", widths);
+    }
+
+    #[test]
+    fn long_accounts_do_not_push_the_column() {
+        let long = format!("expenses:{}", "x".repeat(100));
+        let aligned =
+            align_postings(&postings(&[&long, "assets:bank", "revenues:a"]));
+        let lines: Vec<&str> = aligned.lines().collect();
+
+        assert_eq!(lines[0], format!("    {}  $1050.00", long));
+        assert_eq!(lines[1], "    assets:bank    $1050.00");
+        assert_eq!(lines[2], "    revenues:a     $1050.00");
+    }
+
+    #[test]
+    fn total_cost() {
+        let amount = Money::new(Currency::Usd, Decimal::new(-100000, 2));
+        let cost = Money::new(Currency::Cad, Decimal::new(-135000, 2));
+        assert_eq!(
+            ledger_fmt_with_cost(&LedgerStyle::default(), amount, cost),
+            "-USD$1000.00 @@ $1350.00"
+        );
+        let style: LedgerStyle = "after,code".parse().unwrap();
+        assert_eq!(
+            ledger_fmt_with_cost(&style, amount, cost),
+            "-1000.00 USD @@ 1350.00 CAD"
+        );
+    }
+
+    #[test]
+    fn price_directives() {
+        let date = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let rate = Decimal::new(13751, 4);
+        assert_eq!(
+            price_directive(
+                &LedgerStyle::default(),
+                date,
+                Currency::Usd,
+                Currency::Cad,
+                rate
+            ),
+            "P 2024-05-01 USD$ $1.3751\n"
+        );
+        let style: LedgerStyle = "after,comma,code".parse().unwrap();
+        assert_eq!(
+            price_directive(&style, date, Currency::Usd, Currency::Cad, rate),
+            "P 2024-05-01 USD 1,3751 CAD\n"
+        );
+    }
+}
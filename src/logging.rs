@@ -0,0 +1,96 @@
+use std::cell::{Cell, RefCell};
+use std::fmt;
+
+/// How much detail to write to stderr, each `-v` enables the next level
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Level {
+    Info = 1,
+    Debug = 2,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Level::Info => write!(f, "info"),
+            Level::Debug => write!(f, "debug"),
+        }
+    }
+}
+
+// Commands run on a single thread, keeping the state per thread lets tests
+// capture their own messages while running in parallel
+thread_local! {
+    static VERBOSITY: Cell<u8> = const { Cell::new(0) };
+    static CAPTURED: RefCell<Option<Vec<String>>> =
+        const { RefCell::new(None) };
+}
+
+pub fn set_verbosity(count: u8) {
+    VERBOSITY.with(|verbosity| verbosity.set(count));
+}
+
+/// Writes a message to stderr when the verbosity includes its level, so
+/// output on stdout stays clean for piping
+pub fn log(level: Level, args: fmt::Arguments) {
+    if VERBOSITY.with(Cell::get) < level as u8 {
+        return;
+    }
+    let line = format!("[{}] {}", level, args);
+    CAPTURED.with(|captured| match captured.borrow_mut().as_mut() {
+        Some(lines) => lines.push(line),
+        None => eprintln!("{}", line),
+    });
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::logging::log(
+            $crate::logging::Level::Info,
+            format_args!($($arg)*),
+        )
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::logging::log(
+            $crate::logging::Level::Debug,
+            format_args!($($arg)*),
+        )
+    };
+}
+
+pub(crate) use debug;
+pub(crate) use info;
+
+/// Runs a function at a verbosity, returning the messages it logged
+/// instead of writing them to stderr
+#[cfg(test)]
+pub fn capture<T>(verbosity: u8, f: impl FnOnce() -> T) -> (T, Vec<String>) {
+    set_verbosity(verbosity);
+    CAPTURED.with(|captured| *captured.borrow_mut() = Some(Vec::new()));
+    let result = f();
+    let lines = CAPTURED.with(|captured| captured.borrow_mut().take());
+    set_verbosity(0);
+    (result, lines.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_filter_messages() {
+        let ((), lines) = capture(1, || {
+            info!("read {} events", 3);
+            debug!("hidden");
+        });
+        assert_eq!(lines, vec!["[info] read 3 events"]);
+
+        let ((), lines) = capture(2, || debug!("shown"));
+        assert_eq!(lines, vec!["[debug] shown"]);
+
+        let ((), lines) = capture(0, || info!("quiet"));
+        assert!(lines.is_empty());
+    }
+}
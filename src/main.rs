@@ -39,23 +39,49 @@
  *  Client data stored in TOML?
  */
 
+mod audit;
 mod billing;
 mod calendar;
 mod cli;
 mod clients;
+mod config;
+mod csv;
+mod exchange;
+mod export;
 mod historical;
+mod ical;
+mod import;
 mod input;
+mod introspect;
+mod labels;
 mod ledger_fmt;
+mod logging;
+mod paths;
+mod registration;
 mod run;
+mod search;
+mod snapshot;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod table;
 mod templates;
+mod ubl;
+mod xml;
+
+use std::process::ExitCode;
 
 use crate::cli::Opts;
 use clap::Parser;
 
-fn main() {
+fn main() -> ExitCode {
     let opts = Opts::parse();
 
-    if let Err(error) = run::run_cmd_with_path(opts.subcommand, &opts.file) {
-        eprintln!("{}", error);
+    match run::run_opts(opts) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            logging::debug!("{:?}", error);
+            eprintln!("{}", error);
+            ExitCode::FAILURE
+        }
     }
 }
@@ -0,0 +1,139 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use crate::billing::Invoice;
+use crate::clients::Client;
+use crate::search;
+
+/// Where invoice files go unless the profile sets another pattern
+pub const DEFAULT_PATTERN: &str =
+    "invoices/{year}/{key}/{year}-{month}-{key}-{number}.pdf";
+
+const PLACEHOLDERS: [&str; 5] = ["year", "month", "key", "number", "name"];
+
+/// The pattern with the placeholders filled in for an invoice, e.g.
+/// `{year}/{key}-{number}.pdf` to `2024/innotech-007.pdf`. The year and
+/// month are the invoice date's, numbers are padded to three digits, and
+/// the client name is slugified.
+pub fn invoice_path(
+    pattern: &str,
+    invoice: &Invoice,
+    client: &Client,
+) -> Result<PathBuf, PatternError> {
+    expand(pattern, |placeholder| match placeholder {
+        "year" => Some(invoice.date.format("%Y").to_string()),
+        "month" => Some(invoice.date.format("%m").to_string()),
+        "key" => Some(client.key.clone()),
+        "number" => Some(format!("{:03}", invoice.number)),
+        "name" => Some(slug(&client.name)),
+        _ => None,
+    })
+    .map(PathBuf::from)
+}
+
+/// Checks that a pattern only uses known placeholders
+pub fn validate(pattern: &str) -> Result<(), PatternError> {
+    expand(pattern, |placeholder| {
+        PLACEHOLDERS.contains(&placeholder).then(String::new)
+    })
+    .map(|_| ())
+}
+
+fn expand(
+    pattern: &str,
+    value: impl Fn(&str) -> Option<String>,
+) -> Result<String, PatternError> {
+    let mut expanded = String::with_capacity(pattern.len());
+    let mut rest = pattern;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| PatternError::Unclosed(pattern.to_string()))?;
+        let placeholder = &rest[start + 1..start + end];
+        expanded.push_str(&value(placeholder).ok_or_else(|| {
+            PatternError::Unknown(placeholder.to_string(), pattern.to_string())
+        })?);
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Lowercase words without accents joined by dashes, safe in a file name
+pub fn slug(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in search::fold(text) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum PatternError {
+    #[error(
+        "Unknown placeholder {{{0}}} in '{1}', expected year, month, key, \
+         number, or name"
+    )]
+    Unknown(String, String),
+    #[error("Unclosed {{ in '{0}'")]
+    Unclosed(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::billing::tests::{monthly, ymd};
+    use crate::billing::{Currency, InvoiceItem, Period};
+
+    #[test]
+    fn slugs() {
+        assert_eq!(slug("Müller & Söhne"), "muller-sohne");
+        assert_eq!(slug(" AC/DC Tours, Inc. "), "ac-dc-tours-inc");
+        assert_eq!(slug("2nd  Floor"), "2nd-floor");
+    }
+
+    #[test]
+    fn expand_invoice_paths() {
+        let client = Client::new("innotech", "Innotech / R&D", "Some Place");
+        let item = InvoiceItem::new(
+            "Stuff".to_string(),
+            monthly(Currency::Cad, 1000),
+            Period::new(ymd(2024, 3, 1), ymd(2024, 3, 31)),
+        );
+        let mut invoice = Invoice::new(7, vec![item], vec![]).unwrap();
+        invoice.date = ymd(2024, 4, 1);
+
+        assert_eq!(
+            invoice_path(DEFAULT_PATTERN, &invoice, &client),
+            Ok(PathBuf::from(
+                "invoices/2024/innotech/2024-04-innotech-007.pdf"
+            ))
+        );
+        assert_eq!(
+            invoice_path("/srv/{name}/{number}.pdf", &invoice, &client),
+            Ok(PathBuf::from("/srv/innotech-r-d/007.pdf"))
+        );
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        assert_eq!(validate(DEFAULT_PATTERN), Ok(()));
+        assert_eq!(
+            validate("{year}/{client}.pdf"),
+            Err(PatternError::Unknown(
+                "client".to_string(),
+                "{year}/{client}.pdf".to_string()
+            ))
+        );
+        assert_eq!(
+            validate("{year/x.pdf"),
+            Err(PatternError::Unclosed("{year/x.pdf".to_string()))
+        );
+    }
+}
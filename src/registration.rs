@@ -0,0 +1,268 @@
+use thiserror::Error;
+
+/// Country prefixes of EU VAT numbers, EL for Greece and XI for Northern
+/// Ireland
+const VAT_PREFIXES: [&str; 28] = [
+    "AT", "BE", "BG", "CY", "CZ", "DE", "DK", "EE", "EL", "ES", "FI", "FR",
+    "HR", "HU", "IE", "IT", "LT", "LU", "LV", "MT", "NL", "PL", "PT", "RO",
+    "SE", "SI", "SK", "XI",
+];
+
+/// The number in capitals without the spaces, dots, and dashes it's often
+/// written with
+pub fn normalize(number: &str) -> String {
+    number
+        .chars()
+        .filter(|c| !c.is_whitespace() && !matches!(c, '.' | '-'))
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+/// Checks a Canadian business number with its program account, e.g.
+/// `123456782RT0001` for GST/HST, or an EU VAT number, returning it
+/// normalized. German, Dutch, and Belgian VAT numbers have their check
+/// digits verified, other countries only their prefix.
+pub fn validate(number: &str) -> Result<String, RegistrationError> {
+    let number = normalize(number);
+    if number.starts_with(|c: char| c.is_ascii_digit()) {
+        business_number(&number)?;
+    } else {
+        vat_number(&number)?;
+    }
+    Ok(number)
+}
+
+/// The number as it's usually printed, business numbers with a space
+/// before the program account
+pub fn display(number: &str) -> String {
+    match business_number_format(number) {
+        true => format!("{} {}", &number[..9], &number[9..]),
+        false => number.to_string(),
+    }
+}
+
+fn business_number_format(number: &str) -> bool {
+    number.len() == 15
+        && number.is_ascii()
+        && number[..9].chars().all(|c| c.is_ascii_digit())
+        && number[9..11].chars().all(|c| c.is_ascii_uppercase())
+        && number[11..].chars().all(|c| c.is_ascii_digit())
+}
+
+fn business_number(number: &str) -> Result<(), RegistrationError> {
+    if !business_number_format(number) {
+        return Err(RegistrationError::Format(number.to_string()));
+    }
+    match luhn(&digits(&number[..9])) {
+        true => Ok(()),
+        false => Err(RegistrationError::Checksum(number.to_string())),
+    }
+}
+
+fn vat_number(number: &str) -> Result<(), RegistrationError> {
+    let prefix = number.get(..2).unwrap_or_default();
+    let rest = number.get(2..).unwrap_or_default();
+    if !number.is_ascii() || !VAT_PREFIXES.contains(&prefix) {
+        return Err(RegistrationError::Format(number.to_string()));
+    }
+    let all_digits = |text: &str| text.chars().all(|c| c.is_ascii_digit());
+    let (format, checked): (bool, fn(&str) -> bool) = match prefix {
+        "DE" => (rest.len() == 9 && all_digits(rest), german),
+        "NL" => (
+            rest.len() == 12
+                && all_digits(&rest[..9])
+                && &rest[9..10] == "B"
+                && all_digits(&rest[10..]),
+            dutch,
+        ),
+        "BE" => (
+            rest.len() == 10
+                && all_digits(rest)
+                && matches!(&rest[..1], "0" | "1"),
+            belgian,
+        ),
+        _ => (
+            (2..=12).contains(&rest.len())
+                && rest.chars().all(|c| c.is_ascii_alphanumeric())
+                && rest.chars().any(|c| c.is_ascii_digit()),
+            |_: &str| true,
+        ),
+    };
+    if !format {
+        let example = match prefix {
+            "DE" => "DE123456789",
+            "NL" => "NL123456789B01",
+            "BE" => "BE0123456789",
+            _ => "a country prefix and up to 12 digits or letters",
+        };
+        return Err(RegistrationError::VatFormat(
+            number.to_string(),
+            example.to_string(),
+        ));
+    }
+    match checked(number) {
+        true => Ok(()),
+        false => Err(RegistrationError::Checksum(number.to_string())),
+    }
+}
+
+fn digits(text: &str) -> Vec<u32> {
+    text.chars().filter_map(|c| c.to_digit(10)).collect()
+}
+
+/// Luhn check, the last digit makes the sum of the doubled digits a
+/// multiple of 10
+fn luhn(digits: &[u32]) -> bool {
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| match (i % 2, d * 2) {
+            (0, _) => d,
+            (_, doubled) if doubled > 9 => doubled - 9,
+            (_, doubled) => doubled,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// ISO 7064 MOD 11,10 over the eight digits before the check digit
+fn german(number: &str) -> bool {
+    let digits = digits(&number[2..]);
+    let product = digits[..8].iter().fold(10, |product, d| {
+        let sum = match (d + product) % 10 {
+            0 => 10,
+            sum => sum,
+        };
+        (2 * sum) % 11
+    });
+    let check = match 11 - product {
+        10 => 0,
+        check => check,
+    };
+    check == digits[8]
+}
+
+/// Weighted sum modulo 11 of the older numbers derived from the fiscal
+/// number, or modulo 97 of the whole number of newer ones
+fn dutch(number: &str) -> bool {
+    let digits = digits(&number[2..11]);
+    let weighted: u32 = digits[..8]
+        .iter()
+        .zip((2..=9).rev())
+        .map(|(d, weight)| d * weight)
+        .sum();
+    let letters = number.chars().map(|c| match c {
+        'A'..='Z' => (c as u32 - 'A' as u32 + 10).to_string(),
+        c => c.to_string(),
+    });
+    let mod97 = letters.collect::<String>().chars().fold(0, |rest, c| {
+        (rest * 10 + c.to_digit(10).unwrap_or_default()) % 97
+    });
+    weighted % 11 == digits[8] || mod97 == 1
+}
+
+/// The last two digits are 97 less the first eight modulo 97
+fn belgian(number: &str) -> bool {
+    let base: u32 = number[2..10].parse().unwrap_or_default();
+    let check: u32 = number[10..].parse().unwrap_or_default();
+    97 - base % 97 == check
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum RegistrationError {
+    #[error(
+        "'{0}' isn't a business number like 123456789RT0001 or a VAT number \
+         like DE123456789"
+    )]
+    Format(String),
+    #[error("'{0}' isn't a VAT number like {1}")]
+    VatFormat(String, String),
+    #[error("The check digits of '{0}' don't match")]
+    Checksum(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_numbers() {
+        use RegistrationError::*;
+        let vat_format = |number: &str, example: &str| {
+            Err(VatFormat(number.to_string(), example.to_string()))
+        };
+        let cases = [
+            ("123456782RT0001", Ok("123456782RT0001")),
+            ("123456782 RT 0001", Ok("123456782RT0001")),
+            ("123456782rt0001", Ok("123456782RT0001")),
+            (
+                "123456789RT0001",
+                Err(Checksum("123456789RT0001".to_string())),
+            ),
+            ("123456782RT001", Err(Format("123456782RT001".to_string()))),
+            (
+                "12345678RT00012",
+                Err(Format("12345678RT00012".to_string())),
+            ),
+            ("DE136695976", Ok("DE136695976")),
+            ("DE 136 695 976", Ok("DE136695976")),
+            ("DE136695977", Err(Checksum("DE136695977".to_string()))),
+            ("DE13669597", vat_format("DE13669597", "DE123456789")),
+            ("NL004495445B01", Ok("NL004495445B01")),
+            (
+                "NL004495446B01",
+                Err(Checksum("NL004495446B01".to_string())),
+            ),
+            (
+                "NL004495445X01",
+                vat_format("NL004495445X01", "NL123456789B01"),
+            ),
+            ("BE0403170701", Ok("BE0403170701")),
+            ("BE0403.170.701", Ok("BE0403170701")),
+            ("BE0403170702", Err(Checksum("BE0403170702".to_string()))),
+            ("BE2403170701", vat_format("BE2403170701", "BE0123456789")),
+            ("FRXX123456789", Ok("FRXX123456789")),
+            ("ATU12345678", Ok("ATU12345678")),
+            ("US123456789", Err(Format("US123456789".to_string()))),
+            ("GST", Err(Format("GST".to_string()))),
+            ("DEé3669597", Err(Format("DEÉ3669597".to_string()))),
+            ("", Err(Format("".to_string()))),
+        ];
+        for (number, expected) in cases {
+            assert_eq!(
+                validate(number),
+                expected.map(String::from),
+                "{}",
+                number
+            );
+        }
+    }
+
+    #[test]
+    fn luhn_check_digits() {
+        let cases = [
+            ("123456782", true),
+            ("123456789", false),
+            ("000000000", true),
+            ("79927398713", true),
+            ("79927398710", false),
+        ];
+        for (number, expected) in cases {
+            assert_eq!(luhn(&digits(number)), expected, "{}", number);
+        }
+    }
+
+    #[test]
+    fn display_numbers() {
+        let cases = [
+            ("123456782RT0001", "123456782 RT0001"),
+            ("123456789RT0001", "123456789 RT0001"),
+            ("12345678RT00012", "12345678RT00012"),
+            ("DE136695976", "DE136695976"),
+        ];
+        for (number, expected) in cases {
+            assert_eq!(display(number), expected, "{}", number);
+        }
+    }
+}
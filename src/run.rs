@@ -1,86 +1,1213 @@
 use std::cmp;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::slice;
 
-use crate::billing::{Invoice, InvoiceItem, TaxRate, Unit};
-use crate::cli::{Addable, Command, InvoiceView, Listable, Setable, Showable};
+use crate::audit;
+use crate::billing::{
+    self, Currency, CurrencyMismatch, Dispute, Invoice, InvoiceItem,
+    InvoiceKind, InvoiceTotal, ItemAmountError, Money, NewInvoiceError,
+    PostingDate, Rate, Received, TaxRate, TaxRounding, Unit, Void,
+    WeekProration, WriteOff,
+};
+use crate::calendar::{self, DateBoundaries};
+use crate::cli::{
+    Addable, Command, ExportFormat, InvoiceView, Listable, Opts,
+    ProfileSetable, ReminderFormat, ReminderLevel, Setable, Showable,
+};
 use crate::clients::{
-    self, Change, Client, ClientError, Clients, Event, Update,
+    self, Change, Client, ClientError, Clients, Event, PaymentRecord, Profile,
+    ProfileUpdate, ServiceTemplate, Update,
+};
+use crate::config::{self, Config, ConfigError};
+use crate::csv;
+use crate::exchange::{
+    self, Converted, ExchangeRates, MissingRate, RatesError,
+};
+use crate::export;
+use crate::ical::{Calendar, CalendarEvent};
+use crate::import::{self, ImportError};
+use crate::input::{self, Gap, InquirePrompter, Prompter, Review};
+use crate::introspect;
+use crate::labels::Labels;
+use crate::ledger_fmt::{
+    align_postings, ledger_fmt_with, ledger_fmt_with_cost, price_directive,
+    LedgerStyle, LedgerStyleError,
 };
-use crate::input;
-use crate::ledger_fmt::ledger_fmt;
+use crate::logging;
+use crate::paths::{self, PatternError};
+use crate::registration::{self, RegistrationError};
+use crate::search;
+use crate::snapshot::{self, Coverage, Snapshot};
+#[cfg(feature = "sqlite")]
+use crate::sqlite;
+use crate::table::{self, Cell, Color, Style, Table};
 use crate::templates;
+use crate::ubl;
 
-use chrono::{Datelike, NaiveDate};
+use chrono::{
+    DateTime, Datelike, Days, Duration, Local, NaiveDate, NaiveTime, Utc,
+};
+use clap::CommandFactory;
 use rust_decimal::Decimal;
+use serde::Serialize;
 use thiserror::Error;
 
+const HISTORY_FILE: &str = "client.history";
+const HISTORY_ENV: &str = "INVOGEN_FILE";
+const ORIGIN_ENV: &str = "INVOGEN_ORIGIN";
+
+pub fn run_opts(opts: Opts) -> Result<(), RunError> {
+    logging::set_verbosity(opts.verbose);
+    if opts.help_json {
+        println!("{}", introspect::describe(&Opts::command())?);
+        return Ok(());
+    }
+    if opts.subcommand.is_none() && !io::stdin().is_terminal() {
+        Opts::command().print_help()?;
+        return Ok(());
+    }
+    let env: HashMap<String, String> = env::vars().collect();
+    if let Some(Command::Init { path }) = opts.subcommand {
+        let path = path
+            .or(opts.file)
+            .or_else(|| env.get(HISTORY_ENV).map(PathBuf::from))
+            .unwrap_or_else(|| PathBuf::from(HISTORY_FILE));
+        init_history(&path)?;
+        if io::stdin().is_terminal() {
+            let origin = event_origin(&env);
+            set_up_history(&path, origin.as_deref(), &mut InquirePrompter)?;
+        }
+        print!("{}", next_steps(&path));
+        return Ok(());
+    }
+    let user_config = match config::user_config_path(&env) {
+        Some(path) => Config::from_file(&path)?,
+        None => Config::default(),
+    };
+    let path = resolve_history_path(
+        opts.file,
+        &env,
+        &user_config,
+        &env::current_dir()?,
+    );
+    let config = match &path {
+        Ok(path) => user_config.merge(Config::from_file(
+            &path.with_file_name(config::CONFIG_FILE),
+        )?),
+        Err(_) => user_config,
+    };
+    if let Some(Command::Config) = opts.subcommand {
+        print!(
+            "{}",
+            Config {
+                file: path.ok(),
+                ..config
+            }
+        );
+        return Ok(());
+    }
+    let path = path?;
+    if let Some(cmd) = &opts.subcommand {
+        require_history(cmd, &path)?;
+    }
+    if let Some(Command::Snapshot) = opts.subcommand {
+        return write_snapshot(&path);
+    }
+    let idempotency = opts.idempotency_key.map(|key| IdempotencyKey {
+        key,
+        since: config
+            .idempotency_days
+            .map(|days| Utc::now() - Duration::days(days.into())),
+    });
+    let origin = event_origin(&env);
+    match opts.subcommand {
+        Some(cmd) => run_cmd_with_path(
+            config.apply(cmd),
+            &path,
+            opts.dry_run,
+            idempotency.as_ref(),
+            origin.as_deref(),
+        ),
+        None => run_menu(
+            &path,
+            opts.dry_run,
+            origin.as_deref(),
+            &config,
+            &mut InquirePrompter,
+        ),
+    }
+}
+
+/// Who is adding events, `$INVOGEN_ORIGIN` if set, otherwise the user and
+/// host names, e.g. alice@laptop
+fn event_origin(env: &HashMap<String, String>) -> Option<String> {
+    let var = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| env.get(*name))
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+    if let Some(origin) = var(&[ORIGIN_ENV]) {
+        return Some(origin);
+    }
+    let host = var(&["HOSTNAME", "COMPUTERNAME"]).or_else(|| {
+        fs::read_to_string("/etc/hostname")
+            .ok()
+            .map(|host| host.trim().to_string())
+            .filter(|host| !host.is_empty())
+    });
+    match (var(&["USER", "USERNAME"]), host) {
+        (Some(user), Some(host)) => Some(format!("{}@{}", user, host)),
+        (user, host) => user.or(host),
+    }
+}
+
+/// Runs commands chosen from menus until the client menu is left, with the
+/// configured defaults like commands given on the command line
+fn run_menu(
+    history_path: &PathBuf,
+    dry_run: bool,
+    origin: Option<&str>,
+    config: &Config,
+    prompter: &mut dyn Prompter,
+) -> Result<(), RunError> {
+    let rates = history_path.with_file_name(exchange::RATES_FILE);
+    loop {
+        let clients = load_clients(history_path)?;
+        let Some(cmd) = menu_command(prompter, &clients)? else {
+            return Ok(());
+        };
+        let cmd = config.apply(cmd);
+        // Menu commands don't read the events, so the clients the menu was
+        // built from are all they need
+        let result = run_cmd_on_clients(cmd, &clients, &[], prompter, &rates)
+            .and_then(|new_events| {
+                save_new_events(
+                    history_path,
+                    clients,
+                    new_events,
+                    dry_run,
+                    None,
+                    origin,
+                )
+            });
+        // A failed command, e.g. one left with Esc, returns to the menu
+        if let Err(error) = result {
+            eprintln!("{}", error);
+        }
+    }
+}
+
+const MENU_ADD_CLIENT: &str = "Add a new client";
+const MENU_ACTIONS: [&str; 4] = ["Show", "Invoice", "Mark paid", "Set"];
+const MENU_SETTINGS: [&str; 8] = [
+    "Rate",
+    "Default hours",
+    "Taxes",
+    "Address",
+    "Name",
+    "Template",
+    "Footer",
+    "Rounding",
+];
+
+/// The command for a client and action picked from menus, None when the
+/// client menu is left
+fn menu_command(
+    prompter: &mut dyn Prompter,
+    clients: &Clients,
+) -> Result<Option<Command>, RunError> {
+    let all: Vec<&Client> = clients.iter().collect();
+    loop {
+        let mut options = vec![MENU_ADD_CLIENT.to_string()];
+        options.extend(all.iter().map(|c| format!("{} ({})", c.name, c.key)));
+        let Some(choice) = prompter.menu("Client:", options)? else {
+            return Ok(None);
+        };
+        let Some(client) = choice.checked_sub(1).map(|i| all[i]) else {
+            return Ok(Some(Command::Add {
+                property: Addable::Client,
+                timestamp: None,
+            }));
+        };
+
+        let actions = MENU_ACTIONS.map(String::from).to_vec();
+        let key = client.key.clone();
+        let cmd = match prompter.menu("Action:", actions)? {
+            None => continue,
+            Some(0) => Command::Show {
+                client: key,
+                property: None,
+            },
+            Some(1) => Command::Invoice {
+                client: key,
+                repeat_last: false,
+                deposit: false,
+                advance: false,
+                period: None,
+                weekly: false,
+                confirm_key: None,
+                currency: None,
+                preview: false,
+                timestamp: None,
+            },
+            Some(2) => {
+                let unpaid: Vec<&Invoice> =
+                    client.invoices().filter(|i| i.is_open()).collect();
+                if unpaid.is_empty() {
+                    println!("{} has no unpaid invoices", client.name);
+                    continue;
+                }
+                let options = unpaid
+                    .iter()
+                    .map(|i| {
+                        Ok(format!("#{} {}", i.number, i.calculate()?.total))
+                    })
+                    .collect::<Result<_, CurrencyMismatch>>()?;
+                let Some(i) = prompter.menu("Invoice:", options)? else {
+                    continue;
+                };
+                Command::MarkPaid {
+                    client: key,
+                    number: unpaid[i].display_number(),
+                    other_currency: false,
+                    timestamp: None,
+                }
+            }
+            Some(_) => {
+                let settings = MENU_SETTINGS.map(String::from).to_vec();
+                let property = match prompter.menu("Set:", settings)? {
+                    None => continue,
+                    Some(0) => Setable::Rate,
+                    Some(1) => Setable::DefaultHours,
+                    Some(2) => Setable::Taxes,
+                    Some(3) => Setable::Address { name: None },
+                    Some(4) => Setable::Name,
+                    Some(5) => Setable::Template,
+                    Some(6) => Setable::Footer,
+                    Some(_) => Setable::Rounding,
+                };
+                Command::Set {
+                    client: key,
+                    property,
+                }
+            }
+        };
+        return Ok(Some(cmd));
+    }
+}
+
+/// An explicit path is used first, then `$INVOGEN_FILE`, then the config
+/// file's, then the nearest existing history file in `cwd` or its parents
+fn resolve_history_path(
+    flag: Option<PathBuf>,
+    env: &HashMap<String, String>,
+    config: &Config,
+    cwd: &Path,
+) -> Result<PathBuf, RunError> {
+    flag.or_else(|| env.get(HISTORY_ENV).map(PathBuf::from))
+        .or_else(|| config.file.clone())
+        .or_else(|| nearest_history(cwd.ancestors()))
+        .ok_or(RunError::NoHistory)
+}
+
+/// The history file in the first of the directories that has one
+fn nearest_history<'a>(
+    dirs: impl IntoIterator<Item = &'a Path>,
+) -> Option<PathBuf> {
+    dirs.into_iter()
+        .map(|dir| dir.join(HISTORY_FILE))
+        .find(|path| path.is_file())
+}
+
+fn init_history(path: &Path) -> Result<(), RunError> {
+    fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?;
+    println!("Created empty history file {}", path.display());
+    Ok(())
+}
+
+/// Offers to set the sender and add a first client to a new history
+fn set_up_history(
+    path: &PathBuf,
+    origin: Option<&str>,
+    prompter: &mut dyn Prompter,
+) -> Result<(), RunError> {
+    if !prompter.set_up_now()? {
+        return Ok(());
+    }
+    let sender = Command::Profile {
+        property: Some(ProfileSetable::Sender),
+    };
+    run_cmd_on_file(sender, path, false, None, origin, prompter)?;
+    let client = Command::Add {
+        property: Addable::Client,
+        timestamp: None,
+    };
+    run_cmd_on_file(client, path, false, None, origin, prompter)
+}
+
+/// Commands to run after creating a history, passing --file unless it's
+/// found from the current directory
+fn next_steps(path: &Path) -> String {
+    let file = match path == Path::new(HISTORY_FILE) {
+        true => String::new(),
+        false => format!(" --file {}", path.display()),
+    };
+    let steps = [
+        (
+            "profile sender",
+            "set the name and address on your invoices",
+        ),
+        ("add client", "add a client"),
+        ("add service <client>", "add a service with its rate"),
+        ("invoice <client>", "invoice the client"),
+    ];
+    let steps: Vec<String> = steps
+        .iter()
+        .map(|(command, what)| {
+            format!("  invogen{} {}\n      {}\n", file, command, what)
+        })
+        .collect();
+    format!("\nNext steps:\n{}", steps.concat())
+}
+
+/// Only adding a client starts a history, other commands on a missing file
+/// would run against nothing
+fn require_history(cmd: &Command, path: &Path) -> Result<(), RunError> {
+    let starts_history = matches!(
+        cmd,
+        Command::Add {
+            property: Addable::Client,
+            ..
+        }
+    );
+    match starts_history || path.exists() {
+        true => Ok(()),
+        false => Err(RunError::MissingHistory(path.to_path_buf())),
+    }
+}
+
 pub fn run_cmd_with_path(
     cmd: Command,
     history_path: &PathBuf,
+    dry_run: bool,
+    idempotency: Option<&IdempotencyKey>,
+    origin: Option<&str>,
+) -> Result<(), RunError> {
+    run_cmd_on_file(
+        cmd,
+        history_path,
+        dry_run,
+        idempotency,
+        origin,
+        &mut InquirePrompter,
+    )
+}
+
+/// Key given with --idempotency-key, a command is only applied once per key
+pub struct IdempotencyKey {
+    pub key: String,
+    /// Only events recorded since then are checked for the key, backdated
+    /// events are always checked as their timestamps say nothing about when
+    /// they were added
+    pub since: Option<DateTime<Utc>>,
+}
+
+impl IdempotencyKey {
+    /// Whether any of the events was added with this key, reading them
+    /// until one is found
+    fn applied(
+        &self,
+        events: impl IntoIterator<Item = Result<Event, clients::EventError>>,
+    ) -> Result<bool, clients::EventError> {
+        for event in events {
+            let event = event?;
+            if (event.is_backdated() || self.since.is_none_or(|s| event.1 >= s))
+                && event.4.as_ref() == Some(&self.key)
+            {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Commands that go through the recorded events themselves rather than only
+/// the clients replayed from them
+fn reads_events(cmd: &Command) -> bool {
+    matches!(
+        cmd,
+        Command::Grep { .. } | Command::Log { .. } | Command::Verify { .. }
+    )
+}
+
+/// Runs a command against a history file, appending the new events unless
+/// it's a dry run, which prints them as they would be written instead. The
+/// history is streamed, only commands reading the events hold them all.
+fn run_cmd_on_file(
+    cmd: Command,
+    history_path: &PathBuf,
+    dry_run: bool,
+    idempotency: Option<&IdempotencyKey>,
+    origin: Option<&str>,
+    prompter: &mut dyn Prompter,
+) -> Result<(), RunError> {
+    if let Some(idempotency) = idempotency {
+        if idempotency.applied(clients::stream_events(history_path)?)? {
+            println!(
+                "Already applied with idempotency key '{}'",
+                idempotency.key
+            );
+            return Ok(());
+        }
+    }
+
+    let rates = history_path.with_file_name(exchange::RATES_FILE);
+    let (clients, events) = if reads_events(&cmd) {
+        let events = clients::events_from_file(history_path)?;
+        (replay(history_path, &events)?, events)
+    } else {
+        (load_clients(history_path)?, Vec::new())
+    };
+    let new_events =
+        run_cmd_on_clients(cmd, &clients, &events, prompter, &rates)?;
+    save_new_events(
+        history_path,
+        clients,
+        new_events,
+        dry_run,
+        idempotency,
+        origin,
+    )
+}
+
+/// Appends the events a command added to the history, or prints them as
+/// they would be written on a dry run
+fn save_new_events(
+    history_path: &PathBuf,
+    mut clients: Clients,
+    mut new_events: Vec<Event>,
+    dry_run: bool,
+    idempotency: Option<&IdempotencyKey>,
+    origin: Option<&str>,
+) -> Result<(), RunError> {
+    if let Some(idempotency) = idempotency {
+        for event in new_events.iter_mut() {
+            event.4 = Some(idempotency.key.clone());
+        }
+    }
+    for event in new_events.iter_mut().filter(|e| e.5.is_none()) {
+        event.5 = origin.map(str::to_string);
+    }
+    if dry_run {
+        check_new_events(&mut clients, &new_events)?;
+        for event in new_events.iter() {
+            print!("{}", clients::event_line(event)?);
+        }
+        println!("Dry run, {} was not changed", history_path.display());
+    } else if !new_events.is_empty() {
+        append_events(history_path, clients, new_events)?;
+    }
+    Ok(())
+}
+
+/// Applies new events to the clients they were made against, printing
+/// any warnings about them
+fn check_new_events(
+    clients: &mut Clients,
+    new_events: &[Event],
+) -> Result<(), RunError> {
+    for event in new_events.iter() {
+        for warning in clients.apply_event(event)? {
+            eprintln!("Warning: {}", warning);
+        }
+    }
+    Ok(())
+}
+
+/// Writes the new events once they replay onto the history's clients, so
+/// what's on disk always loads whatever state the command saw
+fn append_events(
+    history_path: &PathBuf,
+    mut clients: Clients,
+    new_events: Vec<Event>,
 ) -> Result<(), RunError> {
-    let mut events = clients::events_from_file(history_path)?;
+    logging::info!(
+        "Writing {} new events to {}",
+        new_events.len(),
+        history_path.display()
+    );
+    for event in new_events.iter() {
+        logging::debug!("Appending {}", clients::event_line(event)?.trim());
+    }
+    check_new_events(&mut clients, &new_events)
+        .map_err(|source| RunError::Unreplayable(Box::new(source)))?;
+    clients::append_to_file(history_path, &new_events)?;
+    Ok(())
+}
+
+/// Clients streamed from a history, resuming from its snapshot when it's of
+/// the same events, for when the events themselves aren't needed
+fn load_clients(history_path: &PathBuf) -> Result<Clients, RunError> {
+    if let Some(snapshot) =
+        Snapshot::read(&snapshot::snapshot_path(history_path))?
+    {
+        let mut stream = clients::stream_events(history_path)?;
+        if let Some(mut clients) = snapshot.resume(&mut stream)? {
+            clients.apply_events(stream)?;
+            return Ok(clients);
+        }
+        logging::info!("The snapshot is out of date, replaying all events");
+    }
+    Ok(Clients::from_events(clients::stream_events(history_path)?)?)
+}
+
+/// Clients replayed from a history's events, resuming from its snapshot when
+/// it's of the same events
+fn replay(history_path: &Path, events: &[Event]) -> Result<Clients, RunError> {
+    if let Some(snapshot) =
+        Snapshot::read(&snapshot::snapshot_path(history_path))?
+    {
+        if snapshot.matches(events)? {
+            let replayed = snapshot.covers.events;
+            let mut clients = snapshot.clients();
+            for event in events[replayed..].iter() {
+                clients.apply_event(event)?;
+            }
+            return Ok(clients);
+        }
+        logging::info!("The snapshot is out of date, replaying all events");
+    }
+    Ok(Clients::from_events(events.iter().cloned().map(Ok))?)
+}
 
-    if let Some(event) = run_cmd(cmd, &events)? {
-        events.push(event);
-        clients::events_to_file(history_path, &events)?;
+/// Saves the replayed clients beside the history, so later commands only
+/// replay the events added after it
+fn write_snapshot(history_path: &PathBuf) -> Result<(), RunError> {
+    let mut clients = Clients::new();
+    let mut coverage = Coverage::new();
+    for event in clients::stream_events(history_path)? {
+        let event = event?;
+        clients.apply_event(&event)?;
+        coverage.push(&event)?;
     }
+    let snapshot = Snapshot::new(clients, coverage);
+    let path = snapshot::snapshot_path(history_path);
+    snapshot.write(&path)?;
+    println!(
+        "Wrote a snapshot of {} events to {}",
+        coverage.events,
+        path.display()
+    );
     Ok(())
 }
 
-type MaybeEvent = Result<Option<Event>, RunError>;
+type NewEvents = Result<Vec<Event>, RunError>;
 
-fn run_cmd(cmd: Command, events: &[Event]) -> MaybeEvent {
-    let mut clients = Clients::from_events(events)?;
+#[cfg(test)]
+fn run_cmd(
+    cmd: Command,
+    events: &[Event],
+    prompter: &mut dyn Prompter,
+    rates: &Path,
+) -> NewEvents {
+    let mut clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+    let new_events =
+        run_cmd_on_clients(cmd, &clients, events, prompter, rates)?;
+    check_new_events(&mut clients, &new_events)?;
+    Ok(new_events)
+}
 
-    if let Some(event) = match cmd {
-        Command::Add { property } => match property {
-            Addable::Client => add_client(),
-            Addable::Service { client } => add_service(clients.get(&client)?),
-        },
-        Command::List { listing } => run_listings(&clients, listing),
-        Command::Invoice { client } => invoice(clients.get(&client)?),
+fn run_cmd_on_clients(
+    cmd: Command,
+    clients: &Clients,
+    events: &[Event],
+    prompter: &mut dyn Prompter,
+    rates: &Path,
+) -> NewEvents {
+    let new_events = match cmd {
+        Command::Add {
+            property,
+            timestamp,
+        } => {
+            let timestamp = parse_timestamp(timestamp)?;
+            let events = match property {
+                Addable::Client => add_client(prompter, clients),
+                Addable::Service { client } => add_service(
+                    prompter,
+                    clients.get(&client)?,
+                    &clients.profile,
+                ),
+                Addable::Retainer { client } => {
+                    add_retainer(prompter, clients.get(&client)?)
+                }
+                Addable::Template => add_template(prompter, &clients.profile),
+                Addable::FromTemplate { client } => add_from_template(
+                    prompter,
+                    clients.get(&client)?,
+                    &clients.profile,
+                ),
+            };
+            restamp(events, timestamp)
+        }
+        Command::List { listing, no_color } => {
+            let color = table::use_color(no_color);
+            run_listings(clients, listing, color, rates)
+        }
+        Command::Invoice {
+            client,
+            repeat_last,
+            deposit,
+            advance,
+            period,
+            weekly,
+            currency,
+            confirm_key,
+            preview,
+            timestamp,
+        } => {
+            let timestamp = parse_timestamp(timestamp)?;
+            let confirm_key = parse_confirm_key(confirm_key)?;
+            let currency = currency
+                .map(|code| {
+                    Currency::from_code(&code)
+                        .ok_or(RunError::UnknownCurrency(code))
+                })
+                .transpose()?;
+            let client = clients.get(&client)?;
+            let number = clients.next_invoice_num(client);
+            let events = if repeat_last {
+                repeat_invoice(prompter, client, number)
+            } else {
+                let today = Local::now().date_naive();
+                let period = period
+                    .map(|text| billing::Period::parse(&text, today))
+                    .transpose()?;
+                let latest = input::latest_period_date(today, advance);
+                if let Some(period) =
+                    period.as_ref().filter(|p| p.until > latest)
+                {
+                    return Err(RunError::PeriodAhead(period.clone(), latest));
+                }
+                let options = ItemOptions {
+                    advance,
+                    weekly,
+                    period,
+                };
+                let preview = preview.then_some(&clients.profile);
+                invoice(
+                    prompter, client, number, deposit, &options, currency,
+                    preview,
+                )
+            };
+            let events = key_confirmed(prompter, client, confirm_key, events?);
+            restamp(events, timestamp)
+        }
         Command::Show { client, property } => {
-            run_show(clients.get(&client)?, property)
+            run_show(clients.get(&client)?, &clients.profile, property, rates)
         }
         Command::Set { client, property } => {
             let client = clients.get(&client)?;
             match property {
-                Setable::Taxes => set_taxes(client),
-                Setable::Rate => set_rate(client),
-                Setable::Name => change_name(client),
-                Setable::Address => change_address(client),
+                Setable::Taxes => set_taxes(prompter, client),
+                Setable::Rate => set_rate(prompter, client, &clients.profile),
+                Setable::DefaultHours => set_default_hours(prompter, client),
+                Setable::Name => change_name(prompter, client),
+                Setable::Address { name } => {
+                    change_address(prompter, client, name)
+                }
+                Setable::InvoiceAddress { name } => {
+                    set_invoice_address(prompter, client, name)
+                }
+                Setable::Template => set_template(prompter, client),
+                Setable::Footer => set_footer(prompter, client),
+                Setable::Rounding => set_rounding(prompter, client),
+                Setable::PaymentAccount { account } => {
+                    set_payment_account(prompter, client, account)
+                }
+                Setable::StaleRateAge { months } => {
+                    set_stale_rate_age(prompter, client, months)
+                }
+                Setable::Language { code } => {
+                    set_language(prompter, client, code)
+                }
+                Setable::Schedule { service, day } => {
+                    set_schedule(prompter, client, service, day)
+                }
+                Setable::PostingDate { date } => {
+                    set_posting_date(prompter, client, date)
+                }
+                Setable::Category { service, category } => {
+                    set_category(prompter, client, service, category)
+                }
+                Setable::TargetHours { service, hours } => {
+                    set_target_hours(prompter, client, service, hours)
+                }
             }
         }
-        Command::MarkPaid { client, number } => {
+        Command::MarkPaid {
+            client,
+            number,
+            other_currency,
+            timestamp,
+        } => {
+            let timestamp = parse_timestamp(timestamp)?;
             let client = clients.get(&client)?;
-            let invoice = client.invoice(&number)?;
-            mark_paid(invoice, client)
+            let invoice = client.find_invoice(&number)?;
+            let events = mark_paid(prompter, invoice, client, other_currency);
+            restamp(events, timestamp)
         }
-        Command::Remove { client: _ } => Ok(None), // TODO impl
-    }? {
-        clients.apply_event(&event)?;
-        Ok(Some(event))
-    } else {
-        Ok(None)
+        Command::MarkSent {
+            client,
+            number,
+            timestamp,
+        } => {
+            let timestamp = parse_timestamp(timestamp)?;
+            let client = clients.get(&client)?;
+            let invoice = client.find_invoice(&number)?;
+            restamp(mark_sent(prompter, invoice, client), timestamp)
+        }
+        Command::WriteOff { client, number } => {
+            let client = clients.get(&client)?;
+            write_off(prompter, client.invoice(&number)?, client)
+        }
+        Command::Void { client, number } => {
+            let client = clients.get(&client)?;
+            void(prompter, client.invoice(&number)?, client)
+        }
+        Command::Dispute { client, number } => {
+            let client = clients.get(&client)?;
+            dispute(prompter, client.invoice(&number)?, client)
+        }
+        Command::ResolveDispute { client, number } => {
+            let client = clients.get(&client)?;
+            resolve_dispute(prompter, client.invoice(&number)?, client)
+        }
+        Command::Remove { client: _ } => Ok(vec![]), // TODO impl
+        Command::Path { client, number } => {
+            let client = clients.get(&client)?;
+            let invoice = client.find_invoice(&number)?;
+            let pattern = clients.profile.invoice_path_pattern();
+            let path = paths::invoice_path(pattern, invoice, client)?;
+            println!("{}", path.display());
+            Ok(vec![])
+        }
+        Command::RemoveTemplate { name } => {
+            if !clients.profile.service_templates.contains_key(&name) {
+                return Err(RunError::NoTemplate(name));
+            }
+            println!("Removing service template {}", name);
+            confirmed(prompter, || {
+                Event::new_profile(ProfileUpdate::RemoveServiceTemplate(name))
+            })
+        }
+        Command::NormalizeServices { client } => {
+            normalize_services(prompter, clients.get(&client)?)
+        }
+        Command::Ical {
+            output,
+            reminders,
+            include_disputed,
+        } => export_ical(clients, &output, reminders, include_disputed),
+        Command::Export { format, output } => {
+            export(clients, format, output.as_deref())
+        }
+        Command::Find { query, key_only } => {
+            find_clients(clients, &query, key_only)
+        }
+        Command::Grep { query, raw } => grep_history(events, &query, raw),
+        Command::Log { origin } => {
+            for line in log_lines(events, origin.as_deref()) {
+                println!("{}", line);
+            }
+            Ok(vec![])
+        }
+        Command::Remind {
+            client,
+            number,
+            level,
+            format,
+            output,
+        } => {
+            let client = clients.get(&client)?;
+            let invoice = client.find_invoice(&number)?;
+            let profile = &clients.profile;
+            let today = Local::now().date_naive();
+            let text =
+                reminder(invoice, client, profile, level, format, today)?;
+            match output {
+                Some(output) => {
+                    fs::write(&output, text)?;
+                    println!("Wrote {}", output.display());
+                }
+                None => print!("{}", text),
+            }
+            Ok(vec![])
+        }
+        Command::Due {
+            generate,
+            confirm_key,
+        } => {
+            let today = Local::now().date_naive();
+            let confirm_key = parse_confirm_key(confirm_key)?;
+            due_invoices(prompter, clients, today, generate, confirm_key)
+        }
+        Command::Unbilled { convert_to } => {
+            let convert = reporting_currency(convert_to, rates)?;
+            list_unbilled(clients, convert.as_ref())
+        }
+        Command::Hours { client, year } => {
+            let today = Local::now().date_naive();
+            let year = year.unwrap_or(today.year());
+            let table = hours_table(clients.get(&client)?, year, today);
+            print!("{}", table.render(false));
+            Ok(vec![])
+        }
+        Command::Accrual {
+            client,
+            period,
+            ledger_style,
+        } => {
+            let client = clients.get(&client)?;
+            let today = Local::now().date_naive();
+            let period = billing::Period::parse(&period, today)?;
+            let style = ledger_style_or(ledger_style, &clients.profile)?;
+            accrual_posting(client, &period, &style)
+        }
+        Command::Revenue { year } => {
+            print!("{}", revenue_table(clients, year).render(false));
+            Ok(vec![])
+        }
+        Command::Invoices { number, json } => {
+            let index = invoice_index(clients, number.as_deref())?;
+            if json {
+                println!("{}", invoice_index_json(&index)?);
+            } else {
+                print!("{}", invoice_index_table(&index)?.render(false));
+            }
+            Ok(vec![])
+        }
+        Command::Audit {
+            client,
+            number,
+            legacy_weeks,
+        } => {
+            let client = clients.get(&client)?;
+            let weeks = week_proration(legacy_weeks);
+            audit_invoice(client, client.invoice(&number)?, weeks)
+        }
+        Command::Verify { legacy_weeks } => {
+            verify(clients, events, week_proration(legacy_weeks))
+        }
+        Command::Explain { from, until, unit } => {
+            let period = billing::Period::new(from.parse()?, until.parse()?);
+            explain(&period, &parse_unit(&unit)?)
+        }
+        Command::ImportInvoices { client, path } => {
+            import_invoices(prompter, clients.get(&client)?, &path)
+        }
+        Command::Init { .. } => unreachable!("init runs without a history"),
+        Command::Config => unreachable!("config is printed before running"),
+        Command::Snapshot => {
+            unreachable!("snapshots are written before running")
+        }
+        Command::Profile { property } => match property {
+            None => show_profile(&clients.profile),
+            Some(ProfileSetable::Sender) => set_sender(prompter),
+            Some(ProfileSetable::Footer) => set_default_footer(prompter),
+            Some(ProfileSetable::LedgerStyle { style }) => {
+                set_ledger_style(prompter, &style)
+            }
+            Some(ProfileSetable::PaymentAccount { account }) => {
+                println!("Setting the default payment account to {}", account);
+                confirmed(prompter, || {
+                    Event::new_profile(ProfileUpdate::PaymentAccount(account))
+                })
+            }
+            Some(ProfileSetable::HoursPerDay { hours }) => {
+                let hours_per_day = hours
+                    .parse::<Decimal>()
+                    .ok()
+                    .filter(|h| *h > Decimal::ZERO && *h <= Decimal::from(24))
+                    .ok_or(RunError::HoursPerDay(hours))?;
+                println!(
+                    "Comparing rates with {}h working days",
+                    hours_per_day
+                );
+                confirmed(prompter, || {
+                    Event::new_profile(ProfileUpdate::HoursPerDay(
+                        hours_per_day,
+                    ))
+                })
+            }
+            Some(ProfileSetable::GlobalNumbering { enabled }) => {
+                if enabled {
+                    println!("Numbering all clients' invoices in one sequence");
+                } else {
+                    println!("Numbering each client's invoices separately");
+                }
+                confirmed(prompter, || {
+                    Event::new_profile(ProfileUpdate::GlobalNumbering(enabled))
+                })
+            }
+            Some(ProfileSetable::InvoicePath { pattern }) => {
+                paths::validate(&pattern)?;
+                println!("Setting the invoice path pattern to {}", pattern);
+                confirmed(prompter, || {
+                    Event::new_profile(ProfileUpdate::InvoicePath(pattern))
+                })
+            }
+            Some(ProfileSetable::Registration {
+                label,
+                number,
+                force,
+            }) => set_registration(
+                prompter,
+                &clients.profile,
+                label,
+                number,
+                force,
+            ),
+        },
+    }?;
+    Ok(new_events)
+}
+
+fn parse_timestamp(
+    timestamp: Option<String>,
+) -> Result<Option<DateTime<Utc>>, RunError> {
+    timestamp
+        .map(|t| match DateTime::parse_from_rfc3339(&t) {
+            Ok(time) => Ok(time.with_timezone(&Utc)),
+            Err(source) => Err(RunError::Timestamp(t, source)),
+        })
+        .transpose()
+}
+
+/// Records new events at an explicit, usually past, time
+fn restamp(events: NewEvents, timestamp: Option<DateTime<Utc>>) -> NewEvents {
+    let Some(timestamp) = timestamp else {
+        return events;
+    };
+    Ok(events?
+        .into_iter()
+        .map(|mut event| {
+            event.1 = timestamp;
+            event.3 = true;
+            event
+        })
+        .collect())
+}
+
+/// Emits the event only when the user confirms it
+fn confirmed(
+    prompter: &mut dyn Prompter,
+    event: impl FnOnce() -> Event,
+) -> NewEvents {
+    Ok(prompter.confirm()?.then(event).into_iter().collect())
+}
+
+/// Emits the event only when the user confirms it for the client
+fn confirmed_for(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+    event: impl FnOnce() -> Event,
+) -> NewEvents {
+    let target = confirm_target(&client.key, &client.name, None);
+    Ok(prompter
+        .confirm_for(&target)?
+        .then(event)
+        .into_iter()
+        .collect())
+}
+
+/// The client a change is for, set apart so changes aren't confirmed for
+/// the wrong one, e.g. [acme] Acme Corp
+fn confirm_target(key: &str, name: &str, detail: Option<&str>) -> String {
+    let key = format!("[{}]", key);
+    let key = match table::use_color(false) {
+        true => table::paint(&key, Color::Cyan),
+        false => key,
+    };
+    match detail {
+        Some(detail) => format!("{} {}: {}", key, name, detail),
+        None => format!("{} {}", key, name),
+    }
+}
+
+/// Confirmation of new invoices naming the client with their totals and
+/// the period they bill
+fn confirm_invoices(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+    invoices: &[Invoice],
+) -> Result<bool, RunError> {
+    let totals = invoices
+        .iter()
+        .map(|invoice| Ok(invoice.calculate()?.total.to_string()))
+        .collect::<Result<Vec<String>, CurrencyMismatch>>()?;
+    let periods = invoices
+        .iter()
+        .flat_map(|i| i.items.iter())
+        .map(|i| (i.period.from, i.period.until));
+    let detail = match periods.reduce(|(a, b), (c, d)| (a.min(c), b.max(d))) {
+        Some((from, until)) => format!(
+            "{} for {}",
+            totals.join(" + "),
+            billing::Period::new(from, until)
+        ),
+        None => totals.join(" + "),
+    };
+    let target = confirm_target(&client.key, &client.name, Some(&detail));
+    Ok(prompter.confirm_for(&target)?)
+}
+
+/// A --confirm-key amount in any currency, e.g. 5,000, invoices totalling
+/// more need the client's key typed to confirm them
+fn parse_confirm_key(
+    amount: Option<String>,
+) -> Result<Option<Decimal>, RunError> {
+    amount
+        .map(|amount| {
+            billing::parse_amount(&amount, Currency::Cad.decimals())
+                .ok_or(RunError::ConfirmKey(amount))
+        })
+        .transpose()
+}
+
+/// The new events, unless they invoice more than the --confirm-key amount
+/// and the client's key isn't typed to confirm them
+fn key_confirmed(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+    confirm_key: Option<Decimal>,
+    events: Vec<Event>,
+) -> NewEvents {
+    let Some(above) = confirm_key else {
+        return Ok(events);
+    };
+    let mut over = false;
+    for event in events.iter() {
+        if let Change::Updated(Update::Invoiced(invoice)) = &event.2 {
+            over |= invoice.calculate()?.total.amount() > above;
+        }
+    }
+    if !over || prompter.typed_key(&client.key)?.trim() == client.key {
+        return Ok(events);
+    }
+    println!("Invoice aborted, {} wasn't typed", client.key);
+    Ok(vec![])
+}
+
+/// The currency to convert totals to, with the rates from the sidecar file
+fn reporting_currency(
+    convert_to: Option<String>,
+    rates: &Path,
+) -> Result<Option<(Currency, ExchangeRates)>, RunError> {
+    convert_to
+        .map(|code| {
+            let currency = Currency::from_code(&code)
+                .ok_or(RunError::UnknownCurrency(code))?;
+            Ok((currency, ExchangeRates::from_file(rates)?))
+        })
+        .transpose()
+}
+
+/// Lists the rates a converted total used and warns about the amounts left
+/// out of it
+fn print_conversion(converted: &Converted) {
+    for (from, date, rate) in converted.rates.iter() {
+        println!(
+            "Rate {} to {}: {} as of {}",
+            from.code(),
+            converted.total.currency().code(),
+            rate,
+            date
+        );
+    }
+    for (label, missing) in converted.missing.iter() {
+        eprintln!("Warning: {} left out of the total, {}", label, missing);
     }
 }
 
-fn run_listings(clients: &Clients, listing: Listable) -> MaybeEvent {
+fn run_listings(
+    clients: &Clients,
+    listing: Listable,
+    color: bool,
+    rates: &Path,
+) -> NewEvents {
+    let style = |porcelain| match porcelain {
+        true => Style::Porcelain,
+        false => Style::Aligned { color },
+    };
     match listing {
-        Listable::Clients => list_clients(clients),
-        Listable::Invoices { client } => list_invoices(clients.get(&client)?),
-        Listable::Services { client } => list_services(clients.get(&client)?),
+        Listable::Clients { key_only: true, .. } => {
+            for client in clients.iter() {
+                println!("{}", client.key);
+            }
+            Ok(vec![])
+        }
+        Listable::Clients {
+            convert_to,
+            porcelain,
+            ..
+        } => {
+            let convert = reporting_currency(convert_to, rates)?;
+            list_clients(clients, style(porcelain), convert.as_ref())
+        }
+        Listable::Invoices { client, porcelain } => {
+            list_invoices(clients.get(&client)?, style(porcelain))
+        }
+        Listable::Services {
+            client,
+            porcelain,
+            history,
+            json,
+        } => {
+            let client = clients.get(&client)?;
+            match (history, json) {
+                (_, true) => {
+                    println!("{}", rate_history_json(client)?);
+                    Ok(vec![])
+                }
+                (true, false) => list_rate_history(client, style(porcelain)),
+                (false, false) => list_services(client, style(porcelain)),
+            }
+        }
+        Listable::Taxes { client, as_of } => {
+            let as_of = as_of.map(|date| date.parse()).transpose()?;
+            list_taxes(clients.get(&client)?, as_of)
+        }
+        Listable::Templates => list_templates(&clients.profile),
     }
 }
 
-fn run_show(client: &Client, property: Option<Showable>) -> MaybeEvent {
+fn run_show(
+    client: &Client,
+    profile: &Profile,
+    property: Option<Showable>,
+    rates: &Path,
+) -> NewEvents {
     match property {
-        None => show_client(client),
+        None => show_client(client, profile),
         Some(prop) => match prop {
-            Showable::Taxes => Ok(None), // TODO show_client_taxes(client),
-            Showable::Invoice { number, view } => {
-                let invoice = client.invoice(&number)?;
-                run_show_invoice(invoice, client, view)
+            Showable::Taxes => show_taxes(client),
+            Showable::Rates => show_rates(client),
+            Showable::Retainer => show_retainers(client),
+            Showable::Payments { year } => show_payments(client, year),
+            Showable::Invoice { number, flat, view } => {
+                let invoice = client.find_invoice(&number)?;
+                run_show_invoice(invoice, client, profile, flat, view, rates)
             }
         },
     }
@@ -89,273 +1216,7331 @@ fn run_show(client: &Client, property: Option<Showable>) -> MaybeEvent {
 fn run_show_invoice(
     invoice: &Invoice,
     client: &Client,
+    profile: &Profile,
+    flat: bool,
     view: Option<InvoiceView>,
-) -> MaybeEvent {
+    rates: &Path,
+) -> NewEvents {
     match view {
-        None => show_invoice(invoice),
+        None => show_invoice(invoice, client, flat),
         Some(view) => match view {
-            InvoiceView::Payment => Ok(None), // TODO invoice_payment_posting(invoice, client),
-            InvoiceView::Posting => invoice_posting(invoice, client),
-            InvoiceView::Latex => invoice_tex(invoice, client),
+            InvoiceView::Payment { ledger_style, date } => {
+                let style = ledger_style_or(ledger_style, profile)?;
+                let date = posting_date_or(date, client)?;
+                let account = client.payment_account(profile);
+                payment_posting(invoice, client, &style, account, date)
+            }
+            InvoiceView::Posting {
+                ledger_style,
+                value_in,
+                price_directive,
+                by_service,
+                date,
+                reverse_accrual,
+            } => {
+                let style = ledger_style_or(ledger_style, profile)?;
+                let date = posting_date_or(date, client)?;
+                let valuation = reporting_currency(value_in, rates)?
+                    .map(|(currency, rates)| {
+                        let (_, rate) = rates.rate(
+                            invoice.currency(),
+                            currency,
+                            invoice.date,
+                        )?;
+                        Ok::<_, RunError>(Valuation {
+                            currency,
+                            rate,
+                            price_directive,
+                        })
+                    })
+                    .transpose()?;
+                let valuation = valuation.as_ref();
+                invoice_posting(
+                    invoice,
+                    client,
+                    &style,
+                    valuation,
+                    by_service,
+                    date,
+                    reverse_accrual,
+                )
+            }
+            InvoiceView::WriteOff { ledger_style } => {
+                let style = ledger_style_or(ledger_style, profile)?;
+                write_off_posting(invoice, client, &style)
+            }
+            InvoiceView::Latex { output } => {
+                let output = match output {
+                    Some(None) => Some(
+                        paths::invoice_path(
+                            profile.invoice_path_pattern(),
+                            invoice,
+                            client,
+                        )?
+                        .with_extension("tex"),
+                    ),
+                    Some(Some(path)) => Some(path),
+                    None => None,
+                };
+                invoice_tex(invoice, client, profile, flat, output.as_deref())
+            }
+            InvoiceView::Csv => invoice_csv(invoice),
+            InvoiceView::Ubl => invoice_ubl(invoice, client, profile),
         },
     }
 }
 
-fn add_client() -> MaybeEvent {
-    let (key, name, address) = input::client()?;
+/// A style given on the command line takes precedence over the profile's
+fn ledger_style_or(
+    spec: Option<String>,
+    profile: &Profile,
+) -> Result<LedgerStyle, LedgerStyleError> {
+    spec.map_or(Ok(profile.ledger_style), |spec| spec.parse())
+}
+
+fn posting_date_or(
+    date: Option<String>,
+    client: &Client,
+) -> Result<PostingDate, RunError> {
+    date.map_or(Ok(client.posting_date()), |date| parse_posting_date(&date))
+}
+
+fn add_client(prompter: &mut dyn Prompter, clients: &Clients) -> NewEvents {
+    let taken = clients.iter().map(|c| c.key.clone()).collect();
+    let (key, name, address) = prompter.client(taken)?;
+    clients::validate_key(&key, clients.iter().map(|c| c.key.as_str()))?;
+    let taxes = match prompter.taxes_now()? {
+        true => Some(prompter.taxes()?),
+        false => None,
+    };
     println!("\nAdding client {}:\n\n{}\n{}", key, name, address);
-    Ok(input::confirm()?
-        .then(|| Event::new(&key, Change::Added { name, address })))
+    if let Some((taxes, effective)) = &taxes {
+        println!("\nTaxes from {}: {}", effective, tax_list(taxes));
+    }
+    if !prompter.confirm_for(&confirm_target(&key, &name, None))? {
+        return Ok(vec![]);
+    }
+    let mut events = vec![Event::new(&key, Change::Added { name, address })];
+    if let Some((taxes, effective)) = taxes {
+        events.push(Event::new_update(&key, Update::Taxes(effective, taxes)));
+    }
+    Ok(events)
+}
+
+/// Taxes separated by commas, or none
+fn tax_list(taxes: &[TaxRate]) -> String {
+    match taxes.is_empty() {
+        true => "none".to_string(),
+        false => {
+            let taxes: Vec<String> =
+                taxes.iter().map(|t| t.to_string()).collect();
+            taxes.join(", ")
+        }
+    }
 }
 
-fn add_service(client: &Client) -> MaybeEvent {
-    let (name, rate, effective) = input::service()?;
+fn add_service(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+    profile: &Profile,
+) -> NewEvents {
+    let (name, rate, effective) = prompter.service()?;
+    check_service_name(client, &name)?;
     println!("\nAdding service {} for client {}", name, client.name);
     println!("Billing at: {}", rate);
+    let today = Local::now().date_naive();
+    if let Some(equivalents) =
+        rate_equivalents(&rate, profile.hours_per_day(), today)
+    {
+        println!("{}", equivalents);
+    }
     println!("Effective: {}", effective);
-    Ok(input::confirm()?.then(|| {
+    println!("Client taxes: {}", tax_list(&client.current_taxes()));
+    confirmed_for(prompter, client, || {
         Event::new_update(
             &client.key,
             Update::ServiceRate(name, effective, rate),
         )
-    }))
+    })
 }
 
-fn list_clients(clients: &Clients) -> MaybeEvent {
-    for client in clients.iter() {
-        println!("{}", client);
+fn check_service_name(client: &Client, name: &str) -> Result<(), RunError> {
+    match client.similar_service(name) {
+        Some(existing) => Err(ClientError::SimilarService(
+            name.to_string(),
+            existing.to_string(),
+        )
+        .into()),
+        None => Ok(()),
     }
-    Ok(None)
 }
 
-fn show_client(client: &Client) -> MaybeEvent {
-    println!("{}", client);
-
-    list_services(client)?;
-
-    for tax in client.current_taxes().iter() {
-        println!("Tax: {}", tax);
-    }
-
-    if let Some(date) = client.billed_until() {
-        println!("Billed Until: {}", date);
+/// Merges each group of similarly named services into the one whose name
+/// is already normalized, or the first otherwise
+fn normalize_services(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+) -> NewEvents {
+    let groups = client.duplicate_services();
+    if groups.is_empty() {
+        println!("No duplicate services for {}", client.name);
+        return Ok(vec![]);
     }
-
-    print!("Outstanding invoices:");
-    for num in client.unpaid_invoices() {
-        print!(" #{}", num);
+    let mut events = vec![];
+    for names in groups {
+        let into = names
+            .iter()
+            .find(|name| clients::normalize_service_name(name) == **name)
+            .unwrap_or(&names[0]);
+        for from in names.iter().filter(|name| *name != into) {
+            println!("Merging '{}' into '{}'", from, into);
+            events.push(Event::new_update(
+                &client.key,
+                Update::ServicesMerged(from.to_string(), into.to_string()),
+            ));
+        }
     }
-
-    Ok(None)
+    let target = confirm_target(&client.key, &client.name, None);
+    Ok(if prompter.confirm_for(&target)? {
+        events
+    } else {
+        vec![]
+    })
 }
 
-fn invoice(client: &Client) -> MaybeEvent {
-    let mut items: Vec<InvoiceItem> = Vec::new();
-    let mut start = NaiveDate::MAX;
+fn add_template(prompter: &mut dyn Prompter, profile: &Profile) -> NewEvents {
+    let name = prompter.template_name()?;
+    let mut template = ServiceTemplate::default();
     loop {
-        let period = input::period(client.billed_until())?;
-        let name = input::service_select(client.service_names())?;
-        let rate = client
-            .service(name.clone())
-            .and_then(|s| s.rates.as_of(period.from))
-            .ok_or(ClientError::NoRate(client.key.clone(), period.from))?;
-        let item = if rate.per == Unit::Hour {
-            let quantity = input::num_hours()?;
-            InvoiceItem::new_hourly(name, rate.clone(), period, quantity)
-        } else {
-            InvoiceItem::new(name, rate.clone(), period)
-        };
-        start = cmp::min(start, item.period.from);
-        items.push(item);
-
-        if !input::another()? {
+        template.services.push(prompter.template_service()?);
+        if !prompter.another()? {
             break;
         }
     }
-    let taxes = client.taxes_as_of(start);
-    let invoice = Invoice::new(client.next_invoice_num(), items, taxes);
+    template.taxes = prompter.template_taxes()?;
 
-    println!("Adding invoice:\n\n{}", invoice);
-    Ok(input::confirm()?
-        .then(|| Event::new_update(&client.key, Update::Invoiced(invoice))))
+    match profile.service_templates.contains_key(&name) {
+        true => println!("\nReplacing service template {}:", name),
+        false => println!("\nAdding service template {}:", name),
+    }
+    print!("{}", template);
+    confirmed(prompter, || {
+        Event::new_profile(ProfileUpdate::ServiceTemplate(name, template))
+    })
 }
 
-fn set_taxes(client: &Client) -> MaybeEvent {
-    let (taxes, effective) = input::taxes()?;
+fn add_from_template(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+    profile: &Profile,
+) -> NewEvents {
+    let names: Vec<&str> = profile
+        .service_templates
+        .keys()
+        .map(String::as_str)
+        .collect();
+    if names.is_empty() {
+        return Err(RunError::NoTemplates);
+    }
+    let name = prompter.template_select(names)?;
+    let template = profile
+        .service_templates
+        .get(&name)
+        .ok_or(RunError::NoTemplate(name.clone()))?;
+    let effective = prompter.effective()?;
+
+    println!(
+        "\nAdding template {} to {} from {}:",
+        name, client.name, effective
+    );
+    print!("{}", template);
+    for (service, _) in template.services.iter() {
+        check_service_name(client, service)?;
+    }
+    let events = template
+        .updates(effective)
+        .into_iter()
+        .map(|update| Event::new_update(&client.key, update))
+        .collect();
+    let target = confirm_target(&client.key, &client.name, None);
+    Ok(if prompter.confirm_for(&target)? {
+        events
+    } else {
+        vec![]
+    })
+}
 
-    println!("Setting taxes for {} to:", client.name);
-    for tax in taxes.iter() {
-        println!("{}", tax);
+fn list_templates(profile: &Profile) -> NewEvents {
+    if profile.service_templates.is_empty() {
+        println!("No service templates, add one with `invogen add template`");
     }
-    println!("Effective: {}", effective);
-    Ok(input::confirm()?.then(|| {
-        Event::new_update(&client.key, Update::Taxes(effective, taxes))
-    }))
+    for (name, template) in profile.service_templates.iter() {
+        println!("{}:\n{}", name, template);
+    }
+    Ok(vec![])
 }
 
-fn set_rate(client: &Client) -> MaybeEvent {
-    let service = input::service_select(client.service_names())?;
-    let (rate, effective) = input::rate()?;
+fn add_retainer(prompter: &mut dyn Prompter, client: &Client) -> NewEvents {
+    let service = prompter.service_select(client.service_names())?;
+    let period = prompter.period(None, &Unit::Month, false, None)?;
+    let hours = prompter.retainer_hours()?;
 
     println!(
-        "Setting billing rate for {}, for {} to: {}",
-        service, client.name, rate
+        "\nAdding a retainer of {} hours of {} for {}",
+        hours, service, client.name
     );
-    println!("Effective: {}", effective);
-    Ok(input::confirm()?.then(|| {
+    println!("Period: {}", period);
+    confirmed_for(prompter, client, || {
         Event::new_update(
             &client.key,
-            Update::ServiceRate(service, effective, rate),
+            Update::RetainerPurchased(service, period, hours),
         )
-    }))
+    })
 }
 
-fn change_address(client: &Client) -> MaybeEvent {
-    let address = input::address()?;
+fn list_clients(
+    clients: &Clients,
+    style: Style,
+    convert: Option<&(Currency, ExchangeRates)>,
+) -> NewEvents {
+    print!("{}", clients_table(clients, convert)?.render_as(style));
+    // Totals would break the one client per line of porcelain output
+    if let (Some((currency, rates)), Style::Aligned { .. }) = (convert, style) {
+        let mut amounts = Vec::new();
+        for client in clients.iter() {
+            amounts.extend(open_amounts(client)?);
+        }
+        let all = rates.convert_all(amounts, *currency);
+        println!("Total outstanding in {}: {}", currency.code(), all.total);
+        print_conversion(&all);
+    }
+    Ok(vec![])
+}
 
-    println!("Changing address for {} to: \n\n{}", client.name, address);
-    Ok(input::confirm()?
-        .then(|| Event::new_update(&client.key, Update::Address(address))))
+fn clients_table(
+    clients: &Clients,
+    convert: Option<&(Currency, ExchangeRates)>,
+) -> Result<Table, CurrencyMismatch> {
+    let outstanding = match convert {
+        Some((currency, _)) => format!("Outstanding in {}", currency.code()),
+        None => "Outstanding".to_string(),
+    };
+    let mut table =
+        Table::new(&["Key", "Name", outstanding.as_str(), "Billed Until"]);
+    for client in clients.iter() {
+        let amount = match convert {
+            Some((currency, rates)) => rates
+                .convert_all(open_amounts(client)?, *currency)
+                .total
+                .to_string(),
+            None => outstanding_amounts(client)?,
+        };
+        table.push(vec![
+            Cell::from(client.key.as_str()),
+            Cell::from(client.name.as_str()),
+            Cell::from(amount),
+            Cell::from(
+                client
+                    .billed_until()
+                    .map_or(String::new(), |d| d.to_string()),
+            ),
+        ]);
+    }
+    Ok(table)
 }
 
-fn change_name(client: &Client) -> MaybeEvent {
-    let name = input::name()?;
-    println!(
-        "Changing client {} ({}) to: \n\n{}",
-        client.name, client.key, name
-    );
-    Ok(input::confirm()?
-        .then(|| Event::new_update(&client.key, Update::Name(name))))
+/// Unpaid invoice totals labelled for conversion at their invoice date
+fn open_amounts(
+    client: &Client,
+) -> Result<Vec<(String, Money, NaiveDate)>, CurrencyMismatch> {
+    client
+        .invoices()
+        .filter(|i| i.is_open())
+        .map(|invoice| {
+            Ok((
+                format!("{} #{}", client.key, invoice.number),
+                invoice.calculate()?.total,
+                invoice.date,
+            ))
+        })
+        .collect()
 }
 
-fn list_invoices(client: &Client) -> MaybeEvent {
-    for i in client.invoices() {
-        let paid = if let Some(when) = i.paid {
-            format!("Paid {}", when)
-        } else {
-            "Unpaid".to_string()
+fn find_clients(clients: &Clients, query: &str, key_only: bool) -> NewEvents {
+    let matches = search::find_clients(clients.iter(), query);
+    if key_only {
+        return match matches.as_slice() {
+            [found] => {
+                println!("{}", found.client.key);
+                Ok(vec![])
+            }
+            _ => Err(RunError::NoUniqueMatch(query.to_string(), matches.len())),
         };
-        let total = i.calculate();
-        println!("#{} {}, {} ({})", i.number, i.date, total.total, paid)
     }
-    Ok(None)
+
+    let color = table::use_color(false);
+    for found in matches.iter() {
+        println!(
+            "{}\t{}",
+            found.client.key,
+            highlight(found.text, found.chars.clone(), color)
+        );
+    }
+    Ok(vec![])
 }
 
-fn list_services(client: &Client) -> MaybeEvent {
-    for service in client.services.values() {
-        println!("{}", service);
+fn highlight(text: &str, found: Range<usize>, color: bool) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let part = |range: Range<usize>| chars[range].iter().collect::<String>();
+    let matched = part(found.clone());
+    let matched = if color {
+        table::paint(&matched, Color::Yellow)
+    } else {
+        matched
+    };
+    format!(
+        "{}{}{}",
+        part(0..found.start),
+        matched,
+        part(found.end..chars.len())
+    )
+}
+
+/// Characters shown either side of a match in the history
+const GREP_CONTEXT: usize = 30;
+
+fn grep_history(events: &[Event], query: &str, raw: bool) -> NewEvents {
+    let color = table::use_color(false);
+    for line in grep_lines(events, query, raw)? {
+        let (text, found) = line.excerpt;
+        println!("{}{}", line.prefix, highlight(&text, found, color));
     }
-    Ok(None)
+    Ok(vec![])
 }
 
-fn show_invoice(invoice: &Invoice) -> MaybeEvent {
-    println!("{}", invoice);
-    Ok(None)
+/// A match in the history, with where it was found and the excerpt around
+/// it
+struct GrepLine {
+    prefix: String,
+    excerpt: (String, Range<usize>),
 }
 
-fn mark_paid(invoice: &Invoice, client: &Client) -> MaybeEvent {
-    let when = input::paid_date(invoice.date)?;
+/// Matches in the text recorded by events, prefixed by the client key,
+/// event time, and field, or in the raw event lines prefixed by their
+/// line numbers
+fn grep_lines(
+    events: &[Event],
+    query: &str,
+    raw: bool,
+) -> Result<Vec<GrepLine>, RunError> {
+    if raw {
+        let lines = events
+            .iter()
+            .map(clients::event_line)
+            .collect::<Result<Vec<String>, _>>()?;
+        let found =
+            search::search_lines(lines.iter().map(|l| l.trim_end()), query);
+        return Ok(found
+            .into_iter()
+            .map(|(number, line, chars)| GrepLine {
+                prefix: format!("{}:\t", number),
+                excerpt: search::excerpt(line, chars, GREP_CONTEXT),
+            })
+            .collect());
+    }
+    Ok(search::search_events(events, query)
+        .into_iter()
+        .map(|found| GrepLine {
+            prefix: format!(
+                "{}\t{}\t{}: ",
+                found.event.0,
+                found.event.1.format("%Y-%m-%d %H:%M"),
+                found.field
+            ),
+            excerpt: search::excerpt(found.text, found.chars, GREP_CONTEXT),
+        })
+        .collect())
+}
 
-    println!("Marking invoice #{} as paid on {}", invoice.number, when);
-    Ok(input::confirm()?.then(|| {
-        Event::new_update(&client.key, Update::Paid(invoice.number, when))
-    }))
-}
-
-fn invoice_posting(invoice: &Invoice, client: &Client) -> MaybeEvent {
-    let total = invoice.calculate();
-    let period = invoice.overall_period();
-    let start = period.from.format("%b %-d");
-    let end =
-        period
-            .until
-            .format(if period.from.month() == period.until.month() {
-                "%-d"
-            } else {
-                "%b %-d"
-            });
+/// Events with their line numbers, times, client keys, and what changed,
+/// optionally only those added with an origin
+fn log_lines(events: &[Event], origin: Option<&str>) -> Vec<String> {
+    events
+        .iter()
+        .enumerate()
+        .filter(|(_, event)| origin.is_none() || event.origin() == origin)
+        .map(|(i, event)| {
+            format!(
+                "{}:\t{}\t{}\t{}\t{}",
+                i + 1,
+                event.1.format("%Y-%m-%d %H:%M"),
+                event.0,
+                change_kind(&event.2),
+                event.origin().unwrap_or("(unknown origin)")
+            )
+        })
+        .collect()
+}
+
+/// The kind of change, e.g. Updated Invoiced
+fn change_kind(change: &Change) -> String {
+    fn variant<T: fmt::Debug>(value: &T) -> String {
+        let text = format!("{:?}", value);
+        let end = text
+            .find(|c: char| !c.is_alphanumeric())
+            .unwrap_or(text.len());
+        text[..end].to_string()
+    }
+    match change {
+        Change::Updated(update) => format!("Updated {}", variant(update)),
+        Change::Profile(update) => format!("Profile {}", variant(update)),
+        _ => variant(change),
+    }
+}
+
+fn list_unbilled(
+    clients: &Clients,
+    convert: Option<&(Currency, ExchangeRates)>,
+) -> NewEvents {
+    let today = Local::now().date_naive();
+    for client in clients.iter() {
+        let items = unbilled(client, today);
+        if items.is_empty() {
+            println!("{}: up to date", client.key);
+            continue;
+        }
+        println!("{}:", client.key);
+        for item in items.iter() {
+            println!("  {} {} est. {}", item.name, item.period, item.amount);
+        }
+        if let Some((currency, rates)) = convert {
+            let converted =
+                converted_unbilled(client, &items, rates, *currency);
+            println!("  Total in {}: {}", currency.code(), converted.total);
+            print_conversion(&converted);
+        }
+    }
+    Ok(vec![])
+}
+
+/// Every client's invoices, or those matching a number, which must belong
+/// to a single client so an external reference isn't guessed
+fn invoice_index<'a>(
+    clients: &'a Clients,
+    number: Option<&str>,
+) -> Result<Vec<(&'a Client, &'a Invoice)>, RunError> {
+    let Some(number) = number else {
+        return Ok(clients
+            .iter()
+            .flat_map(|c| c.invoices().map(move |i| (c, i)))
+            .collect());
+    };
+    let found: Vec<(&Client, &Invoice)> = clients
+        .iter()
+        .filter_map(|c| c.find_invoice(number).ok().map(|i| (c, i)))
+        .collect();
+    match found.as_slice() {
+        [] => Err(RunError::NoInvoiceMatch(number.to_string())),
+        [_] => Ok(found),
+        _ => {
+            let keys: Vec<&str> =
+                found.iter().map(|(c, _)| c.key.as_str()).collect();
+            Err(RunError::AmbiguousInvoice(
+                number.to_string(),
+                keys.join(", "),
+            ))
+        }
+    }
+}
+
+fn invoice_index_table(
+    index: &[(&Client, &Invoice)],
+) -> Result<Table, CurrencyMismatch> {
+    let mut table = Table::new(&["Client", "Number", "Date", "Total"]);
+    for (client, invoice) in index {
+        table.push(vec![
+            Cell::from(client.key.clone()),
+            Cell::from(invoice.display_number()),
+            Cell::from(invoice.date.to_string()),
+            Cell::from(invoice.calculate()?.total.to_string()),
+        ]);
+    }
+    Ok(table)
+}
+
+/// An invoice in the JSON index, with its total split from the currency
+#[derive(Serialize)]
+struct InvoiceIndexRow<'a> {
+    client: &'a str,
+    number: String,
+    date: NaiveDate,
+    total: String,
+    currency: &'static str,
+}
+
+fn invoice_index_json(
+    index: &[(&Client, &Invoice)],
+) -> Result<String, RunError> {
+    let rows = index
+        .iter()
+        .map(|(client, invoice)| {
+            let total = invoice.calculate()?.total;
+            Ok(InvoiceIndexRow {
+                client: &client.key,
+                number: invoice.display_number(),
+                date: invoice.date,
+                total: format!("{:.2}", total.amount()),
+                currency: total.currency().code(),
+            })
+        })
+        .collect::<Result<Vec<InvoiceIndexRow>, CurrencyMismatch>>()?;
+    Ok(serde_json::to_string_pretty(&rows)?)
+}
+
+/// Unbilled estimates converted at the rate at the end of their period
+fn converted_unbilled(
+    client: &Client,
+    items: &[InvoiceItem],
+    rates: &ExchangeRates,
+    to: Currency,
+) -> Converted {
+    rates.convert_all(
+        items.iter().map(|item| {
+            (
+                format!("{} {}", client.key, item.name),
+                item.amount,
+                item.period.until,
+            )
+        }),
+        to,
+    )
+}
+
+/// Category of services without one, and of items not named for a service
+const OTHER_CATEGORY: &str = "other";
+
+/// Whether an invoice's items count as billed. Voided invoices don't, and
+/// a deposit counts until a final invoice deducts it and bills its items.
+fn bills_items(client: &Client, invoice: &Invoice) -> bool {
+    let deducted = invoice.kind == InvoiceKind::Deposit
+        && client.applied_to(invoice.number).is_some();
+    invoice.voided.is_none() && !deducted
+}
+
+/// Item amounts before taxes of every client's invoices by the category of
+/// their service, totalled per currency. Items are matched to the services
+/// of their own client by name.
+fn revenue_by_category(
+    clients: &Clients,
+    year: Option<i32>,
+) -> BTreeMap<String, Vec<Money>> {
+    let mut categories: BTreeMap<String, Vec<Money>> = BTreeMap::new();
+    for client in clients.iter() {
+        let invoices = client.invoices().filter(|invoice| {
+            bills_items(client, invoice)
+                && year.is_none_or(|year| invoice.date.year() == year)
+        });
+        for item in invoices.flat_map(|invoice| invoice.items.iter()) {
+            let category = client
+                .service(item.name.clone())
+                .and_then(|service| service.category.clone())
+                .unwrap_or_else(|| OTHER_CATEGORY.to_string());
+            let totals = categories.entry(category).or_default();
+            let currency = item.amount.currency();
+            match totals.iter_mut().find(|t| t.currency() == currency) {
+                Some(total) => *total = *total + item.amount,
+                None => totals.push(item.amount),
+            }
+        }
+    }
+    categories
+}
+
+fn revenue_table(clients: &Clients, year: Option<i32>) -> Table {
+    let mut table = Table::new(&["Category", "Revenue"]);
+    for (category, totals) in revenue_by_category(clients, year) {
+        let totals: Vec<String> = totals.iter().map(Money::to_string).collect();
+        table.push(vec![Cell::from(category), Cell::from(totals.join(", "))]);
+    }
+    table
+}
+
+/// Hours billed for each service with a target or hourly items in the
+/// months of a year, to date when it's this year, against the targets.
+/// Items spanning months are split by their working days in each.
+fn hours_table(client: &Client, year: i32, today: NaiveDate) -> Table {
+    let months = match year.cmp(&today.year()) {
+        cmp::Ordering::Less => 12,
+        cmp::Ordering::Equal => today.month(),
+        cmp::Ordering::Greater => 0,
+    };
+    let starts: Vec<NaiveDate> = (1..=months)
+        .filter_map(|month| NaiveDate::from_ymd_opt(year, month, 1))
+        .collect();
+    let none = || vec![Decimal::ZERO; starts.len()];
+    let mut billed: BTreeMap<&str, Vec<Decimal>> = client
+        .services
+        .values()
+        .filter(|service| service.target_hours.is_some())
+        .map(|service| (service.name.as_str(), none()))
+        .collect();
+    let items = client
+        .invoices()
+        .filter(|invoice| bills_items(client, invoice))
+        .flat_map(|invoice| invoice.items.iter())
+        .filter(|item| item.rate.per == Unit::Hour);
+    for item in items {
+        for (i, month) in starts.iter().enumerate() {
+            let hours = billing::quantity_in_month(item, *month);
+            if !hours.is_zero() {
+                billed.entry(&item.name).or_insert_with(none)[i] += hours;
+            }
+        }
+    }
+
+    let target = |name: &str| {
+        client
+            .service(name.to_string())
+            .and_then(|service| service.target_hours)
+    };
+    let row = |label, name: &str, hours: Decimal, target: Option<Decimal>| {
+        vec![
+            Cell::from(label),
+            Cell::from(name),
+            Cell::from(format!("{:.2}", hours)),
+            Cell::from(target.map_or(String::new(), |t| format!("{:.2}", t))),
+            Cell::from(
+                target.map_or(String::new(), |t| format!("{:.2}", hours - t)),
+            ),
+        ]
+    };
+    let mut table =
+        Table::new(&["Month", "Service", "Billed", "Target", "Variance"]);
+    for (i, month) in starts.iter().enumerate() {
+        for (name, hours) in billed.iter() {
+            let label = month.format("%Y-%m").to_string();
+            table.push(row(label, name, hours[i], target(name)));
+        }
+    }
+    let months = Decimal::from(starts.len());
+    for (name, hours) in billed.iter() {
+        let total = hours.iter().sum();
+        let target = target(name).map(|hours| hours * months);
+        table.push(row("YTD".to_string(), name, total, target));
+    }
+    table
+}
+
+/// Units by name, with spans of weeks written like `2-weeks`
+fn parse_unit(name: &str) -> Result<Unit, RunError> {
+    let name = name.trim().to_lowercase();
+    let unit = match name.as_str() {
+        "month" => Unit::Month,
+        "week" => Unit::Week,
+        "day" => Unit::Day,
+        "hour" => Unit::Hour,
+        "quarter" => Unit::Quarter,
+        "year" => Unit::Year,
+        _ => name
+            .strip_suffix("-weeks")
+            .and_then(|weeks| weeks.parse().ok())
+            .map(Unit::EveryNWeeks)
+            .ok_or_else(|| RunError::UnknownUnit(name.clone()))?,
+    };
+    Ok(unit)
+}
+
+fn explain(period: &billing::Period, unit: &Unit) -> NewEvents {
+    match period.explain(unit) {
+        Some(proration) => println!("{}", proration),
+        None => println!("Hours are billed as entered, not prorated"),
+    }
+    Ok(vec![])
+}
 
-    let mut items = Vec::new();
+fn week_proration(legacy: bool) -> WeekProration {
+    if legacy {
+        WeekProration::Envelope
+    } else {
+        WeekProration::WorkingDays
+    }
+}
 
-    items.push((
-        format!("assets:receivable:{}", client.name),
-        ledger_fmt(total.subtotal),
-    ));
+fn audit_invoice(
+    client: &Client,
+    invoice: &Invoice,
+    weeks: WeekProration,
+) -> NewEvents {
+    let findings = audit::audit_invoice(client, invoice, weeks);
+    if findings.is_empty() {
+        println!("Invoice #{} matches the history", invoice.number);
+    }
+    for finding in findings {
+        println!("{}", finding);
+    }
+    Ok(vec![])
+}
 
-    for (TaxRate(name, _), amount) in total.taxes.iter() {
-        items
-            .push((format!("assets:receivable:{}", name), ledger_fmt(*amount)));
+fn verify(
+    clients: &Clients,
+    events: &[Event],
+    weeks: WeekProration,
+) -> NewEvents {
+    let mut problems = 0;
+    let added_by = |origin: Option<&str>| {
+        origin.map_or(String::new(), |o| format!(" (added by {})", o))
+    };
+    for line in audit::out_of_order_events(events) {
+        problems += 1;
+        println!(
+            "Event on line {}{} is earlier than the events before it, record \
+             past changes with --timestamp",
+            line,
+            added_by(events[line - 1].origin())
+        );
     }
-    items.push((
-        format!("revenues:clients:{}", client.name),
-        ledger_fmt(total.total * Decimal::from(-1)),
-    ));
+    for client in clients.iter() {
+        for invoice in client.invoices() {
+            let findings = audit::audit_invoice(client, invoice, weeks);
+            problems += findings.len();
+            let origin = invoice_origin(events, &client.key, invoice.number);
+            for finding in findings {
+                println!(
+                    "{} #{}: {}{}",
+                    client.key,
+                    invoice.number,
+                    finding,
+                    added_by(origin)
+                );
+            }
+        }
+    }
+    if let Some(from) = clients.profile.global_numbering {
+        for problem in audit::shared_numbering(clients, from) {
+            problems += 1;
+            println!("Invoice {}", problem);
+        }
+    }
+    if problems == 0 {
+        println!("No problems found");
+    }
+    Ok(vec![])
+}
 
+/// Origin of the event that added an invoice
+fn invoice_origin<'a>(
+    events: &'a [Event],
+    key: &str,
+    number: usize,
+) -> Option<&'a str> {
+    events.iter().rev().find_map(|event| match &event.2 {
+        Change::Updated(Update::Invoiced(invoice))
+            if event.0 == key && invoice.number == number =>
+        {
+            event.origin()
+        }
+        _ => None,
+    })
+}
+
+fn import_invoices(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+    path: &Path,
+) -> NewEvents {
+    let invoices =
+        import::invoices_from_csv(client, &fs::read_to_string(path)?)?;
+    let (Some(first), Some(last)) = (invoices.first(), invoices.last()) else {
+        println!("No invoices to import");
+        return Ok(vec![]);
+    };
+    let paid = invoices.iter().filter(|i| i.paid.is_some()).count();
     println!(
-        "{} {} invoice  ; {} - {}",
-        invoice.date, client.name, start, end
+        "Importing {} invoices, #{} to #{}, {} of them paid",
+        invoices.len(),
+        first.number,
+        last.number,
+        paid
     );
+    let detail = format!("invoices #{} to #{}", first.number, last.number);
+    if !prompter.confirm_for(&confirm_target(
+        &client.key,
+        &client.name,
+        Some(&detail),
+    ))? {
+        return Ok(vec![]);
+    }
+
+    // Recorded as of the invoice and payment dates rather than now
+    let at = |date: NaiveDate| date.and_time(NaiveTime::MIN).and_utc();
+    let mut events = Vec::new();
+    for mut invoice in invoices {
+        let (number, date, paid) = (invoice.number, invoice.date, invoice.paid);
+        invoice.paid = None;
+        events.push(Event::new_at(
+            &client.key,
+            at(date),
+            Change::Updated(Update::Invoiced(invoice)),
+        ));
+        if let Some(paid) = paid {
+            events.push(Event::new_at(
+                &client.key,
+                at(paid),
+                Change::Updated(Update::Paid(number, paid, None)),
+            ));
+        }
+    }
+    Ok(events)
+}
+
+/// Estimates for periodic services not yet billed up to the end of the last
+/// complete month, priced at their current rates
+fn unbilled(client: &Client, today: NaiveDate) -> Vec<InvoiceItem> {
+    let until = today
+        .start_of_month()
+        .and_then(|d| d.pred_opt())
+        .expect("Error in chrono-utilities start_of_month");
+
+    client
+        .services
+        .values()
+        .filter_map(|service| {
+            let rate = service.rates.as_of(today)?;
+            if rate.per == Unit::Hour {
+                return None;
+            }
+            let from = match client.service_billed_until(&service.name) {
+                Some(billed) => billed.succ_opt()?,
+                None => *service.rates.first()?.0,
+            };
+            (from <= until).then(|| {
+                InvoiceItem::new(
+                    service.name.clone(),
+                    rate.clone(),
+                    billing::Period::new(from, until),
+                )
+            })
+        })
+        .collect()
+}
+
+/// A service's scheduled invoice that hasn't been issued
+#[derive(Debug, PartialEq)]
+struct Due {
+    service: String,
+    /// The latest scheduled day up to today
+    scheduled: NaiveDate,
+    /// What hasn't been billed up to the end of the month before the
+    /// scheduled day
+    period: billing::Period,
+}
+
+/// Scheduled services not billed up to the end of the month before their
+/// latest scheduled day, a service never billed is due for that month
+fn due(client: &Client, today: NaiveDate) -> Vec<Due> {
+    client
+        .services
+        .values()
+        .filter_map(|service| {
+            let day = service.schedule?;
+            let mut scheduled = calendar::day_of_month(today, day)?;
+            if scheduled > today {
+                let last_month = today.start_of_month()?.pred_opt()?;
+                scheduled = calendar::day_of_month(last_month, day)?;
+            }
+            let until = scheduled.start_of_month()?.pred_opt()?;
+            let from = match client.service_billed_until(&service.name) {
+                Some(billed) => billed.succ_opt()?,
+                None => until.start_of_month()?,
+            };
+            (from <= until).then(|| Due {
+                service: service.name.clone(),
+                scheduled,
+                period: billing::Period::new(from, until),
+            })
+        })
+        .collect()
+}
+
+/// Lists the due scheduled invoices, and with `generate` creates them
+fn due_invoices(
+    prompter: &mut dyn Prompter,
+    clients: &Clients,
+    today: NaiveDate,
+    generate: bool,
+    confirm_key: Option<Decimal>,
+) -> NewEvents {
+    let mut events = Vec::new();
+    let mut any_due = false;
+    // Numbers taken by invoices generated for earlier clients when they
+    // share one sequence
+    let mut taken = 0;
+    for client in clients.iter() {
+        let due = due(client, today);
+        for item in due.iter() {
+            println!(
+                "{}: {} scheduled {} for {}",
+                client.key, item.service, item.scheduled, item.period
+            );
+        }
+        any_due |= !due.is_empty();
+        if generate && !due.is_empty() {
+            let mut number = clients.next_invoice_num(client);
+            if clients.profile.global_numbering.is_some() {
+                number = number.max(taken + 1);
+            }
+            let generated = invoice_due(prompter, client, number, &due)?;
+            let generated =
+                key_confirmed(prompter, client, confirm_key, generated)?;
+            for event in generated.iter() {
+                if let Change::Updated(Update::Invoiced(invoice)) = &event.2 {
+                    taken = taken.max(invoice.number);
+                }
+            }
+            events.extend(generated);
+        }
+    }
+    if !any_due {
+        println!("No scheduled invoices are due");
+    }
+    Ok(events)
+}
+
+/// Invoices the due services at the rates of their periods, only asking
+/// for the hours of hourly services without default hours
+fn invoice_due(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+    number: usize,
+    due: &[Due],
+) -> NewEvents {
+    let mut items: Vec<InvoiceItem> = Vec::new();
+    for item in due {
+        let name = item.service.clone();
+        let period = item.period.clone();
+        let rate = client.rate_as_of(&name, period.from)?.clone();
+        items.push(if rate.per == Unit::Hour {
+            let default = client
+                .service(name.clone())
+                .and_then(|s| s.default_quantity);
+            let quantity = match default {
+                Some(quantity) => quantity,
+                None => {
+                    println!("{} {}", name, period);
+                    prompter.num_hours(None)?
+                }
+            };
+            InvoiceItem::new_hourly(name, rate, period, quantity)
+        } else {
+            InvoiceItem::new(name, rate, period)
+        });
+    }
+    billing::tier_by_month(&mut items)?;
+
+    let invoices = billing::group_by_currency(items)
+        .into_iter()
+        .enumerate()
+        .map(|(i, items)| {
+            let start = items
+                .iter()
+                .map(|item| item.period.from)
+                .fold(NaiveDate::MAX, cmp::min);
+            let mut invoice =
+                Invoice::new(number + i, items, client.taxes_as_of(start))?;
+            invoice.rounding = client.rounding;
+            Ok(invoice)
+        })
+        .collect::<Result<Vec<Invoice>, NewInvoiceError>>()?;
+
+    for invoice in invoices.iter() {
+        println!("Adding invoice for {}:\n\n{}\n", client.name, invoice);
+    }
+    Ok(if confirm_invoices(prompter, client, &invoices)? {
+        invoices
+            .into_iter()
+            .map(|invoice| {
+                Event::new_update(&client.key, Update::Invoiced(invoice))
+            })
+            .collect()
+    } else {
+        vec![]
+    })
+}
+
+fn show_client(client: &Client, profile: &Profile) -> NewEvents {
+    let today = Local::now().date_naive();
+    print!("{}", client_details(client, profile, today)?);
+    Ok(vec![])
+}
 
-    let max_len = items
+/// Number of the latest invoices shown with a client
+const RECENT_INVOICES: usize = 3;
+
+/// A client's details in labelled sections, empty sections say none
+fn client_details(
+    client: &Client,
+    profile: &Profile,
+    today: NaiveDate,
+) -> Result<String, CurrencyMismatch> {
+    let mut identity = vec![
+        format!("Key: {}", client.key),
+        format!("Name: {}", client.name),
+    ];
+    for (name, address) in client.addresses() {
+        identity.push(format!("{} address:", name));
+        identity.extend(address.lines().map(|line| format!("  {}", line)));
+    }
+    if let Some(name) = client.invoice_address_name() {
+        identity.push(format!("Invoices show the {} address", name));
+    }
+
+    let services = client
+        .services
+        .values()
+        .map(|service| {
+            let current = service.rates.range(..=today).next_back();
+            match (current, service.rates.last()) {
+                (Some((since, rate)), Some((date, next))) if *date > today => {
+                    format!(
+                        "{}: {} since {}, changes to {} on {}",
+                        service.name, rate, since, next, date
+                    )
+                }
+                (Some((since, rate)), _) => {
+                    format!("{}: {} since {}", service.name, rate, since)
+                }
+                (None, Some((date, rate))) => {
+                    format!("{}: {} from {}", service.name, rate, date)
+                }
+                (None, None) => format!("{}: no rate set", service.name),
+            }
+        })
+        .collect();
+
+    let taxes = client
+        .current_taxes()
         .iter()
-        .map(|(a, b)| a.len() + b.len())
-        .fold(0, |max, x| if max > x { max } else { x });
+        .map(|tax| tax.to_string())
+        .collect();
 
-    for (account, amount) in items.iter() {
-        let padding = max_len - account.len() + 4;
-        println!("    {0}{1:>2$}", account, amount, padding);
+    let mut billing = vec![format!(
+        "Billed until: {}",
+        client
+            .billed_until()
+            .map_or("none".to_string(), |date| date.to_string())
+    )];
+    let unpaid: Vec<String> = client
+        .unpaid_invoices()
+        .map(|n| format!("#{}", n))
+        .collect();
+    billing.push(match unpaid.len() {
+        0 => "Outstanding: none".to_string(),
+        count => format!(
+            "Outstanding: {} ({} invoice{}: {})",
+            outstanding_amounts(client)?,
+            count,
+            if count == 1 { "" } else { "s" },
+            unpaid.join(" ")
+        ),
+    });
+    let written_off: Vec<String> = client
+        .written_off_invoices()
+        .map(|i| Ok(format!("#{} {}", i.number, i.calculate()?.total)))
+        .collect::<Result<_, CurrencyMismatch>>()?;
+    if !written_off.is_empty() {
+        billing.push(format!("Written off: {}", written_off.join(", ")));
+    }
+    let voided: Vec<String> = client
+        .voided_invoices()
+        .map(|i| format!("#{}", i.number))
+        .collect();
+    if !voided.is_empty() {
+        billing.push(format!("Voided: {}", voided.join(", ")));
     }
+    if let Some(footer) = client.footer(profile) {
+        billing.push("Footer:".to_string());
+        billing.extend(footer.lines().map(|line| format!("  {}", line)));
+    }
+
+    let invoices: Vec<&Invoice> = client.invoices().collect();
+    let recent = invoices
+        .iter()
+        .rev()
+        .take(RECENT_INVOICES)
+        .map(|i| {
+            Ok(format!(
+                "#{} {} {} {}",
+                i.number,
+                i.date,
+                i.calculate()?.total,
+                invoice_status(i, today).0
+            ))
+        })
+        .collect::<Result<_, CurrencyMismatch>>()?;
 
-    Ok(None)
+    let sections: [(&str, Vec<String>); 5] = [
+        ("Identity", identity),
+        ("Services", services),
+        ("Taxes", taxes),
+        ("Billing", billing),
+        ("Recent invoices", recent),
+    ];
+    let mut details = format!("{}\n", client.key);
+    for (title, lines) in sections {
+        details += &format!("\n{}\n", title);
+        if lines.is_empty() {
+            details += "  none\n";
+        }
+        for line in lines {
+            details += &format!("  {}\n", line);
+        }
+    }
+    Ok(details)
 }
 
-fn invoice_tex(invoice: &Invoice, client: &Client) -> MaybeEvent {
-    templates::invoice(invoice, client)?;
-    Ok(None)
+fn outstanding_amounts(client: &Client) -> Result<String, CurrencyMismatch> {
+    let amounts: Vec<String> = client
+        .outstanding()?
+        .into_iter()
+        .map(|(currency, amount)| Money::new(currency, amount).to_string())
+        .collect();
+    Ok(amounts.join(", "))
 }
 
-#[derive(Debug, Error)]
-pub enum RunError {
-    #[error("Error processing event history: {source}")]
-    Event {
-        #[from]
-        source: clients::EventError,
-    },
+/// Hours drawn from a retainer, by service and retainer start
+type Drawn = (String, NaiveDate, Decimal);
 
-    #[error("Input Error: {source}")]
-    Input {
-        #[from]
-        source: inquire::error::InquireError,
-    },
+/// How items are entered, the period applies to every item instead of
+/// prompting for one
+struct ItemOptions {
+    advance: bool,
+    weekly: bool,
+    period: Option<billing::Period>,
+}
 
-    #[error("Render Error: {source}")]
-    Render {
-        #[from]
-        source: askama::Error,
-    },
+/// Items entered for one service and period, several when it's split at
+/// rate changes, along with any hours they draw from a retainer
+struct Entry {
+    name: String,
+    period: billing::Period,
+    items: Vec<InvoiceItem>,
+    drawn: Vec<Drawn>,
+}
 
-    #[error("{source}")]
-    Client {
-        #[from]
-        source: ClientError,
-    },
+impl Entry {
+    fn label(&self) -> String {
+        if self.items.is_empty() {
+            return format!(
+                "{} {} covered by the retainer",
+                self.name, self.period
+            );
+        }
+        let items: Vec<String> = self
+            .items
+            .iter()
+            .map(|i| format!("{} {} {}", i.name, i.period, i.amount))
+            .collect();
+        items.join(", ")
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::clients::tests::EVENTS_STR;
-    use serde_lexpr::from_str;
+/// Hours drawn by the entries, leaving out one being re-entered
+fn drawn_except(entries: &[Entry], skip: Option<usize>) -> Vec<Drawn> {
+    entries
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| Some(*i) != skip)
+        .flat_map(|(_, entry)| entry.drawn.iter().cloned())
+        .collect()
+}
 
-    #[test]
-    fn list() -> Result<(), RunError> {
-        let history: Vec<Event> = from_str(EVENTS_STR).unwrap();
-        run_cmd(
-            Command::List {
-                listing: Listable::Clients,
-            },
-            &history,
-        )?;
+/// Prompts for a service, the period unless given, and the hours for hourly
+/// services, week by week when `weekly`, drawing on a retainer beyond what
+/// the other entries have drawn. None when a stale rate isn't confirmed
+fn enter_item(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+    pending: &[Drawn],
+    options: &ItemOptions,
+) -> Result<Option<Entry>, RunError> {
+    let name = prompter.service_select(client.service_names())?;
+    let per = client
+        .service(name.clone())
+        .and_then(|s| s.rates.current())
+        .map_or(Unit::Month, |rate| rate.per.clone());
+    let billed_until = client.billed_until();
+    // A service starting mid-month is suggested from its first rate
+    let suggested = client
+        .service(name.clone())
+        .and_then(|s| s.rates.first())
+        .and_then(|(effective, _)| {
+            let today = Local::now().date_naive();
+            billing::suggested_start(today, billed_until, *effective)
+        });
+    let mut period = match &options.period {
+        Some(period) => period.clone(),
+        None => {
+            prompter.period(billed_until, &per, options.advance, suggested)?
+        }
+    };
+    if let Some((until, gap)) = billed_until.and_then(|until| {
+        billing::billing_gap(until, period.from).map(|gap| (until, gap))
+    }) {
+        println!(
+            "WARNING: {} is not billed, the last invoice ended on {}",
+            gap, until
+        );
+        match prompter.billing_gap(&gap)? {
+            Gap::Extend => period = billing::cover_gap(&period, &gap),
+            Gap::Leave => {}
+            Gap::Abort => return Ok(None),
+        }
+    }
+    let service = client.service(name.clone());
+    let rate = client.rate_as_of(&name, period.from)?;
+    let effective = service
+        .and_then(|s| s.rates.range(..=period.from).next_back())
+        .map(|(effective, _)| *effective);
+    if let Some(effective) = effective.filter(|effective| {
+        billing::rate_is_stale(
+            *effective,
+            period.from,
+            client.stale_rate_months(),
+        )
+    }) {
+        println!(
+            "WARNING: the {} rate of {} was set on {}, more than {} months \
+             before {}",
+            name,
+            rate,
+            effective,
+            client.stale_rate_months(),
+            period.from
+        );
+        if !prompter.use_stale_rate(effective)? {
+            return Ok(None);
+        }
+    }
+    let mut parts = service
+        .map(|s| billing::split_item_at_rate_changes(s, &period))
+        .unwrap_or_default();
+    if parts.len() > 1 {
+        for (part, rate) in parts.iter().skip(1) {
+            println!(
+                "The rate for {} changes to {} on {}",
+                name, rate, part.from
+            );
+        }
+        if !prompter.split_at_rate_changes()? {
+            parts = vec![(period.clone(), rate.clone())];
+        }
+    }
+
+    let mut entry = Entry {
+        name: name.clone(),
+        period,
+        items: Vec::new(),
+        drawn: Vec::new(),
+    };
+    let default_hours = service.and_then(|s| s.default_quantity);
+    for (period, rate) in parts {
+        let item = if rate.per == Unit::Hour {
+            let (mut quantity, weeks) = if options.weekly {
+                week_by_week_hours(prompter, &period)?
+            } else {
+                (prompter.num_hours(default_hours)?, Vec::new())
+            };
+            if quantity < Decimal::ZERO {
+                return Err(ItemAmountError::NegativeQuantity(quantity).into());
+            }
+            let mut drew = false;
+            if let Some(retainer) = client.retainer_for(&name, period.from) {
+                let start = retainer.period.from;
+                let pending: Decimal = pending
+                    .iter()
+                    .chain(entry.drawn.iter())
+                    .filter(|(n, from, _)| *n == name && *from == start)
+                    .map(|(_, _, hours)| *hours)
+                    .sum();
+                let remaining = retainer.remaining() - pending;
+                if remaining > Decimal::ZERO
+                    && prompter.bill_overage(remaining)?
+                {
+                    let draw = quantity.min(remaining);
+                    entry.drawn.push((name.clone(), start, draw));
+                    quantity -= draw;
+                    drew = true;
+                }
+            }
+            if drew && quantity <= Decimal::ZERO {
+                println!("{} {} is covered by the retainer", name, period);
+                continue;
+            }
+            let mut item =
+                InvoiceItem::new_hourly(name.clone(), rate, period, quantity);
+            item.weeks = weeks;
+            item
+        } else {
+            InvoiceItem::new(name.clone(), rate, period)
+        };
+        match item.check_amount() {
+            Ok(()) => {}
+            Err(error) if error.is_zero() => {
+                println!("WARNING: {} {}: {}", name, item.period, error);
+                if !prompter.keep_zero_item()? {
+                    continue;
+                }
+            }
+            Err(error) => return Err(error.into()),
+        }
+        entry.items.push(item);
+    }
+    Ok(Some(entry))
+}
+
+/// Hours entered for each week of the period and their total, weeks without
+/// hours are left out
+fn week_by_week_hours(
+    prompter: &mut dyn Prompter,
+    period: &billing::Period,
+) -> Result<(Decimal, Vec<(NaiveDate, Decimal)>), RunError> {
+    let mut weeks = Vec::new();
+    for week in period.weeks() {
+        let hours = prompter.week_hours(&week)?;
+        if hours < Decimal::ZERO {
+            return Err(ItemAmountError::NegativeQuantity(hours).into());
+        }
+        if !hours.is_zero() {
+            weeks.push((week.from, hours));
+        }
+    }
+    Ok((weeks.iter().map(|(_, hours)| *hours).sum(), weeks))
+}
+
+/// Asks to split the invoice the first time its items mix currencies,
+/// false when the invoice should be aborted instead
+fn accepts_currencies(
+    prompter: &mut dyn Prompter,
+    entries: &[Entry],
+    split: &mut bool,
+) -> Result<bool, RunError> {
+    let mut items = entries.iter().flat_map(|e| e.items.iter());
+    let Some(first) = items.next().map(|i| i.amount.currency()) else {
+        return Ok(true);
+    };
+    if *split {
+        return Ok(true);
+    }
+    if let Some(other) =
+        items.map(|i| i.amount.currency()).find(|c| *c != first)
+    {
+        println!(
+            "This item is in {} but the invoice already contains {} items",
+            other.code(),
+            first.code()
+        );
+        if !prompter.split_invoice()? {
+            return Ok(false);
+        }
+        *split = true;
+    }
+    Ok(true)
+}
+
+fn invoice(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+    number: usize,
+    deposit: bool,
+    options: &ItemOptions,
+    currency: Option<Currency>,
+    preview: Option<&Profile>,
+) -> NewEvents {
+    let mut entries: Vec<Entry> = Vec::new();
+    // Items in other currencies are converted instead of split off
+    let mut split = currency.is_some();
+    let mut reviewing = false;
+    loop {
+        // The entry being re-entered, otherwise a new one is added
+        let editing = if reviewing {
+            let labels: Vec<String> =
+                entries.iter().map(Entry::label).collect();
+            match prompter.review_items(&labels)? {
+                Review::Done => break,
+                Review::Add => None,
+                Review::Edit(i) => Some(i),
+                Review::Remove(i) => {
+                    entries.remove(i);
+                    continue;
+                }
+            }
+        } else {
+            None
+        };
+        let pending = drawn_except(&entries, editing);
+        let Some(entry) = enter_item(prompter, client, &pending, options)?
+        else {
+            println!("Invoice aborted");
+            return Ok(vec![]);
+        };
+        match editing {
+            Some(i) => entries[i] = entry,
+            None => entries.push(entry),
+        }
+        if !accepts_currencies(prompter, &entries, &mut split)? {
+            println!("Invoice aborted");
+            return Ok(vec![]);
+        }
+        if !reviewing && !prompter.another()? {
+            reviewing = true;
+        }
+    }
+
+    let drawn = drawn_except(&entries, None);
+    let mut items: Vec<InvoiceItem> =
+        entries.into_iter().flat_map(|entry| entry.items).collect();
+    billing::tier_by_month(&mut items)?;
+    let (items, fx) = match currency {
+        Some(currency) => convert_items(prompter, items, currency)?,
+        None => (items, None),
+    };
+    let mut invoices = billing::group_by_currency(items)
+        .into_iter()
+        .enumerate()
+        .map(|(i, items)| {
+            let start = items
+                .iter()
+                .map(|item| item.period.from)
+                .fold(NaiveDate::MAX, cmp::min);
+            let mut invoice =
+                Invoice::new(number + i, items, client.taxes_as_of(start))?;
+            invoice.rounding = client.rounding;
+            invoice.fx = fx;
+            Ok(invoice)
+        })
+        .collect::<Result<Vec<Invoice>, NewInvoiceError>>()?;
+
+    // Deposits only make sense for a single invoice, not one split by currency
+    if deposit && invoices.len() > 1 {
+        return Err(RunError::DepositSplit(invoices.len()));
+    }
+    if let [invoice] = invoices.as_mut_slice() {
+        invoice.kind = if deposit {
+            InvoiceKind::Deposit
+        } else {
+            final_kind(prompter, client, invoice.currency())?
+        };
+    }
+
+    for invoice in invoices.iter() {
+        println!(
+            "Adding {} invoice:\n\n{}\n",
+            invoice.currency().code(),
+            invoice
+        );
+    }
+    let today = Local::now().date_naive();
+    if invoices
+        .iter()
+        .any(|invoice| bills_in_advance(invoice, today))
+    {
+        println!("Billing in advance for a future period");
+    }
+    for (name, from, hours) in drawn.iter() {
+        println!(
+            "Drawing {} hours from the {} retainer of {}",
+            hours, name, from
+        );
+    }
+    if let Some(profile) = preview {
+        preview_invoices(prompter, client, profile, &invoices)?;
+    }
+    Ok(if confirm_invoices(prompter, client, &invoices)? {
+        let consumed = drawn.into_iter().map(|(name, from, hours)| {
+            Update::RetainerConsumed(name, from, hours)
+        });
+        consumed
+            .chain(invoices.into_iter().map(Update::Invoiced))
+            .map(|update| Event::new_update(&client.key, update))
+            .collect()
+    } else {
+        vec![]
+    })
+}
+
+/// Views of an invoice offered before it's confirmed
+const PREVIEWS: [&str; 4] = ["none", "text", "latex", "posting"];
+
+/// Shows each invoice as `show invoice` would, until none is chosen or the
+/// menu is left for the confirmation
+fn preview_invoices(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+    profile: &Profile,
+    invoices: &[Invoice],
+) -> Result<(), RunError> {
+    for invoice in invoices {
+        let message = format!("Preview invoice {}:", invoice.display_number());
+        loop {
+            let options = PREVIEWS.iter().map(|p| p.to_string()).collect();
+            match prompter.menu(&message, options)? {
+                None | Some(0) => break,
+                Some(view) => print!(
+                    "{}",
+                    invoice_preview(PREVIEWS[view], invoice, client, profile)?
+                ),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What `show invoice` prints for the text, latex, or posting view
+fn invoice_preview(
+    view: &str,
+    invoice: &Invoice,
+    client: &Client,
+    profile: &Profile,
+) -> Result<String, RunError> {
+    Ok(match view {
+        "latex" => {
+            format!(
+                "{}\n",
+                templates::render_invoice(invoice, client, profile, false)?
+            )
+        }
+        "posting" => posting(
+            invoice,
+            client,
+            &profile.ledger_style,
+            None,
+            false,
+            client.posting_date(),
+        )?,
+        _ => {
+            let today = Local::now().date_naive();
+            format!("{}\n", invoice_details(invoice, client, today, false))
+        }
+    })
+}
+
+/// Currency items were converted from and the rate they were converted at
+type Fx = (Currency, Decimal);
+
+/// Converts the items billed in another currency at a rate entered for it,
+/// with the currency and rate to store on the invoice
+fn convert_items(
+    prompter: &mut dyn Prompter,
+    items: Vec<InvoiceItem>,
+    currency: Currency,
+) -> Result<(Vec<InvoiceItem>, Option<Fx>), RunError> {
+    let mut others: Vec<Currency> = Vec::new();
+    for other in items.iter().map(|item| item.amount.currency()) {
+        if other != currency && !others.contains(&other) {
+            others.push(other);
+        }
+    }
+    let from = match others.as_slice() {
+        [] => return Ok((items, None)),
+        [from] => *from,
+        _ => {
+            let codes: Vec<&str> = others.iter().map(Currency::code).collect();
+            return Err(RunError::ConvertMany(codes.join(", ")));
+        }
+    };
+    let rate = prompter.conversion_rate(from, currency)?;
+    let items = items
+        .into_iter()
+        .map(|item| match item.amount.currency() == from {
+            true => item.converted(currency, rate),
+            false => item,
+        })
+        .collect();
+    Ok((items, Some((from, rate))))
+}
+
+/// Whether any of the invoice's periods ends after today
+fn bills_in_advance(invoice: &Invoice, today: NaiveDate) -> bool {
+    invoice.items.iter().any(|item| item.period.until > today)
+}
+
+/// Offers the client's paid deposits in the currency to deduct, making the
+/// invoice final when any are selected
+fn final_kind(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+    currency: Currency,
+) -> Result<InvoiceKind, RunError> {
+    let deposits: Vec<&Invoice> = client
+        .unapplied_deposits()
+        .filter(|d| d.currency() == currency)
+        .collect();
+    if deposits.is_empty() {
+        return Ok(InvoiceKind::Standard);
+    }
+    let selected = prompter.apply_deposits(&deposits)?;
+    let applies: Vec<(usize, Money)> = deposits
+        .iter()
+        .filter(|d| selected.contains(&d.number))
+        .map(|d| Ok((d.number, d.calculate()?.total)))
+        .collect::<Result<_, CurrencyMismatch>>()?;
+    Ok(if applies.is_empty() {
+        InvoiceKind::Standard
+    } else {
+        InvoiceKind::Final { applies }
+    })
+}
+
+/// Bills the items of the last invoice for the periods that follow, at the
+/// rates in effect for the new periods. Voided, deposit, and final invoices
+/// don't bill a regular period so they're passed over.
+fn repeat_invoice(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+    number: usize,
+) -> NewEvents {
+    let last = client
+        .invoices()
+        .filter(|i| i.voided.is_none() && i.kind.is_standard())
+        .last()
+        .ok_or(ClientError::NoInvoices(client.key.clone()))?;
+
+    let mut items: Vec<InvoiceItem> = Vec::new();
+    for item in last.items.iter() {
+        let period = item.period.next();
+        let rate = client.rate_as_of(&item.name, period.from)?;
+        items.push(if rate.per == Unit::Hour {
+            println!("{} {}", item.name, period);
+            let default = client
+                .service(item.name.clone())
+                .and_then(|s| s.default_quantity);
+            let quantity = prompter.num_hours(default)?;
+            InvoiceItem::new_hourly(
+                item.name.clone(),
+                rate.clone(),
+                period,
+                quantity,
+            )
+        } else {
+            InvoiceItem::new(item.name.clone(), rate.clone(), period)
+        });
+    }
+    billing::tier_by_month(&mut items)?;
+
+    let start = items
+        .iter()
+        .map(|item| item.period.from)
+        .fold(NaiveDate::MAX, cmp::min);
+    let mut invoice = Invoice::new(number, items, client.taxes_as_of(start))?;
+    invoice.rounding = client.rounding;
+
+    println!("Adding invoice:\n\n{}\n", invoice);
+    let confirmed =
+        confirm_invoices(prompter, client, slice::from_ref(&invoice))?;
+    Ok(confirmed
+        .then(|| Event::new_update(&client.key, Update::Invoiced(invoice)))
+        .into_iter()
+        .collect())
+}
+
+/// Lines of the old text missing from the new one prefixed with `-`, and
+/// new lines with `+`, keeping their order
+fn diff_lines(old: &[String], new: &[String]) -> Vec<String> {
+    // Longest common subsequence lengths of the remaining lines
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push(format!("  {}", old[i]));
+            i += 1;
+            j += 1;
+        } else if j == new.len()
+            || (i < old.len() && common[i + 1][j] >= common[i][j + 1])
+        {
+            lines.push(format!("- {}", old[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", new[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+/// What an update changes about a client, shown before confirming it
+fn describe_change(client: &Client, update: &Update) -> String {
+    match update {
+        Update::Name(name) => {
+            format!(
+                "{} ({})\nName: {} → {}",
+                client.name, client.key, client.name, name
+            )
+        }
+        Update::EffectiveAddress(effective, address) => {
+            let lines =
+                |text: &str| text.lines().map(String::from).collect::<Vec<_>>();
+            let diff = diff_lines(
+                &lines(client.address_as_of(*effective)),
+                &lines(address),
+            );
+            format!(
+                "{} address effective {}:\n{}",
+                client.name,
+                effective,
+                diff.join("\n")
+            )
+        }
+        Update::NamedAddress(name, address) => {
+            let lines =
+                |text: &str| text.lines().map(String::from).collect::<Vec<_>>();
+            let old = client.address_named(name).unwrap_or_default();
+            let diff = diff_lines(&lines(old), &lines(address));
+            format!("{} {} address:\n{}", client.name, name, diff.join("\n"))
+        }
+        Update::ServiceRate(name, effective, rate) => {
+            let service = client.service(name.clone());
+            let old = service
+                .and_then(|s| s.rates.range(..=*effective).next_back())
+                .map_or("(none)".to_string(), |(since, old)| {
+                    format!("{} since {}", old, since)
+                });
+            let mut description = format!(
+                "{} {} rate effective {}: {} → {}",
+                client.name, name, effective, old, rate
+            );
+            let invoices: Vec<String> = client
+                .invoices()
+                .filter(|invoice| invoice.items.iter().any(|i| i.name == *name))
+                .map(|invoice| format!("#{}", invoice.number))
+                .collect();
+            if !invoices.is_empty() {
+                description.push_str(&format!(
+                    "\nInvoices billing {}: {}",
+                    name,
+                    invoices.join(", ")
+                ));
+            }
+            description
+        }
+        Update::Taxes(effective, taxes) => {
+            let lines = |taxes: &[TaxRate]| {
+                taxes.iter().map(TaxRate::to_string).collect::<Vec<_>>()
+            };
+            let diff = diff_lines(
+                &lines(&client.taxes_as_of(*effective)),
+                &lines(taxes),
+            );
+            format!(
+                "{} taxes effective {}:\n{}",
+                client.name,
+                effective,
+                diff.join("\n")
+            )
+        }
+        other => format!("{}: {:?}", client.name, other),
+    }
+}
+
+fn set_taxes(prompter: &mut dyn Prompter, client: &Client) -> NewEvents {
+    let (taxes, effective) = prompter.taxes()?;
+
+    let update = Update::Taxes(effective, taxes);
+    println!("{}", describe_change(client, &update));
+    confirmed_for(prompter, client, || Event::new_update(&client.key, update))
+}
+
+fn set_rate(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+    profile: &Profile,
+) -> NewEvents {
+    let service = prompter.service_select(client.service_names())?;
+    let (rate, effective) = prompter.rate()?;
+
+    let today = Local::now().date_naive();
+    let equivalents = rate_equivalents(&rate, profile.hours_per_day(), today);
+    let update = Update::ServiceRate(service, effective, rate);
+    println!("{}", describe_change(client, &update));
+    if let Some(equivalents) = equivalents {
+        println!("{}", equivalents);
+    }
+    confirmed_for(prompter, client, || Event::new_update(&client.key, update))
+}
+
+/// The rate in other units for catching mistakes, with months of this
+/// month's working days
+fn rate_equivalents(
+    rate: &Rate,
+    hours_per_day: Decimal,
+    today: NaiveDate,
+) -> Option<String> {
+    let month =
+        billing::Period::new(today.start_of_month()?, today.end_of_month()?);
+    let equivalents: Vec<String> = rate
+        .equivalents(hours_per_day, &month)
+        .iter()
+        .map(Rate::to_string)
+        .collect();
+    if equivalents.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "{} ≈ {}, assuming {} working days × {}h",
+        rate,
+        equivalents.join(", "),
+        month.working_days(),
+        hours_per_day
+    ))
+}
+
+fn set_default_hours(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+) -> NewEvents {
+    let service = prompter.service_select(client.service_names())?;
+    let hours = prompter.default_hours()?;
+
+    println!(
+        "Pre-filling {} hours when invoicing {} for {}",
+        hours, service, client.name
+    );
+    confirmed_for(prompter, client, || {
+        Event::new_update(
+            &client.key,
+            Update::ServiceDefaultQuantity(service, hours),
+        )
+    })
+}
+
+fn change_address(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+    name: Option<String>,
+) -> NewEvents {
+    let address = prompter.address()?;
+    let update = match name.filter(|name| name != clients::BILLING_ADDRESS) {
+        Some(name) => Update::NamedAddress(name, address),
+        None => Update::EffectiveAddress(prompter.effective()?, address),
+    };
+    println!("{}", describe_change(client, &update));
+    confirmed_for(prompter, client, || Event::new_update(&client.key, update))
+}
+
+fn set_invoice_address(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+    name: String,
+) -> NewEvents {
+    let address = client
+        .address_named(&name)
+        .ok_or(ClientError::NoAddress(name.clone()))?;
+    println!(
+        "Invoices for {} will show the {} address:\n{}",
+        client.name, name, address
+    );
+    confirmed_for(prompter, client, || {
+        Event::new_update(&client.key, Update::InvoiceAddress(name))
+    })
+}
+
+fn change_name(prompter: &mut dyn Prompter, client: &Client) -> NewEvents {
+    let name = prompter.name()?;
+
+    let update = Update::Name(name);
+    println!("{}", describe_change(client, &update));
+    confirmed_for(prompter, client, || Event::new_update(&client.key, update))
+}
+
+fn set_template(prompter: &mut dyn Prompter, client: &Client) -> NewEvents {
+    let (accent, logo_path, template) = prompter.template_options()?;
+    let default = "(default)".to_string();
+
+    println!("Setting invoice template options for {}:", client.name);
+    println!("Accent: {}", accent.as_ref().unwrap_or(&default));
+    println!("Logo: {}", logo_path.as_ref().unwrap_or(&default));
+    println!("Document class: {}", template.as_ref().unwrap_or(&default));
+    confirmed_for(prompter, client, || {
+        Event::new_update(
+            &client.key,
+            Update::TemplateOptions {
+                accent,
+                logo_path,
+                template,
+            },
+        )
+    })
+}
+
+fn set_footer(prompter: &mut dyn Prompter, client: &Client) -> NewEvents {
+    let footer = prompter
+        .footer("Save an empty footer to suppress the profile default")?;
+
+    if footer.is_empty() {
+        println!("Removing the invoice footer for {}", client.name);
+    } else {
+        println!(
+            "Setting invoice footer for {} to:\n\n{}",
+            client.name, footer
+        );
+    }
+    confirmed_for(prompter, client, || {
+        Event::new_update(&client.key, Update::Footer(footer))
+    })
+}
+
+fn set_rounding(prompter: &mut dyn Prompter, client: &Client) -> NewEvents {
+    let policy = prompter.rounding()?;
+
+    println!("Setting new invoices for {} to: {}", client.name, policy);
+    confirmed_for(prompter, client, || {
+        Event::new_update(&client.key, Update::Rounding(policy))
+    })
+}
+
+fn set_payment_account(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+    account: String,
+) -> NewEvents {
+    println!("Depositing payments from {} to {}", client.name, account);
+    confirmed_for(prompter, client, || {
+        Event::new_update(&client.key, Update::PaymentAccount(account))
+    })
+}
+
+fn set_stale_rate_age(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+    months: u32,
+) -> NewEvents {
+    println!(
+        "Confirming rates older than {} months when invoicing {}",
+        months, client.name
+    );
+    confirmed_for(prompter, client, || {
+        Event::new_update(&client.key, Update::StaleRateAge(months))
+    })
+}
+
+fn set_language(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+    code: String,
+) -> NewEvents {
+    let labels = Labels::for_language(&code)
+        .ok_or_else(|| RunError::UnknownLanguage(code.clone()))?;
+    println!("Writing invoices for {} in {}", client.name, labels.code);
+    confirmed_for(prompter, client, || {
+        let code = labels.code.to_string();
+        Event::new_update(&client.key, Update::Language(code))
+    })
+}
+
+fn set_schedule(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+    service: String,
+    day: u8,
+) -> NewEvents {
+    if client.service(service.clone()).is_none() {
+        return Err(ClientError::NoService(service).into());
+    }
+    println!(
+        "Invoicing {} for {} on day {} of every month",
+        service, client.name, day
+    );
+    confirmed_for(prompter, client, || {
+        Event::new_update(
+            &client.key,
+            Update::Schedule {
+                service,
+                day_of_month: day,
+            },
+        )
+    })
+}
+
+fn set_posting_date(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+    date: String,
+) -> NewEvents {
+    let date = parse_posting_date(&date)?;
+    println!(
+        "Dating invoice postings for {} by the {}",
+        client.name, date
+    );
+    confirmed_for(prompter, client, || {
+        Event::new_update(&client.key, Update::PostingDate(date))
+    })
+}
+
+fn set_category(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+    service: String,
+    category: String,
+) -> NewEvents {
+    if client.service(service.clone()).is_none() {
+        return Err(ClientError::NoService(service).into());
+    }
+    println!(
+        "Reporting the revenue of {} for {} under {}",
+        service, client.name, category
+    );
+    confirmed_for(prompter, client, || {
+        Event::new_update(
+            &client.key,
+            Update::ServiceCategory(service, category),
+        )
+    })
+}
+
+fn set_target_hours(
+    prompter: &mut dyn Prompter,
+    client: &Client,
+    service: String,
+    hours: String,
+) -> NewEvents {
+    if client.service(service.clone()).is_none() {
+        return Err(ClientError::NoService(service).into());
+    }
+    let target = hours
+        .trim()
+        .parse::<Decimal>()
+        .ok()
+        .filter(|hours| *hours > Decimal::ZERO)
+        .ok_or(RunError::TargetHours(hours))?;
+    println!(
+        "Aiming to bill {} hours of {} for {} every month",
+        target, service, client.name
+    );
+    confirmed_for(prompter, client, || {
+        Event::new_update(
+            &client.key,
+            Update::ServiceTargetHours(service, target),
+        )
+    })
+}
+
+fn parse_posting_date(date: &str) -> Result<PostingDate, RunError> {
+    date.trim()
+        .to_lowercase()
+        .parse()
+        .map_err(|_| RunError::UnknownPostingDate(date.to_string()))
+}
+
+fn show_profile(profile: &Profile) -> NewEvents {
+    println!("{}", profile);
+    Ok(vec![])
+}
+
+fn set_sender(prompter: &mut dyn Prompter) -> NewEvents {
+    let name = prompter.name()?;
+    let address = prompter.address()?;
+
+    println!("Sending invoices from:\n\n{}\n{}", name, address);
+    confirmed(prompter, || {
+        Event::new_profile(ProfileUpdate::Sender(name, address))
+    })
+}
+
+fn set_default_footer(prompter: &mut dyn Prompter) -> NewEvents {
+    let footer =
+        prompter.footer("Used for clients without their own footer")?;
+
+    println!("Setting default invoice footer to:\n\n{}", footer);
+    confirmed(prompter, || {
+        Event::new_profile(ProfileUpdate::Footer(footer))
+    })
+}
+
+fn set_registration(
+    prompter: &mut dyn Prompter,
+    profile: &Profile,
+    label: String,
+    number: Option<String>,
+    force: bool,
+) -> NewEvents {
+    let Some(number) = number else {
+        if !profile
+            .registrations
+            .iter()
+            .any(|(other, _)| *other == label)
+        {
+            return Err(RunError::NoRegistration(label));
+        }
+        println!("Removing the {} registration", label);
+        return confirmed(prompter, || {
+            Event::new_profile(ProfileUpdate::RemoveRegistration(label))
+        });
+    };
+    let number = match registration::validate(&number) {
+        Ok(number) => number,
+        Err(error) if force => {
+            eprintln!("Warning: {}", error);
+            number.trim().to_string()
+        }
+        Err(error) => return Err(error.into()),
+    };
+
+    println!(
+        "Showing {}: {} on invoices",
+        label,
+        registration::display(&number)
+    );
+    confirmed(prompter, || {
+        Event::new_profile(ProfileUpdate::Registration(label, number))
+    })
+}
+
+fn set_ledger_style(prompter: &mut dyn Prompter, spec: &str) -> NewEvents {
+    let style: LedgerStyle = spec.parse()?;
+    let example = Money::new(Currency::Eur, Decimal::new(-123456, 2));
+
+    println!(
+        "Setting ledger style to {} (e.g. {})",
+        style,
+        ledger_fmt_with(&style, example)
+    );
+    confirmed(prompter, || {
+        Event::new_profile(ProfileUpdate::LedgerStyle(style))
+    })
+}
+
+fn list_invoices(client: &Client, style: Style) -> NewEvents {
+    let today = Local::now().date_naive();
+    print!("{}", invoices_table(client, today)?.render_as(style));
+    Ok(vec![])
+}
+
+fn invoices_table(
+    client: &Client,
+    today: NaiveDate,
+) -> Result<Table, CurrencyMismatch> {
+    let mut table =
+        Table::new(&["Number", "Date", "Sent", "Period", "Total", "Status"]);
+    for i in client.invoices() {
+        let (status, color) = invoice_status(i, today);
+        table.push(vec![
+            Cell::from(format!("#{}", i.number)),
+            Cell::from(i.date.to_string()),
+            Cell::from(i.sent.map_or(String::new(), |d| d.to_string())),
+            Cell::from(i.overall_period().to_string()),
+            Cell::from(i.calculate()?.total.to_string()),
+            Cell::colored(status, color),
+        ]);
+    }
+    Ok(table)
+}
+
+/// Whether an invoice was paid, written off, or voided, or is still open,
+/// with the colour it's listed in
+fn invoice_status(
+    invoice: &Invoice,
+    today: NaiveDate,
+) -> (String, Option<Color>) {
+    match (invoice.paid, &invoice.written_off, &invoice.voided) {
+        (_, _, Some(void)) => (format!("VOID {}", void.date), Some(Color::Red)),
+        (Some(when), _, None) => (format!("paid {}", when), Some(Color::Green)),
+        (None, Some(write_off), None) => (
+            format!("written off {}", write_off.date),
+            Some(Color::Yellow),
+        ),
+        _ if invoice.is_disputed() => {
+            ("DISPUTED".to_string(), Some(Color::Yellow))
+        }
+        _ if invoice.due_date() < today => {
+            ("overdue".to_string(), Some(Color::Red))
+        }
+        _ => ("unpaid".to_string(), None),
+    }
+}
+
+fn list_services(client: &Client, style: Style) -> NewEvents {
+    let today = Local::now().date_naive();
+    print!("{}", services_table(client, today).render_as(style));
+    Ok(vec![])
+}
+
+fn services_table(client: &Client, today: NaiveDate) -> Table {
+    let mut table = Table::new(&[
+        "Service",
+        "Rate",
+        "Effective",
+        "Default Hours",
+        "Category",
+    ]);
+    for service in client.services.values() {
+        let current = service
+            .rates
+            .range(..=today)
+            .next_back()
+            .or_else(|| service.rates.first());
+        let (effective, rate) = current
+            .map_or((String::new(), String::new()), |(date, rate)| {
+                (date.to_string(), rate.to_string())
+            });
+        // Highlight rates older than the client's stale rate age
+        let stale = current.is_some_and(|(date, _)| {
+            billing::rate_is_stale(*date, today, client.stale_rate_months())
+        });
+        table.push(vec![
+            Cell::from(service.name.as_str()),
+            Cell::from(rate),
+            Cell::colored(effective, stale.then_some(Color::Yellow)),
+            Cell::from(
+                service
+                    .default_quantity
+                    .map_or(String::new(), |hours| hours.to_string()),
+            ),
+            Cell::from(service.category.clone().unwrap_or_default()),
+        ]);
+    }
+    table
+}
+
+/// Each service's rate history as an aligned table below its name, or as
+/// tab separated fields starting with the service
+fn list_rate_history(client: &Client, style: Style) -> NewEvents {
+    for service in client.services.values() {
+        let table = rate_history_table(service);
+        if style == Style::Porcelain {
+            for line in table.porcelain().lines() {
+                println!("{}\t{}", service.name, line);
+            }
+            continue;
+        }
+        println!("{}", service.name);
+        for line in table.render_as(style).lines() {
+            println!("  {}", line);
+        }
+    }
+    Ok(vec![])
+}
+
+/// Rates in order of their effective dates with the rate before, if any
+fn rate_history(
+    service: &billing::Service,
+) -> impl Iterator<Item = (&NaiveDate, &Rate, Option<&Rate>)> {
+    let earlier = [None].into_iter().chain(service.rates.iter().map(Some));
+    service
+        .rates
+        .iter()
+        .zip(earlier)
+        .map(|((effective, rate), earlier)| {
+            (effective, rate, earlier.map(|(_, rate)| rate))
+        })
+}
+
+/// Percentage change from the earlier rate, an arrow where the unit or
+/// currency changed as the amounts can't be compared
+fn rate_change(rate: &Rate, earlier: Option<&Rate>) -> String {
+    let Some(earlier) = earlier else {
+        return String::new();
+    };
+    match rate.change_from(earlier) {
+        Some(change) if change > Decimal::ZERO => format!("+{:.1}%", change),
+        Some(change) => format!("{:.1}%", change),
+        None => "→".to_string(),
+    }
+}
+
+fn rate_history_table(service: &billing::Service) -> Table {
+    let mut table = Table::new(&["Effective", "Amount", "Unit", "Change"]);
+    for (effective, rate, earlier) in rate_history(service) {
+        table.push(vec![
+            Cell::from(effective.to_string()),
+            Cell::from(rate.amount.to_string()),
+            Cell::from(rate.per.to_string()),
+            Cell::from(rate_change(rate, earlier)),
+        ]);
+    }
+    table
+}
+
+/// A rate in the JSON history of a service
+#[derive(Serialize)]
+struct RateHistoryRow<'a> {
+    service: &'a str,
+    effective: NaiveDate,
+    amount: String,
+    currency: &'static str,
+    unit: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    change: Option<String>,
+}
+
+/// The rate histories with a percentage change only between comparable
+/// rates
+fn rate_history_json(client: &Client) -> Result<String, serde_json::Error> {
+    let mut rows: Vec<RateHistoryRow> = Vec::new();
+    for service in client.services.values() {
+        for (effective, rate, earlier) in rate_history(service) {
+            rows.push(RateHistoryRow {
+                service: &service.name,
+                effective: *effective,
+                amount: rate.amount.amount().to_string(),
+                currency: rate.amount.currency().code(),
+                unit: rate.per.to_string(),
+                change: earlier
+                    .and_then(|e| rate.change_from(e))
+                    .map(|change| format!("{:.1}", change)),
+            });
+        }
+    }
+    serde_json::to_string_pretty(&rows)
+}
+
+fn show_taxes(client: &Client) -> NewEvents {
+    let history = client.tax_history();
+    if history.is_empty() {
+        println!("No taxes set for {}", client.name);
+    }
+    for (effective, taxes) in history.iter() {
+        let taxes: Vec<String> = taxes
+            .iter()
+            .map(|tax| match tax.2 {
+                TaxRounding::HalfEven => tax.to_string(),
+                rounding => format!("{} ({})", tax, rounding),
+            })
+            .collect();
+        println!("{}: {}", effective, taxes.join(", "));
+    }
+    Ok(vec![])
+}
+
+fn show_payments(client: &Client, year: Option<i32>) -> NewEvents {
+    let payments: Vec<PaymentRecord> = client
+        .payments()?
+        .into_iter()
+        .filter(|payment| year.is_none_or(|y| payment.date.year() == y))
+        .collect();
+    if payments.is_empty() {
+        println!("No payments received from {}", client.name);
+        return Ok(vec![]);
+    }
+    let mut table = Table::new(&["Number", "Paid", "Amount"]);
+    for payment in payments.iter() {
+        table.push(vec![
+            Cell::from(format!("#{}", payment.number)),
+            Cell::from(payment.date.to_string()),
+            Cell::from(payment.amount.to_string()),
+        ]);
+    }
+    print!("{}", table.render(false));
+    for (year, totals) in payment_totals(&payments) {
+        println!("Total {}: {}", year, totals);
+    }
+    Ok(vec![])
+}
+
+/// Payment totals per year and currency, years in order
+fn payment_totals(payments: &[PaymentRecord]) -> Vec<(i32, String)> {
+    let mut years: Vec<(i32, Vec<(Currency, Decimal)>)> = Vec::new();
+    for payment in payments {
+        let year = payment.date.year();
+        if years.last().is_none_or(|(last, _)| *last != year) {
+            years.push((year, Vec::new()));
+        }
+        let (_, totals) = years.last_mut().expect("year was just pushed");
+        let currency = payment.amount.currency();
+        match totals.iter_mut().find(|(c, _)| *c == currency) {
+            Some((_, sum)) => *sum += payment.amount.amount(),
+            None => totals.push((currency, payment.amount.amount())),
+        }
+    }
+    years
+        .into_iter()
+        .map(|(year, totals)| {
+            let totals: Vec<String> = totals
+                .into_iter()
+                .map(|(currency, amount)| {
+                    Money::new(currency, amount).to_string()
+                })
+                .collect();
+            (year, totals.join(", "))
+        })
+        .collect()
+}
+
+fn show_retainers(client: &Client) -> NewEvents {
+    let mut table = Table::new(&[
+        "Service",
+        "Period",
+        "Purchased",
+        "Consumed",
+        "Remaining",
+    ]);
+    for (name, retainer) in client.retainers() {
+        table.push(vec![
+            Cell::from(name),
+            Cell::from(retainer.period.to_string()),
+            Cell::from(retainer.hours.to_string()),
+            Cell::from(retainer.consumed.to_string()),
+            Cell::from(retainer.remaining().to_string()),
+        ]);
+    }
+    print!("{}", table.render(false));
+    Ok(vec![])
+}
+
+fn show_rates(client: &Client) -> NewEvents {
+    for service in client.services.values() {
+        println!("{} ({} rates)", service.name, service.rates.len());
+        for (effective, rate) in service.rates.iter() {
+            println!("  {}: {}", effective, rate);
+        }
+    }
+    Ok(vec![])
+}
+
+fn list_taxes(client: &Client, as_of: Option<NaiveDate>) -> NewEvents {
+    let today = Local::now().date_naive();
+    for line in tax_lines(client, as_of, today) {
+        println!("{}", line);
+    }
+    Ok(vec![])
+}
+
+/// Tab separated effective date, tax name, and percentage, with a `*` on
+/// the taxes in effect today
+fn tax_lines(
+    client: &Client,
+    as_of: Option<NaiveDate>,
+    today: NaiveDate,
+) -> Vec<String> {
+    let history = client.tax_history();
+    let active = history.range(..=today).next_back().map(|(date, _)| *date);
+    let selected = as_of.and_then(|date| history.range(..=date).next_back());
+
+    let entries: Vec<(&NaiveDate, &Vec<TaxRate>)> = match as_of {
+        Some(_) => selected.into_iter().collect(),
+        None => history.iter().collect(),
+    };
+    entries
+        .into_iter()
+        .flat_map(|(effective, taxes)| {
+            let marker = if Some(*effective) == active { "*" } else { "" };
+            taxes.iter().map(move |tax| {
+                let percent = (tax.1 * Decimal::from(100)).normalize();
+                format!("{}\t{}\t{}%\t{}", effective, tax.0, percent, marker)
+            })
+        })
+        .collect()
+}
+
+fn show_invoice(invoice: &Invoice, client: &Client, flat: bool) -> NewEvents {
+    let today = Local::now().date_naive();
+    println!("{}", invoice_details(invoice, client, today, flat));
+    Ok(vec![])
+}
+
+fn invoice_details(
+    invoice: &Invoice,
+    client: &Client,
+    today: NaiveDate,
+    flat: bool,
+) -> String {
+    let status = match (invoice.paid, &invoice.written_off, &invoice.voided) {
+        (_, _, Some(Void { date, reason })) => match reason {
+            Some(reason) => format!("VOID {}, {}", date, reason),
+            None => format!("VOID {}", date),
+        },
+        (Some(when), _, None) => format!("PAID {}", when),
+        (None, Some(WriteOff { date, reason }), None) => match reason {
+            Some(reason) => format!("WRITTEN OFF {}, {}", date, reason),
+            None => format!("WRITTEN OFF {}", date),
+        },
+        (None, None, None) => format!(
+            "UNPAID, {} days outstanding, due {}",
+            (today - invoice.issued()).num_days(),
+            invoice.due_date()
+        ),
+    };
+    let status = match &invoice.disputed {
+        Some(Dispute {
+            date,
+            reason,
+            resolved: None,
+        }) if invoice.is_open() => match reason {
+            Some(reason) => {
+                format!("{}\nDISPUTED {}, {}", status, date, reason)
+            }
+            None => format!("{}\nDISPUTED {}", status, date),
+        },
+        _ => status,
+    };
+    let items = if flat {
+        format!("{:#}", invoice)
+    } else {
+        invoice.to_string()
+    };
+    let mut details = format!(
+        "{}\n\nPeriod: {}\nStatus: {}",
+        items,
+        invoice.overall_period(),
+        status
+    );
+    if invoice.is_open() {
+        details.push_str(&format!(
+            "\nLedger posting: invogen show {} invoice {} posting",
+            client.key, invoice.number
+        ));
+    }
+    details
+}
+
+fn mark_paid(
+    prompter: &mut dyn Prompter,
+    invoice: &Invoice,
+    client: &Client,
+    other_currency: bool,
+) -> NewEvents {
+    let when = prompter.paid_date(invoice.date)?;
+    let received = if other_currency {
+        Some(prompter.received(invoice.currency())?)
+    } else {
+        None
+    };
+
+    println!("Marking invoice #{} as paid on {}", invoice.number, when);
+    if let Some(received) = &received {
+        let total = invoice.calculate()?.total;
+        let booked = billing::settle(&[total], received);
+        println!(
+            "Received {} for {}, booked at {}, difference {}",
+            received.amount, total, booked.costs[0], booked.difference
+        );
+    }
+    confirmed_for(prompter, client, || {
+        Event::new_update(
+            &client.key,
+            Update::Paid(invoice.number, when, received),
+        )
+    })
+}
+
+fn mark_sent(
+    prompter: &mut dyn Prompter,
+    invoice: &Invoice,
+    client: &Client,
+) -> NewEvents {
+    if let Some(sent) = invoice.sent {
+        return Err(ClientError::Invoice(
+            invoice.number,
+            clients::InvoiceError::AlreadySent(sent),
+        )
+        .into());
+    }
+    let when = prompter.sent_date(invoice.date)?;
+
+    println!("Marking invoice #{} as sent on {}", invoice.number, when);
+    println!("Due: {}", when + billing::DEFAULT_TERMS);
+    confirmed_for(prompter, client, || {
+        Event::new_update(&client.key, Update::Sent(invoice.number, when))
+    })
+}
+
+fn write_off(
+    prompter: &mut dyn Prompter,
+    invoice: &Invoice,
+    client: &Client,
+) -> NewEvents {
+    let (when, reason) = prompter.write_off(invoice.date)?;
+
+    println!(
+        "Writing off invoice #{} of {} on {}",
+        invoice.number,
+        invoice.calculate()?.total,
+        when
+    );
+    confirmed_for(prompter, client, || {
+        Event::new_update(
+            &client.key,
+            Update::WrittenOff(invoice.number, when, reason),
+        )
+    })
+}
+
+fn dispute(
+    prompter: &mut dyn Prompter,
+    invoice: &Invoice,
+    client: &Client,
+) -> NewEvents {
+    use clients::InvoiceError::{
+        AlreadyDisputed, AlreadyPaid, Voided, WrittenOff,
+    };
+    let error = match (invoice.paid, &invoice.written_off, &invoice.voided) {
+        (Some(_), _, _) => Some(AlreadyPaid),
+        (_, Some(_), _) => Some(WrittenOff),
+        (_, _, Some(_)) => Some(Voided),
+        _ => invoice
+            .disputed
+            .as_ref()
+            .filter(|_| invoice.is_disputed())
+            .map(|d| AlreadyDisputed(d.date)),
+    };
+    if let Some(error) = error {
+        return Err(ClientError::Invoice(invoice.number, error).into());
+    }
+    let (when, reason) = prompter.dispute(invoice.date)?;
+
+    println!(
+        "Marking invoice #{} of {} as disputed on {}, it's not shown as \
+         overdue until the dispute is resolved",
+        invoice.number,
+        invoice.calculate()?.total,
+        when
+    );
+    confirmed_for(prompter, client, || {
+        Event::new_update(
+            &client.key,
+            Update::Disputed(invoice.number, when, reason),
+        )
+    })
+}
+
+fn resolve_dispute(
+    prompter: &mut dyn Prompter,
+    invoice: &Invoice,
+    client: &Client,
+) -> NewEvents {
+    let Some(dispute) =
+        invoice.disputed.as_ref().filter(|_| invoice.is_disputed())
+    else {
+        return Err(ClientError::Invoice(
+            invoice.number,
+            clients::InvoiceError::NotDisputed,
+        )
+        .into());
+    };
+    let when = prompter.dispute_resolved(dispute.date)?;
+
+    println!(
+        "Resolving the dispute of invoice #{} on {}, due {}",
+        invoice.number,
+        when,
+        invoice.due_date()
+    );
+    confirmed_for(prompter, client, || {
+        Event::new_update(
+            &client.key,
+            Update::DisputeResolved(invoice.number, when),
+        )
+    })
+}
+
+fn void(
+    prompter: &mut dyn Prompter,
+    invoice: &Invoice,
+    client: &Client,
+) -> NewEvents {
+    if invoice.paid.is_some() {
+        return Err(ClientError::Invoice(
+            invoice.number,
+            clients::InvoiceError::PaidNotVoidable,
+        )
+        .into());
+    }
+    let (when, reason) = prompter.void(invoice.date)?;
+
+    println!(
+        "Voiding invoice #{} of {} on {}, the number won't be reused",
+        invoice.number,
+        invoice.calculate()?.total,
+        when
+    );
+    confirmed_for(prompter, client, || {
+        Event::new_update(
+            &client.key,
+            Update::Voided(invoice.number, when, reason),
+        )
+    })
+}
+
+fn invoice_posting(
+    invoice: &Invoice,
+    client: &Client,
+    style: &LedgerStyle,
+    valuation: Option<&Valuation>,
+    by_service: bool,
+    date: PostingDate,
+    reverse_accrual: bool,
+) -> NewEvents {
+    print!(
+        "{}",
+        posting(invoice, client, style, valuation, by_service, date)?
+    );
+    if reverse_accrual {
+        let period = invoice.overall_period();
+        let reversal = accrual(client, &period, style, date.of(invoice), true)?;
+        print!("\n{}", reversal);
+    }
+    Ok(vec![])
+}
+
+/// An invoice's amounts valued in another currency for hledger, with `@@`
+/// costs on each leg or a price directive before the transaction
+struct Valuation {
+    currency: Currency,
+    rate: Decimal,
+    price_directive: bool,
+}
+
+fn posting(
+    invoice: &Invoice,
+    client: &Client,
+    style: &LedgerStyle,
+    valuation: Option<&Valuation>,
+    by_service: bool,
+    date: PostingDate,
+) -> Result<String, CurrencyMismatch> {
+    let total = invoice.calculate()?;
+
+    let mut legs =
+        vec![(format!("assets:receivable:{}", client.name), total.subtotal)];
+    for (TaxRate(name, ..), amount) in posted_taxes(&total) {
+        legs.push((format!("assets:receivable:{}", name), *amount));
+    }
+    // The deposits were already booked as revenue when they were invoiced
+    for (_, deduction) in total.deductions.iter() {
+        legs.push((format!("assets:receivable:{}", client.name), *deduction));
+    }
+    let mut revenue_amount = -total.total;
+    if let Some(adjustment) = total.adjustment {
+        legs.push((format!("assets:receivable:{}", client.name), adjustment));
+        revenue_amount = revenue_amount + adjustment;
+    }
+    let revenue = format!("revenues:clients:{}", client.name);
+    if by_service {
+        legs.extend(revenue_by_service(invoice, &revenue, revenue_amount));
+    } else {
+        legs.push((revenue, revenue_amount));
+    }
+    if let Some(adjustment) = total.adjustment {
+        legs.push(("expenses:rounding".to_string(), -adjustment));
+    }
+
+    let mut directive = String::new();
+    let items: Vec<(String, String)> = match valuation {
+        Some(value) if value.currency != invoice.currency() => {
+            if value.price_directive {
+                directive = price_directive(
+                    style,
+                    date.of(invoice),
+                    invoice.currency(),
+                    value.currency,
+                    value.rate,
+                );
+                format_legs(legs, style)
+            } else {
+                // The legs sum to zero, so their costs do too
+                let amounts: Vec<Money> =
+                    legs.iter().map(|(_, a)| *a).collect();
+                let costs =
+                    billing::value_at(&amounts, value.currency, value.rate);
+                legs.into_iter()
+                    .zip(costs)
+                    .map(|((account, amount), cost)| {
+                        (account, ledger_fmt_with_cost(style, amount, cost))
+                    })
+                    .collect()
+            }
+        }
+        _ => format_legs(legs, style),
+    };
+
+    // The issue date stays in the comment when the period dates it
+    let issued = match date {
+        PostingDate::Issue => String::new(),
+        _ => format!(", issued {}", invoice.date),
+    };
+    Ok(format!(
+        "{}{} {} invoice  ; {}{}\n{}",
+        directive,
+        date.of(invoice),
+        client.name,
+        period_range(&invoice.overall_period()),
+        issued,
+        align_postings(&items)
+    ))
+}
+
+/// A period for a transaction's comment, e.g. Apr 1 - 30 or Apr 15 - May 14
+fn period_range(period: &billing::Period) -> String {
+    let end = if period.from.month() == period.until.month() {
+        "%-d"
+    } else {
+        "%b %-d"
+    };
+    format!(
+        "{} - {}",
+        period.from.format("%b %-d"),
+        period.until.format(end)
+    )
+}
+
+/// Revenue split into a sub-account per service in proportion to its items,
+/// in the order the services first appear on the invoice
+fn revenue_by_service(
+    invoice: &Invoice,
+    account: &str,
+    revenue: Money,
+) -> Vec<(String, Money)> {
+    let mut services: Vec<(&str, Money)> = Vec::new();
+    for item in invoice.items.iter() {
+        match services.iter_mut().find(|(name, _)| *name == item.name) {
+            Some((_, amount)) => *amount = *amount + item.amount,
+            None => services.push((&item.name, item.amount)),
+        }
+    }
+    let amounts: Vec<Money> = services.iter().map(|(_, a)| *a).collect();
+    services
+        .iter()
+        .zip(billing::allocate(revenue, &amounts))
+        .map(|((name, _), share)| (format!("{}:{}", account, name), share))
+        .collect()
+}
+
+fn format_legs(
+    legs: Vec<(String, Money)>,
+    style: &LedgerStyle,
+) -> Vec<(String, String)> {
+    legs.into_iter()
+        .map(|(account, amount)| (account, ledger_fmt_with(style, amount)))
+        .collect()
+}
+
+fn accrual_posting(
+    client: &Client,
+    period: &billing::Period,
+    style: &LedgerStyle,
+) -> NewEvents {
+    print!("{}", accrual(client, period, style, period.until, false)?);
+    Ok(vec![])
+}
+
+/// Revenue earned in a period recognized before it's invoiced, or with
+/// `reverse` the transaction undoing it once the invoice is posted
+fn accrual(
+    client: &Client,
+    period: &billing::Period,
+    style: &LedgerStyle,
+    date: NaiveDate,
+    reverse: bool,
+) -> Result<String, RunError> {
+    let amounts = accrued_revenue(client, period);
+    if amounts.is_empty() {
+        return Err(RunError::NothingToAccrue(
+            client.name.clone(),
+            period.clone(),
+        ));
+    }
+    let accrued = format!("assets:accrued revenue:{}", client.name);
+    let revenue = format!("revenues:clients:{}", client.name);
+    let (label, debit, credit) = match reverse {
+        false => ("accrual", accrued, revenue),
+        true => ("accrual reversal", revenue, accrued),
+    };
+    let mut legs = Vec::new();
+    for amount in amounts {
+        legs.push((debit.clone(), amount));
+        legs.push((credit.clone(), -amount));
+    }
+    Ok(format!(
+        "{} {} {}  ; {}\n{}",
+        date,
+        client.name,
+        label,
+        period_range(period),
+        align_postings(&format_legs(legs, style))
+    ))
+}
+
+/// What every service would bill for the period at the rate in effect when
+/// it starts, by currency, hourly services count their default hours
+fn accrued_revenue(client: &Client, period: &billing::Period) -> Vec<Money> {
+    let mut totals: Vec<Money> = Vec::new();
+    for service in client.services.values() {
+        let Some(rate) = service.rates.as_of(period.from) else {
+            continue;
+        };
+        let name = service.name.clone();
+        let item = match (rate.per == Unit::Hour, service.default_quantity) {
+            (false, _) => InvoiceItem::new(name, rate.clone(), period.clone()),
+            (true, Some(hours)) => InvoiceItem::new_hourly(
+                name,
+                rate.clone(),
+                period.clone(),
+                hours,
+            ),
+            (true, None) => continue,
+        };
+        let amount = item.amount;
+        if amount.amount().is_zero() {
+            continue;
+        }
+        match totals
+            .iter_mut()
+            .find(|t| t.currency() == amount.currency())
+        {
+            Some(total) => *total = *total + amount,
+            None => totals.push(amount),
+        }
+    }
+    totals
+}
+
+fn payment_posting(
+    invoice: &Invoice,
+    client: &Client,
+    style: &LedgerStyle,
+    account: &str,
+    date: PostingDate,
+) -> NewEvents {
+    print!("{}", payment(invoice, client, style, account, date)?);
+    Ok(vec![])
+}
+
+/// The payment dated when paid, with the invoice's period date as the
+/// secondary date when postings are dated by the period
+fn payment(
+    invoice: &Invoice,
+    client: &Client,
+    style: &LedgerStyle,
+    account: &str,
+    date: PostingDate,
+) -> Result<String, RunError> {
+    let paid = invoice.paid.ok_or(ClientError::Invoice(
+        invoice.number,
+        clients::InvoiceError::NotPaid,
+    ))?;
+    let items = match &invoice.received {
+        Some(received) => {
+            settled_payment(invoice, client, received, style, account)?
+        }
+        None => {
+            let total = invoice.calculate()?;
+            let mut items = vec![(
+                account.to_string(),
+                ledger_fmt_with(style, total.total),
+            )];
+            items.extend(receivable_credits(invoice, client, style)?);
+            items
+        }
+    };
+
+    let secondary = match date {
+        PostingDate::Issue => String::new(),
+        _ => format!("={}", date.of(invoice)),
+    };
+    Ok(format!(
+        "{}{} {} payment  ; invoice #{}\n{}",
+        paid,
+        secondary,
+        client.name,
+        invoice.number,
+        align_postings(&items)
+    ))
+}
+
+/// Payment received in another currency, the receivables are cleared at
+/// the rate they were booked at and the difference is an exchange gain or
+/// loss
+fn settled_payment(
+    invoice: &Invoice,
+    client: &Client,
+    received: &Received,
+    style: &LedgerStyle,
+    account: &str,
+) -> Result<Vec<(String, String)>, CurrencyMismatch> {
+    let receivables = receivable_amounts(invoice, client)?;
+    let amounts: Vec<Money> = receivables.iter().map(|(_, a)| *a).collect();
+    let settlement = billing::settle(&amounts, received);
+
+    let mut items =
+        vec![(account.to_string(), ledger_fmt_with(style, received.amount))];
+    for ((account, amount), cost) in receivables.iter().zip(settlement.costs) {
+        items.push((
+            account.clone(),
+            ledger_fmt_with_cost(style, -*amount, -cost),
+        ));
+    }
+    let difference = settlement.difference;
+    if difference.amount() > Decimal::ZERO {
+        items.push((
+            "income:fx gain".to_string(),
+            ledger_fmt_with(style, -difference),
+        ));
+    } else if difference.is_negative() {
+        items.push((
+            "expenses:fx loss".to_string(),
+            ledger_fmt_with(style, -difference),
+        ));
+    }
+    Ok(items)
+}
+
+/// Taxes with an amount to post, the zero amounts of zero rated taxes are
+/// shown on invoices but left out of ledger postings
+fn posted_taxes(
+    total: &InvoiceTotal,
+) -> impl Iterator<Item = &(TaxRate, Money)> {
+    total
+        .taxes
+        .iter()
+        .filter(|(_, amount)| !amount.amount().is_zero())
+}
+
+/// Postings clearing what the invoice left receivable from the client and
+/// for each tax
+fn receivable_credits(
+    invoice: &Invoice,
+    client: &Client,
+    style: &LedgerStyle,
+) -> Result<Vec<(String, String)>, CurrencyMismatch> {
+    Ok(receivable_amounts(invoice, client)?
+        .into_iter()
+        .map(|(account, amount)| (account, ledger_fmt_with(style, -amount)))
+        .collect())
+}
+
+fn receivable_amounts(
+    invoice: &Invoice,
+    client: &Client,
+) -> Result<Vec<(String, Money)>, CurrencyMismatch> {
+    let total = invoice.calculate()?;
+    let receivable = total
+        .deductions
+        .iter()
+        .map(|(_, x)| *x)
+        .chain(total.adjustment)
+        .fold(total.subtotal, |a, x| a + x);
+
+    let mut amounts =
+        vec![(format!("assets:receivable:{}", client.name), receivable)];
+    for (TaxRate(name, ..), amount) in posted_taxes(&total) {
+        amounts.push((format!("assets:receivable:{}", name), *amount));
+    }
+    Ok(amounts)
+}
+
+fn write_off_posting(
+    invoice: &Invoice,
+    client: &Client,
+    style: &LedgerStyle,
+) -> NewEvents {
+    print!("{}", bad_debt(invoice, client, style)?);
+    Ok(vec![])
+}
+
+fn bad_debt(
+    invoice: &Invoice,
+    client: &Client,
+    style: &LedgerStyle,
+) -> Result<String, RunError> {
+    let write_off =
+        invoice.written_off.as_ref().ok_or(ClientError::Invoice(
+            invoice.number,
+            clients::InvoiceError::NotWrittenOff,
+        ))?;
+    let total = invoice.calculate()?;
+
+    let mut items = vec![(
+        format!("expenses:bad debt:{}", client.name),
+        ledger_fmt_with(style, total.total),
+    )];
+    items.extend(receivable_credits(invoice, client, style)?);
+
+    Ok(format!(
+        "{} {} bad debt  ; invoice #{}\n{}",
+        write_off.date,
+        client.name,
+        invoice.number,
+        align_postings(&items)
+    ))
+}
+
+fn invoice_csv(invoice: &Invoice) -> NewEvents {
+    print!("{}", csv::invoice_items(invoice)?);
+    Ok(vec![])
+}
+
+fn invoice_ubl(
+    invoice: &Invoice,
+    client: &Client,
+    profile: &Profile,
+) -> NewEvents {
+    let (name, address) = profile.sender.as_ref().ok_or(RunError::NoSender)?;
+    let supplier = ubl::Party { name, address };
+    let customer = ubl::Party {
+        name: &client.name,
+        address: client.invoice_address(invoice.date),
+    };
+    print!("{}", ubl::invoice_xml(invoice, &supplier, &customer)?);
+    Ok(vec![])
+}
+
+fn invoice_tex(
+    invoice: &Invoice,
+    client: &Client,
+    profile: &Profile,
+    flat: bool,
+    output: Option<&Path>,
+) -> NewEvents {
+    let Some(output) = output else {
+        templates::invoice(invoice, client, profile, flat)?;
+        return Ok(vec![]);
+    };
+    let tex = templates::render_invoice(invoice, client, profile, flat)?;
+    if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty())
+    {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(output, tex)?;
+    println!("Wrote {}", output.display());
+    Ok(vec![])
+}
+
+/// Only open invoices past their due date and not in dispute are chased
+fn reminder(
+    invoice: &Invoice,
+    client: &Client,
+    profile: &Profile,
+    level: ReminderLevel,
+    format: ReminderFormat,
+    today: NaiveDate,
+) -> Result<String, RunError> {
+    use clients::InvoiceError;
+    let due = invoice.due_date();
+    let dispute = invoice.disputed.as_ref().filter(|d| d.resolved.is_none());
+    let not_chased = if invoice.paid.is_some() {
+        Some(InvoiceError::AlreadyPaid)
+    } else if invoice.written_off.is_some() {
+        Some(InvoiceError::WrittenOff)
+    } else if invoice.voided.is_some() {
+        Some(InvoiceError::Voided)
+    } else if let Some(dispute) = dispute {
+        Some(InvoiceError::AlreadyDisputed(dispute.date))
+    } else if today <= due {
+        Some(InvoiceError::NotOverdue(due))
+    } else {
+        None
+    };
+    if let Some(error) = not_chased {
+        return Err(ClientError::Invoice(invoice.number, error).into());
+    }
+    Ok(templates::render_reminder(
+        invoice, client, profile, level, format, today,
+    )?)
+}
+
+fn export_ical(
+    clients: &Clients,
+    output: &Path,
+    reminders: bool,
+    include_disputed: bool,
+) -> NewEvents {
+    let today = Local::now().date_naive();
+    let next_month = today
+        .end_of_month()
+        .expect("Error in chrono-utilities end_of_month")
+        + Days::new(1);
+    let mut calendar = Calendar::new();
+
+    for client in clients.iter() {
+        let unpaid = client
+            .invoices()
+            .filter(|i| i.is_open() && (include_disputed || !i.is_disputed()));
+        for invoice in unpaid {
+            calendar.push(CalendarEvent {
+                uid: format!(
+                    "invoice-{}-{}@invogen",
+                    client.key, invoice.number
+                ),
+                start: invoice.due_date(),
+                summary: format!(
+                    "Invoice #{} for {} due — {}",
+                    invoice.number,
+                    client.name,
+                    invoice.calculate()?.total
+                ),
+                rrule: None,
+            });
+        }
+
+        if reminders && !client.services.is_empty() {
+            calendar.push(CalendarEvent {
+                uid: format!("billing-{}@invogen", client.key),
+                start: next_month,
+                summary: format!("Generate invoice for {}", client.name),
+                rrule: Some("FREQ=MONTHLY;BYMONTHDAY=1".to_string()),
+            });
+        }
+    }
+
+    fs::write(output, calendar.render(Utc::now()))?;
+    println!("Calendar written to {}", output.display());
+    Ok(vec![])
+}
+
+fn export(
+    clients: &Clients,
+    format: ExportFormat,
+    output: Option<&Path>,
+) -> NewEvents {
+    let text = match format {
+        ExportFormat::Toml => export::clients_toml(clients)?,
+        #[cfg(feature = "sqlite")]
+        ExportFormat::Sqlite => {
+            let output = output.ok_or(RunError::NoExportOutput)?;
+            sqlite::export(clients, output)?;
+            println!("Exported to {}", output.display());
+            return Ok(vec![]);
+        }
+    };
+    match output {
+        Some(output) => {
+            fs::write(output, text)?;
+            println!("Exported to {}", output.display());
+        }
+        None => print!("{}", text),
+    }
+    Ok(vec![])
+}
+
+#[derive(Debug, Error)]
+pub enum RunError {
+    #[error(
+        "No history file found; pass --file, set INVOGEN_FILE, or run \
+         `invogen init`"
+    )]
+    NoHistory,
+
+    #[error(
+        "No history file at {}; run `invogen init` or pass --file",
+        .0.display()
+    )]
+    MissingHistory(PathBuf),
+
+    #[error("Error processing event history: {source}")]
+    Event {
+        #[from]
+        source: clients::EventError,
+    },
+
+    #[error("Input Error: {source}")]
+    Input {
+        #[from]
+        source: inquire::error::InquireError,
+    },
+
+    #[error("Render Error: {source}")]
+    Render {
+        #[from]
+        source: askama::Error,
+    },
+
+    #[error("{source}")]
+    Client {
+        #[from]
+        source: ClientError,
+    },
+
+    #[error("IO Error: {source}")]
+    Io {
+        #[from]
+        source: io::Error,
+    },
+
+    #[error("Export Error: {source}")]
+    Export {
+        #[from]
+        source: toml::ser::Error,
+    },
+
+    #[error("JSON Error: {source}")]
+    Json {
+        #[from]
+        source: serde_json::Error,
+    },
+
+    #[cfg(feature = "sqlite")]
+    #[error("Export Error: {source}")]
+    Sqlite {
+        #[from]
+        source: sqlite::SqliteError,
+    },
+
+    #[cfg(feature = "sqlite")]
+    #[error("Export to a database needs an --output path")]
+    NoExportOutput,
+
+    #[error("Invoice Error: {source}")]
+    Currency {
+        #[from]
+        source: CurrencyMismatch,
+    },
+
+    #[error("Invoice Error: {source}")]
+    NewInvoice {
+        #[from]
+        source: NewInvoiceError,
+    },
+
+    #[error("Invoice item: {source}")]
+    ItemAmount {
+        #[from]
+        source: ItemAmountError,
+    },
+
+    #[error("Import Error: {source}")]
+    Import {
+        #[from]
+        source: ImportError,
+    },
+
+    #[error("{source}")]
+    LedgerStyle {
+        #[from]
+        source: LedgerStyleError,
+    },
+
+    #[error("Unknown currency '{0}', expected one of CAD, USD, or EUR")]
+    UnknownCurrency(String),
+
+    #[error("Items in {0} can't all be converted, bill one at a time")]
+    ConvertMany(String),
+
+    #[error(
+        "A deposit is a single invoice but the items are in {0} currencies, \
+         bill them separately or convert them with --currency"
+    )]
+    DepositSplit(usize),
+
+    #[error("Hours per day must be a number up to 24, got '{0}'")]
+    HoursPerDay(String),
+
+    #[error("Target hours must be a positive number, got '{0}'")]
+    TargetHours(String),
+
+    #[error("--confirm-key must be an amount, got '{0}'")]
+    ConfirmKey(String),
+
+    #[error(
+        "Unknown unit '{0}', expected month, week, day, hour, quarter, year, \
+         or a span of weeks like 2-weeks"
+    )]
+    UnknownUnit(String),
+
+    #[error("No sender for e-invoices, set one with `invogen profile sender`")]
+    NoSender,
+
+    #[error("No client has invoice {0}")]
+    NoInvoiceMatch(String),
+
+    #[error("Invoice {0} is ambiguous, clients {1} have one")]
+    AmbiguousInvoice(String, String),
+
+    #[error("Unknown language '{0}', expected one of en, fr, or de")]
+    UnknownLanguage(String),
+
+    #[error(
+        "Unknown posting date '{0}', expected issue, period-end, or \
+         period-start"
+    )]
+    UnknownPostingDate(String),
+
+    #[error("{source}")]
+    Pattern {
+        #[from]
+        source: PatternError,
+    },
+
+    #[error("{source}, pass --force to store it anyway")]
+    Registration {
+        #[from]
+        source: RegistrationError,
+    },
+
+    #[error("No registration labelled '{0}' in the profile")]
+    NoRegistration(String),
+
+    #[error("No service templates, add one with `invogen add template`")]
+    NoTemplates,
+
+    #[error("No service template named '{0}'")]
+    NoTemplate(String),
+
+    #[error("{source}")]
+    Rates {
+        #[from]
+        source: RatesError,
+    },
+
+    #[error("{source}")]
+    Config {
+        #[from]
+        source: ConfigError,
+    },
+
+    #[error("{source}")]
+    MissingRate {
+        #[from]
+        source: MissingRate,
+    },
+
+    #[error("Found {1} clients matching '{0}', expected exactly one")]
+    NoUniqueMatch(String, usize),
+
+    #[error("{source}")]
+    Key {
+        #[from]
+        source: clients::KeyError,
+    },
+
+    #[error("Invalid date, expected YYYY-MM-DD: {source}")]
+    Date {
+        #[from]
+        source: chrono::ParseError,
+    },
+
+    #[error(
+        "Invalid timestamp '{0}', expected e.g. 2021-04-15T10:30:00Z: {1}"
+    )]
+    Timestamp(String, chrono::ParseError),
+
+    #[error("Invalid --period: {source}")]
+    Period {
+        #[from]
+        source: billing::PeriodParseError,
+    },
+
+    #[error("The period {0} ends after {1}, pass --advance to bill ahead")]
+    PeriodAhead(billing::Period, NaiveDate),
+
+    #[error("Nothing was written, the history wouldn't replay: {0}")]
+    Unreplayable(Box<RunError>),
+
+    #[error("No rated services of {0} to accrue revenue for {1}")]
+    NothingToAccrue(String, billing::Period),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::billing::{Period, RoundingPolicy};
+    use crate::clients::tests::EVENTS_STR;
+    use crate::input::tests::{Answer, ScriptedPrompter};
+    use clap::Parser;
+    use serde_lexpr::from_str;
+
+    fn ymd(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    /// Runs a command with scripted answers, appending any resulting event
+    pub fn run_scripted(
+        cmd: Command,
+        events: &mut Vec<Event>,
+        answers: Vec<Answer>,
+    ) -> Result<Vec<Event>, RunError> {
+        let mut prompter = ScriptedPrompter::new(answers);
+        let rates = Path::new(exchange::RATES_FILE);
+        let new_events = run_cmd(cmd, events, &mut prompter, rates)?;
+        assert!(prompter.is_finished(), "Not all answers were used");
+        events.extend(new_events.iter().cloned());
+        Ok(new_events)
+    }
+
+    #[test]
+    fn list() -> Result<(), RunError> {
+        let history: Vec<Event> = from_str(EVENTS_STR).unwrap();
+        run_cmd(
+            Command::List {
+                listing: Listable::Clients {
+                    convert_to: None,
+                    key_only: false,
+                    porcelain: false,
+                },
+                no_color: true,
+            },
+            &history,
+            &mut ScriptedPrompter::new(vec![]),
+            Path::new(exchange::RATES_FILE),
+        )?;
+        Ok(())
+    }
+
+    #[test]
+    fn add_client_with_taxes() -> Result<(), RunError> {
+        let mut events = Vec::new();
+        let gst = vec![TaxRate::new("GST".to_string(), 5)];
+        let new_events = run_scripted(
+            Command::Add {
+                property: Addable::Client,
+                timestamp: None,
+            },
+            &mut events,
+            vec![
+                Answer::Client(
+                    "innotech".to_string(),
+                    "Innotech".to_string(),
+                    "Some Place".to_string(),
+                ),
+                Answer::Bool(true),
+                Answer::Taxes(gst.clone(), ymd(2024, 1, 1)),
+                Answer::Bool(true),
+            ],
+        )?;
+
+        assert_eq!(new_events.len(), 2);
+        assert!(matches!(new_events[0].2, Change::Added { .. }));
+        assert_eq!(
+            new_events[1].2,
+            Change::Updated(Update::Taxes(ymd(2024, 1, 1), gst.clone()))
+        );
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        assert_eq!(client.current_taxes(), gst);
+        assert_eq!(tax_list(&gst), "GST @ 5.00%");
+        assert_eq!(tax_list(&[]), "none");
+        Ok(())
+    }
+
+    #[test]
+    fn declined_confirmation_emits_nothing() -> Result<(), RunError> {
+        let mut events = Vec::new();
+        let new_events = run_scripted(
+            Command::Add {
+                property: Addable::Client,
+                timestamp: None,
+            },
+            &mut events,
+            vec![
+                Answer::Client(
+                    "innotech".to_string(),
+                    "Innotech".to_string(),
+                    "Some Place".to_string(),
+                ),
+                Answer::Bool(false),
+                Answer::Bool(false),
+            ],
+        )?;
+
+        assert!(new_events.is_empty());
+        assert!(events.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn explicit_timestamp_backdates_events() -> Result<(), RunError> {
+        let mut events = Vec::new();
+        let add = |timestamp: &str| Command::Add {
+            property: Addable::Client,
+            timestamp: Some(timestamp.to_string()),
+        };
+        let answers = vec![
+            Answer::Client(
+                "innotech".to_string(),
+                "Innotech".to_string(),
+                "Some Place".to_string(),
+            ),
+            Answer::Bool(false),
+            Answer::Bool(true),
+        ];
+        run_scripted(add("2019-02-01T09:00:00-05:00"), &mut events, answers)?;
+
+        let Event(_, timestamp, _, backdated, ..) = &events[0];
+        assert_eq!(timestamp.to_rfc3339(), "2019-02-01T14:00:00+00:00");
+        assert!(*backdated);
+        assert!(matches!(
+            run_scripted(add("2019-02-01"), &mut events, vec![]),
+            Err(RunError::Timestamp(..))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn client_to_paid_invoice() -> Result<(), RunError> {
+        let key = "innotech".to_string();
+        // New invoices are dated today, so can't be paid any earlier
+        let today = Local::now().date_naive();
+        let rate = Rate {
+            amount: Money::new(Currency::Usd, Decimal::from(1000)),
+            per: Unit::Month,
+            tiers: Vec::new(),
+        };
+        let mut events = Vec::new();
+
+        run_scripted(
+            Command::Add {
+                property: Addable::Client,
+                timestamp: None,
+            },
+            &mut events,
+            vec![
+                Answer::Client(
+                    key.clone(),
+                    "Innotech".to_string(),
+                    "Some Place".to_string(),
+                ),
+                Answer::Bool(false),
+                Answer::Bool(true),
+            ],
+        )?;
+        run_scripted(
+            Command::Add {
+                property: Addable::Service {
+                    client: key.clone(),
+                },
+                timestamp: None,
+            },
+            &mut events,
+            vec![
+                Answer::Service("Stuff".to_string(), rate, ymd(2024, 1, 1)),
+                Answer::Bool(true),
+            ],
+        )?;
+        run_scripted(
+            Command::Invoice {
+                client: key.clone(),
+                repeat_last: false,
+                deposit: false,
+                advance: false,
+                period: None,
+                weekly: false,
+                confirm_key: None,
+                currency: None,
+                preview: false,
+                timestamp: None,
+            },
+            &mut events,
+            vec![
+                Answer::Text("Stuff".to_string()),
+                Answer::Period(Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30))),
+                Answer::Bool(false),
+                Answer::Review(Review::Done),
+                Answer::Bool(true),
+            ],
+        )?;
+        run_scripted(
+            Command::MarkPaid {
+                client: key.clone(),
+                number: "1".to_string(),
+                other_currency: false,
+                timestamp: None,
+            },
+            &mut events,
+            vec![Answer::Date(today), Answer::Bool(true)],
+        )?;
+
+        assert_eq!(events.len(), 4);
+        assert!(events.iter().all(|Event(k, ..)| k == &key));
+        assert!(matches!(events[0].2, Change::Added { .. }));
+        assert!(matches!(
+            events[1].2,
+            Change::Updated(Update::ServiceRate(..))
+        ));
+        assert!(matches!(events[2].2, Change::Updated(Update::Invoiced(_))));
+        assert_eq!(events[3].2, Change::Updated(Update::Paid(1, today, None)));
+
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&key)?;
+        let invoice = client.invoice(&1)?;
+        assert_eq!(invoice.paid, Some(today));
+        assert_eq!(
+            invoice.calculate().unwrap().total,
+            Money::new(Currency::Usd, Decimal::from(1000))
+        );
+        assert_eq!(client.unpaid_invoices().count(), 0);
+        assert_eq!(client.next_invoice_num(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn mark_paid_in_other_currency() -> Result<(), RunError> {
+        let key = "innotech".to_string();
+        let mut events = paid_invoice_history()[..3].to_vec();
+        let received = Received {
+            amount: Money::new(Currency::Usd, Decimal::from(950)),
+            booked_rate: Decimal::new(136, 2),
+        };
+
+        run_scripted(
+            Command::MarkPaid {
+                client: key.clone(),
+                number: "1".to_string(),
+                other_currency: true,
+                timestamp: None,
+            },
+            &mut events,
+            vec![
+                Answer::Date(ymd(2024, 5, 20)),
+                Answer::Received(received.clone()),
+                Answer::Bool(true),
+            ],
+        )?;
+
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let invoice = clients.get(&key)?.invoice(&1)?;
+        assert_eq!(invoice.paid, Some(ymd(2024, 5, 20)));
+        assert_eq!(invoice.received, Some(received));
+        Ok(())
+    }
+
+    fn paid_invoice_history() -> Vec<Event> {
+        let rate = Rate {
+            amount: Money::new(Currency::Cad, Decimal::new(123456, 2)),
+            per: Unit::Month,
+            tiers: Vec::new(),
+        };
+        let april = Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30));
+        let mut invoice = Invoice::new(
+            1,
+            vec![InvoiceItem::new("Stuff".to_string(), rate.clone(), april)],
+            vec![TaxRate::new("GST".to_string(), 5)],
+        )
+        .unwrap();
+        invoice.date = ymd(2024, 5, 1);
+        vec![
+            Event::new(
+                "innotech",
+                Change::Added {
+                    name: "Innotech".to_string(),
+                    address: "Some Place".to_string(),
+                },
+            ),
+            Event::new_update(
+                "innotech",
+                Update::ServiceRate("Stuff".to_string(), ymd(2024, 1, 1), rate),
+            ),
+            Event::new_update("innotech", Update::Invoiced(invoice)),
+            Event::new_update(
+                "innotech",
+                Update::Paid(1, ymd(2024, 5, 20), None),
+            ),
+        ]
+    }
+
+    #[test]
+    fn invoice_index_reports_ambiguous_numbers() -> Result<(), RunError> {
+        let mut events = paid_invoice_history();
+        events.extend(paid_invoice_history()[..2].iter().map(|event| {
+            Event(
+                "bigco".to_string(),
+                event.1,
+                event.2.clone(),
+                event.3,
+                None,
+                None,
+            )
+        }));
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+
+        assert_eq!(invoice_index(&clients, None)?.len(), 1);
+        let found = invoice_index(&clients, Some("#1"))?;
+        assert_eq!(found[0].0.key, "innotech");
+        let json: serde_json::Value =
+            serde_json::from_str(&invoice_index_json(&found)?)?;
+        assert_eq!(
+            json,
+            serde_json::json!([{
+                "client": "innotech",
+                "number": "#1",
+                "date": "2024-05-01",
+                "total": "1296.29",
+                "currency": "CAD",
+            }])
+        );
+        assert!(matches!(
+            invoice_index(&clients, Some("2")),
+            Err(RunError::NoInvoiceMatch(_))
+        ));
+
+        events.extend(paid_invoice_history()[2..3].iter().map(|event| {
+            Event(
+                "bigco".to_string(),
+                event.1,
+                event.2.clone(),
+                event.3,
+                None,
+                None,
+            )
+        }));
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        assert_eq!(invoice_index(&clients, None)?.len(), 2);
+        match invoice_index(&clients, Some("1")) {
+            Err(RunError::AmbiguousInvoice(number, keys)) => {
+                assert_eq!(number, "1");
+                assert_eq!(keys, "bigco, innotech");
+            }
+            other => panic!("Expected an ambiguous invoice, got {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn posting_styles() -> Result<(), RunError> {
+        let clients =
+            Clients::from_events(paid_invoice_history().into_iter().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let invoice = client.invoice(&1)?;
+
+        assert_eq!(
+            posting(
+                invoice,
+                client,
+                &LedgerStyle::default(),
+                None,
+                false,
+                PostingDate::Issue
+            )?,
+            "2024-05-01 Innotech invoice  ; Apr 1 - 30\n    \
+             assets:receivable:Innotech    $1234.56\n    \
+             assets:receivable:GST           $61.73\n    \
+             revenues:clients:Innotech    -$1296.29\n"
+        );
+
+        let style: LedgerStyle = "after,comma,grouped,code".parse()?;
+        assert_eq!(
+            payment(
+                invoice,
+                client,
+                &style,
+                "assets:bank",
+                PostingDate::Issue
+            )?,
+            "2024-05-20 Innotech payment  ; invoice #1\n    \
+             assets:bank                    1.296,29 CAD\n    \
+             assets:receivable:Innotech    -1.234,56 CAD\n    \
+             assets:receivable:GST            -61,73 CAD\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn posting_dates() -> Result<(), RunError> {
+        let mut events = paid_invoice_history();
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let invoice = client.invoice(&1)?;
+        let style = LedgerStyle::default();
+        let legs = "    assets:receivable:Innotech    $1234.56\n    \
+                    assets:receivable:GST           $61.73\n    \
+                    revenues:clients:Innotech    -$1296.29\n";
+        let paid = "    assets:bank                    $1296.29\n    \
+                    assets:receivable:Innotech    -$1234.56\n    \
+                    assets:receivable:GST           -$61.73\n";
+        for (date, heading, payment_heading) in [
+            (
+                PostingDate::Issue,
+                "2024-05-01 Innotech invoice  ; Apr 1 - 30\n",
+                "2024-05-20 Innotech payment  ; invoice #1\n",
+            ),
+            (
+                PostingDate::PeriodEnd,
+                "2024-04-30 Innotech invoice  ; Apr 1 - 30, issued \
+                 2024-05-01\n",
+                "2024-05-20=2024-04-30 Innotech payment  ; invoice #1\n",
+            ),
+            (
+                PostingDate::PeriodStart,
+                "2024-04-01 Innotech invoice  ; Apr 1 - 30, issued \
+                 2024-05-01\n",
+                "2024-05-20=2024-04-01 Innotech payment  ; invoice #1\n",
+            ),
+        ] {
+            assert_eq!(
+                posting(invoice, client, &style, None, false, date)?,
+                format!("{}{}", heading, legs)
+            );
+            assert_eq!(
+                payment(invoice, client, &style, "assets:bank", date)?,
+                format!("{}{}", payment_heading, paid)
+            );
+        }
+
+        let set = |date: &str| Command::Set {
+            client: "innotech".to_string(),
+            property: Setable::PostingDate {
+                date: date.to_string(),
+            },
+        };
+        assert!(matches!(
+            run_scripted(set("period-middle"), &mut events, vec![]),
+            Err(RunError::UnknownPostingDate(_))
+        ));
+        run_scripted(set("Period-End"), &mut events, vec![Answer::Bool(true)])?;
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        assert_eq!(client.posting_date(), PostingDate::PeriodEnd);
+        assert_eq!(posting_date_or(None, client)?, PostingDate::PeriodEnd);
+        assert_eq!(
+            posting_date_or(Some("issue".to_string()), client)?,
+            PostingDate::Issue
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn accrual_and_its_reversal() -> Result<(), RunError> {
+        let mut events = paid_invoice_history();
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let style = LedgerStyle::default();
+
+        let may = Period::new(ymd(2024, 5, 1), ymd(2024, 5, 31));
+        assert_eq!(
+            accrual(client, &may, &style, may.until, false)?,
+            "2024-05-31 Innotech accrual  ; May 1 - 31\n    \
+             assets:accrued revenue:Innotech    $1234.56\n    \
+             revenues:clients:Innotech         -$1234.56\n"
+        );
+        let invoice = client.invoice(&1)?;
+        assert_eq!(
+            accrual(
+                client,
+                &invoice.overall_period(),
+                &style,
+                invoice.date,
+                true
+            )?,
+            "2024-05-01 Innotech accrual reversal  ; Apr 1 - 30\n    \
+             revenues:clients:Innotech           $1234.56\n    \
+             assets:accrued revenue:Innotech    -$1234.56\n"
+        );
+
+        let before = Period::new(ymd(2023, 12, 1), ymd(2023, 12, 31));
+        assert!(matches!(
+            accrual(client, &before, &style, before.until, false),
+            Err(RunError::NothingToAccrue(..))
+        ));
+        let accrue = |period: &str| Command::Accrual {
+            client: "innotech".to_string(),
+            period: period.to_string(),
+            ledger_style: None,
+        };
+        assert!(
+            run_scripted(accrue("2024-05"), &mut events, vec![])?.is_empty()
+        );
+        assert!(matches!(
+            run_scripted(accrue("May"), &mut events, vec![]),
+            Err(RunError::Period { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn posting_valued_in_another_currency() -> Result<(), RunError> {
+        let clients =
+            Clients::from_events(paid_invoice_history().into_iter().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let invoice = client.invoice(&1)?;
+        let style = LedgerStyle::default();
+        let mut valuation = Valuation {
+            currency: Currency::Usd,
+            rate: Decimal::new(73, 2),
+            price_directive: false,
+        };
+
+        assert_eq!(
+            posting(
+                invoice,
+                client,
+                &style,
+                Some(&valuation),
+                false,
+                PostingDate::Issue
+            )?,
+            "2024-05-01 Innotech invoice  ; Apr 1 - 30\n    \
+             assets:receivable:Innotech    $1234.56 @@ USD$901.23\n    \
+             assets:receivable:GST            $61.73 @@ USD$45.06\n    \
+             revenues:clients:Innotech    -$1296.29 @@ USD$946.29\n"
+        );
+
+        valuation.price_directive = true;
+        assert_eq!(
+            posting(
+                invoice,
+                client,
+                &style,
+                Some(&valuation),
+                false,
+                PostingDate::Issue
+            )?,
+            "P 2024-05-01 $ USD$0.73\n\
+             2024-05-01 Innotech invoice  ; Apr 1 - 30\n    \
+             assets:receivable:Innotech    $1234.56\n    \
+             assets:receivable:GST           $61.73\n    \
+             revenues:clients:Innotech    -$1296.29\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn posting_revenue_by_service() -> Result<(), RunError> {
+        let clients =
+            Clients::from_events(paid_invoice_history().into_iter().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let style = LedgerStyle::default();
+
+        assert_eq!(
+            posting(
+                client.invoice(&1)?,
+                client,
+                &style,
+                None,
+                true,
+                PostingDate::Issue
+            )?,
+            "2024-05-01 Innotech invoice  ; Apr 1 - 30\n    \
+             assets:receivable:Innotech          $1234.56\n    \
+             assets:receivable:GST                 $61.73\n    \
+             revenues:clients:Innotech:Stuff    -$1296.29\n"
+        );
+
+        let april = Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30));
+        let rate = |cents| Rate {
+            amount: Money::new(Currency::Cad, Decimal::new(cents, 2)),
+            per: Unit::Month,
+            tiers: Vec::new(),
+        };
+        let mut invoice = Invoice::new(
+            2,
+            vec![
+                InvoiceItem::new(
+                    "Stuff".to_string(),
+                    rate(123456),
+                    april.clone(),
+                ),
+                InvoiceItem::new("Support".to_string(), rate(100000), april),
+            ],
+            vec![TaxRate::new("GST".to_string(), 5)],
+        )?;
+        invoice.date = ymd(2024, 5, 1);
+        assert_eq!(
+            posting(&invoice, client, &style, None, true, PostingDate::Issue)?,
+            "2024-05-01 Innotech invoice  ; Apr 1 - 30\n    \
+             assets:receivable:Innotech            $2234.56\n    \
+             assets:receivable:GST                  $111.73\n    \
+             revenues:clients:Innotech:Stuff      -$1296.29\n    \
+             revenues:clients:Innotech:Support    -$1050.00\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn posting_rounding_adjustment() -> Result<(), RunError> {
+        let clients =
+            Clients::from_events(paid_invoice_history().into_iter().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let rate = Rate {
+            amount: Money::new(Currency::Cad, Decimal::new(10010, 2)),
+            per: Unit::Month,
+            tiers: Vec::new(),
+        };
+        let april = Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30));
+        let mut invoice = Invoice::new(
+            2,
+            vec![InvoiceItem::new("Stuff".to_string(), rate, april)],
+            vec![
+                TaxRate::new("GST".to_string(), 5),
+                TaxRate(
+                    "QST".to_string(),
+                    Decimal::new(9975, 5),
+                    TaxRounding::HalfEven,
+                ),
+            ],
+        )?;
+        invoice.date = ymd(2024, 5, 1);
+        invoice.rounding = RoundingPolicy::Total;
+
+        assert_eq!(
+            posting(
+                &invoice,
+                client,
+                &LedgerStyle::default(),
+                None,
+                false,
+                PostingDate::Issue
+            )?,
+            "2024-05-01 Innotech invoice  ; Apr 1 - 30\n    \
+             assets:receivable:Innotech    $100.10\n    \
+             assets:receivable:GST           $5.00\n    \
+             assets:receivable:QST           $9.98\n    \
+             assets:receivable:Innotech      $0.01\n    \
+             revenues:clients:Innotech    -$115.08\n    \
+             expenses:rounding              -$0.01\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn zero_rated_tax_not_posted() -> Result<(), RunError> {
+        let clients =
+            Clients::from_events(paid_invoice_history().into_iter().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let mut invoice = client.invoice(&1)?.clone();
+        invoice.tax_rates = vec![TaxRate::new("GST".to_string(), 0)];
+        let style = LedgerStyle::default();
+
+        assert_eq!(
+            posting(&invoice, client, &style, None, false, PostingDate::Issue)?,
+            "2024-05-01 Innotech invoice  ; Apr 1 - 30\n    \
+             assets:receivable:Innotech    $1234.56\n    \
+             revenues:clients:Innotech    -$1234.56\n"
+        );
+        assert_eq!(
+            payment(
+                &invoice,
+                client,
+                &style,
+                "assets:bank",
+                PostingDate::Issue
+            )?,
+            "2024-05-20 Innotech payment  ; invoice #1\n    \
+             assets:bank                    $1234.56\n    \
+             assets:receivable:Innotech    -$1234.56\n"
+        );
+        Ok(())
+    }
+
+    fn two_currency_history() -> Vec<Event> {
+        let usd = Rate {
+            amount: Money::new(Currency::Usd, Decimal::from(1000)),
+            per: Unit::Month,
+            tiers: Vec::new(),
+        };
+        let cad = Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(2000)),
+            per: Unit::Month,
+            tiers: Vec::new(),
+        };
+        vec![
+            Event::new(
+                "innotech",
+                Change::Added {
+                    name: "Innotech".to_string(),
+                    address: "Some Place".to_string(),
+                },
+            ),
+            Event::new_update(
+                "innotech",
+                Update::ServiceRate("Legacy".to_string(), ymd(2024, 1, 1), usd),
+            ),
+            Event::new_update(
+                "innotech",
+                Update::ServiceRate("New".to_string(), ymd(2024, 1, 1), cad),
+            ),
+        ]
+    }
+
+    fn mixed_currency_answers(split: bool) -> Vec<Answer> {
+        let april = Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30));
+        let mut answers = vec![
+            Answer::Text("Legacy".to_string()),
+            Answer::Period(april.clone()),
+            Answer::Bool(true),
+            Answer::Text("New".to_string()),
+            Answer::Period(april),
+            Answer::Bool(split),
+        ];
+        if split {
+            answers.extend([
+                Answer::Bool(false),
+                Answer::Review(Review::Done),
+                Answer::Bool(true),
+            ]);
+        }
+        answers
+    }
+
+    #[test]
+    fn mixed_currency_invoice_aborts() -> Result<(), RunError> {
+        let mut events = two_currency_history();
+        let new_events = run_scripted(
+            Command::Invoice {
+                client: "innotech".to_string(),
+                repeat_last: false,
+                deposit: false,
+                advance: false,
+                period: None,
+                weekly: false,
+                confirm_key: None,
+                currency: None,
+                preview: false,
+                timestamp: None,
+            },
+            &mut events,
+            mixed_currency_answers(false),
+        )?;
+
+        assert!(new_events.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn mixed_currency_invoice_splits() -> Result<(), RunError> {
+        let key = "innotech".to_string();
+        let mut events = two_currency_history();
+        let new_events = run_scripted(
+            Command::Invoice {
+                client: key.clone(),
+                repeat_last: false,
+                deposit: false,
+                advance: false,
+                period: None,
+                weekly: false,
+                confirm_key: None,
+                currency: None,
+                preview: false,
+                timestamp: None,
+            },
+            &mut events,
+            mixed_currency_answers(true),
+        )?;
+
+        assert_eq!(new_events.len(), 2);
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&key)?;
+        let first = client.invoice(&1)?;
+        let second = client.invoice(&2)?;
+
+        assert_eq!(first.currency(), Currency::Usd);
+        assert_eq!(first.items[0].name, "Legacy");
+        assert_eq!(second.currency(), Currency::Cad);
+        assert_eq!(second.items[0].name, "New");
+        assert_eq!(
+            second.calculate().unwrap().total,
+            Money::new(Currency::Cad, Decimal::from(2000))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn mixed_currency_deposit_is_refused() {
+        let mut events = two_currency_history();
+        let mut answers = mixed_currency_answers(true);
+        answers.pop();
+        let result = run_scripted(
+            Command::Invoice {
+                client: "innotech".to_string(),
+                repeat_last: false,
+                deposit: true,
+                advance: false,
+                period: None,
+                weekly: false,
+                confirm_key: None,
+                currency: None,
+                preview: false,
+                timestamp: None,
+            },
+            &mut events,
+            answers,
+        );
+
+        assert!(matches!(result, Err(RunError::DepositSplit(2))));
+        assert_eq!(events.len(), two_currency_history().len());
+    }
+
+    #[test]
+    fn rate_change_splits_item() -> Result<(), RunError> {
+        let key = "innotech".to_string();
+        let mut events = two_currency_history();
+        let raise = Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(3000)),
+            per: Unit::Month,
+            tiers: Vec::new(),
+        };
+        events.push(Event::new_update(
+            &key,
+            Update::ServiceRate("New".to_string(), ymd(2024, 4, 15), raise),
+        ));
+        run_scripted(
+            Command::Invoice {
+                client: key.clone(),
+                repeat_last: false,
+                deposit: false,
+                advance: false,
+                period: None,
+                weekly: false,
+                confirm_key: None,
+                currency: None,
+                preview: false,
+                timestamp: None,
+            },
+            &mut events,
+            vec![
+                Answer::Text("New".to_string()),
+                Answer::Period(Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30))),
+                Answer::Bool(true),
+                Answer::Bool(false),
+                Answer::Review(Review::Done),
+                Answer::Bool(true),
+            ],
+        )?;
+
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let invoice = clients.get(&key)?.invoice(&1)?;
+        let parts: Vec<(NaiveDate, NaiveDate, Money)> = invoice
+            .items
+            .iter()
+            .map(|i| (i.period.from, i.period.until, i.rate.amount))
+            .collect();
+
+        assert_eq!(
+            parts,
+            vec![
+                (
+                    ymd(2024, 4, 1),
+                    ymd(2024, 4, 14),
+                    Money::new(Currency::Cad, Decimal::from(2000))
+                ),
+                (
+                    ymd(2024, 4, 15),
+                    ymd(2024, 4, 30),
+                    Money::new(Currency::Cad, Decimal::from(3000))
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    fn tax_history() -> Vec<Event> {
+        let changes = [
+            (ymd(2023, 1, 1), vec![TaxRate::new("GST".to_string(), 5)]),
+            (
+                ymd(2024, 1, 1),
+                vec![
+                    TaxRate::new("GST".to_string(), 5),
+                    TaxRate::new("PST".to_string(), 7),
+                ],
+            ),
+            (ymd(2024, 7, 1), vec![TaxRate::new("HST".to_string(), 13)]),
+        ];
+        let mut events = two_currency_history();
+        events.extend(changes.into_iter().map(|(effective, taxes)| {
+            Event::new_update("innotech", Update::Taxes(effective, taxes))
+        }));
+        events
+    }
+
+    #[test]
+    fn tax_lines_in_order() -> Result<(), RunError> {
+        let clients = Clients::from_events(tax_history().into_iter().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+
+        assert_eq!(
+            tax_lines(client, None, ymd(2024, 3, 1)),
+            vec![
+                "2023-01-01\tGST\t5%\t",
+                "2024-01-01\tGST\t5%\t*",
+                "2024-01-01\tPST\t7%\t*",
+                "2024-07-01\tHST\t13%\t",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn tax_lines_as_of() -> Result<(), RunError> {
+        let clients = Clients::from_events(tax_history().into_iter().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let today = ymd(2024, 8, 1);
+
+        assert_eq!(
+            tax_lines(client, Some(ymd(2023, 12, 31)), today),
+            vec!["2023-01-01\tGST\t5%\t"]
+        );
+        assert_eq!(
+            tax_lines(client, Some(ymd(2024, 7, 1)), today),
+            vec!["2024-07-01\tHST\t13%\t*"]
+        );
+        assert!(tax_lines(client, Some(ymd(2022, 1, 1)), today).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn dry_run_leaves_history_untouched() -> Result<(), RunError> {
+        let root = temp_tree("dry-run");
+        let path = root.join(HISTORY_FILE);
+        clients::events_to_file(&path, &paid_invoice_history())?;
+        let before = fs::read(&path)?;
+        let set_account = || Command::Set {
+            client: "innotech".to_string(),
+            property: Setable::PaymentAccount {
+                account: "assets:stripe".to_string(),
+            },
+        };
+
+        let mut prompter = ScriptedPrompter::new(vec![Answer::Bool(true)]);
+        run_cmd_on_file(set_account(), &path, true, None, None, &mut prompter)?;
+        assert!(prompter.is_finished());
+        assert_eq!(fs::read(&path)?, before);
+
+        let mut prompter = ScriptedPrompter::new(vec![Answer::Bool(true)]);
+        let origin = Some("alice@laptop");
+        run_cmd_on_file(
+            set_account(),
+            &path,
+            false,
+            None,
+            origin,
+            &mut prompter,
+        )?;
+        let events = clients::events_from_file(&path)?;
+        assert_eq!(events.len(), 5);
+        assert_eq!(events[4].origin(), origin);
+        Ok(())
+    }
+
+    #[test]
+    fn unreplayable_events_are_not_written() -> Result<(), RunError> {
+        let root = temp_tree("unreplayable");
+        let path = root.join(HISTORY_FILE);
+        let events = paid_invoice_history();
+        clients::events_to_file(&path, &events)?;
+        let before = fs::read(&path)?;
+
+        // Paid again, as if made against state older than the history
+        let paid = Event::new_update(
+            "innotech",
+            Update::Paid(1, ymd(2024, 5, 21), None),
+        );
+        let clients = || load_clients(&path);
+        assert!(matches!(
+            append_events(&path, clients()?, vec![paid]),
+            Err(RunError::Unreplayable(_))
+        ));
+        assert_eq!(fs::read(&path)?, before);
+
+        let unknown =
+            Event::new_update("bigco", Update::Name("BigCo".to_string()));
+        assert!(append_events(&path, clients()?, vec![unknown]).is_err());
+        assert_eq!(fs::read(&path)?, before);
+        Ok(())
+    }
+
+    #[test]
+    fn idempotency_key_applies_once() -> Result<(), RunError> {
+        let root = temp_tree("idempotency");
+        let path = root.join(HISTORY_FILE);
+        clients::events_to_file(&path, &paid_invoice_history())?;
+        let set_account = || Command::Set {
+            client: "innotech".to_string(),
+            property: Setable::PaymentAccount {
+                account: "assets:stripe".to_string(),
+            },
+        };
+        let key = IdempotencyKey {
+            key: "stripe-setup".to_string(),
+            since: None,
+        };
+
+        for _ in 0..2 {
+            let mut prompter = ScriptedPrompter::new(vec![Answer::Bool(true)]);
+            run_cmd_on_file(
+                set_account(),
+                &path,
+                false,
+                Some(&key),
+                None,
+                &mut prompter,
+            )?;
+        }
+        let events = clients::events_from_file(&path)?;
+        assert_eq!(events.len(), 5);
+        assert_eq!(events[4].4.as_deref(), Some("stripe-setup"));
+        assert!(key.applied(events.iter().cloned().map(Ok))?);
+
+        // Keys recorded before the horizon are no longer looked for
+        let recent = IdempotencyKey {
+            since: Some(Utc::now() + Duration::days(1)),
+            ..key
+        };
+        assert!(!recent.applied(events.into_iter().map(Ok))?);
+        Ok(())
+    }
+
+    #[test]
+    fn menu_runs_chosen_commands() -> Result<(), RunError> {
+        let root = temp_tree("menu");
+        let path = root.join(HISTORY_FILE);
+        clients::events_to_file(&path, &paid_invoice_history())?;
+
+        let mut prompter = ScriptedPrompter::new(vec![
+            Answer::Text("Innotech (innotech)".to_string()),
+            Answer::Text("Set".to_string()),
+            Answer::Text("Name".to_string()),
+            Answer::Text("Innotech Inc".to_string()),
+            Answer::Bool(true),
+            Answer::Text("Innotech Inc (innotech)".to_string()),
+            // The only invoice is paid, so this returns to the client menu
+            Answer::Text("Mark paid".to_string()),
+            Answer::Cancel,
+        ]);
+        let config = Config::default();
+        let (result, lines) = logging::capture(1, || {
+            run_menu(&path, false, None, &config, &mut prompter)
+        });
+        result?;
+        assert!(prompter.is_finished());
+        // Each command runs on the clients its menu was built from
+        let reads = lines.iter().filter(|l| l.contains("Streaming events"));
+        assert_eq!(reads.count(), 2);
+
+        let events = clients::events_from_file(&path)?;
+        assert_eq!(events.len(), 5);
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        assert_eq!(clients.get(&"innotech".to_string())?.name, "Innotech Inc");
+        Ok(())
+    }
+
+    #[test]
+    fn menu_commands_use_the_config() -> Result<(), RunError> {
+        let root = temp_tree("menu-config");
+        let path = root.join(HISTORY_FILE);
+        clients::events_to_file(&path, &paid_invoice_history())?;
+        let config = Config {
+            confirm_key: Some("1000".to_string()),
+            ..Config::default()
+        };
+
+        let may = Period::new(ymd(2024, 5, 1), ymd(2024, 5, 31));
+        let mut prompter = ScriptedPrompter::new(vec![
+            Answer::Text("Innotech (innotech)".to_string()),
+            Answer::Text("Invoice".to_string()),
+            Answer::Text("Stuff".to_string()),
+            Answer::Period(may),
+            Answer::Bool(false),
+            Answer::Review(Review::Done),
+            Answer::Bool(true),
+            // The total is over the configured amount, so the key is typed
+            Answer::Text("apex".to_string()),
+            Answer::Cancel,
+        ]);
+        run_menu(&path, false, None, &config, &mut prompter)?;
+        assert!(prompter.is_finished());
+        assert_eq!(clients::events_from_file(&path)?.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn menu_adds_clients() -> Result<(), RunError> {
+        let mut prompter = ScriptedPrompter::new(vec![Answer::Text(
+            MENU_ADD_CLIENT.to_string(),
+        )]);
+        let clients = Clients::from_events(std::iter::empty())?;
+        let cmd = menu_command(&mut prompter, &clients)?;
+        assert!(matches!(
+            cmd,
+            Some(Command::Add {
+                property: Addable::Client,
+                ..
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn verbose_logs_reads_and_writes() -> Result<(), RunError> {
+        let root = temp_tree("verbose");
+        let path = root.join(HISTORY_FILE);
+        clients::events_to_file(&path, &paid_invoice_history())?;
+        let set_account = Command::Set {
+            client: "innotech".to_string(),
+            property: Setable::PaymentAccount {
+                account: "assets:stripe".to_string(),
+            },
+        };
+
+        let mut prompter = ScriptedPrompter::new(vec![Answer::Bool(true)]);
+        let (result, lines) = logging::capture(2, || {
+            run_cmd_on_file(
+                set_account,
+                &path,
+                false,
+                None,
+                None,
+                &mut prompter,
+            )
+        });
+        result?;
+
+        let logged = |start: &str| lines.iter().any(|l| l.starts_with(start));
+        assert!(logged(&format!(
+            "[info] Streaming events from {}",
+            path.display()
+        )));
+        assert!(logged("[info] Writing 1 new events"));
+        assert!(logged("[debug] Appending #(\"innotech\""));
+        assert!(logged("[debug] Renaming "));
+        Ok(())
+    }
+
+    fn temp_tree(name: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("invogen-{}", name));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        root
+    }
+
+    #[test]
+    fn history_path_precedence() -> Result<(), RunError> {
+        let root = temp_tree("precedence");
+        let cwd = root.join("a/b");
+        fs::write(root.join(HISTORY_FILE), "")?;
+        let flag = Some(PathBuf::from("flag.history"));
+        let env = HashMap::from([(
+            HISTORY_ENV.to_string(),
+            "env.history".to_string(),
+        )]);
+        let config = Config {
+            file: Some(PathBuf::from("config.history")),
+            ..Config::default()
+        };
+        let none = Config::default();
+
+        assert_eq!(
+            resolve_history_path(flag.clone(), &env, &config, &cwd)?,
+            PathBuf::from("flag.history")
+        );
+        assert_eq!(
+            resolve_history_path(None, &env, &config, &cwd)?,
+            PathBuf::from("env.history")
+        );
+        assert_eq!(
+            resolve_history_path(None, &HashMap::new(), &config, &cwd)?,
+            PathBuf::from("config.history")
+        );
+        assert_eq!(
+            resolve_history_path(None, &HashMap::new(), &none, &cwd)?,
+            root.join(HISTORY_FILE)
+        );
+
+        fs::write(root.join("a").join(HISTORY_FILE), "")?;
+        assert_eq!(
+            resolve_history_path(None, &HashMap::new(), &none, &cwd)?,
+            root.join("a").join(HISTORY_FILE)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn history_missing_from_tree() {
+        let root = temp_tree("missing");
+        let cwd = root.join("a/b");
+        // Only the temporary tree is searched, a history further up on the
+        // machine running the tests would be found
+        let tree = || cwd.ancestors().take_while(|dir| dir.starts_with(&root));
+        assert_eq!(nearest_history(tree()), None);
+
+        let path = root.join("a").join(HISTORY_FILE);
+        fs::write(&path, "").unwrap();
+        assert_eq!(nearest_history(tree()), Some(path));
+    }
+
+    #[test]
+    fn init_creates_empty_history() -> Result<(), RunError> {
+        let root = temp_tree("init");
+        let path = root.join(HISTORY_FILE);
+
+        init_history(&path)?;
+        assert!(clients::events_from_file(&path)?.is_empty());
+        assert!(init_history(&path).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn init_sets_up_sender_and_client() -> Result<(), RunError> {
+        let path = temp_tree("init-set-up").join(HISTORY_FILE);
+        init_history(&path)?;
+        let mut prompter = ScriptedPrompter::new(vec![
+            Answer::Bool(true),
+            Answer::Text("Smith & Sons".to_string()),
+            Answer::Text("1 Main St".to_string()),
+            Answer::Bool(true),
+            Answer::Client(
+                "innotech".to_string(),
+                "Innotech".to_string(),
+                "Some Place".to_string(),
+            ),
+            Answer::Bool(false),
+            Answer::Bool(true),
+        ]);
+        set_up_history(&path, None, &mut prompter)?;
+        assert!(prompter.is_finished(), "Not all answers were used");
+
+        let events = clients::events_from_file(&path)?;
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        assert_eq!(
+            clients.profile.sender,
+            Some(("Smith & Sons".to_string(), "1 Main St".to_string()))
+        );
+        assert!(clients.get(&"innotech".to_string()).is_ok());
+
+        assert!(next_steps(Path::new(HISTORY_FILE))
+            .contains("  invogen add client\n"));
+        assert!(next_steps(&path).contains(&format!(
+            "  invogen --file {} invoice <client>\n",
+            path.display()
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn missing_history_file_by_command() {
+        let root = temp_tree("missing-file");
+        let path = root.join(HISTORY_FILE);
+        let parse = |args: &[&str]| {
+            Opts::try_parse_from([&["invogen"][..], args].concat())
+                .unwrap_or_else(|e| panic!("{}", e))
+                .subcommand
+                .expect("a command")
+        };
+        let reading = [
+            parse(&["list", "clients"]),
+            parse(&["show", "foo"]),
+            parse(&["due"]),
+            parse(&["profile"]),
+        ];
+        let changing = [
+            parse(&["invoice", "foo"]),
+            parse(&["add", "service", "foo"]),
+            parse(&["profile", "sender"]),
+            parse(&["mark-paid", "foo", "1"]),
+        ];
+        for cmd in reading.iter().chain(changing.iter()) {
+            let error = require_history(cmd, &path).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!(
+                    "No history file at {}; run `invogen init` or pass --file",
+                    path.display()
+                )
+            );
+        }
+        assert!(require_history(&parse(&["add", "client"]), &path).is_ok());
+
+        fs::write(&path, "").unwrap();
+        assert!(require_history(&parse(&["show", "foo"]), &path).is_ok());
+    }
+
+    #[test]
+    fn unknown_invoice_lists_known_numbers() {
+        let history = paid_invoice_history();
+        let cli = Opts::try_parse_from([
+            "invogen", "show", "innotech", "invoice", "#9",
+        ])
+        .unwrap_or_else(|e| panic!("{}", e));
+        let error = run_cmd(
+            cli.subcommand.expect("a command"),
+            &history,
+            &mut ScriptedPrompter::new(vec![]),
+            Path::new(exchange::RATES_FILE),
+        )
+        .unwrap_err();
+
+        assert_eq!(error.to_string(), "Invoice #9 not found, invoices are #1");
+    }
+
+    #[test]
+    fn invoice_status_colors() -> Result<(), RunError> {
+        let events = paid_invoice_history();
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+
+        assert_eq!(
+            invoices_table(client, ymd(2024, 8, 1))
+                .unwrap()
+                .render(true),
+            "Number  Date        Sent  Period                   Total         \
+             Status\n\
+             #1      2024-05-01        2024-04-01 — 2024-04-30  CAD $1296.29  \
+             \x1b[32mpaid 2024-05-20\x1b[0m\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn porcelain_listings() -> Result<(), RunError> {
+        let events = paid_invoice_history();
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+
+        assert_eq!(
+            clients_table(&clients, None).unwrap().porcelain(),
+            "innotech\tInnotech\t\t2024-04-30\n"
+        );
+        assert_eq!(
+            invoices_table(client, ymd(2024, 8, 1)).unwrap().porcelain(),
+            "#1\t2024-05-01\t\t2024-04-01 — 2024-04-30\tCAD $1296.29\t\
+             paid 2024-05-20\n"
+        );
+        assert_eq!(
+            services_table(client, ymd(2024, 8, 1)).porcelain(),
+            "Stuff\tCAD $1234.56/Month\t2024-01-01\t\t\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rate_history_with_changes() -> Result<(), RunError> {
+        let mut client = Client::new("innotech", "Innotech", "Some Place");
+        let rates = [
+            (ymd(2023, 1, 1), 10000, Unit::Hour),
+            (ymd(2023, 7, 1), 11000, Unit::Hour),
+            (ymd(2024, 1, 1), 80000, Unit::Day),
+            (ymd(2024, 6, 1), 76050, Unit::Day),
+        ];
+        for (effective, cents, per) in rates {
+            let amount = Money::new(Currency::Cad, Decimal::new(cents, 2));
+            client.update(&Update::ServiceRate(
+                "Consulting".to_string(),
+                effective,
+                Rate {
+                    amount,
+                    per,
+                    tiers: Vec::new(),
+                },
+            ))?;
+        }
+        let service = client.service("Consulting".to_string()).unwrap();
+
+        assert_eq!(
+            rate_history_table(service).porcelain(),
+            "2023-01-01\tCAD $100.00\tHour\t\n\
+             2023-07-01\tCAD $110.00\tHour\t+10.0%\n\
+             2024-01-01\tCAD $800.00\tDay\t→\n\
+             2024-06-01\tCAD $760.50\tDay\t-4.9%\n"
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&rate_history_json(&client)?)?;
+        let rows = json.as_array().unwrap();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(
+            rows[1],
+            serde_json::json!({
+                "service": "Consulting",
+                "effective": "2023-07-01",
+                "amount": "110.00",
+                "currency": "CAD",
+                "unit": "Hour",
+                "change": "10.0",
+            })
+        );
+        // No change between a rate per hour and per day
+        assert!(rows[2].get("change").is_none());
+        assert_eq!(rows[3]["change"], "-4.9");
+        Ok(())
+    }
+
+    #[test]
+    fn payment_totals_per_year() {
+        let payment = |number, date, amount: i64, currency| PaymentRecord {
+            number,
+            date,
+            amount: Money::new(currency, Decimal::from(amount)),
+        };
+        let payments = vec![
+            payment(1, ymd(2024, 6, 1), 100, Currency::Cad),
+            payment(3, ymd(2024, 12, 20), 50, Currency::Eur),
+            payment(2, ymd(2024, 12, 30), 20, Currency::Cad),
+            payment(4, ymd(2025, 1, 10), 100, Currency::Cad),
+        ];
+
+        assert_eq!(
+            payment_totals(&payments),
+            vec![
+                (2024, "CAD $120.00, EUR €50.00".to_string()),
+                (2025, "CAD $100.00".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn highlight_matched_text() {
+        let client = Client::new("muller", "Müller & Söhne", "München");
+        let found = search::find_clients([&client].into_iter(), "sohne");
+
+        let highlighted =
+            |color| highlight(found[0].text, found[0].chars.clone(), color);
+        assert_eq!(highlighted(true), "Müller & \x1b[33mSöhne\x1b[0m");
+        assert_eq!(highlighted(false), "Müller & Söhne");
+    }
+
+    #[test]
+    fn client_details_in_sections() -> Result<(), RunError> {
+        let clients =
+            Clients::from_events(paid_invoice_history().into_iter().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        assert_eq!(
+            client_details(client, &clients.profile, ymd(2024, 6, 1)).unwrap(),
+            "innotech\n\
+             \nIdentity\n\
+             \x20 Key: innotech\n\
+             \x20 Name: Innotech\n\
+             \x20 billing address:\n\
+             \x20   Some Place\n\
+             \nServices\n\
+             \x20 Stuff: CAD $1234.56/Month since 2024-01-01\n\
+             \nTaxes\n\
+             \x20 none\n\
+             \nBilling\n\
+             \x20 Billed until: 2024-04-30\n\
+             \x20 Outstanding: none\n\
+             \nRecent invoices\n\
+             \x20 #1 2024-05-01 CAD $1296.29 paid 2024-05-20\n"
+        );
+
+        let mut events = paid_invoice_history();
+        events.pop();
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let details =
+            client_details(client, &clients.profile, ymd(2024, 5, 2)).unwrap();
+        assert!(
+            details.contains("  Outstanding: CAD $1296.29 (1 invoice: #1)\n")
+        );
+        assert!(details.contains("  #1 2024-05-01 CAD $1296.29 unpaid\n"));
+
+        let clients = Clients::from_events(
+            paid_invoice_history().into_iter().take(1).map(Ok),
+        )?;
+        let client = clients.get(&"innotech".to_string())?;
+        let details =
+            client_details(client, &clients.profile, ymd(2024, 6, 1)).unwrap();
+        assert!(details.contains("\nServices\n  none\n"));
+        assert!(details.contains("  Billed until: none\n"));
+        assert!(details.ends_with("\nRecent invoices\n  none\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn invoice_paths_follow_the_profile_pattern() -> Result<(), RunError> {
+        let mut events = paid_invoice_history();
+        let path = || Command::Path {
+            client: "innotech".to_string(),
+            number: "#1".to_string(),
+        };
+        run_scripted(path(), &mut events, vec![])?;
+
+        let set_pattern = |pattern: &str| Command::Profile {
+            property: Some(ProfileSetable::InvoicePath {
+                pattern: pattern.to_string(),
+            }),
+        };
+        assert!(matches!(
+            run_scripted(
+                set_pattern("{year}/{client}.pdf"),
+                &mut events,
+                vec![]
+            ),
+            Err(RunError::Pattern { .. })
+        ));
+        run_scripted(
+            set_pattern("{year}/{name}-{number}.pdf"),
+            &mut events,
+            vec![Answer::Bool(true)],
+        )?;
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let invoice = client.find_invoice("1")?;
+        let pattern = clients.profile.invoice_path_pattern();
+        assert_eq!(
+            paths::invoice_path(pattern, invoice, client)?,
+            PathBuf::from("2024/innotech-001.pdf")
+        );
+
+        let output = temp_tree("latex-output").join("out/invoice.tex");
+        run_scripted(
+            Command::Show {
+                client: "innotech".to_string(),
+                property: Some(Showable::Invoice {
+                    number: "1".to_string(),
+                    flat: false,
+                    view: Some(InvoiceView::Latex {
+                        output: Some(Some(output.clone())),
+                    }),
+                }),
+            },
+            &mut events,
+            vec![],
+        )?;
+        assert!(fs::read_to_string(output)?.contains("Innotech"));
+        Ok(())
+    }
+
+    #[test]
+    fn similar_service_names() -> Result<(), RunError> {
+        let mut events = paid_invoice_history();
+        let monthly = |amount| Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(amount)),
+            per: Unit::Month,
+            tiers: Vec::new(),
+        };
+        let add_service = || Command::Add {
+            property: Addable::Service {
+                client: "innotech".to_string(),
+            },
+            timestamp: None,
+        };
+        let result = run_scripted(
+            add_service(),
+            &mut events,
+            vec![Answer::Service(
+                "stuff".to_string(),
+                monthly(1500),
+                ymd(2024, 6, 1),
+            )],
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Client Error: Service 'stuff' differs from the existing 'Stuff' \
+             only by case or spacing, use that name instead"
+        );
+
+        let normalize = || Command::NormalizeServices {
+            client: "innotech".to_string(),
+        };
+        assert!(run_scripted(normalize(), &mut events, vec![])?.is_empty());
+
+        // Histories from before names were checked
+        events.push(Event::new_update(
+            "innotech",
+            Update::ServiceRate(
+                "stuff  ".to_string(),
+                ymd(2024, 6, 1),
+                monthly(1500),
+            ),
+        ));
+        let merged =
+            run_scripted(normalize(), &mut events, vec![Answer::Bool(true)])?;
+        assert_eq!(merged.len(), 1);
+        assert!(matches!(
+            &merged[0].2,
+            Change::Updated(Update::ServicesMerged(from, into))
+                if from == "stuff  " && into == "Stuff"
+        ));
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        assert_eq!(client.service_names(), vec!["Stuff"]);
+        assert_eq!(client.services["Stuff"].rates.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn onboarding_from_a_service_template() -> Result<(), RunError> {
+        let mut events = paid_invoice_history();
+        let rate = |amount, per| Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(amount)),
+            per,
+            tiers: Vec::new(),
+        };
+        let add = |property| Command::Add {
+            property,
+            timestamp: None,
+        };
+        run_scripted(
+            add(Addable::Template),
+            &mut events,
+            vec![
+                Answer::Text("standard".to_string()),
+                Answer::ServiceRate(
+                    "Hosting".to_string(),
+                    rate(50, Unit::Month),
+                ),
+                Answer::Bool(true),
+                Answer::ServiceRate(
+                    "Support".to_string(),
+                    rate(90, Unit::Hour),
+                ),
+                Answer::Bool(false),
+                Answer::TaxRates(vec![TaxRate::new("HST".to_string(), 13)]),
+                Answer::Bool(true),
+            ],
+        )?;
+        run_scripted(
+            add(Addable::Client),
+            &mut events,
+            vec![
+                Answer::Client(
+                    "acme".to_string(),
+                    "Acme".to_string(),
+                    "1 Road".to_string(),
+                ),
+                Answer::Bool(false),
+                Answer::Bool(true),
+            ],
+        )?;
+        let added = run_scripted(
+            add(Addable::FromTemplate {
+                client: "acme".to_string(),
+            }),
+            &mut events,
+            vec![
+                Answer::Text("standard".to_string()),
+                Answer::Date(ymd(2024, 6, 1)),
+                Answer::Bool(true),
+            ],
+        )?;
+        assert_eq!(added.len(), 3);
+        assert!(added.iter().all(|Event(key, ..)| key == "acme"));
+
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let acme = clients.get(&"acme".to_string())?;
+        assert_eq!(acme.service_names(), vec!["Hosting", "Support"]);
+        assert_eq!(
+            acme.taxes_as_of(ymd(2024, 6, 1)),
+            vec![TaxRate::new("HST".to_string(), 13)]
+        );
+        assert_eq!(
+            clients_table(&clients, None)
+                .unwrap()
+                .porcelain()
+                .lines()
+                .count(),
+            2
+        );
+
+        run_scripted(
+            Command::RemoveTemplate {
+                name: "standard".to_string(),
+            },
+            &mut events,
+            vec![Answer::Bool(true)],
+        )?;
+        let error = run_scripted(
+            add(Addable::FromTemplate {
+                client: "acme".to_string(),
+            }),
+            &mut events,
+            vec![],
+        )
+        .unwrap_err();
+        assert!(matches!(error, RunError::NoTemplates));
+        Ok(())
+    }
+
+    #[test]
+    fn zero_and_negative_items() -> Result<(), RunError> {
+        let mut events = paid_invoice_history();
+        let rate = |amount, per| Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(amount)),
+            per,
+            tiers: Vec::new(),
+        };
+        for (name, rate) in [
+            ("Onsite", rate(500, Unit::Day)),
+            ("Support", rate(80, Unit::Hour)),
+        ] {
+            events.push(Event::new_update(
+                "innotech",
+                Update::ServiceRate(name.to_string(), ymd(2024, 1, 1), rate),
+            ));
+        }
+        let invoice = || Command::Invoice {
+            client: "innotech".to_string(),
+            repeat_last: false,
+            deposit: false,
+            advance: false,
+            period: None,
+            weekly: false,
+            confirm_key: None,
+            currency: None,
+            preview: false,
+            timestamp: None,
+        };
+        let may = Period::new(ymd(2024, 5, 1), ymd(2024, 5, 31));
+        let weekend = Period::new(ymd(2024, 5, 4), ymd(2024, 5, 5));
+
+        let error = run_scripted(
+            invoice(),
+            &mut events,
+            vec![
+                Answer::Text("Support".to_string()),
+                Answer::Period(may.clone()),
+                Answer::Number(Decimal::from(-8)),
+            ],
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invoice item: the quantity -8 is negative"
+        );
+
+        let error = run_scripted(
+            invoice(),
+            &mut events,
+            vec![
+                Answer::Text("Onsite".to_string()),
+                Answer::Period(weekend.clone()),
+                Answer::Gap(Gap::Leave),
+                Answer::Bool(true),
+                Answer::Bool(false),
+                Answer::Review(Review::Done),
+            ],
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            RunError::NewInvoice {
+                source: NewInvoiceError::Subtotal(_)
+            }
+        ));
+
+        run_scripted(
+            invoice(),
+            &mut events,
+            vec![
+                Answer::Text("Stuff".to_string()),
+                Answer::Period(may),
+                Answer::Bool(true),
+                Answer::Text("Onsite".to_string()),
+                Answer::Period(weekend),
+                Answer::Gap(Gap::Leave),
+                Answer::Bool(true),
+                Answer::Bool(false),
+                Answer::Review(Review::Done),
+                Answer::Bool(true),
+            ],
+        )?;
+        let Some(Event(_, _, Change::Updated(Update::Invoiced(invoice)), ..)) =
+            events.last()
+        else {
+            panic!("expected an invoice");
+        };
+        assert_eq!(invoice.items.len(), 2);
+        assert!(invoice.items[1].amount.amount().is_zero());
+        Ok(())
+    }
+
+    #[test]
+    fn preview_before_confirming() -> Result<(), RunError> {
+        let mut events = paid_invoice_history();
+        let invoice = Command::Invoice {
+            client: "innotech".to_string(),
+            repeat_last: false,
+            deposit: false,
+            advance: false,
+            period: Some("2024-05".to_string()),
+            weekly: false,
+            confirm_key: None,
+            currency: None,
+            preview: true,
+            timestamp: None,
+        };
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let before = clients.get(&"innotech".to_string())?.clone();
+
+        let added = run_scripted(
+            invoice,
+            &mut events,
+            vec![
+                Answer::Text("Stuff".to_string()),
+                Answer::Bool(false),
+                Answer::Review(Review::Done),
+                Answer::Text("text".to_string()),
+                Answer::Text("latex".to_string()),
+                Answer::Text("posting".to_string()),
+                Answer::Cancel,
+                Answer::Bool(true),
+            ],
+        )?;
+        let [Event(_, _, Change::Updated(Update::Invoiced(previewed)), ..)] =
+            added.as_slice()
+        else {
+            panic!("expected an invoice");
+        };
+
+        // The views of the invoice before it was recorded are the ones
+        // shown for it afterwards
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let shown = client.invoice(&2)?;
+        for view in &PREVIEWS[1..] {
+            assert_eq!(
+                invoice_preview(view, previewed, &before, &clients.profile)?,
+                invoice_preview(view, shown, client, &clients.profile)?
+            );
+        }
+        assert_eq!(
+            invoice_preview("posting", shown, client, &clients.profile)?,
+            posting(
+                shown,
+                client,
+                &clients.profile.ledger_style,
+                None,
+                false,
+                client.posting_date()
+            )?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn invoice_for_a_given_period() -> Result<(), RunError> {
+        let mut events = paid_invoice_history();
+        let invoice = |period: &str| Command::Invoice {
+            client: "innotech".to_string(),
+            repeat_last: false,
+            deposit: false,
+            advance: false,
+            period: Some(period.to_string()),
+            weekly: false,
+            confirm_key: None,
+            currency: None,
+            preview: false,
+            timestamp: None,
+        };
+
+        let error =
+            run_scripted(invoice("2024-13"), &mut events, vec![]).unwrap_err();
+        assert!(matches!(error, RunError::Period { .. }));
+        let error =
+            run_scripted(invoice("2099-01"), &mut events, vec![]).unwrap_err();
+        assert!(matches!(error, RunError::PeriodAhead(..)));
+
+        run_scripted(
+            invoice("2024-05"),
+            &mut events,
+            vec![
+                Answer::Text("Stuff".to_string()),
+                Answer::Bool(false),
+                Answer::Review(Review::Done),
+                Answer::Bool(true),
+            ],
+        )?;
+        let Some(Event(_, _, Change::Updated(Update::Invoiced(invoice)), ..)) =
+            events.last()
+        else {
+            panic!("expected an invoice");
+        };
+        assert_eq!(
+            invoice.items[0].period,
+            Period::new(ymd(2024, 5, 1), ymd(2024, 5, 31))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn gap_before_period_can_be_covered() -> Result<(), RunError> {
+        let key = "innotech".to_string();
+        let mut events = paid_invoice_history();
+        let invoice = || Command::Invoice {
+            client: key.clone(),
+            repeat_last: false,
+            deposit: false,
+            advance: false,
+            period: None,
+            weekly: false,
+            confirm_key: None,
+            currency: None,
+            preview: false,
+            timestamp: None,
+        };
+        let june = Period::new(ymd(2024, 6, 1), ymd(2024, 6, 30));
+
+        let aborted = run_scripted(
+            invoice(),
+            &mut events,
+            vec![
+                Answer::Text("Stuff".to_string()),
+                Answer::Period(june.clone()),
+                Answer::Gap(Gap::Abort),
+            ],
+        )?;
+        assert!(aborted.is_empty());
+
+        run_scripted(
+            invoice(),
+            &mut events,
+            vec![
+                Answer::Text("Stuff".to_string()),
+                Answer::Period(june),
+                Answer::Gap(Gap::Extend),
+                Answer::Bool(false),
+                Answer::Review(Review::Done),
+                Answer::Bool(true),
+            ],
+        )?;
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let invoice = clients.get(&key)?.invoice(&2)?;
+        assert_eq!(
+            invoice.items[0].period,
+            Period::new(ymd(2024, 5, 1), ymd(2024, 6, 30))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn invoice_in_another_currency() -> Result<(), RunError> {
+        let key = "innotech".to_string();
+        let mut events = paid_invoice_history();
+        let invoice = |currency: &str| Command::Invoice {
+            client: key.clone(),
+            repeat_last: false,
+            deposit: false,
+            advance: false,
+            period: None,
+            weekly: false,
+            confirm_key: None,
+            currency: Some(currency.to_string()),
+            preview: false,
+            timestamp: None,
+        };
+        assert!(matches!(
+            run_scripted(invoice("XYZ"), &mut events, vec![]),
+            Err(RunError::UnknownCurrency(_))
+        ));
+
+        let may = Period::new(ymd(2024, 5, 1), ymd(2024, 5, 31));
+        run_scripted(
+            invoice("EUR"),
+            &mut events,
+            vec![
+                Answer::Text("Stuff".to_string()),
+                Answer::Period(may),
+                Answer::Bool(false),
+                Answer::Review(Review::Done),
+                Answer::Number(Decimal::new(68, 2)),
+                Answer::Bool(true),
+            ],
+        )?;
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&key)?;
+        let invoice = client.invoice(&2)?;
+        assert_eq!(invoice.currency(), Currency::Eur);
+        assert_eq!(invoice.fx, Some((Currency::Cad, Decimal::new(68, 2))));
+        assert_eq!(invoice.items[0].amount.to_string(), "EUR €839.50");
+        assert_eq!(invoice.items[0].rate.amount.currency(), Currency::Cad);
+        assert!(audit::audit_invoice(client, invoice, Default::default())
+            .is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn rates_shown_in_other_units() -> Result<(), RunError> {
+        let monthly = Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(8000)),
+            per: Unit::Month,
+            tiers: Vec::new(),
+        };
+        assert_eq!(
+            rate_equivalents(&monthly, Decimal::from(8), ymd(2024, 4, 15))
+                .unwrap(),
+            "CAD $8000.00/Month ≈ CAD $45.45/Hour, CAD $363.64/Day, \
+             CAD $1818.18/Week, assuming 22 working days × 8h"
+        );
+
+        let mut events = paid_invoice_history();
+        let set_hours = |hours: &str| Command::Profile {
+            property: Some(ProfileSetable::HoursPerDay {
+                hours: hours.to_string(),
+            }),
+        };
+        for invalid in ["0", "25", "eight"] {
+            assert!(matches!(
+                run_scripted(set_hours(invalid), &mut events, vec![]),
+                Err(RunError::HoursPerDay(_))
+            ));
+        }
+        run_scripted(set_hours("7.5"), &mut events, vec![Answer::Bool(true)])?;
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        assert_eq!(clients.profile.hours_per_day(), Decimal::new(75, 1));
+        Ok(())
+    }
+
+    #[test]
+    fn registrations_validated_unless_forced() -> Result<(), RunError> {
+        let mut events = paid_invoice_history();
+        let set = |label: &str, number: Option<&str>, force| Command::Profile {
+            property: Some(ProfileSetable::Registration {
+                label: label.to_string(),
+                number: number.map(String::from),
+                force,
+            }),
+        };
+        let yes = || vec![Answer::Bool(true)];
+        let registrations = |events: &[Event]| {
+            Clients::from_events(events.iter().cloned().map(Ok))
+                .unwrap()
+                .profile
+                .registrations
+        };
+
+        run_scripted(
+            set("GST/HST", Some("123 456 782 RT0001"), false),
+            &mut events,
+            yes(),
+        )?;
+        assert!(matches!(
+            run_scripted(
+                set("VAT", Some("DE136695977"), false),
+                &mut events,
+                vec![]
+            ),
+            Err(RunError::Registration {
+                source: RegistrationError::Checksum(_)
+            })
+        ));
+        run_scripted(
+            set("VAT", Some("DE136695977"), true),
+            &mut events,
+            yes(),
+        )?;
+        assert_eq!(
+            registrations(&events),
+            vec![
+                ("GST/HST".to_string(), "123456782RT0001".to_string()),
+                ("VAT".to_string(), "DE136695977".to_string()),
+            ]
+        );
+
+        run_scripted(
+            set("VAT", Some("DE136695976"), false),
+            &mut events,
+            yes(),
+        )?;
+        run_scripted(set("GST/HST", None, false), &mut events, yes())?;
+        assert_eq!(
+            registrations(&events),
+            vec![("VAT".to_string(), "DE136695976".to_string())]
+        );
+        assert!(matches!(
+            run_scripted(set("QST", None, false), &mut events, vec![]),
+            Err(RunError::NoRegistration(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn advance_invoices_end_after_today() -> Result<(), RunError> {
+        let clients =
+            Clients::from_events(paid_invoice_history().into_iter().map(Ok))?;
+        let invoice = clients.get(&"innotech".to_string())?.invoice(&1)?;
+        assert!(bills_in_advance(invoice, ymd(2024, 4, 15)));
+        assert!(!bills_in_advance(invoice, ymd(2024, 4, 30)));
+        Ok(())
+    }
+
+    #[test]
+    fn repeat_last_invoice() -> Result<(), RunError> {
+        let key = "innotech".to_string();
+        let mut events = paid_invoice_history();
+        run_scripted(
+            Command::Invoice {
+                client: key.clone(),
+                repeat_last: true,
+                deposit: false,
+                advance: false,
+                period: None,
+                weekly: false,
+                confirm_key: None,
+                currency: None,
+                preview: false,
+                timestamp: None,
+            },
+            &mut events,
+            vec![Answer::Bool(true)],
+        )?;
+
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let invoice = clients.get(&key)?.invoice(&2)?;
+        assert_eq!(
+            invoice.items[0].period,
+            Period::new(ymd(2024, 5, 1), ymd(2024, 5, 31))
+        );
+        assert_eq!(invoice.items[0].quantity, Decimal::ONE);
+        Ok(())
+    }
+
+    #[test]
+    fn repeat_last_skips_voided_and_deposit_invoices() -> Result<(), RunError> {
+        let key = "innotech".to_string();
+        let mut events = paid_invoice_history();
+        let rate = Rate {
+            amount: Money::new(Currency::Cad, Decimal::new(50000, 2)),
+            per: Unit::Month,
+            tiers: Vec::new(),
+        };
+        let june = Period::new(ymd(2024, 6, 1), ymd(2024, 6, 30));
+        let mut voided = Invoice::new(
+            2,
+            vec![InvoiceItem::new(
+                "Stuff".to_string(),
+                rate.clone(),
+                june.clone(),
+            )],
+            Vec::new(),
+        )
+        .unwrap();
+        voided.date = ymd(2024, 6, 1);
+        let mut deposit = Invoice::new(
+            3,
+            vec![InvoiceItem::new("Stuff".to_string(), rate, june)],
+            Vec::new(),
+        )
+        .unwrap();
+        deposit.date = ymd(2024, 6, 2);
+        deposit.kind = InvoiceKind::Deposit;
+        events.extend([
+            Event::new_update(&key, Update::Invoiced(voided)),
+            Event::new_update(&key, Update::Voided(2, ymd(2024, 6, 2), None)),
+            Event::new_update(&key, Update::Invoiced(deposit)),
+        ]);
+        run_scripted(
+            Command::Invoice {
+                client: key.clone(),
+                repeat_last: true,
+                deposit: false,
+                advance: false,
+                period: None,
+                weekly: false,
+                confirm_key: None,
+                currency: None,
+                preview: false,
+                timestamp: None,
+            },
+            &mut events,
+            vec![Answer::Bool(true)],
+        )?;
+
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let invoice = clients.get(&key)?.invoice(&4)?;
+        assert_eq!(
+            invoice.items[0].period,
+            Period::new(ymd(2024, 5, 1), ymd(2024, 5, 31))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn global_invoice_numbering() -> Result<(), RunError> {
+        let mut events = paid_invoice_history();
+        let acme: Vec<Event> = events
+            .iter()
+            .cloned()
+            .map(|mut event| {
+                event.0 = "acme".to_string();
+                event
+            })
+            .collect();
+        events.extend(acme);
+        let repeat = |client: &str| Command::Invoice {
+            client: client.to_string(),
+            repeat_last: true,
+            deposit: false,
+            advance: false,
+            period: None,
+            weekly: false,
+            confirm_key: None,
+            currency: None,
+            preview: false,
+            timestamp: None,
+        };
+        let numbering = |enabled| Command::Profile {
+            property: Some(ProfileSetable::GlobalNumbering { enabled }),
+        };
+        let numbers = |events: &[Event], key: &str| {
+            let clients =
+                Clients::from_events(events.iter().cloned().map(Ok)).unwrap();
+            let client = clients.get(&key.to_string()).unwrap();
+            client.invoices().map(|i| i.number).collect::<Vec<_>>()
+        };
+
+        run_scripted(numbering(true), &mut events, vec![Answer::Bool(true)])?;
+        for client in ["innotech", "acme", "innotech"] {
+            run_scripted(
+                repeat(client),
+                &mut events,
+                vec![Answer::Bool(true)],
+            )?;
+        }
+        assert_eq!(numbers(&events, "innotech"), vec![1, 2, 4]);
+        assert_eq!(numbers(&events, "acme"), vec![1, 3]);
+        let mut shared = Clients::from_events(events.iter().cloned().map(Ok))?;
+        assert_eq!(shared.profile.global_numbering, Some(2));
+        assert_eq!(audit::shared_numbering(&shared, 2), vec![]);
+
+        // Numbers taken by another client are rejected
+        let acme = shared.get(&"acme".to_string())?;
+        let mut taken = acme.invoice(&3)?.clone();
+        taken.number = 4;
+        assert!(matches!(
+            shared.update(&"acme".to_string(), &Update::Invoiced(taken)),
+            Err(ClientError::Invoice(
+                4,
+                clients::InvoiceError::OutOfSequence(5)
+            ))
+        ));
+
+        run_scripted(numbering(false), &mut events, vec![Answer::Bool(true)])?;
+        run_scripted(repeat("acme"), &mut events, vec![Answer::Bool(true)])?;
+        assert_eq!(numbers(&events, "acme"), vec![1, 3, 4]);
+        Ok(())
+    }
+
+    #[test]
+    fn log_filters_by_origin() {
+        let mut events = paid_invoice_history();
+        events[2].5 = Some("alice@laptop".to_string());
+        events[3].5 = Some("bob@desktop".to_string());
+
+        let lines = log_lines(&events, None);
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].ends_with("\tinnotech\tAdded\t(unknown origin)"));
+        let bob = log_lines(&events, Some("bob@desktop"));
+        assert_eq!(bob.len(), 1);
+        assert!(bob[0].starts_with("4:\t"), "{}", bob[0]);
+        assert!(
+            bob[0].ends_with("\tUpdated Paid\tbob@desktop"),
+            "{}",
+            bob[0]
+        );
+        assert_eq!(log_lines(&events, Some("carol")), Vec::<String>::new());
+        assert_eq!(
+            invoice_origin(&events, "innotech", 1),
+            Some("alice@laptop")
+        );
+    }
+
+    #[test]
+    fn origin_from_environment() {
+        let env = |vars: &[(&str, &str)]| -> HashMap<String, String> {
+            vars.iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            event_origin(&env(&[
+                ("INVOGEN_ORIGIN", "office"),
+                ("USER", "alice"),
+            ])),
+            Some("office".to_string())
+        );
+        assert_eq!(
+            event_origin(&env(&[
+                ("INVOGEN_ORIGIN", " "),
+                ("USER", "alice"),
+                ("HOSTNAME", "laptop"),
+            ])),
+            Some("alice@laptop".to_string())
+        );
+    }
+
+    #[test]
+    fn confirm_key_above_amount() -> Result<(), RunError> {
+        let repeat = |above: Option<&str>| Command::Invoice {
+            client: "innotech".to_string(),
+            repeat_last: true,
+            deposit: false,
+            advance: false,
+            period: None,
+            weekly: false,
+            confirm_key: above.map(String::from),
+            currency: None,
+            preview: false,
+            timestamp: None,
+        };
+        let typed = |key: &str| Answer::Text(key.to_string());
+
+        // The total of CAD $1296.29 is under the amount, no key is asked
+        let mut events = paid_invoice_history();
+        run_scripted(
+            repeat(Some("2,000")),
+            &mut events,
+            vec![Answer::Bool(true)],
+        )?;
+        assert_eq!(events.len(), 5);
+
+        let mut events = paid_invoice_history();
+        let answers = vec![Answer::Bool(true), typed("apex")];
+        run_scripted(repeat(Some("1000")), &mut events, answers)?;
+        assert_eq!(events.len(), 4);
+        let answers = vec![Answer::Bool(true), typed("innotech")];
+        run_scripted(repeat(Some("1000")), &mut events, answers)?;
+        assert_eq!(events.len(), 5);
+
+        for invalid in ["lots", "-5", "1.2.3"] {
+            assert!(matches!(
+                run_scripted(repeat(Some(invalid)), &mut events, vec![]),
+                Err(RunError::ConfirmKey(_))
+            ));
+        }
+        let target = confirm_target("innotech", "Innotech", Some("$1"));
+        assert!(target.contains("[innotech]"), "{}", target);
+        assert!(target.ends_with(" Innotech: $1"), "{}", target);
+        Ok(())
+    }
+
+    #[test]
+    fn add_client_rejects_taken_key() {
+        let mut events = paid_invoice_history();
+        let result = run_scripted(
+            Command::Add {
+                property: Addable::Client,
+                timestamp: None,
+            },
+            &mut events,
+            vec![Answer::Client(
+                "innotech".to_string(),
+                "Innotech Again".to_string(),
+                "Elsewhere".to_string(),
+            )],
+        );
+
+        assert!(matches!(
+            result,
+            Err(RunError::Key {
+                source: clients::KeyError::Taken(_)
+            })
+        ));
+    }
+
+    #[test]
+    fn invoice_details_paid_and_unpaid() -> Result<(), RunError> {
+        let mut events = paid_invoice_history();
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let invoice = client.invoice(&1)?;
+        let body = "Invoice: #1\n\
+                    Date: 2024-05-01\n\n\
+                    Stuff 2024-04-01 — 2024-04-30, 1.00 @ CAD $1234.56/Month: \
+                    CAD $1234.56\n\n\n\
+                    Subtotal: CAD $1234.56\n\
+                    GST @ 5.00%: CAD $61.73\n\n\
+                    Total: CAD $1296.29\n\n\
+                    Period: 2024-04-01 — 2024-04-30\n";
+
+        assert_eq!(
+            invoice_details(invoice, client, ymd(2024, 6, 7), false),
+            format!("{}Status: PAID 2024-05-20", body)
+        );
+
+        events.pop();
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        assert_eq!(
+            invoice_details(
+                client.invoice(&1)?,
+                client,
+                ymd(2024, 6, 7),
+                false
+            ),
+            format!(
+                "{}Status: UNPAID, 37 days outstanding, due 2024-05-31\n\
+                 Ledger posting: invogen show innotech invoice 1 posting",
+                body
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn sent_date_starts_the_terms() -> Result<(), RunError> {
+        let mut events = paid_invoice_history();
+        events.pop();
+        let mark_sent = || Command::MarkSent {
+            client: "innotech".to_string(),
+            number: "#1".to_string(),
+            timestamp: None,
+        };
+        run_scripted(
+            mark_sent(),
+            &mut events,
+            vec![Answer::Date(ymd(2024, 5, 6)), Answer::Bool(true)],
+        )?;
+        assert!(matches!(
+            run_scripted(mark_sent(), &mut events, vec![]),
+            Err(RunError::Client {
+                source: ClientError::Invoice(
+                    1,
+                    clients::InvoiceError::AlreadySent(_)
+                )
+            })
+        ));
+
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let invoice = client.invoice(&1)?;
+        assert!(invoice_details(invoice, client, ymd(2024, 6, 7), false)
+            .contains("Status: UNPAID, 32 days outstanding, due 2024-06-05"));
+        assert_eq!(
+            invoices_table(client, ymd(2024, 6, 4)).unwrap().porcelain(),
+            "#1\t2024-05-01\t2024-05-06\t2024-04-01 — 2024-04-30\t\
+             CAD $1296.29\tunpaid\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn grep_structured_and_raw() -> Result<(), RunError> {
+        let mut events = paid_invoice_history();
+        events.push(Event::new_update(
+            "innotech",
+            Update::NamedAddress(
+                "registered".to_string(),
+                "99 Gateway Migration Ave".to_string(),
+            ),
+        ));
+        let prefixes = |lines: &[GrepLine]| -> Vec<String> {
+            lines.iter().map(|line| line.prefix.clone()).collect()
+        };
+
+        let found = grep_lines(&events, "gateway migration", false)?;
+        let time = events[4].1.format("%Y-%m-%d %H:%M");
+        assert_eq!(
+            prefixes(&found),
+            [format!("innotech\t{}\taddress: ", time)]
+        );
+        assert_eq!(
+            found[0].excerpt,
+            ("99 Gateway Migration Ave".to_string(), 3..20)
+        );
+        let found = grep_lines(&events, "STUFF", false)?;
+        assert!(found[0].prefix.ends_with("\tservice: "));
+        assert!(found[1].prefix.ends_with("\titem: "));
+
+        let found = grep_lines(&events, "gateway migration", true)?;
+        assert_eq!(prefixes(&found), ["5:\t"]);
+        let (excerpt, chars) = &found[0].excerpt;
+        assert!(excerpt.starts_with('…'));
+        assert_eq!(
+            excerpt
+                .chars()
+                .skip(chars.start)
+                .take(chars.len())
+                .collect::<String>(),
+            "Gateway Migration"
+        );
+        assert!(grep_lines(&events, "nowhere", true)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn reminders_only_for_overdue_invoices() -> Result<(), RunError> {
+        let mut events = paid_invoice_history();
+        let remind = |events: &[Event], today| -> Result<String, RunError> {
+            let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+            let client = clients.get(&"innotech".to_string())?;
+            reminder(
+                client.invoice(&1)?,
+                client,
+                &clients.profile,
+                ReminderLevel::Second,
+                ReminderFormat::Text,
+                today,
+            )
+        };
+        assert!(matches!(
+            remind(&events, ymd(2024, 7, 1)),
+            Err(RunError::Client {
+                source: ClientError::Invoice(
+                    1,
+                    clients::InvoiceError::AlreadyPaid
+                )
+            })
+        ));
+        events.pop();
+        assert!(matches!(
+            remind(&events, ymd(2024, 5, 31)),
+            Err(RunError::Client {
+                source: ClientError::Invoice(
+                    1,
+                    clients::InvoiceError::NotOverdue(due)
+                )
+            }) if due == ymd(2024, 5, 31)
+        ));
+        let text = remind(&events, ymd(2024, 6, 1))?;
+        assert!(text.contains("was due on 2024-05-31, 1 day ago"));
+        Ok(())
+    }
+
+    #[test]
+    fn disputed_invoices_are_not_overdue() -> Result<(), RunError> {
+        let mut events = paid_invoice_history();
+        events.pop();
+        let dispute = || Command::Dispute {
+            client: "innotech".to_string(),
+            number: 1,
+        };
+        let resolve = || Command::ResolveDispute {
+            client: "innotech".to_string(),
+            number: 1,
+        };
+        run_scripted(
+            dispute(),
+            &mut events,
+            vec![
+                Answer::Dispute(ymd(2024, 6, 3), Some("Hours".to_string())),
+                Answer::Bool(true),
+            ],
+        )?;
+        assert!(matches!(
+            run_scripted(dispute(), &mut events, vec![]),
+            Err(RunError::Client {
+                source: ClientError::Invoice(
+                    1,
+                    clients::InvoiceError::AlreadyDisputed(_)
+                )
+            })
+        ));
+
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let invoice = client.invoice(&1)?;
+        assert!(invoice_details(invoice, client, ymd(2024, 7, 1), false)
+            .contains("\nDISPUTED 2024-06-03, Hours"));
+        assert_eq!(
+            invoices_table(client, ymd(2024, 7, 1)).unwrap().porcelain(),
+            "#1\t2024-05-01\t\t2024-04-01 — 2024-04-30\t\
+             CAD $1296.29\tDISPUTED\n"
+        );
+        let root = temp_tree("disputed-ical");
+        let ics = root.join("due.ics");
+        export_ical(&clients, &ics, false, false)?;
+        assert!(!fs::read_to_string(&ics)?.contains("invoice-innotech-1"));
+        export_ical(&clients, &ics, false, true)?;
+        assert!(fs::read_to_string(&ics)?.contains("invoice-innotech-1"));
+
+        run_scripted(
+            resolve(),
+            &mut events,
+            vec![Answer::Date(ymd(2024, 6, 10)), Answer::Bool(true)],
+        )?;
+        assert!(matches!(
+            run_scripted(resolve(), &mut events, vec![]),
+            Err(RunError::Client {
+                source: ClientError::Invoice(
+                    1,
+                    clients::InvoiceError::NotDisputed
+                )
+            })
+        ));
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        assert!(invoices_table(client, ymd(2024, 7, 1))
+            .unwrap()
+            .porcelain()
+            .ends_with("\toverdue\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn unbilled_services() -> Result<(), RunError> {
+        let key = "innotech".to_string();
+        let mut events = paid_invoice_history();
+        let rate = Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(1000)),
+            per: Unit::Month,
+            tiers: Vec::new(),
+        };
+        events.push(Event::new_update(
+            &key,
+            Update::ServiceRate("Support".to_string(), ymd(2024, 3, 1), rate),
+        ));
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&key)?;
+
+        let names: Vec<String> = unbilled(client, ymd(2024, 5, 20))
+            .into_iter()
+            .map(|item| item.name)
+            .collect();
+        assert_eq!(names, vec!["Support"]);
+
+        let items = unbilled(client, ymd(2024, 7, 3));
+        let gaps: Vec<(&str, Period, Money)> = items
+            .iter()
+            .map(|i| (i.name.as_str(), i.period.clone(), i.amount))
+            .collect();
+        assert_eq!(
+            gaps,
+            vec![
+                (
+                    "Stuff",
+                    Period::new(ymd(2024, 5, 1), ymd(2024, 6, 30)),
+                    Money::new(Currency::Cad, Decimal::new(246912, 2))
+                ),
+                (
+                    "Support",
+                    Period::new(ymd(2024, 3, 1), ymd(2024, 6, 30)),
+                    Money::new(Currency::Cad, Decimal::from(4000))
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fully_billed_client_is_up_to_date() -> Result<(), RunError> {
+        let clients =
+            Clients::from_events(paid_invoice_history().into_iter().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+
+        assert!(unbilled(client, ymd(2024, 5, 20)).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn invoices_keep_address_as_issued() -> Result<(), RunError> {
+        let key = "innotech".to_string();
+        let mut events = paid_invoice_history();
+        run_scripted(
+            Command::Set {
+                client: key.clone(),
+                property: Setable::Address { name: None },
+            },
+            &mut events,
+            vec![
+                Answer::Text("New Place".to_string()),
+                Answer::Date(ymd(2024, 6, 1)),
+                Answer::Bool(true),
+            ],
+        )?;
+        let mut clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let mut second = clients.get(&key)?.invoice(&1)?.clone();
+        second.number = 2;
+        second.date = ymd(2024, 6, 3);
+        clients.update(&key, &Update::Invoiced(second))?;
+
+        let client = clients.get(&key)?;
+        let profile = Profile::default();
+        let first = templates::render_invoice(
+            client.invoice(&1)?,
+            client,
+            &profile,
+            false,
+        )?;
+        let second = templates::render_invoice(
+            client.invoice(&2)?,
+            client,
+            &profile,
+            false,
+        )?;
+
+        assert!(first.contains("Some Place"));
+        assert!(!first.contains("New Place"));
+        assert!(second.contains("New Place"));
+        Ok(())
+    }
+
+    #[test]
+    fn invoices_show_the_chosen_address() -> Result<(), RunError> {
+        let key = "innotech".to_string();
+        let mut events = paid_invoice_history();
+        let invoice_address = |name: &str| Command::Set {
+            client: key.clone(),
+            property: Setable::InvoiceAddress {
+                name: name.to_string(),
+            },
+        };
+        assert!(matches!(
+            run_scripted(invoice_address("registered"), &mut events, vec![]),
+            Err(RunError::Client {
+                source: ClientError::NoAddress(_)
+            })
+        ));
+        run_scripted(
+            Command::Set {
+                client: key.clone(),
+                property: Setable::Address {
+                    name: Some("registered".to_string()),
+                },
+            },
+            &mut events,
+            vec![
+                Answer::Text("9 Registered Way".to_string()),
+                Answer::Bool(true),
+            ],
+        )?;
+        run_scripted(
+            invoice_address("registered"),
+            &mut events,
+            vec![Answer::Bool(true)],
+        )?;
+
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&key)?;
+        assert_eq!(client.current_address(), "Some Place");
+        let today = Local::now().date_naive();
+        assert_eq!(client.invoice_address(today), "9 Registered Way");
+        // An invoice issued before the address was chosen is reprinted as
+        // it was sent
+        let rendered = templates::render_invoice(
+            client.invoice(&1)?,
+            client,
+            &Profile::default(),
+            false,
+        )?;
+        assert!(rendered.contains("Some Place"));
+        assert!(!rendered.contains("9 Registered Way"));
+        Ok(())
+    }
+
+    #[test]
+    fn import_legacy_invoices() -> Result<(), RunError> {
+        let root = temp_tree("import");
+        let path = root.join("legacy.csv");
+        fs::write(
+            &path,
+            "number,date,service,from,until,quantity,amount,paid_date\n\
+             2,2024-06-01,Stuff,2024-05-01,2024-05-31,1,1200.00,2024-06-15\n\
+             3,2024-07-02,Stuff,2024-06-01,2024-06-30,1,1200.00,\n",
+        )?;
+        let mut events = paid_invoice_history();
+        let new_events = run_scripted(
+            Command::ImportInvoices {
+                client: "innotech".to_string(),
+                path,
+            },
+            &mut events,
+            vec![Answer::Bool(true)],
+        )?;
+
+        assert_eq!(new_events.len(), 3);
+        assert!(new_events.iter().all(|event| event.is_backdated()));
+        assert_eq!(new_events[1].1.to_rfc3339(), "2024-06-15T00:00:00+00:00");
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let imported = client.invoice(&2)?;
+        assert_eq!(imported.date, ymd(2024, 6, 1));
+        assert_eq!(imported.paid, Some(ymd(2024, 6, 15)));
+        assert_eq!(
+            imported.items[0].amount,
+            Money::new(Currency::Cad, Decimal::from(1200))
+        );
+        assert_eq!(client.invoice(&3)?.paid, None);
+        assert_eq!(client.next_invoice_num(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn default_hours_prefill_invoice() -> Result<(), RunError> {
+        let key = "innotech".to_string();
+        let hourly = Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(100)),
+            per: Unit::Hour,
+            tiers: Vec::new(),
+        };
+        let mut events = vec![
+            Event::new(
+                &key,
+                Change::Added {
+                    name: "Innotech".to_string(),
+                    address: "Some Place".to_string(),
+                },
+            ),
+            Event::new_update(
+                &key,
+                Update::ServiceRate(
+                    "Retainer".to_string(),
+                    ymd(2024, 1, 1),
+                    hourly,
+                ),
+            ),
+        ];
+
+        run_scripted(
+            Command::Set {
+                client: key.clone(),
+                property: Setable::DefaultHours,
+            },
+            &mut events,
+            vec![
+                Answer::Text("Retainer".to_string()),
+                Answer::Number(Decimal::from(40)),
+                Answer::Bool(true),
+            ],
+        )?;
+        run_scripted(
+            Command::Invoice {
+                client: key.clone(),
+                repeat_last: false,
+                deposit: false,
+                advance: false,
+                period: None,
+                weekly: false,
+                confirm_key: None,
+                currency: None,
+                preview: false,
+                timestamp: None,
+            },
+            &mut events,
+            vec![
+                Answer::Text("Retainer".to_string()),
+                Answer::Period(Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30))),
+                Answer::Default,
+                Answer::Bool(false),
+                Answer::Review(Review::Done),
+                Answer::Bool(true),
+            ],
+        )?;
+
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&key)?;
+        assert_eq!(
+            client
+                .service("Retainer".to_string())
+                .unwrap()
+                .default_quantity,
+            Some(Decimal::from(40))
+        );
+        let item = &client.invoice(&1)?.items[0];
+        assert_eq!(item.quantity, Decimal::from(40));
+        assert_eq!(item.amount, Money::new(Currency::Cad, Decimal::from(4000)));
+        Ok(())
+    }
+
+    #[test]
+    fn retainer_purchase_drawdown_and_overage() -> Result<(), RunError> {
+        let key = "innotech".to_string();
+        let hourly = Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(100)),
+            per: Unit::Hour,
+            tiers: Vec::new(),
+        };
+        let april = Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30));
+        let mut events = vec![
+            Event::new(
+                &key,
+                Change::Added {
+                    name: "Innotech".to_string(),
+                    address: "Some Place".to_string(),
+                },
+            ),
+            Event::new_update(
+                &key,
+                Update::ServiceRate(
+                    "Support".to_string(),
+                    ymd(2024, 1, 1),
+                    hourly,
+                ),
+            ),
+        ];
+        run_scripted(
+            Command::Add {
+                property: Addable::Retainer {
+                    client: key.clone(),
+                },
+                timestamp: None,
+            },
+            &mut events,
+            vec![
+                Answer::Text("Support".to_string()),
+                Answer::Period(april.clone()),
+                Answer::Number(Decimal::from(20)),
+                Answer::Bool(true),
+            ],
+        )?;
+        let invoice_hours = |hours: i64| {
+            vec![
+                Answer::Text("Support".to_string()),
+                Answer::Period(april.clone()),
+                Answer::Number(Decimal::from(hours)),
+                Answer::Bool(true),
+                Answer::Bool(false),
+                Answer::Review(Review::Done),
+                Answer::Bool(true),
+            ]
+        };
+        let invoice = || Command::Invoice {
+            client: key.clone(),
+            repeat_last: false,
+            deposit: false,
+            advance: false,
+            period: None,
+            weekly: false,
+            confirm_key: None,
+            currency: None,
+            preview: false,
+            timestamp: None,
+        };
+
+        let covered = run_scripted(invoice(), &mut events, invoice_hours(15))?;
+        assert_eq!(
+            covered.iter().map(|e| &e.2).collect::<Vec<_>>(),
+            vec![&Change::Updated(Update::RetainerConsumed(
+                "Support".to_string(),
+                ymd(2024, 4, 1),
+                Decimal::from(15)
+            ))]
+        );
+
+        let overage = run_scripted(invoice(), &mut events, invoice_hours(10))?;
+        assert_eq!(overage.len(), 2);
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&key)?;
+        let item = &client.invoice(&1)?.items[0];
+        assert_eq!(item.quantity, Decimal::from(5));
+        assert_eq!(item.amount, Money::new(Currency::Cad, Decimal::from(500)));
+
+        let retainer =
+            client.retainer_for("Support", ymd(2024, 4, 15)).unwrap();
+        assert_eq!(retainer.consumed, Decimal::from(20));
+        assert_eq!(retainer.remaining(), Decimal::ZERO);
+        assert!(client.retainer_for("Support", ymd(2024, 5, 1)).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn line_diffs() {
+        let lines = |text: &str| -> Vec<String> {
+            text.lines().map(String::from).collect()
+        };
+        let diff = diff_lines(&lines("a\nb\nc"), &lines("a\nx\nc\nd"));
+        assert_eq!(diff, vec!["  a", "- b", "+ x", "  c", "+ d"]);
+        assert_eq!(diff_lines(&[], &lines("a")), vec!["+ a"]);
+    }
+
+    #[test]
+    fn describe_name_and_address_changes() -> Result<(), RunError> {
+        let clients =
+            Clients::from_events(paid_invoice_history().into_iter().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+
+        assert_eq!(
+            describe_change(client, &Update::Name("Innotech Inc".to_string())),
+            "Innotech (innotech)\nName: Innotech → Innotech Inc"
+        );
+        assert_eq!(
+            describe_change(
+                client,
+                &Update::EffectiveAddress(
+                    ymd(2024, 6, 1),
+                    "Other Place\nSuite 4".to_string()
+                )
+            ),
+            "Innotech address effective 2024-06-01:\n\
+             - Some Place\n\
+             + Other Place\n\
+             + Suite 4"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn describe_rate_changes() -> Result<(), RunError> {
+        let clients =
+            Clients::from_events(paid_invoice_history().into_iter().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let monthly = |amount| Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(amount)),
+            per: Unit::Month,
+            tiers: Vec::new(),
+        };
+
+        assert_eq!(
+            describe_change(
+                client,
+                &Update::ServiceRate(
+                    "Stuff".to_string(),
+                    ymd(2024, 6, 1),
+                    monthly(1500)
+                )
+            ),
+            "Innotech Stuff rate effective 2024-06-01: CAD $1234.56/Month \
+             since 2024-01-01 → CAD $1500.00/Month\n\
+             Invoices billing Stuff: #1"
+        );
+        assert_eq!(
+            describe_change(
+                client,
+                &Update::ServiceRate(
+                    "Design".to_string(),
+                    ymd(2024, 6, 1),
+                    monthly(800)
+                )
+            ),
+            "Innotech Design rate effective 2024-06-01: (none) → \
+             CAD $800.00/Month"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn describe_tax_changes() -> Result<(), RunError> {
+        let mut events = paid_invoice_history();
+        let gst = TaxRate::new("GST".to_string(), 5);
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let update = Update::Taxes(ymd(2024, 7, 1), vec![gst.clone()]);
+        assert_eq!(
+            describe_change(client, &update),
+            "Innotech taxes effective 2024-07-01:\n+ GST @ 5.00%"
+        );
+
+        events.push(Event::new_update("innotech", update));
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let hst = TaxRate::new("HST".to_string(), 13);
+        assert_eq!(
+            describe_change(client, &Update::Taxes(ymd(2025, 1, 1), vec![hst])),
+            "Innotech taxes effective 2025-01-01:\n\
+             - GST @ 5.00%\n\
+             + HST @ 13.00%"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn stale_rate_needs_confirmation() -> Result<(), RunError> {
+        let key = "innotech".to_string();
+        let mut events = paid_invoice_history()[..2].to_vec();
+        let invoice = || Command::Invoice {
+            client: key.clone(),
+            repeat_last: false,
+            deposit: false,
+            advance: false,
+            period: None,
+            weekly: false,
+            confirm_key: None,
+            currency: None,
+            preview: false,
+            timestamp: None,
+        };
+        let february = Period::new(ymd(2025, 2, 1), ymd(2025, 2, 28));
+
+        let declined = run_scripted(
+            invoice(),
+            &mut events,
+            vec![
+                Answer::Text("Stuff".to_string()),
+                Answer::Period(february.clone()),
+                Answer::Bool(false),
+            ],
+        )?;
+        assert!(declined.is_empty());
+
+        run_scripted(
+            Command::Set {
+                client: key.clone(),
+                property: Setable::StaleRateAge { months: 24 },
+            },
+            &mut events,
+            vec![Answer::Bool(true)],
+        )?;
+        let invoiced = run_scripted(
+            invoice(),
+            &mut events,
+            vec![
+                Answer::Text("Stuff".to_string()),
+                Answer::Period(february),
+                Answer::Bool(false),
+                Answer::Review(Review::Done),
+                Answer::Bool(true),
+            ],
+        )?;
+        assert_eq!(invoiced.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn review_edits_and_removes_items() -> Result<(), RunError> {
+        let key = "innotech".to_string();
+        let mut events = paid_invoice_history()[..2].to_vec();
+        let month = |m, last| Period::new(ymd(2024, m, 1), ymd(2024, m, last));
+        run_scripted(
+            Command::Invoice {
+                client: key.clone(),
+                repeat_last: false,
+                deposit: false,
+                advance: false,
+                period: None,
+                weekly: false,
+                confirm_key: None,
+                currency: None,
+                preview: false,
+                timestamp: None,
+            },
+            &mut events,
+            vec![
+                Answer::Text("Stuff".to_string()),
+                Answer::Period(month(4, 30)),
+                Answer::Bool(true),
+                Answer::Text("Stuff".to_string()),
+                Answer::Period(month(5, 31)),
+                Answer::Bool(false),
+                Answer::Review(Review::Edit(0)),
+                Answer::Text("Stuff".to_string()),
+                Answer::Period(month(3, 31)),
+                Answer::Review(Review::Remove(1)),
+                Answer::Review(Review::Add),
+                Answer::Text("Stuff".to_string()),
+                Answer::Period(month(6, 30)),
+                Answer::Review(Review::Done),
+                Answer::Bool(true),
+            ],
+        )?;
+
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let invoice = clients.get(&key)?.invoice(&1)?;
+        let periods: Vec<&Period> =
+            invoice.items.iter().map(|i| &i.period).collect();
+        assert_eq!(periods, vec![&month(3, 31), &month(6, 30)]);
+        Ok(())
+    }
+
+    #[test]
+    fn final_invoice_deducts_paid_deposit() -> Result<(), RunError> {
+        let key = "innotech".to_string();
+        let mut events = paid_invoice_history()[..2].to_vec();
+        let invoice = |deposit| Command::Invoice {
+            client: key.clone(),
+            repeat_last: false,
+            deposit,
+            advance: false,
+            period: None,
+            weekly: false,
+            confirm_key: None,
+            currency: None,
+            preview: false,
+            timestamp: None,
+        };
+        let item = |period| {
+            vec![
+                Answer::Text("Stuff".to_string()),
+                Answer::Period(period),
+                Answer::Bool(false),
+                Answer::Review(Review::Done),
+            ]
+        };
+        let mut answers = item(Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30)));
+        answers.push(Answer::Bool(true));
+        run_scripted(invoice(true), &mut events, answers)?;
+        run_scripted(
+            Command::MarkPaid {
+                client: key.clone(),
+                number: "1".to_string(),
+                other_currency: false,
+                timestamp: None,
+            },
+            &mut events,
+            vec![Answer::Date(Local::now().date_naive()), Answer::Bool(true)],
+        )?;
+
+        let mut answers = item(Period::new(ymd(2024, 5, 1), ymd(2024, 5, 31)));
+        answers.extend([Answer::Numbers(vec![1]), Answer::Bool(true)]);
+        run_scripted(invoice(false), &mut events, answers)?;
+
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&key)?;
+        let deposit = client.invoice(&1)?;
+        assert_eq!(deposit.kind, InvoiceKind::Deposit);
+        let total = client.invoice(&2)?.calculate().unwrap();
+        assert_eq!(
+            total.deductions,
+            vec![(1, -deposit.calculate().unwrap().total)]
+        );
+        assert_eq!(total.total, Money::new(Currency::Cad, Decimal::ZERO));
+        assert_eq!(client.applied_to(1), Some(2));
+        Ok(())
+    }
+
+    #[test]
+    fn void_then_invoice_continues_numbering() -> Result<(), RunError> {
+        let key = "innotech".to_string();
+        let mut events = paid_invoice_history()[..2].to_vec();
+        let invoice = || Command::Invoice {
+            client: key.clone(),
+            repeat_last: false,
+            deposit: false,
+            advance: false,
+            period: None,
+            weekly: false,
+            confirm_key: None,
+            currency: None,
+            preview: false,
+            timestamp: None,
+        };
+        let april = || {
+            vec![
+                Answer::Text("Stuff".to_string()),
+                Answer::Period(Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30))),
+                Answer::Bool(false),
+                Answer::Review(Review::Done),
+                Answer::Bool(true),
+            ]
+        };
+        run_scripted(invoice(), &mut events, april())?;
+        run_scripted(
+            Command::Void {
+                client: key.clone(),
+                number: 1,
+            },
+            &mut events,
+            vec![
+                Answer::Void(ymd(2024, 5, 3), Some("Cancelled".to_string())),
+                Answer::Bool(true),
+            ],
+        )?;
+        run_scripted(invoice(), &mut events, april())?;
+
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&key)?;
+        assert!(client.invoice(&1)?.voided.is_some());
+        assert!(client.invoice(&2)?.is_open());
+        let unpaid: Vec<&usize> = client.unpaid_invoices().collect();
+        assert_eq!(unpaid, vec![&2]);
+        Ok(())
+    }
+
+    #[test]
+    fn voiding_paid_invoice_is_rejected() {
+        let mut events = paid_invoice_history();
+        let result = run_scripted(
+            Command::Void {
+                client: "innotech".to_string(),
+                number: 1,
+            },
+            &mut events,
+            vec![],
+        );
+        assert!(matches!(
+            result,
+            Err(RunError::Client {
+                source: ClientError::Invoice(
+                    1,
+                    clients::InvoiceError::PaidNotVoidable
+                )
+            })
+        ));
+    }
+
+    #[test]
+    fn written_off_invoice_bad_debt_posting() -> Result<(), RunError> {
+        let mut events = paid_invoice_history()[..3].to_vec();
+        run_scripted(
+            Command::WriteOff {
+                client: "innotech".to_string(),
+                number: 1,
+            },
+            &mut events,
+            vec![
+                Answer::WriteOff(
+                    ymd(2024, 6, 30),
+                    Some("Bankrupt".to_string()),
+                ),
+                Answer::Bool(true),
+            ],
+        )?;
+
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let invoice = client.invoice(&1)?;
+        assert_eq!(
+            bad_debt(invoice, client, &LedgerStyle::default())?,
+            "2024-06-30 Innotech bad debt  ; invoice #1\n    \
+             expenses:bad debt:Innotech     $1296.29\n    \
+             assets:receivable:Innotech    -$1234.56\n    \
+             assets:receivable:GST           -$61.73\n"
+        );
+        assert!(invoice_details(invoice, client, ymd(2024, 7, 1), false)
+            .contains("Status: WRITTEN OFF 2024-06-30, Bankrupt"));
+        assert!(matches!(
+            payment(
+                invoice,
+                client,
+                &LedgerStyle::default(),
+                "assets:bank",
+                PostingDate::Issue,
+            ),
+            Err(RunError::Client {
+                source: ClientError::Invoice(1, clients::InvoiceError::NotPaid)
+            })
+        ));
+        Ok(())
+    }
+
+    fn paid_in_usd(amount: i64) -> Result<String, RunError> {
+        let mut events = paid_invoice_history()[..3].to_vec();
+        let received = Received {
+            amount: Money::new(Currency::Usd, Decimal::new(amount, 2)),
+            booked_rate: Decimal::new(75, 2),
+        };
+        events.push(Event::new_update(
+            "innotech",
+            Update::Paid(1, ymd(2024, 5, 20), Some(received)),
+        ));
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let style = LedgerStyle::default();
+        let invoice = client.invoice(&1)?;
+        let date = PostingDate::Issue;
+        payment(invoice, client, &style, "assets:bank", date)
+    }
+
+    #[test]
+    fn payment_in_another_currency() -> Result<(), RunError> {
+        assert_eq!(
+            paid_in_usd(98000)?,
+            "2024-05-20 Innotech payment  ; invoice #1\n    \
+             assets:bank                                USD$980.00\n    \
+             assets:receivable:Innotech    -$1234.56 @@ USD$925.92\n    \
+             assets:receivable:GST            -$61.73 @@ USD$46.30\n    \
+             income:fx gain                              -USD$7.78\n"
+        );
+        assert_eq!(
+            paid_in_usd(97000)?,
+            "2024-05-20 Innotech payment  ; invoice #1\n    \
+             assets:bank                                USD$970.00\n    \
+             assets:receivable:Innotech    -$1234.56 @@ USD$925.92\n    \
+             assets:receivable:GST            -$61.73 @@ USD$46.30\n    \
+             expenses:fx loss                             USD$2.22\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unbilled_converted_to_reporting_currency() -> Result<(), RunError> {
+        let clients =
+            Clients::from_events(two_currency_history().into_iter().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let items = unbilled(client, ymd(2024, 3, 5));
+        let rates = ExchangeRates::parse("[USD.CAD]\n2024-01-01 = 1.35\n")?;
+
+        let converted =
+            converted_unbilled(client, &items, &rates, Currency::Cad);
+        assert_eq!(
+            converted.total,
+            Money::new(Currency::Cad, Decimal::from(6700))
+        );
+        assert_eq!(
+            converted.rates,
+            vec![(Currency::Usd, ymd(2024, 1, 1), Decimal::new(135, 2))]
+        );
+
+        let converted =
+            converted_unbilled(client, &items, &rates, Currency::Eur);
+        assert_eq!(converted.missing.len(), 2);
+        assert_eq!(converted.missing[0].0, "innotech Legacy");
+
+        assert!(matches!(
+            reporting_currency(
+                Some("XYZ".to_string()),
+                Path::new("rates.toml")
+            ),
+            Err(RunError::UnknownCurrency(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn ubl_needs_sender() -> Result<(), RunError> {
+        let mut events = paid_invoice_history();
+        let show_ubl = || Command::Show {
+            client: "innotech".to_string(),
+            property: Some(Showable::Invoice {
+                number: "1".to_string(),
+                flat: false,
+                view: Some(InvoiceView::Ubl),
+            }),
+        };
+        assert!(matches!(
+            run_scripted(show_ubl(), &mut events, vec![]),
+            Err(RunError::NoSender)
+        ));
+
+        run_scripted(
+            Command::Profile {
+                property: Some(ProfileSetable::Sender),
+            },
+            &mut events,
+            vec![
+                Answer::Text("Smith Consulting".to_string()),
+                Answer::Text("1 Main St\nVancouver".to_string()),
+                Answer::Bool(true),
+            ],
+        )?;
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        assert_eq!(
+            clients.profile.sender,
+            Some((
+                "Smith Consulting".to_string(),
+                "1 Main St\nVancouver".to_string()
+            ))
+        );
+        run_scripted(show_ubl(), &mut events, vec![])?;
+        Ok(())
+    }
+
+    #[test]
+    fn scheduled_invoices_due() -> Result<(), RunError> {
+        let mut events = paid_invoice_history();
+        let schedule = |service: &str| Command::Set {
+            client: "innotech".to_string(),
+            property: Setable::Schedule {
+                service: service.to_string(),
+                day: 31,
+            },
+        };
+        assert!(matches!(
+            run_scripted(schedule("Other"), &mut events, vec![]),
+            Err(RunError::Client {
+                source: ClientError::NoService(_)
+            })
+        ));
+        run_scripted(schedule("Stuff"), &mut events, vec![Answer::Bool(true)])?;
+
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        // April was billed, May's invoice is scheduled on the last of June
+        assert_eq!(due(client, ymd(2024, 5, 30)), vec![]);
+        assert_eq!(due(client, ymd(2024, 6, 29)), vec![]);
+        let may = billing::Period::new(ymd(2024, 5, 1), ymd(2024, 5, 31));
+        assert_eq!(
+            due(client, ymd(2024, 6, 30)),
+            vec![Due {
+                service: "Stuff".to_string(),
+                scheduled: ymd(2024, 6, 30),
+                period: may.clone(),
+            }]
+        );
+
+        let mut prompter = ScriptedPrompter::new(vec![Answer::Bool(true)]);
+        let generated =
+            due_invoices(&mut prompter, &clients, ymd(2024, 7, 2), true, None)?;
+        assert!(prompter.is_finished());
+        events.extend(generated);
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let invoice = client.invoice(&2)?;
+        assert_eq!(invoice.items[0].period, may);
+        assert_eq!(due(client, ymd(2024, 7, 2)), vec![]);
+        assert_eq!(
+            due(client, ymd(2024, 7, 31))[0].period.until,
+            ymd(2024, 6, 30)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn billed_hours_against_target() -> Result<(), RunError> {
+        let mut events = paid_invoice_history();
+        let hourly = Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(100)),
+            per: Unit::Hour,
+            tiers: Vec::new(),
+        };
+        let item = |from, until, hours| {
+            InvoiceItem::new_hourly(
+                "Support".to_string(),
+                hourly.clone(),
+                Period::new(from, until),
+                Decimal::from(hours),
+            )
+        };
+        let mut invoice = Invoice::new(
+            2,
+            vec![
+                item(ymd(2024, 4, 1), ymd(2024, 4, 30), 30),
+                // 12 working days in April and 10 in May
+                item(ymd(2024, 4, 15), ymd(2024, 5, 14), 44),
+            ],
+            vec![],
+        )
+        .unwrap();
+        invoice.date = ymd(2024, 5, 15);
+        events.extend([
+            Event::new_update(
+                "innotech",
+                Update::ServiceRate(
+                    "Support".to_string(),
+                    ymd(2024, 1, 1),
+                    hourly.clone(),
+                ),
+            ),
+            Event::new_update("innotech", Update::Invoiced(invoice)),
+        ]);
+
+        let set = |service: &str, hours: &str| Command::Set {
+            client: "innotech".to_string(),
+            property: Setable::TargetHours {
+                service: service.to_string(),
+                hours: hours.to_string(),
+            },
+        };
+        assert!(matches!(
+            run_scripted(set("Hosting", "40"), &mut events, vec![]),
+            Err(RunError::Client {
+                source: ClientError::NoService(_)
+            })
+        ));
+        for invalid in ["0", "-5", "forty"] {
+            assert!(matches!(
+                run_scripted(set("Support", invalid), &mut events, vec![]),
+                Err(RunError::TargetHours(_))
+            ));
+        }
+        run_scripted(
+            set("Support", "40"),
+            &mut events,
+            vec![Answer::Bool(true)],
+        )?;
+
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        let today = ymd(2024, 5, 20);
+        assert_eq!(
+            hours_table(client, 2024, today).porcelain(),
+            "2024-01\tSupport\t0.00\t40.00\t-40.00\n\
+             2024-02\tSupport\t0.00\t40.00\t-40.00\n\
+             2024-03\tSupport\t0.00\t40.00\t-40.00\n\
+             2024-04\tSupport\t54.00\t40.00\t14.00\n\
+             2024-05\tSupport\t20.00\t40.00\t-20.00\n\
+             YTD\tSupport\t74.00\t200.00\t-126.00\n"
+        );
+        assert_eq!(
+            hours_table(client, 2023, today).porcelain().lines().count(),
+            13
+        );
+        assert_eq!(
+            hours_table(client, 2025, today).porcelain(),
+            "YTD\tSupport\t0.00\t0.00\t0.00\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn revenue_by_category_across_clients() -> Result<(), RunError> {
+        let mut events = paid_invoice_history();
+        let hourly = Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(100)),
+            per: Unit::Hour,
+            tiers: Vec::new(),
+        };
+        let may = Period::new(ymd(2024, 5, 1), ymd(2024, 5, 31));
+        let item = |name: &str, hours: i64| {
+            let hours = Decimal::from(hours);
+            let amount = hourly.amount * hours;
+            InvoiceItem::raw(
+                name.to_string(),
+                hourly.clone(),
+                may.clone(),
+                hours,
+                amount,
+            )
+        };
+        let mut invoice = Invoice::new(
+            1,
+            vec![item("Stuff", 10), item("Support", 5), item("Setup", 2)],
+            vec![TaxRate::new("GST".to_string(), 5)],
+        )
+        .unwrap();
+        invoice.date = ymd(2024, 6, 1);
+        events.extend([
+            Event::new(
+                "bigco",
+                Change::Added {
+                    name: "BigCo".to_string(),
+                    address: "Elsewhere".to_string(),
+                },
+            ),
+            Event::new_update(
+                "bigco",
+                Update::ServiceRate(
+                    "Stuff".to_string(),
+                    ymd(2024, 1, 1),
+                    hourly.clone(),
+                ),
+            ),
+            Event::new_update(
+                "bigco",
+                Update::ServiceRate(
+                    "Support".to_string(),
+                    ymd(2024, 1, 1),
+                    hourly.clone(),
+                ),
+            ),
+            Event::new_update("bigco", Update::Invoiced(invoice)),
+        ]);
+        let categorize =
+            |client: &str, service: &str, category: &str| Command::Set {
+                client: client.to_string(),
+                property: Setable::Category {
+                    service: service.to_string(),
+                    category: category.to_string(),
+                },
+            };
+        assert!(matches!(
+            run_scripted(
+                categorize("bigco", "Hosting", "ops"),
+                &mut events,
+                vec![]
+            ),
+            Err(RunError::Client {
+                source: ClientError::NoService(_)
+            })
+        ));
+        for (client, service, category) in [
+            ("innotech", "Stuff", "development"),
+            ("bigco", "Stuff", "development"),
+            ("bigco", "Support", "support"),
+        ] {
+            let set = categorize(client, service, category);
+            run_scripted(set, &mut events, vec![Answer::Bool(true)])?;
+        }
+
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        // Setup isn't one of BigCo's services, so it has no category
+        assert_eq!(
+            revenue_table(&clients, None).porcelain(),
+            "development\tCAD $2234.56\n\
+             other\tCAD $200.00\n\
+             support\tCAD $500.00\n"
+        );
+        assert_eq!(
+            revenue_table(&clients, Some(2024)).porcelain(),
+            revenue_table(&clients, None).porcelain()
+        );
+        assert_eq!(revenue_table(&clients, Some(2023)).porcelain(), "");
+        let bigco = clients.get(&"bigco".to_string())?;
+        assert!(services_table(bigco, ymd(2024, 8, 1))
+            .porcelain()
+            .contains("\t\tsupport\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn set_language_checks_code() -> Result<(), RunError> {
+        let mut events = paid_invoice_history();
+        let set = |code: &str| Command::Set {
+            client: "innotech".to_string(),
+            property: Setable::Language {
+                code: code.to_string(),
+            },
+        };
+        assert!(matches!(
+            run_scripted(set("klingon"), &mut events, vec![]),
+            Err(RunError::UnknownLanguage(_))
+        ));
+
+        run_scripted(set("FR"), &mut events, vec![Answer::Bool(true)])?;
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&"innotech".to_string())?;
+        assert_eq!(client.language.as_deref(), Some("fr"));
+        assert_eq!(client.labels().invoice, "Facture");
+        Ok(())
+    }
+
+    #[test]
+    fn unit_names() {
+        assert_eq!(parse_unit("Month").unwrap(), Unit::Month);
+        assert_eq!(parse_unit("2-weeks").unwrap(), Unit::EveryNWeeks(2));
+        assert!(matches!(
+            parse_unit("fortnight"),
+            Err(RunError::UnknownUnit(_))
+        ));
+    }
+
+    #[test]
+    fn set_rate_taxes_and_template_options() -> Result<(), RunError> {
+        let key = "innotech".to_string();
+        let set = |property| Command::Set {
+            client: key.clone(),
+            property,
+        };
+        let monthly = Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(1000)),
+            per: Unit::Month,
+            tiers: Vec::new(),
+        };
+        let hourly = Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(100)),
+            per: Unit::Hour,
+            tiers: Vec::new(),
+        };
+        let taxes = vec![TaxRate::new("GST".to_string(), 5)];
+        let mut events = vec![
+            Event::new(
+                &key,
+                Change::Added {
+                    name: "Innotech".to_string(),
+                    address: "Some Place".to_string(),
+                },
+            ),
+            Event::new_update(
+                &key,
+                Update::ServiceRate(
+                    "Stuff".to_string(),
+                    ymd(2024, 1, 1),
+                    monthly,
+                ),
+            ),
+        ];
+
+        run_scripted(
+            set(Setable::Rate),
+            &mut events,
+            vec![
+                Answer::Text("Stuff".to_string()),
+                Answer::Rate(hourly.clone(), ymd(2024, 6, 1)),
+                Answer::Bool(true),
+            ],
+        )?;
+        run_scripted(
+            set(Setable::Taxes),
+            &mut events,
+            vec![
+                Answer::Taxes(taxes.clone(), ymd(2024, 6, 1)),
+                Answer::Bool(true),
+            ],
+        )?;
+        run_scripted(
+            set(Setable::Template),
+            &mut events,
+            vec![
+                Answer::TemplateOptions(Some("1f77b4".to_string()), None, None),
+                Answer::Bool(true),
+            ],
+        )?;
+        run_scripted(
+            Command::Invoice {
+                client: key.clone(),
+                repeat_last: false,
+                deposit: false,
+                advance: false,
+                period: None,
+                weekly: false,
+                confirm_key: None,
+                currency: None,
+                preview: false,
+                timestamp: None,
+            },
+            &mut events,
+            vec![
+                Answer::Text("Stuff".to_string()),
+                Answer::Period(Period::new(ymd(2024, 6, 1), ymd(2024, 6, 30))),
+                Answer::Number(Decimal::from(10)),
+                Answer::Bool(false),
+                Answer::Review(Review::Done),
+                Answer::Bool(true),
+            ],
+        )?;
+
+        let clients = Clients::from_events(events.iter().cloned().map(Ok))?;
+        let client = clients.get(&key)?;
+        assert_eq!(client.taxes_as_of(ymd(2024, 5, 31)), Vec::new());
+        assert_eq!(client.taxes_as_of(ymd(2024, 6, 1)), taxes);
+        assert_eq!(client.template.accent.as_deref(), Some("1f77b4"));
+        assert_eq!(client.template.logo_path, None);
+        assert_eq!(
+            client.invoice(&1)?.calculate().unwrap().total,
+            Money::new(Currency::Cad, Decimal::from(1050))
+        );
         Ok(())
     }
 }
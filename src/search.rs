@@ -0,0 +1,336 @@
+use std::ops::Range;
+
+use crate::clients::{Change, Client, Event, ProfileUpdate, Update};
+
+/// Where a query matched a client, `chars` indexes the characters of `text`
+#[derive(Debug, PartialEq)]
+pub struct ClientMatch<'a> {
+    pub client: &'a Client,
+    pub text: &'a str,
+    pub chars: Range<usize>,
+}
+
+/// Matches the query against each client's key, name, and address lines,
+/// ignoring case and accents
+pub fn find_clients<'a>(
+    clients: impl Iterator<Item = &'a Client>,
+    query: &str,
+) -> Vec<ClientMatch<'a>> {
+    let query = fold(query.trim());
+    if query.is_empty() {
+        return Vec::new();
+    }
+    clients
+        .filter_map(|client| {
+            [client.key.as_str(), client.name.as_str()]
+                .into_iter()
+                .chain(client.current_address().lines())
+                .find_map(|text| {
+                    let start = find_folded(&fold(text), &query)?;
+                    Some(ClientMatch {
+                        client,
+                        text,
+                        chars: start..start + query.len(),
+                    })
+                })
+        })
+        .collect()
+}
+
+/// Where a query matched the text of a history event, `chars` indexes the
+/// characters of `text`
+#[derive(Debug, PartialEq)]
+pub struct EventMatch<'a> {
+    pub event: &'a Event,
+    /// What the text is, e.g. item or address
+    pub field: &'static str,
+    pub text: &'a str,
+    pub chars: Range<usize>,
+}
+
+/// Matches the query against the names, addresses, services, invoice items,
+/// footers, and reasons recorded by each event, a line at a time and
+/// ignoring case and accents
+pub fn search_events<'a>(
+    events: &'a [Event],
+    query: &str,
+) -> Vec<EventMatch<'a>> {
+    let query = fold(query.trim());
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let mut matches = Vec::new();
+    for event in events {
+        for (field, text) in event_texts(&event.2) {
+            for line in text.lines() {
+                if let Some(start) = find_folded(&fold(line), &query) {
+                    matches.push(EventMatch {
+                        event,
+                        field,
+                        text: line,
+                        chars: start..start + query.len(),
+                    });
+                }
+            }
+        }
+    }
+    matches
+}
+
+/// Matches the query against lines of text, e.g. events as they're written
+/// to the history, giving the line numbers and where the query matched
+pub fn search_lines<'a>(
+    lines: impl Iterator<Item = &'a str>,
+    query: &str,
+) -> Vec<(usize, &'a str, Range<usize>)> {
+    let query = fold(query.trim());
+    if query.is_empty() {
+        return Vec::new();
+    }
+    lines
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let start = find_folded(&fold(line), &query)?;
+            Some((i + 1, line, start..start + query.len()))
+        })
+        .collect()
+}
+
+/// The text a change records, by what it is
+fn event_texts(change: &Change) -> Vec<(&'static str, &str)> {
+    match change {
+        Change::Added { name, address } => {
+            vec![("name", name.as_str()), ("address", address.as_str())]
+        }
+        Change::Updated(update) => match update {
+            Update::Address(address)
+            | Update::EffectiveAddress(_, address)
+            | Update::NamedAddress(_, address) => {
+                vec![("address", address.as_str())]
+            }
+            Update::Name(name) => vec![("name", name.as_str())],
+            Update::ServiceRate(service, ..)
+            | Update::ServiceDefaultQuantity(service, _)
+            | Update::ServiceTargetHours(service, _)
+            | Update::RetainerPurchased(service, ..)
+            | Update::RetainerConsumed(service, ..)
+            | Update::Schedule { service, .. } => {
+                vec![("service", service.as_str())]
+            }
+            Update::ServiceCategory(service, category) => {
+                vec![
+                    ("service", service.as_str()),
+                    ("category", category.as_str()),
+                ]
+            }
+            Update::ServicesMerged(from, into) => {
+                vec![("service", from.as_str()), ("service", into.as_str())]
+            }
+            Update::Invoiced(invoice) => invoice
+                .items
+                .iter()
+                .map(|item| ("item", item.name.as_str()))
+                .collect(),
+            Update::Footer(footer) => vec![("footer", footer.as_str())],
+            Update::WrittenOff(_, _, Some(reason))
+            | Update::Voided(_, _, Some(reason))
+            | Update::Disputed(_, _, Some(reason)) => {
+                vec![("reason", reason.as_str())]
+            }
+            _ => vec![],
+        },
+        Change::Profile(update) => match update {
+            ProfileUpdate::Footer(footer) => vec![("footer", footer.as_str())],
+            ProfileUpdate::Sender(name, address) => {
+                vec![("name", name.as_str()), ("address", address.as_str())]
+            }
+            ProfileUpdate::ServiceTemplate(_, template) => template
+                .services
+                .iter()
+                .map(|(service, _)| ("service", service.as_str()))
+                .collect(),
+            ProfileUpdate::Registration(label, number) => {
+                vec![("label", label.as_str()), ("number", number.as_str())]
+            }
+            _ => vec![],
+        },
+        Change::Removed => vec![],
+    }
+}
+
+/// At most `context` characters either side of a match, with ellipses
+/// where the text was cut, and where the match is in the excerpt
+pub fn excerpt(
+    text: &str,
+    chars: Range<usize>,
+    context: usize,
+) -> (String, Range<usize>) {
+    let all: Vec<char> = text.chars().collect();
+    let from = chars.start.saturating_sub(context);
+    let until = (chars.end + context).min(all.len());
+    let mut excerpt = String::new();
+    let mut offset = from;
+    if from > 0 {
+        excerpt.push('…');
+        offset -= 1;
+    }
+    excerpt.extend(&all[from..until]);
+    if until < all.len() {
+        excerpt.push('…');
+    }
+    (excerpt, chars.start - offset..chars.end - offset)
+}
+
+fn find_folded(haystack: &[char], needle: &[char]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Lowercases and strips accents one character at a time, so positions in
+/// the folded text line up with the original
+pub fn fold(text: &str) -> Vec<char> {
+    text.chars()
+        .map(|c| {
+            let lower = c.to_lowercase().next().unwrap_or(c);
+            base_letter(lower).unwrap_or(lower)
+        })
+        .collect()
+}
+
+fn base_letter(c: char) -> Option<char> {
+    let base = match c {
+        'à'..='å' | 'ā' | 'ă' | 'ą' => 'a',
+        'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => 'c',
+        'ď' | 'đ' => 'd',
+        'è'..='ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => 'e',
+        'ĝ' | 'ğ' | 'ġ' | 'ģ' => 'g',
+        'ĥ' | 'ħ' => 'h',
+        'ì'..='ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => 'i',
+        'ĵ' => 'j',
+        'ķ' => 'k',
+        'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => 'l',
+        'ñ' | 'ń' | 'ņ' | 'ň' => 'n',
+        'ò'..='ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => 'o',
+        'ŕ' | 'ŗ' | 'ř' => 'r',
+        'ś' | 'ŝ' | 'ş' | 'š' => 's',
+        'ţ' | 'ť' | 'ŧ' => 't',
+        'ù'..='ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => 'u',
+        'ŵ' => 'w',
+        'ý' | 'ÿ' | 'ŷ' => 'y',
+        'ź' | 'ż' | 'ž' => 'z',
+        _ => return None,
+    };
+    Some(base)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn clients() -> Vec<Client> {
+        vec![
+            Client::new("acme", "Acme Corp", "1 Road Runner Way\nPhoenix"),
+            Client::new("muller", "Müller & Söhne", "Königstraße 5\nMünchen"),
+            Client::new("eglise", "Café de l'Église", "12 Rue Saint-Éloi"),
+        ]
+    }
+
+    fn keys<'a>(matches: &[ClientMatch<'a>]) -> Vec<&'a str> {
+        matches.iter().map(|m| m.client.key.as_str()).collect()
+    }
+
+    #[test]
+    fn matches_key_name_and_address() {
+        let clients = clients();
+
+        assert_eq!(keys(&find_clients(clients.iter(), "ACME")), ["acme"]);
+        assert_eq!(keys(&find_clients(clients.iter(), "phoenix")), ["acme"]);
+        assert_eq!(keys(&find_clients(clients.iter(), "söhne")), ["muller"]);
+        assert!(find_clients(clients.iter(), "nowhere").is_empty());
+        assert!(find_clients(clients.iter(), "  ").is_empty());
+    }
+
+    #[test]
+    fn ignores_diacritics() {
+        let clients = clients();
+
+        assert_eq!(keys(&find_clients(clients.iter(), "muller")), ["muller"]);
+        assert_eq!(keys(&find_clients(clients.iter(), "MUNCHEN")), ["muller"]);
+        assert_eq!(keys(&find_clients(clients.iter(), "eglise")), ["eglise"]);
+        assert_eq!(keys(&find_clients(clients.iter(), "ÉGLISE")), ["eglise"]);
+    }
+
+    #[test]
+    fn match_position_in_original_text() {
+        let clients = clients();
+        let matches = find_clients(clients.iter(), "sohne");
+
+        assert_eq!(matches[0].text, "Müller & Söhne");
+        assert_eq!(matches[0].chars, 9..14);
+    }
+
+    #[test]
+    fn several_matches() {
+        let clients = clients();
+        assert_eq!(
+            keys(&find_clients(clients.iter(), "r")),
+            ["acme", "muller", "eglise"]
+        );
+    }
+
+    #[test]
+    fn searches_text_recorded_by_events() {
+        let may = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let events = vec![
+            Event::new(
+                "acme",
+                Change::Added {
+                    name: "Acme Corp".to_string(),
+                    address: "1 Road Runner Way\nPhoenix".to_string(),
+                },
+            ),
+            Event::new_update(
+                "acme",
+                Update::Footer("Thanks for the Gateway migration".to_string()),
+            ),
+            Event::new_update(
+                "acme",
+                Update::Voided(1, may, Some("Duplicate".to_string())),
+            ),
+            Event::new_update("acme", Update::Sent(2, may)),
+        ];
+
+        let found = search_events(&events, "gateway MIGRATION");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].event, &events[1]);
+        assert_eq!(found[0].field, "footer");
+        assert_eq!(found[0].chars, 15..32);
+
+        let found = search_events(&events, "phoenix");
+        assert_eq!((found[0].field, found[0].text), ("address", "Phoenix"));
+        assert_eq!(search_events(&events, "duplicate")[0].field, "reason");
+        assert!(search_events(&events, "2024").is_empty());
+        assert!(search_events(&events, " ").is_empty());
+    }
+
+    #[test]
+    fn searches_lines_by_number() {
+        let lines = ["first", "Second line", "third"];
+        assert_eq!(
+            search_lines(lines.into_iter(), "LINE"),
+            vec![(2, "Second line", 7..11)]
+        );
+    }
+
+    #[test]
+    fn excerpts_around_matches() {
+        assert_eq!(
+            excerpt("abcdefghij", 4..6, 2),
+            ("…cdefgh…".to_string(), 3..5)
+        );
+        assert_eq!(excerpt("abcdefghij", 0..2, 2), ("abcd…".to_string(), 0..2));
+        assert_eq!(excerpt("abc", 1..2, 5), ("abc".to_string(), 1..2));
+    }
+}
@@ -0,0 +1,251 @@
+use std::fs::{self, File};
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::clients::{self, Clients, Event, EventError, FileOperation};
+use crate::logging;
+
+/// Extension added to the history file's name for its snapshot
+pub const SNAPSHOT_EXTENSION: &str = "snapshot";
+
+/// The snapshot beside a history, e.g. `client.history.snapshot`
+pub fn snapshot_path(history_path: &Path) -> PathBuf {
+    let mut path = history_path.as_os_str().to_owned();
+    path.push(".");
+    path.push(SNAPSHOT_EXTENSION);
+    PathBuf::from(path)
+}
+
+/// Version of the snapshot layout, raised whenever the clients or anything
+/// in them is serialized differently. Snapshots of another version are
+/// replayed over rather than read with missing fields defaulted.
+const FORMAT: u32 = 2;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// The events at the start of a history a snapshot was made from, as their
+/// number and a digest of their lines. A history edited or rewritten
+/// anywhere in them no longer has the same digest.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub struct Coverage {
+    pub events: usize,
+    digest: u64,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self {
+            events: 0,
+            digest: FNV_OFFSET,
+        }
+    }
+
+    /// Adds the next event of the history, as it's written to the file
+    pub fn push(&mut self, event: &Event) -> Result<(), EventError> {
+        for byte in clients::event_line(event)?.bytes() {
+            self.digest =
+                (self.digest ^ u64::from(byte)).wrapping_mul(FNV_PRIME);
+        }
+        self.events += 1;
+        Ok(())
+    }
+}
+
+/// Clients replayed from the start of a history, so loading it only has to
+/// replay the events added since
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Snapshot {
+    format: u32,
+    /// The invogen version that wrote it, as each release may serialize
+    /// the clients differently
+    version: String,
+    pub covers: Coverage,
+    clients: Clients,
+}
+
+impl Snapshot {
+    pub fn new(clients: Clients, covers: Coverage) -> Self {
+        Self {
+            format: FORMAT,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            covers,
+            clients,
+        }
+    }
+
+    /// Reads a snapshot, there being none or one from another version of
+    /// invogen is not an error as the history can always be replayed
+    pub fn read(path: &Path) -> Result<Option<Self>, EventError> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let file = File::open(path)
+            .map_err(EventError::file(FileOperation::Open, path))?;
+        match serde_lexpr::from_reader::<Self>(BufReader::new(file)) {
+            Ok(snapshot) if !snapshot.is_current() => {
+                logging::info!(
+                    "Ignoring {}, it's from invogen {}",
+                    path.display(),
+                    snapshot.version
+                );
+                Ok(None)
+            }
+            Ok(snapshot) => {
+                logging::info!(
+                    "Read a snapshot of {} events from {}",
+                    snapshot.covers.events,
+                    path.display()
+                );
+                Ok(Some(snapshot))
+            }
+            Err(error) => {
+                logging::info!("Ignoring {}: {}", path.display(), error);
+                Ok(None)
+            }
+        }
+    }
+
+    fn is_current(&self) -> bool {
+        self.format == FORMAT && self.version == env!("CARGO_PKG_VERSION")
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), EventError> {
+        let updated_path = path.with_extension("snapshot-updated");
+        let mut f = File::create(&updated_path)
+            .map_err(EventError::file(FileOperation::Create, &updated_path))?;
+        f.write_all(serde_lexpr::to_string(self)?.as_bytes())
+            .map_err(EventError::file(FileOperation::Write, &updated_path))?;
+        fs::rename(&updated_path, path).map_err(EventError::file(
+            FileOperation::Rename(path.to_path_buf()),
+            &updated_path,
+        ))?;
+        Ok(())
+    }
+
+    /// Whether the history starts with the events in the snapshot
+    pub fn matches(&self, events: &[Event]) -> Result<bool, EventError> {
+        let Some(covered) = events.get(..self.covers.events) else {
+            return Ok(false);
+        };
+        let mut coverage = Coverage::new();
+        for event in covered {
+            coverage.push(event)?;
+        }
+        Ok(coverage == self.covers)
+    }
+
+    /// The snapshot's clients, when the history still starts with its
+    /// events. Those events are taken from the stream, leaving the ones to
+    /// replay onto the clients.
+    pub fn resume(
+        self,
+        stream: &mut impl Iterator<Item = Result<Event, EventError>>,
+    ) -> Result<Option<Clients>, EventError> {
+        let mut coverage = Coverage::new();
+        for event in stream.take(self.covers.events) {
+            coverage.push(&event?)?;
+        }
+        Ok((coverage == self.covers).then_some(self.clients))
+    }
+
+    /// The clients as of the snapshot's last event
+    pub fn clients(self) -> Clients {
+        self.clients
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clients::tests::EVENTS_STR;
+    use crate::clients::{Change, Update};
+    use chrono::{Duration, TimeZone, Utc};
+    use std::env;
+
+    /// A client renamed over and over, like a long lived history
+    fn long_history(len: usize) -> Vec<Event> {
+        let mut events: Vec<Event> = serde_lexpr::from_str(EVENTS_STR).unwrap();
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        for i in events.len()..len {
+            let name = format!("Innotech {}", i);
+            events.push(Event(
+                "innotech".to_string(),
+                start + Duration::minutes(i as i64),
+                Change::Updated(Update::Name(name)),
+                false,
+                None,
+                None,
+            ));
+        }
+        events
+    }
+
+    #[test]
+    fn snapshot_resumed_replay_equals_full_replay() -> Result<(), EventError> {
+        let events = long_history(50_000);
+        let path = env::temp_dir().join("invogen-snapshot.history");
+        crate::clients::events_to_file(&path, &events)?;
+
+        let covered = &events[..40_000];
+        let clients =
+            Clients::from_events(covered.iter().cloned().map(Ok)).unwrap();
+        let mut coverage = Coverage::new();
+        for event in covered {
+            coverage.push(event)?;
+        }
+        let snapshot = Snapshot::new(clients, coverage);
+        let snapshot_file = snapshot_path(&path);
+        snapshot.write(&snapshot_file)?;
+        let read = Snapshot::read(&snapshot_file)?.unwrap();
+        assert_eq!(read, snapshot);
+
+        let mut stream = crate::clients::stream_events(&path)?;
+        let mut resumed = read.resume(&mut stream)?.unwrap();
+        for event in stream {
+            resumed.apply_event(&event?).unwrap();
+        }
+        let full =
+            Clients::from_events(events.iter().cloned().map(Ok)).unwrap();
+        assert_eq!(resumed, full);
+        assert_eq!(
+            full.get(&"innotech".to_string()).unwrap().name,
+            "Innotech 49999"
+        );
+
+        // A history rewritten anywhere before the snapshot's end, not only
+        // at its last event, doesn't resume from it
+        let mut rewritten = events.clone();
+        rewritten[10].1 += Duration::seconds(1);
+        assert!(!snapshot.matches(&rewritten)?);
+        assert!(snapshot.matches(&events)?);
+        assert!(!snapshot.matches(&events[..39_999])?);
+        let mut stream = rewritten.into_iter().map(Ok);
+        assert_eq!(snapshot.resume(&mut stream)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn snapshots_of_other_versions_are_ignored() -> Result<(), EventError> {
+        let path = env::temp_dir().join("invogen-old.history.snapshot");
+        let mut snapshot = Snapshot::new(Clients::new(), Coverage::new());
+        snapshot.version = "0.1.3".to_string();
+        snapshot.write(&path)?;
+        assert_eq!(Snapshot::read(&path)?, None);
+
+        // Format 1 had float decimals and named addresses without dates
+        let mut snapshot = Snapshot::new(Clients::new(), Coverage::new());
+        snapshot.format = 1;
+        snapshot.write(&path)?;
+        assert_eq!(Snapshot::read(&path)?, None);
+
+        // Written before snapshots had a version, fields the clients have
+        // since gained would otherwise read as defaults
+        let unversioned = "((events . 0) (last) (clients . #nil))";
+        fs::write(&path, unversioned).unwrap();
+        assert_eq!(Snapshot::read(&path)?, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,333 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, Transaction};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use thiserror::Error;
+
+use crate::billing::{CurrencyMismatch, InvoiceKind, Money};
+use crate::clients::{Client, Clients};
+
+/// Tables of the exported database, amounts are integer cents beside the
+/// currency code and dates are ISO strings
+const SCHEMA: &str = "
+CREATE TABLE clients (
+    key TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    address TEXT NOT NULL
+);
+CREATE TABLE services (
+    client TEXT NOT NULL REFERENCES clients (key),
+    name TEXT NOT NULL,
+    default_hours TEXT,
+    schedule INTEGER,
+    category TEXT,
+    target_hours TEXT,
+    PRIMARY KEY (client, name)
+);
+CREATE TABLE rates (
+    client TEXT NOT NULL,
+    service TEXT NOT NULL,
+    effective TEXT NOT NULL,
+    amount_cents INTEGER NOT NULL,
+    currency TEXT NOT NULL,
+    per TEXT NOT NULL,
+    PRIMARY KEY (client, service, effective),
+    FOREIGN KEY (client, service) REFERENCES services (client, name)
+);
+CREATE TABLE taxes (
+    client TEXT NOT NULL REFERENCES clients (key),
+    effective TEXT NOT NULL,
+    name TEXT NOT NULL,
+    rate TEXT NOT NULL
+);
+CREATE TABLE invoices (
+    client TEXT NOT NULL REFERENCES clients (key),
+    number INTEGER NOT NULL,
+    date TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    subtotal_cents INTEGER NOT NULL,
+    total_cents INTEGER NOT NULL,
+    sent TEXT,
+    written_off TEXT,
+    voided TEXT,
+    disputed TEXT,
+    PRIMARY KEY (client, number)
+);
+CREATE TABLE invoice_items (
+    client TEXT NOT NULL,
+    invoice INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    period_from TEXT NOT NULL,
+    period_until TEXT NOT NULL,
+    quantity TEXT NOT NULL,
+    rate_cents INTEGER NOT NULL,
+    per TEXT NOT NULL,
+    amount_cents INTEGER NOT NULL,
+    currency TEXT NOT NULL,
+    PRIMARY KEY (client, invoice, position),
+    FOREIGN KEY (client, invoice) REFERENCES invoices (client, number)
+);
+CREATE TABLE payments (
+    client TEXT NOT NULL,
+    invoice INTEGER NOT NULL,
+    date TEXT NOT NULL,
+    amount_cents INTEGER NOT NULL,
+    currency TEXT NOT NULL,
+    FOREIGN KEY (client, invoice) REFERENCES invoices (client, number)
+);
+";
+
+#[derive(Debug, Error)]
+pub enum SqliteError {
+    #[error("Database Error: {0}")]
+    Database(#[from] rusqlite::Error),
+
+    #[error("Can't replace {path}: {source}")]
+    Io { path: String, source: io::Error },
+
+    #[error("{0} doesn't fit in a database integer")]
+    TooLarge(Decimal),
+
+    #[error("Invoice Error: {0}")]
+    Currency(#[from] CurrencyMismatch),
+}
+
+/// Writes the clients to a new database beside the output, replacing the
+/// output only once it's complete
+pub fn export(clients: &Clients, output: &Path) -> Result<(), SqliteError> {
+    let io_error = |source| SqliteError::Io {
+        path: output.display().to_string(),
+        source,
+    };
+    let updated = output.with_extension("sqlite-updated");
+    if updated.exists() {
+        fs::remove_file(&updated).map_err(io_error)?;
+    }
+    let mut connection = Connection::open(&updated)?;
+    connection.execute_batch(SCHEMA)?;
+    let transaction = connection.transaction()?;
+    for client in clients.iter() {
+        insert_client(&transaction, client)?;
+    }
+    transaction.commit()?;
+    connection.close().map_err(|(_, error)| error)?;
+    fs::rename(&updated, output).map_err(io_error)
+}
+
+fn cents(amount: Decimal) -> Result<i64, SqliteError> {
+    (amount * Decimal::ONE_HUNDRED)
+        .round()
+        .to_i64()
+        .ok_or(SqliteError::TooLarge(amount))
+}
+
+fn money(amount: &Money) -> Result<(i64, &'static str), SqliteError> {
+    Ok((cents(amount.amount())?, amount.currency().code()))
+}
+
+fn insert_client(
+    transaction: &Transaction,
+    client: &Client,
+) -> Result<(), SqliteError> {
+    let key = &client.key;
+    transaction.execute(
+        "INSERT INTO clients VALUES (?1, ?2, ?3)",
+        params![key, client.name, client.current_address()],
+    )?;
+
+    for service in client.services.values() {
+        transaction.execute(
+            "INSERT INTO services VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                key,
+                service.name,
+                service.default_quantity.map(|hours| hours.to_string()),
+                service.schedule,
+                service.category,
+                service.target_hours.map(|hours| hours.to_string()),
+            ],
+        )?;
+        for (effective, rate) in service.rates.iter() {
+            let (amount, currency) = money(&rate.amount)?;
+            transaction.execute(
+                "INSERT INTO rates VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    key,
+                    service.name,
+                    effective.to_string(),
+                    amount,
+                    currency,
+                    rate.per.to_string(),
+                ],
+            )?;
+        }
+    }
+
+    for (effective, taxes) in client.tax_history().iter() {
+        for tax in taxes {
+            transaction.execute(
+                "INSERT INTO taxes VALUES (?1, ?2, ?3, ?4)",
+                params![key, effective.to_string(), tax.0, tax.1.to_string()],
+            )?;
+        }
+    }
+
+    let date = |date: Option<NaiveDate>| date.map(|d| d.to_string());
+    for invoice in client.invoices() {
+        let total = invoice.calculate()?;
+        let kind = match invoice.kind {
+            InvoiceKind::Standard => "standard",
+            InvoiceKind::Deposit => "deposit",
+            InvoiceKind::Final { .. } => "final",
+        };
+        transaction.execute(
+            "INSERT INTO invoices VALUES
+                (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                key,
+                invoice.number as i64,
+                invoice.date.to_string(),
+                kind,
+                invoice.currency().code(),
+                cents(total.subtotal.amount())?,
+                cents(total.total.amount())?,
+                date(invoice.sent),
+                date(invoice.written_off.as_ref().map(|w| w.date)),
+                date(invoice.voided.as_ref().map(|v| v.date)),
+                date(invoice.disputed.as_ref().map(|d| d.date)),
+            ],
+        )?;
+        for (position, item) in invoice.items.iter().enumerate() {
+            let (amount, currency) = money(&item.amount)?;
+            transaction.execute(
+                "INSERT INTO invoice_items VALUES
+                    (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    key,
+                    invoice.number as i64,
+                    position as i64 + 1,
+                    item.name,
+                    item.period.from.to_string(),
+                    item.period.until.to_string(),
+                    item.quantity.to_string(),
+                    cents(item.rate.amount.amount())?,
+                    item.rate.per.to_string(),
+                    amount,
+                    currency,
+                ],
+            )?;
+        }
+    }
+
+    for payment in client.payments()? {
+        let (amount, currency) = money(&payment.amount)?;
+        transaction.execute(
+            "INSERT INTO payments VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                key,
+                payment.number as i64,
+                payment.date.to_string(),
+                amount,
+                currency,
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::billing::{
+        Currency, Invoice, InvoiceItem, Period, Rate, TaxRate, Unit,
+    };
+    use crate::calendar::DateBoundaries;
+    use crate::clients::Update;
+    use std::env;
+
+    fn ymd(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn clients() -> Clients {
+        let mut clients = Clients::new();
+        let rate = Rate {
+            amount: Money::new(Currency::Cad, Decimal::new(123456, 2)),
+            per: Unit::Month,
+            tiers: Vec::new(),
+        };
+        let gst = vec![TaxRate::new("GST".to_string(), 5)];
+        for key in ["acme", "innotech"] {
+            let key = key.to_string();
+            let client = Client::new(&key, &key.to_uppercase(), "Some Place");
+            clients.add(&key, client).unwrap();
+            let service = "Stuff".to_string();
+            let updates = vec![
+                Update::ServiceRate(service, ymd(2024, 1, 1), rate.clone()),
+                Update::Taxes(ymd(2024, 1, 1), gst.clone()),
+            ];
+            for update in updates {
+                clients.update(&key, &update).unwrap();
+            }
+        }
+        let key = "innotech".to_string();
+        for (number, month) in [(1, 3), (2, 4)] {
+            let period = Period::new(
+                ymd(2024, month, 1),
+                ymd(2024, month, 1).end_of_month().unwrap(),
+            );
+            let item =
+                InvoiceItem::new("Stuff".to_string(), rate.clone(), period);
+            let mut invoice =
+                Invoice::new(number, vec![item], gst.clone()).unwrap();
+            invoice.date = ymd(2024, month + 1, 1);
+            clients.update(&key, &Update::Invoiced(invoice)).unwrap();
+        }
+        let paid = Update::Paid(1, ymd(2024, 4, 20), None);
+        clients.update(&key, &paid).unwrap();
+        clients
+    }
+
+    #[test]
+    fn exported_tables() -> Result<(), SqliteError> {
+        let path = env::temp_dir().join("invogen-export.sqlite");
+        export(&clients(), &path)?;
+        // Exporting again replaces the database
+        export(&clients(), &path)?;
+
+        let connection = Connection::open(&path)?;
+        let count = |table: &str| -> rusqlite::Result<i64> {
+            let query = format!("SELECT COUNT(*) FROM {}", table);
+            connection.query_row(&query, [], |row| row.get(0))
+        };
+        assert_eq!(count("clients")?, 2);
+        assert_eq!(count("services")?, 2);
+        assert_eq!(count("rates")?, 2);
+        assert_eq!(count("taxes")?, 2);
+        assert_eq!(count("invoices")?, 2);
+        assert_eq!(count("invoice_items")?, 2);
+        assert_eq!(count("payments")?, 1);
+
+        let paid: (String, i64, i64, String) = connection.query_row(
+            "SELECT clients.name, invoices.number, payments.amount_cents,
+                payments.currency
+            FROM payments
+            JOIN invoices ON invoices.client = payments.client
+                AND invoices.number = payments.invoice
+            JOIN clients ON clients.key = invoices.client",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+        assert_eq!(
+            paid,
+            ("INNOTECH".to_string(), 1, 129629, "CAD".to_string())
+        );
+        Ok(())
+    }
+}
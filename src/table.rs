@@ -0,0 +1,228 @@
+use std::env;
+use std::io::{self, IsTerminal};
+
+use unicode_width::UnicodeWidthStr;
+
+const COLUMN_GAP: &str = "  ";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+    Cyan,
+}
+
+impl Color {
+    fn ansi_code(&self) -> &'static str {
+        match self {
+            Color::Red => "31",
+            Color::Green => "32",
+            Color::Yellow => "33",
+            Color::Cyan => "36",
+        }
+    }
+}
+
+pub fn paint(text: &str, color: Color) -> String {
+    format!("\x1b[{}m{}\x1b[0m", color.ansi_code(), text)
+}
+
+pub struct Cell {
+    text: String,
+    color: Option<Color>,
+}
+
+impl Cell {
+    pub fn colored(text: impl Into<String>, color: Option<Color>) -> Self {
+        Self {
+            text: text.into(),
+            color,
+        }
+    }
+}
+
+impl<T: Into<String>> From<T> for Cell {
+    fn from(text: T) -> Self {
+        Self::colored(text, None)
+    }
+}
+
+/// How a table is printed
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Style {
+    /// Columns aligned under a header, coloured when enabled
+    Aligned { color: bool },
+    /// Tab separated fields without a header or colour, for scripts
+    Porcelain,
+}
+
+/// Rows of text aligned into columns under a header, padded by display
+/// width so accented and wide characters line up
+pub struct Table {
+    header: Vec<Cell>,
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    pub fn new(header: &[&str]) -> Self {
+        Self {
+            header: header.iter().map(|h| Cell::from(*h)).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, row: Vec<Cell>) {
+        self.rows.push(row);
+    }
+
+    fn widths(&self) -> Vec<usize> {
+        let mut widths: Vec<usize> = Vec::new();
+        for row in [&self.header].into_iter().chain(self.rows.iter()) {
+            for (i, cell) in row.iter().enumerate() {
+                let width = cell.text.width();
+                match widths.get_mut(i) {
+                    Some(max) => *max = (*max).max(width),
+                    None => widths.push(width),
+                }
+            }
+        }
+        widths
+    }
+
+    pub fn render(&self, color: bool) -> String {
+        let widths = self.widths();
+        [&self.header]
+            .into_iter()
+            .chain(self.rows.iter())
+            .map(|row| render_row(row, &widths, color))
+            .collect()
+    }
+
+    /// The rows as tab separated fields with tabs, newlines, and
+    /// backslashes escaped so each row stays on one line
+    pub fn porcelain(&self) -> String {
+        self.rows
+            .iter()
+            .map(|row| {
+                let fields: Vec<String> =
+                    row.iter().map(|cell| escape_field(&cell.text)).collect();
+                format!("{}\n", fields.join("\t"))
+            })
+            .collect()
+    }
+
+    pub fn render_as(&self, style: Style) -> String {
+        match style {
+            Style::Aligned { color } => self.render(color),
+            Style::Porcelain => self.porcelain(),
+        }
+    }
+}
+
+fn escape_field(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn render_row(row: &[Cell], widths: &[usize], color: bool) -> String {
+    let mut line = String::new();
+    for (i, (cell, width)) in row.iter().zip(widths).enumerate() {
+        if i > 0 {
+            line.push_str(COLUMN_GAP);
+        }
+        match cell.color.filter(|_| color) {
+            Some(c) => line.push_str(&paint(&cell.text, c)),
+            None => line.push_str(&cell.text),
+        }
+        if i + 1 < row.len() {
+            line.push_str(&" ".repeat(width - cell.text.width()));
+        }
+    }
+    line.push('\n');
+    line
+}
+
+/// Colour is used on a terminal unless disabled by flag or `NO_COLOR`
+pub fn use_color(no_color: bool) -> bool {
+    let no_color_env = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    !no_color && !no_color_env && io::stdout().is_terminal()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(rows: &[[&str; 2]]) -> Table {
+        let mut table = Table::new(&["Key", "Name"]);
+        for [key, name] in rows {
+            table.push(vec![Cell::from(*key), Cell::from(*name)]);
+        }
+        table
+    }
+
+    #[test]
+    fn columns_align_to_widest_cell() {
+        assert_eq!(
+            table(&[["acme", "Acme Corp"], ["innotech", "Innotech"]])
+                .render(false),
+            "Key       Name\n\
+             acme      Acme Corp\n\
+             innotech  Innotech\n"
+        );
+    }
+
+    #[test]
+    fn wide_and_accented_names() {
+        let rendered = table(&[["株式会社", "Tokyo"], ["müller", "München"]])
+            .render(false);
+        let widths: Vec<usize> = rendered
+            .lines()
+            .map(|l| l.find(['T', 'N', 'M']).map(|i| l[..i].width()).unwrap())
+            .collect();
+
+        assert_eq!(widths, vec![10, 10, 10]);
+    }
+
+    #[test]
+    fn color_only_when_enabled() {
+        let mut table = Table::new(&["Status", "Total"]);
+        table.push(vec![
+            Cell::colored("overdue", Some(Color::Red)),
+            Cell::from("$1.00"),
+        ]);
+
+        assert_eq!(
+            table.render(true),
+            "Status   Total\n\x1b[31moverdue\x1b[0m  $1.00\n"
+        );
+        assert_eq!(table.render(false), "Status   Total\noverdue  $1.00\n");
+    }
+
+    #[test]
+    fn porcelain_escapes_fields() {
+        let mut table = table(&[["acme", "Acme\tCorp\nLtd \\ Co"]]);
+        table.push(vec![
+            Cell::colored("overdue", Some(Color::Red)),
+            Cell::from(""),
+        ]);
+        let porcelain = table.porcelain();
+        assert_eq!(porcelain, "acme\tAcme\\tCorp\\nLtd \\\\ Co\noverdue\t\n");
+        assert!(porcelain.lines().all(|l| l.split('\t').count() == 2));
+        assert_eq!(table.render_as(Style::Porcelain), porcelain);
+    }
+
+    #[test]
+    fn no_color_flag_disables_color() {
+        assert!(!use_color(true));
+    }
+}
@@ -1,35 +1,251 @@
 use std::fmt;
+use std::path::Path;
 
-use crate::billing::{Invoice, InvoiceTotal};
-use crate::clients::Client;
+use crate::billing::{Invoice, InvoiceItem, InvoiceTotal, Money};
+use crate::cli::{ReminderFormat, ReminderLevel};
+use crate::clients::{Client, Profile};
+use crate::labels::Labels;
+use crate::registration;
 use crate::run::RunError;
 
 use askama::Template;
-use askama_escape::Escaper;
+use askama_escape::{escape, Escaper, Html};
+use chrono::NaiveDate;
+
+const DEFAULT_DOCUMENT_CLASS: &str = "invoice";
+
+/// An item with its localized period and hours of each week
+type ItemLines<'a> = (&'a InvoiceItem, String, Vec<String>);
 
 #[derive(Template)]
 #[template(path = "invoice.tex")]
 struct InvoiceData<'a> {
     invoice: &'a Invoice,
+    /// Items by service, with their localized periods and weekly hours,
+    /// and the label and amount of each service's subtotal, one group when
+    /// flat
+    groups: Vec<(String, Vec<ItemLines<'a>>, Money)>,
+    labels: &'a Labels,
+    date: String,
+    /// Localized date the invoice was voided on
+    void_date: Option<String>,
     client_name: &'a str,
     address_lines: Vec<&'a str>,
     total: &'a InvoiceTotal,
+    document_class: &'a str,
+    accent: Option<&'a str>,
+    logo_path: Option<&'a str>,
+    footer_lines: Vec<&'a str>,
+    /// The sender's tax registrations, label and number as printed
+    registrations: Vec<(&'a str, String)>,
+    /// Localized note on the currency items were converted from
+    conversion: Option<String>,
 }
 
-pub fn invoice<'a>(
-    invoice: &'a Invoice,
-    client: &'a Client,
+pub fn invoice(
+    invoice: &Invoice,
+    client: &Client,
+    profile: &Profile,
+    flat: bool,
 ) -> Result<(), RunError> {
+    println!("{}", render_invoice(invoice, client, profile, flat)?);
+
+    Ok(())
+}
+
+pub fn render_invoice(
+    invoice: &Invoice,
+    client: &Client,
+    profile: &Profile,
+    flat: bool,
+) -> Result<String, askama::Error> {
+    let options = &client.template;
+    let logo_path = options.logo_path.as_deref().filter(|path| {
+        let exists = Path::new(path).exists();
+        if !exists {
+            eprintln!(
+                "Warning: logo '{}' not found, rendering without it",
+                path
+            );
+        }
+        exists
+    });
+
+    let labels = client.labels();
+    let total = invoice
+        .calculate()
+        .map_err(|error| askama::Error::Custom(Box::new(error)))?;
+    let groups = if flat {
+        let items = invoice.items.iter().collect();
+        vec![(String::new(), items, total.subtotal)]
+    } else {
+        invoice.items_by_service()
+    };
+    let groups = groups
+        .into_iter()
+        .map(|(name, items, subtotal)| {
+            let items = items
+                .into_iter()
+                .map(|item| {
+                    let weeks = item
+                        .weeks
+                        .iter()
+                        .map(|(week, hours)| labels.week_hours(*week, *hours))
+                        .collect();
+                    (item, labels.period(&item.period), weeks)
+                })
+                .collect();
+            (labels.service_subtotal(&name), items, subtotal)
+        })
+        .collect();
     let data = InvoiceData {
         invoice,
+        groups,
+        labels,
+        date: labels.date(invoice.date),
+        void_date: invoice.voided.as_ref().map(|void| labels.date(void.date)),
         client_name: client.name.as_str(),
-        address_lines: client.address.split('\n').collect(),
-        total: &invoice.calculate(),
+        address_lines: client
+            .invoice_address(invoice.date)
+            .split('\n')
+            .collect(),
+        total: &total,
+        document_class: options
+            .template
+            .as_deref()
+            .unwrap_or(DEFAULT_DOCUMENT_CLASS),
+        accent: options.accent.as_deref(),
+        logo_path,
+        footer_lines: client
+            .footer(profile)
+            .map(|footer| footer.lines().collect())
+            .unwrap_or_default(),
+        registrations: profile
+            .registrations
+            .iter()
+            .map(|(label, number)| {
+                (label.as_str(), registration::display(number))
+            })
+            .collect(),
+        conversion: invoice.fx.map(|(from, rate)| {
+            labels.converted(from.code(), &rate.to_string())
+        }),
     };
 
-    println!("{}", data.render()?);
+    data.render()
+}
 
-    Ok(())
+/// What a reminder says about the overdue invoice, escaped for its format
+struct ReminderFields {
+    client_name: String,
+    number: String,
+    issued: String,
+    due: String,
+    days_overdue: String,
+    outstanding: String,
+    /// Payment details from the invoice footer, empty without one
+    payment: String,
+    sender: String,
+}
+
+// In prose a word after `>>` would parse as a shift, so the reminders use
+// askama's default `{{ }}` syntax rather than the TeX one
+#[derive(Template)]
+#[template(path = "reminder_first.txt", syntax = "default")]
+struct FirstReminder<'a> {
+    reminder: &'a ReminderFields,
+}
+
+#[derive(Template)]
+#[template(path = "reminder_second.txt", syntax = "default")]
+struct SecondReminder<'a> {
+    reminder: &'a ReminderFields,
+}
+
+#[derive(Template)]
+#[template(path = "reminder_final.txt", syntax = "default")]
+struct FinalReminder<'a> {
+    reminder: &'a ReminderFields,
+}
+
+/// A reminder of an overdue invoice, plain text paragraphs for an email or
+/// the same paragraphs as LaTeX or HTML
+pub fn render_reminder(
+    invoice: &Invoice,
+    client: &Client,
+    profile: &Profile,
+    level: ReminderLevel,
+    format: ReminderFormat,
+    today: NaiveDate,
+) -> Result<String, askama::Error> {
+    let escaped = |text: &str| match format {
+        ReminderFormat::Text => text.to_string(),
+        ReminderFormat::Tex => escape(text, Tex).to_string(),
+        ReminderFormat::Html => escape(text, Html).to_string(),
+    };
+    let labels = client.labels();
+    let days = (today - invoice.due_date()).num_days();
+    let payment = client.footer(profile).map_or(String::new(), |footer| {
+        format!("Payment details:\n{}", footer)
+    });
+    let reminder = ReminderFields {
+        client_name: escaped(&client.name),
+        number: escaped(&invoice.display_number()),
+        issued: escaped(&labels.date(invoice.issued())),
+        due: escaped(&labels.date(invoice.due_date())),
+        days_overdue: match days {
+            1 => "1 day".to_string(),
+            days => format!("{} days", days),
+        },
+        outstanding: escaped(
+            &invoice
+                .calculate()
+                .map_err(|error| askama::Error::Custom(Box::new(error)))?
+                .total
+                .to_string(),
+        ),
+        payment: escaped(&payment),
+        sender: escaped(profile.sender.as_ref().map_or("", |(name, _)| name)),
+    };
+    let text = match level {
+        ReminderLevel::First => FirstReminder {
+            reminder: &reminder,
+        }
+        .render()?,
+        ReminderLevel::Second => SecondReminder {
+            reminder: &reminder,
+        }
+        .render()?,
+        ReminderLevel::Final => FinalReminder {
+            reminder: &reminder,
+        }
+        .render()?,
+    };
+
+    // Each paragraph is one line of the template, or lines kept apart
+    let (open, close, line_break) = match format {
+        ReminderFormat::Text => ("", "", "\n"),
+        ReminderFormat::Tex => ("", "", "\\\\\n"),
+        ReminderFormat::Html => ("<p>", "</p>", "<br>\n"),
+    };
+    let paragraphs: Vec<String> = text
+        .split("\n\n")
+        .map(str::trim)
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| {
+            let lines: Vec<&str> = paragraph.lines().collect();
+            format!("{}{}{}", open, lines.join(line_break), close)
+        })
+        .collect();
+    Ok(format!("{}\n", paragraphs.join("\n\n")))
+}
+
+/// Normalizes a hex colour like `#1a2b3c` to the `1A2B3C` form expected by
+/// xcolor's HTML model
+pub fn parse_accent(color: &str) -> Option<String> {
+    let hex = color.trim().trim_start_matches('#');
+    (hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .then(|| hex.to_ascii_uppercase())
 }
 
 pub struct Tex;
@@ -43,9 +259,381 @@ impl Escaper for Tex {
             match c {
                 '%' => fmt.write_str("\\%")?,
                 '$' => fmt.write_str("\\$")?,
+                '&' => fmt.write_str("\\&")?,
+                '#' => fmt.write_str("\\#")?,
+                '_' => fmt.write_str("\\_")?,
+                '{' => fmt.write_str("\\{")?,
+                '}' => fmt.write_str("\\}")?,
+                '~' => fmt.write_str("\\textasciitilde{}")?,
+                '^' => fmt.write_str("\\textasciicircum{}")?,
+                '\\' => fmt.write_str("\\textbackslash{}")?,
                 _ => fmt.write_char(c)?,
             }
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::billing::{
+        Currency, InvoiceItem, Money, Period, Rate, TaxRate, Unit, Void,
+    };
+    use crate::clients::{ClientError, TemplateOptions, Update};
+    use chrono::NaiveDate;
+    use rust_decimal::Decimal;
+
+    pub fn fixture_invoice() -> Invoice {
+        let rate = Rate {
+            amount: Money::new(Currency::Cad, Decimal::from(1000)),
+            per: Unit::Month,
+            tiers: Vec::new(),
+        };
+        let period = Period::new(
+            NaiveDate::from_ymd_opt(2024, 4, 1).unwrap(),
+            NaiveDate::from_ymd_opt(2024, 4, 30).unwrap(),
+        );
+        let item = InvoiceItem::new("Stuff".to_string(), rate, period);
+        Invoice::new(1, vec![item], vec![TaxRate::new("GST".to_string(), 5)])
+            .unwrap()
+    }
+
+    fn fixture_client() -> Client {
+        Client::new("innotech", "Innotech", "1 Some Place\nCity")
+    }
+
+    #[test]
+    fn render_without_template_options() -> Result<(), askama::Error> {
+        let profile = Profile::default();
+        let rendered = render_invoice(
+            &fixture_invoice(),
+            &fixture_client(),
+            &profile,
+            false,
+        )?;
+
+        assert!(rendered.contains("\\documentclass{invoice}"));
+        assert!(!rendered.contains("\\definecolor"));
+        assert!(!rendered.contains("\\includegraphics"));
+        assert!(rendered.contains("1 Some Place\\\\"));
+        Ok(())
+    }
+
+    #[test]
+    fn render_with_template_options() -> Result<(), askama::Error> {
+        let logo = std::env::temp_dir().join("invogen-test-logo.png");
+        std::fs::write(&logo, b"").unwrap();
+        let mut client = fixture_client();
+        client.template = TemplateOptions {
+            accent: Some("1A2B3C".to_string()),
+            logo_path: Some(logo.display().to_string()),
+            template: Some("brand".to_string()),
+        };
+        let rendered = render_invoice(
+            &fixture_invoice(),
+            &client,
+            &Profile::default(),
+            false,
+        )?;
+
+        assert!(rendered.contains("\\documentclass{brand}"));
+        assert!(rendered.contains("\\definecolor{accent}{HTML}{1A2B3C}"));
+        assert!(rendered
+            .contains(&format!("\\includegraphics{{{}}}", logo.display())));
+        Ok(())
+    }
+
+    #[test]
+    fn render_zero_rated_tax() -> Result<(), askama::Error> {
+        let mut invoice = fixture_invoice();
+        invoice.tax_rates = vec![TaxRate::new("GST".to_string(), 0)];
+        let rendered = render_invoice(
+            &invoice,
+            &fixture_client(),
+            &Profile::default(),
+            false,
+        )?;
+
+        assert!(rendered.contains("\\taxitem{GST @ 0\\%}{CAD \\$0.00}"));
+        Ok(())
+    }
+
+    #[test]
+    fn render_skips_missing_logo() -> Result<(), askama::Error> {
+        let mut client = fixture_client();
+        client.template.logo_path =
+            Some("/nonexistent/invogen/logo.png".to_string());
+        let rendered = render_invoice(
+            &fixture_invoice(),
+            &client,
+            &Profile::default(),
+            false,
+        )?;
+
+        assert!(!rendered.contains("\\includegraphics"));
+        Ok(())
+    }
+
+    #[test]
+    fn render_footer() -> Result<(), askama::Error> {
+        let profile = Profile {
+            footer: Some("Payable within 30 days".to_string()),
+            ..Profile::default()
+        };
+        let mut client = fixture_client();
+        let rendered =
+            render_invoice(&fixture_invoice(), &client, &profile, false)?;
+        assert!(rendered.contains("Payable within 30 days\\par"));
+
+        client.footer = Some("Contract #12 & terms\n100% due".to_string());
+        let rendered =
+            render_invoice(&fixture_invoice(), &client, &profile, false)?;
+        assert!(!rendered.contains("Payable within 30 days"));
+        assert!(rendered.contains("Contract \\#12 \\& terms\\par"));
+        assert!(rendered.contains("100\\% due\\par"));
+
+        client.footer = Some(String::new());
+        let rendered =
+            render_invoice(&fixture_invoice(), &client, &profile, false)?;
+        assert!(!rendered.contains("\\par"));
+        Ok(())
+    }
+
+    #[test]
+    fn render_registrations() -> Result<(), askama::Error> {
+        let profile = Profile {
+            registrations: vec![
+                ("GST/HST".to_string(), "123456782RT0001".to_string()),
+                ("VAT".to_string(), "DE136695976".to_string()),
+            ],
+            ..Profile::default()
+        };
+        let rendered = render_invoice(
+            &fixture_invoice(),
+            &fixture_client(),
+            &profile,
+            false,
+        )?;
+        assert!(rendered.contains("GST/HST: 123456782 RT0001\\par"));
+        assert!(rendered.contains("VAT: DE136695976\\par"));
+        assert!(
+            rendered.find("\\end{invoiceTable}")
+                < rendered.find("GST/HST: 123456782 RT0001")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn render_weekly_hours() -> Result<(), askama::Error> {
+        let mut invoice = fixture_invoice();
+        let week = |day| NaiveDate::from_ymd_opt(2024, 4, day).unwrap();
+        invoice.items[0].weeks = vec![
+            (week(1), Decimal::new(325, 1)),
+            (week(8), Decimal::from(30)),
+        ];
+        let profile = Profile::default();
+        let rendered =
+            render_invoice(&invoice, &fixture_client(), &profile, false)?;
+        assert!(rendered
+            .contains("\\lineitem{\\quad Week of 2024-04-01: 32.5h}{}{}{}{}"));
+        assert!(rendered.contains("Week of 2024-04-08: 30h"));
+        Ok(())
+    }
+
+    #[test]
+    fn render_service_subtotals() -> Result<(), askama::Error> {
+        let mut invoice = fixture_invoice();
+        let mut design = invoice.items[0].clone();
+        design.name = "Design".to_string();
+        invoice.items.push(design);
+        let client = fixture_client();
+        let profile = Profile::default();
+
+        let grouped = render_invoice(&invoice, &client, &profile, false)?;
+        let design_at = grouped.find("\\lineitem{Design}").unwrap();
+        assert!(design_at < grouped.find("\\lineitem{Stuff}").unwrap());
+        assert!(grouped
+            .contains("\\lineitem{Design subtotal}{}{}{}{CAD \\$1000.00}"));
+        assert!(grouped.contains("\\lineitem{Stuff subtotal}"));
+
+        let flat = render_invoice(&invoice, &client, &profile, true)?;
+        assert!(!flat.contains("subtotal}"));
+        let stuff_at = flat.find("\\lineitem{Stuff}").unwrap();
+        assert!(stuff_at < flat.find("\\lineitem{Design}").unwrap());
+        Ok(())
+    }
+
+    fn render_in(language: &str) -> Result<String, askama::Error> {
+        let mut invoice = fixture_invoice();
+        invoice.date = NaiveDate::from_ymd_opt(2024, 4, 15).unwrap();
+        let mut client = fixture_client();
+        client.language = Some(language.to_string());
+        render_invoice(&invoice, &client, &Profile::default(), false)
+    }
+
+    #[test]
+    fn render_english_by_default() -> Result<(), askama::Error> {
+        let rendered = render_in("en")?;
+        assert!(rendered.contains("\\def\\invoiceLabel{Invoice}"));
+        assert!(rendered.contains("{1 }{2024-04-15}"));
+        assert!(rendered.contains("{2024-04-01 — 2024-04-30}"));
+        Ok(())
+    }
+
+    #[test]
+    fn render_french() -> Result<(), askama::Error> {
+        let rendered = render_in("fr")?;
+        assert!(rendered.contains("\\def\\invoiceLabel{Facture}"));
+        assert!(rendered.contains("\\def\\subtotalLabel{Sous-total}"));
+        assert!(rendered.contains("{1 }{15 avril 2024}"));
+        assert!(rendered.contains("{1er avril 2024 — 30 avril 2024}"));
+        Ok(())
+    }
+
+    #[test]
+    fn render_german() -> Result<(), askama::Error> {
+        let rendered = render_in("de")?;
+        assert!(rendered.contains("\\def\\invoiceLabel{Rechnung}"));
+        assert!(rendered.contains("\\def\\totalLabel{Gesamtbetrag}"));
+        assert!(rendered.contains("{1 }{15. April 2024}"));
+        assert!(rendered.contains("{1. April 2024 — 30. April 2024}"));
+        Ok(())
+    }
+
+    #[test]
+    fn render_conversion_note() -> Result<(), askama::Error> {
+        let mut invoice = fixture_invoice();
+        invoice.items = invoice
+            .items
+            .into_iter()
+            .map(|item| item.converted(Currency::Eur, Decimal::new(68, 2)))
+            .collect();
+        invoice.fx = Some((Currency::Cad, Decimal::new(68, 2)));
+        let mut client = fixture_client();
+        let profile = Profile::default();
+        let rendered = render_invoice(&invoice, &client, &profile, false)?;
+        assert!(rendered.contains("Converted from CAD at 0.68\\par"));
+        assert!(rendered.contains("{EUR €680.00}"));
+
+        client.language = Some("fr".to_string());
+        let rendered = render_invoice(&invoice, &client, &profile, false)?;
+        assert!(rendered.contains("Converti de CAD au taux de 0.68"));
+        Ok(())
+    }
+
+    #[test]
+    fn render_void_note() -> Result<(), askama::Error> {
+        let mut invoice = fixture_invoice();
+        let client = fixture_client();
+        let profile = Profile::default();
+        let rendered = render_invoice(&invoice, &client, &profile, false)?;
+        assert!(!rendered.contains("VOID"));
+
+        invoice.voided = Some(Void {
+            date: NaiveDate::from_ymd_opt(2024, 5, 3).unwrap(),
+            reason: None,
+        });
+        let rendered = render_invoice(&invoice, &client, &profile, false)?;
+        assert!(rendered.contains("\\Huge\\bfseries VOID 2024-05-03"));
+        Ok(())
+    }
+
+    #[test]
+    fn render_named_address() -> Result<(), ClientError> {
+        let mut client = fixture_client();
+        let profile = Profile::default();
+        client.update(&Update::NamedAddress(
+            "registered".to_string(),
+            "9 Registered Way\nCapital".to_string(),
+        ))?;
+        let rendered =
+            render_invoice(&fixture_invoice(), &client, &profile, false)
+                .unwrap();
+        assert!(rendered.contains("1 Some Place"));
+
+        client.update(&Update::InvoiceAddress("registered".to_string()))?;
+        let rendered =
+            render_invoice(&fixture_invoice(), &client, &profile, false)
+                .unwrap();
+        assert!(rendered.contains("9 Registered Way"));
+        assert!(!rendered.contains("1 Some Place"));
+        Ok(())
+    }
+
+    fn reminder(
+        level: ReminderLevel,
+        format: ReminderFormat,
+    ) -> Result<String, askama::Error> {
+        let mut invoice = fixture_invoice();
+        invoice.date = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let mut client = fixture_client();
+        client.name = "R&D_Co".to_string();
+        client.footer = Some("Transfer to #123\nRef: invoice".to_string());
+        let profile = Profile {
+            sender: Some(("Jo Smith".to_string(), "Here".to_string())),
+            ..Profile::default()
+        };
+        let today = NaiveDate::from_ymd_opt(2024, 6, 20).unwrap();
+        render_reminder(&invoice, &client, &profile, level, format, today)
+    }
+
+    #[test]
+    fn reminders_by_level() -> Result<(), askama::Error> {
+        let first = reminder(ReminderLevel::First, ReminderFormat::Text)?;
+        assert_eq!(
+            first,
+            "Dear R&D_Co,\n\n\
+             This is a friendly reminder that invoice #1 for CAD $1050.00, \
+             issued on 2024-05-01, was due on 2024-05-31 and is now 20 days \
+             overdue. If it's already on its way, please disregard this \
+             note.\n\n\
+             Payment details:\nTransfer to #123\nRef: invoice\n\n\
+             Kind regards,\nJo Smith\n"
+        );
+
+        let second = reminder(ReminderLevel::Second, ReminderFormat::Text)?;
+        assert!(second.contains("is still unpaid and was due on 2024-05-31"));
+        assert!(second.contains("20 days ago"));
+        assert!(second.ends_with("Regards,\nJo Smith\n"));
+
+        let last = reminder(ReminderLevel::Final, ReminderFormat::Text)?;
+        assert!(last.contains("within 7 days"));
+        assert!(last.contains("remains unpaid 20 days after its due date"));
+        assert!(last.ends_with("Sincerely,\nJo Smith\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn reminder_formats() -> Result<(), askama::Error> {
+        let tex = reminder(ReminderLevel::First, ReminderFormat::Tex)?;
+        assert!(tex.starts_with("Dear R\\&D\\_Co,\n\n"));
+        assert!(tex.contains("invoice \\#1 for CAD \\$1050.00"));
+        assert!(tex.contains("Payment details:\\\\\nTransfer to \\#123"));
+
+        let html = reminder(ReminderLevel::Final, ReminderFormat::Html)?;
+        assert!(html.starts_with("<p>Dear R&amp;D_Co,</p>\n\n<p>"));
+        assert!(html.ends_with("<p>Sincerely,<br>\nJo Smith</p>\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn tex_escaping() {
+        let mut escaped = String::new();
+        Tex.write_escaped(&mut escaped, "M_1 & #2 {50%} $3 ~^\\")
+            .unwrap();
+        assert_eq!(
+            escaped,
+            "M\\_1 \\& \\#2 \\{50\\%\\} \\$3 \\textasciitilde{}\\textasciicircum{}\\textbackslash{}"
+        );
+    }
+
+    #[test]
+    fn accent_parsing() {
+        assert_eq!(parse_accent("#1a2b3c"), Some("1A2B3C".to_string()));
+        assert_eq!(parse_accent("FFFFFF"), Some("FFFFFF".to_string()));
+        assert_eq!(parse_accent("#fff"), None);
+        assert_eq!(parse_accent("12345G"), None);
+        assert_eq!(parse_accent(""), None);
+    }
+}
@@ -0,0 +1,408 @@
+use rust_decimal::Decimal;
+
+use crate::billing::{CurrencyMismatch, Invoice, InvoiceKind, Money, Unit};
+use crate::xml::XmlWriter;
+
+const INVOICE_NS: &str =
+    "urn:oasis:names:specification:ubl:schema:xsd:Invoice-2";
+const CAC_NS: &str =
+    "urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2";
+const CBC_NS: &str =
+    "urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2";
+const CUSTOMIZATION_ID: &str = "urn:cen.eu:en16931:2017#compliant#\
+                                urn:fdc:peppol.eu:2017:poacc:billing:3.0";
+const PROFILE_ID: &str = "urn:fdc:peppol.eu:2017:poacc:billing:01:1.0";
+
+/// Name and address lines of the seller or buyer
+pub struct Party<'a> {
+    pub name: &'a str,
+    pub address: &'a str,
+}
+
+/// UN/ECE Recommendation 20 code of a billing unit, spans of several weeks
+/// have no code of their own so they're counted as units
+fn unit_code(unit: &Unit) -> &'static str {
+    match unit {
+        Unit::Hour => "HUR",
+        Unit::Day => "DAY",
+        Unit::Week => "WEE",
+        Unit::Month => "MON",
+        Unit::Quarter => "QAN",
+        Unit::Year => "ANN",
+        Unit::EveryNWeeks(_) => "C62",
+    }
+}
+
+/// UBL 2.1 invoice following the Peppol BIS Billing 3.0 structure
+pub fn invoice_xml(
+    invoice: &Invoice,
+    supplier: &Party,
+    customer: &Party,
+) -> Result<String, CurrencyMismatch> {
+    let total = invoice.calculate()?;
+    let currency = invoice.currency().code();
+    let amount = |money: Money| format!("{:.2}", money.amount());
+    // Unit prices keep the digits of a conversion, amounts are to the cent
+    let price = |money: Money| {
+        let price = money.amount().normalize();
+        format!("{:.*}", price.scale().max(2) as usize, price)
+    };
+    let currency_id = [("currencyID", currency)];
+    let tax_percent: Decimal = invoice.tax_rates.iter().map(|tr| tr.1).sum();
+
+    let mut xml = XmlWriter::new();
+    xml.start(
+        "Invoice",
+        &[
+            ("xmlns", INVOICE_NS),
+            ("xmlns:cac", CAC_NS),
+            ("xmlns:cbc", CBC_NS),
+        ],
+    );
+    xml.text("cbc:CustomizationID", &[], CUSTOMIZATION_ID);
+    xml.text("cbc:ProfileID", &[], PROFILE_ID);
+    xml.text("cbc:ID", &[], &invoice.number.to_string());
+    xml.text("cbc:IssueDate", &[], &invoice.date.to_string());
+    xml.text("cbc:DueDate", &[], &invoice.due_date().to_string());
+    // 386 is a prepayment invoice, 380 a commercial one
+    let type_code = match invoice.kind {
+        InvoiceKind::Deposit => "386",
+        _ => "380",
+    };
+    xml.text("cbc:InvoiceTypeCode", &[], type_code);
+    xml.text("cbc:DocumentCurrencyCode", &[], currency);
+    let period = invoice.overall_period();
+    xml.start("cac:InvoicePeriod", &[]);
+    xml.text("cbc:StartDate", &[], &period.from.to_string());
+    xml.text("cbc:EndDate", &[], &period.until.to_string());
+    xml.end();
+
+    for (role, party) in [
+        ("cac:AccountingSupplierParty", supplier),
+        ("cac:AccountingCustomerParty", customer),
+    ] {
+        xml.start(role, &[]);
+        xml.start("cac:Party", &[]);
+        xml.start("cac:PartyName", &[]);
+        xml.text("cbc:Name", &[], party.name);
+        xml.end();
+        xml.start("cac:PostalAddress", &[]);
+        for line in party.address.lines().filter(|l| !l.trim().is_empty()) {
+            xml.start("cac:AddressLine", &[]);
+            xml.text("cbc:Line", &[], line.trim());
+            xml.end();
+        }
+        xml.end();
+        xml.start("cac:PartyLegalEntity", &[]);
+        xml.text("cbc:RegistrationName", &[], party.name);
+        xml.end();
+        xml.end();
+        xml.end();
+    }
+
+    let tax_amount = total.taxes.iter().fold(
+        Money::new(invoice.currency(), Decimal::ZERO),
+        |a, (_, x)| a + *x,
+    );
+    xml.start("cac:TaxTotal", &[]);
+    xml.text("cbc:TaxAmount", &currency_id, &amount(tax_amount));
+    for (tax_rate, tax) in total.taxes.iter() {
+        xml.start("cac:TaxSubtotal", &[]);
+        xml.text("cbc:TaxableAmount", &currency_id, &amount(total.subtotal));
+        xml.text("cbc:TaxAmount", &currency_id, &amount(*tax));
+        tax_category(
+            &mut xml,
+            "cac:TaxCategory",
+            Some(&tax_rate.0),
+            tax_rate.1,
+        );
+        xml.end();
+    }
+    xml.end();
+
+    let prepaid = total.deductions.iter().fold(
+        Money::new(invoice.currency(), Decimal::ZERO),
+        |a, (_, x)| a - *x,
+    );
+    xml.start("cac:LegalMonetaryTotal", &[]);
+    xml.text(
+        "cbc:LineExtensionAmount",
+        &currency_id,
+        &amount(total.subtotal),
+    );
+    xml.text(
+        "cbc:TaxExclusiveAmount",
+        &currency_id,
+        &amount(total.subtotal),
+    );
+    xml.text(
+        "cbc:TaxInclusiveAmount",
+        &currency_id,
+        &amount(total.subtotal + tax_amount),
+    );
+    if !total.deductions.is_empty() {
+        xml.text("cbc:PrepaidAmount", &currency_id, &amount(prepaid));
+    }
+    if let Some(adjustment) = total.adjustment {
+        xml.text(
+            "cbc:PayableRoundingAmount",
+            &currency_id,
+            &amount(adjustment),
+        );
+    }
+    xml.text("cbc:PayableAmount", &currency_id, &amount(total.total));
+    xml.end();
+
+    for (i, item) in invoice.items.iter().enumerate() {
+        xml.start("cac:InvoiceLine", &[]);
+        xml.text("cbc:ID", &[], &(i + 1).to_string());
+        xml.text(
+            "cbc:InvoicedQuantity",
+            &[("unitCode", unit_code(&item.rate.per))],
+            &item.quantity.normalize().to_string(),
+        );
+        xml.text(
+            "cbc:LineExtensionAmount",
+            &currency_id,
+            &amount(item.amount),
+        );
+        xml.start("cac:InvoicePeriod", &[]);
+        xml.text("cbc:StartDate", &[], &item.period.from.to_string());
+        xml.text("cbc:EndDate", &[], &item.period.until.to_string());
+        xml.end();
+        xml.start("cac:Item", &[]);
+        xml.text("cbc:Name", &[], &item.name);
+        tax_category(&mut xml, "cac:ClassifiedTaxCategory", None, tax_percent);
+        xml.end();
+        // Converted items keep their rate in the currency they were billed
+        // in, the price is in the invoice's currency like the line amount
+        let unit_price = match invoice.fx {
+            Some((from, rate)) if item.rate.amount.currency() == from => {
+                Money::new(invoice.currency(), item.rate.amount.amount() * rate)
+            }
+            _ => item.rate.amount,
+        };
+        xml.start("cac:Price", &[]);
+        xml.text("cbc:PriceAmount", &currency_id, &price(unit_price));
+        xml.end();
+        xml.end();
+    }
+
+    xml.end();
+    Ok(xml.finish())
+}
+
+/// Standard rated VAT category, zero rated when there are no taxes
+fn tax_category(
+    xml: &mut XmlWriter,
+    element: &'static str,
+    name: Option<&str>,
+    rate: Decimal,
+) {
+    xml.start(element, &[]);
+    xml.text("cbc:ID", &[], if rate.is_zero() { "Z" } else { "S" });
+    if let Some(name) = name {
+        xml.text("cbc:Name", &[], name);
+    }
+    let percent = (rate * Decimal::from(100)).normalize();
+    xml.text("cbc:Percent", &[], &percent.to_string());
+    xml.start("cac:TaxScheme", &[]);
+    xml.text("cbc:ID", &[], "VAT");
+    xml.end();
+    xml.end();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::billing::tests::{monthly, ymd};
+    use crate::billing::{Currency, InvoiceItem, Period, Rate, TaxRate};
+
+    fn supplier() -> Party<'static> {
+        Party {
+            name: "Smith & Sons",
+            address: "1 Main St\nVancouver",
+        }
+    }
+
+    fn customer() -> Party<'static> {
+        Party {
+            name: "Innotech <EU>",
+            address: "Hauptstraße 5\n10115 Berlin\n",
+        }
+    }
+
+    fn invoice() -> Invoice {
+        let hourly = Rate {
+            amount: Money::new(Currency::Eur, Decimal::from(80)),
+            per: Unit::Hour,
+            tiers: Vec::new(),
+        };
+        let april = Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30));
+        let mut invoice = Invoice::new(
+            7,
+            vec![
+                InvoiceItem::new(
+                    "Hosting".to_string(),
+                    monthly(Currency::Eur, 100),
+                    april.clone(),
+                ),
+                InvoiceItem::new_hourly(
+                    "Support".to_string(),
+                    hourly,
+                    april,
+                    Decimal::new(125, 1),
+                ),
+            ],
+            vec![TaxRate::new("VAT".to_string(), 19)],
+        )
+        .unwrap();
+        invoice.date = ymd(2024, 5, 1);
+        invoice
+    }
+
+    #[test]
+    fn unit_codes() {
+        assert_eq!(unit_code(&Unit::Hour), "HUR");
+        assert_eq!(unit_code(&Unit::Month), "MON");
+        assert_eq!(unit_code(&Unit::EveryNWeeks(2)), "C62");
+    }
+
+    #[test]
+    fn header_and_parties() {
+        let xml = invoice_xml(&invoice(), &supplier(), &customer()).unwrap();
+
+        assert!(xml.starts_with(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <Invoice xmlns=\"urn:oasis:names:specification:ubl:schema:xsd:\
+             Invoice-2\""
+        ));
+        assert!(xml.contains(
+            "  <cbc:ID>7</cbc:ID>\n\
+             \x20 <cbc:IssueDate>2024-05-01</cbc:IssueDate>\n\
+             \x20 <cbc:DueDate>2024-05-31</cbc:DueDate>\n\
+             \x20 <cbc:InvoiceTypeCode>380</cbc:InvoiceTypeCode>\n\
+             \x20 <cbc:DocumentCurrencyCode>EUR</cbc:DocumentCurrencyCode>\n"
+        ));
+        assert!(xml.contains("<cbc:Name>Smith &amp; Sons</cbc:Name>"));
+        assert!(xml.contains(
+            "<cbc:RegistrationName>Innotech &lt;EU&gt;</cbc:RegistrationName>"
+        ));
+        assert!(xml.contains("<cbc:Line>10115 Berlin</cbc:Line>"));
+        assert_eq!(xml.matches("<cac:AddressLine>").count(), 4);
+        assert!(xml.ends_with("</Invoice>\n"));
+    }
+
+    #[test]
+    fn lines_and_totals() {
+        let xml = invoice_xml(&invoice(), &supplier(), &customer()).unwrap();
+
+        assert!(xml.contains(
+            "<cbc:InvoicedQuantity unitCode=\"HUR\">12.5</cbc:InvoicedQuantity>\n\
+             \x20   <cbc:LineExtensionAmount currencyID=\"EUR\">1000.00\
+             </cbc:LineExtensionAmount>"
+        ));
+        assert!(xml.contains(
+            "<cbc:InvoicedQuantity unitCode=\"MON\">1</cbc:InvoicedQuantity>"
+        ));
+        assert!(xml.contains(
+            "<cbc:PriceAmount currencyID=\"EUR\">80.00</cbc:PriceAmount>"
+        ));
+        assert!(xml.contains(
+            "  <cac:TaxTotal>\n\
+             \x20   <cbc:TaxAmount currencyID=\"EUR\">209.00</cbc:TaxAmount>\n\
+             \x20   <cac:TaxSubtotal>\n\
+             \x20     <cbc:TaxableAmount currencyID=\"EUR\">1100.00\
+             </cbc:TaxableAmount>\n\
+             \x20     <cbc:TaxAmount currencyID=\"EUR\">209.00</cbc:TaxAmount>\n\
+             \x20     <cac:TaxCategory>\n\
+             \x20       <cbc:ID>S</cbc:ID>\n\
+             \x20       <cbc:Name>VAT</cbc:Name>\n\
+             \x20       <cbc:Percent>19</cbc:Percent>\n"
+        ));
+        assert!(xml.contains(
+            "  <cac:LegalMonetaryTotal>\n\
+             \x20   <cbc:LineExtensionAmount currencyID=\"EUR\">1100.00\
+             </cbc:LineExtensionAmount>\n\
+             \x20   <cbc:TaxExclusiveAmount currencyID=\"EUR\">1100.00\
+             </cbc:TaxExclusiveAmount>\n\
+             \x20   <cbc:TaxInclusiveAmount currencyID=\"EUR\">1309.00\
+             </cbc:TaxInclusiveAmount>\n\
+             \x20   <cbc:PayableAmount currencyID=\"EUR\">1309.00\
+             </cbc:PayableAmount>\n\
+             \x20 </cac:LegalMonetaryTotal>\n"
+        ));
+    }
+
+    #[test]
+    fn converted_lines_are_priced_in_the_invoice_currency() {
+        let hourly = Rate {
+            amount: Money::new(Currency::Usd, Decimal::from(100)),
+            per: Unit::Hour,
+            tiers: Vec::new(),
+        };
+        let fx = Decimal::new(13751, 4);
+        let item = InvoiceItem::new_hourly(
+            "Support".to_string(),
+            hourly,
+            Period::new(ymd(2024, 4, 1), ymd(2024, 4, 30)),
+            Decimal::from(10),
+        )
+        .converted(Currency::Cad, fx);
+        let mut invoice = Invoice::new(8, vec![item], vec![]).unwrap();
+        invoice.fx = Some((Currency::Usd, fx));
+        let xml = invoice_xml(&invoice, &supplier(), &customer()).unwrap();
+
+        assert!(xml.contains(
+            "<cbc:LineExtensionAmount currencyID=\"CAD\">1375.10\
+             </cbc:LineExtensionAmount>"
+        ));
+        assert!(xml.contains(
+            "<cbc:PriceAmount currencyID=\"CAD\">137.51</cbc:PriceAmount>"
+        ));
+        assert!(!xml.contains("currencyID=\"USD\""));
+
+        // Prices keep the digits of the rate instead of rounding to the cent
+        invoice.fx = Some((Currency::Usd, Decimal::new(13755, 4)));
+        let xml = invoice_xml(&invoice, &supplier(), &customer()).unwrap();
+        assert!(xml.contains(
+            "<cbc:PriceAmount currencyID=\"CAD\">137.55</cbc:PriceAmount>"
+        ));
+        invoice.items[0].rate.amount =
+            Money::new(Currency::Usd, Decimal::new(9505, 2));
+        let xml = invoice_xml(&invoice, &supplier(), &customer()).unwrap();
+        assert!(xml.contains(
+            "<cbc:PriceAmount currencyID=\"CAD\">130.741275</cbc:PriceAmount>"
+        ));
+    }
+
+    #[test]
+    fn zero_rated_tax_subtotal() {
+        let mut invoice = invoice();
+        invoice.tax_rates = vec![TaxRate::new("VAT".to_string(), 0)];
+        let xml = invoice_xml(&invoice, &supplier(), &customer()).unwrap();
+
+        assert!(xml.contains(
+            "\x20     <cbc:TaxAmount currencyID=\"EUR\">0.00</cbc:TaxAmount>\n\
+             \x20     <cac:TaxCategory>\n\
+             \x20       <cbc:ID>Z</cbc:ID>\n\
+             \x20       <cbc:Name>VAT</cbc:Name>\n\
+             \x20       <cbc:Percent>0</cbc:Percent>\n"
+        ));
+    }
+
+    #[test]
+    fn deposit_deductions_are_prepaid() {
+        let mut invoice = invoice();
+        invoice.kind = InvoiceKind::Final {
+            applies: vec![(3, Money::new(Currency::Eur, Decimal::from(300)))],
+        };
+        let xml = invoice_xml(&invoice, &supplier(), &customer()).unwrap();
+
+        assert!(xml.contains(
+            "<cbc:PrepaidAmount currencyID=\"EUR\">300.00</cbc:PrepaidAmount>\n\
+             \x20   <cbc:PayableAmount currencyID=\"EUR\">1009.00\
+             </cbc:PayableAmount>"
+        ));
+    }
+}
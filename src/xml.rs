@@ -0,0 +1,125 @@
+use std::borrow::Cow;
+
+/// Writes an indented XML document element by element, escaping text and
+/// attribute values and closing elements in the order they were opened
+pub struct XmlWriter {
+    out: String,
+    open: Vec<&'static str>,
+}
+
+impl XmlWriter {
+    pub fn new() -> Self {
+        Self {
+            out: "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n".to_string(),
+            open: Vec::new(),
+        }
+    }
+
+    /// Opens an element that later elements are nested in until `end`
+    pub fn start(&mut self, name: &'static str, attrs: &[(&str, &str)]) {
+        self.tag(name, attrs);
+        self.out.push_str(">\n");
+        self.open.push(name);
+    }
+
+    pub fn end(&mut self) {
+        let name = self.open.pop().expect("an element to close");
+        self.indent();
+        self.out.push_str(&format!("</{}>\n", name));
+    }
+
+    /// Writes an element containing only text
+    pub fn text(
+        &mut self,
+        name: &'static str,
+        attrs: &[(&str, &str)],
+        text: &str,
+    ) {
+        self.tag(name, attrs);
+        self.out
+            .push_str(&format!(">{}</{}>\n", escape(text), name));
+    }
+
+    pub fn finish(self) -> String {
+        assert!(self.open.is_empty(), "unclosed elements: {:?}", self.open);
+        self.out
+    }
+
+    fn tag(&mut self, name: &str, attrs: &[(&str, &str)]) {
+        self.indent();
+        self.out.push('<');
+        self.out.push_str(name);
+        for (attr, value) in attrs {
+            self.out
+                .push_str(&format!(" {}=\"{}\"", attr, escape(value)));
+        }
+    }
+
+    fn indent(&mut self) {
+        self.out.push_str(&"  ".repeat(self.open.len()));
+    }
+}
+
+/// Escapes the characters with special meaning in text and quoted
+/// attribute values
+pub fn escape(text: &str) -> Cow<'_, str> {
+    if !text.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len() + 8);
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape("plain"), "plain");
+        assert_eq!(
+            escape("<a href=\"x\">R&D's</a>"),
+            "&lt;a href=&quot;x&quot;&gt;R&amp;D&apos;s&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn nested_elements() {
+        let mut xml = XmlWriter::new();
+        xml.start("Invoice", &[("xmlns", "urn:example")]);
+        xml.text("ID", &[], "7");
+        xml.start("Party", &[]);
+        xml.text("Amount", &[("currencyID", "CAD")], "1.00");
+        xml.end();
+        xml.end();
+
+        assert_eq!(
+            xml.finish(),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <Invoice xmlns=\"urn:example\">\n\
+             \x20 <ID>7</ID>\n\
+             \x20 <Party>\n\
+             \x20   <Amount currencyID=\"CAD\">1.00</Amount>\n\
+             \x20 </Party>\n\
+             </Invoice>\n"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "unclosed elements")]
+    fn unclosed_element() {
+        let mut xml = XmlWriter::new();
+        xml.start("Invoice", &[]);
+        xml.finish();
+    }
+}